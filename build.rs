@@ -0,0 +1,16 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    // The gRPC service is generated from its schema, with the bundled protoc unless PROTOC
+    // points at another
+    #[cfg(feature = "grpc")]
+    {
+        if std::env::var_os("PROTOC").is_none() {
+            let protoc = protoc_bin_vendored::protoc_bin_path().expect("bundled protoc");
+            std::env::set_var("PROTOC", protoc);
+        }
+        tonic_prost_build::configure()
+            .build_client(false)
+            .compile_protos(&["proto/observations.proto"], &["proto"])
+            .expect("failed to compile proto/observations.proto");
+    }
+}
@@ -0,0 +1,137 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+
+use crate::clock::Stamp;
+
+/// What a monitor keeps from each block it records
+#[derive(Debug, Clone)]
+pub struct BlockHeader {
+    /// Block timestamp in milliseconds since the epoch
+    pub timestamp_ms: u64,
+    /// Block hash, for chains whose blocks link to their parent's
+    pub hash: Option<String>,
+    pub parent_hash: Option<String>,
+    /// Gas the block's transactions used, on chains that meter gas
+    pub gas_used: Option<u64>,
+    /// Transactions in the block; `None` for pushed heads, which don't list them
+    pub tx_count: Option<u64>,
+}
+
+/// Headers fetched in one round trip
+#[derive(Debug)]
+pub struct BlockBatch {
+    /// When the request was sent
+    pub received: Stamp,
+    /// One result per requested height, in order
+    pub headers: Vec<Result<BlockHeader>>,
+}
+
+/// The L1 block an OP-stack L2 block was derived from, as recorded by the L1 attributes
+/// deposit the L2 block starts with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct L1Origin {
+    pub number: u64,
+    /// L1 block timestamp in milliseconds since the epoch
+    pub timestamp_ms: u64,
+}
+
+/// What a node reports about its own state: whether it is still catching up with the
+/// chain, and how many peers it has
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NodeHealth {
+    pub syncing: bool,
+    /// Height the node has synced to and the highest it knows of, while syncing
+    pub current_block: Option<u64>,
+    pub highest_block: Option<u64>,
+    /// `None` when the node doesn't say
+    pub peer_count: Option<u64>,
+}
+
+impl NodeHealth {
+    /// Blocks the node is behind the highest block it knows of, while syncing
+    pub fn sync_lag_blocks(&self) -> Option<u64> {
+        Some(self.highest_block?.saturating_sub(self.current_block?)).filter(|_| self.syncing)
+    }
+}
+
+/// A provider returned no block for a height its own head already reported
+#[derive(Debug)]
+pub struct StaleResponse(pub u64);
+
+impl std::fmt::Display for StaleResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Block {} not available yet (stale backend)", self.0)
+    }
+}
+
+impl std::error::Error for StaleResponse {}
+
+/// Request outcomes an adapter counts for the chain's stats
+#[derive(Debug, Default, Clone)]
+pub struct AdapterCounters {
+    /// Transient failures that were retried
+    pub retries: usize,
+    /// Strict mode: responses deviating from the expected schema
+    pub schema_deviations: usize,
+    /// Round-trip times (ms) of answered head height requests
+    pub head_latencies_ms: Vec<u64>,
+    /// Round-trip times (ms) of answered block requests, batches, tagged heads and L1 origins
+    /// included
+    pub block_latencies_ms: Vec<u64>,
+    /// The provider's clock at answered HTTP requests, from their `Date` header (ms, at the
+    /// middle of the whole second it names), with the local time halfway through each request
+    pub server_dates: Vec<(i64, DateTime<Utc>)>,
+}
+
+/// How a monitor talks to one chain. Implementing this is all it takes to monitor a chain
+/// with a different RPC protocol; the stats engine only sees heights and headers.
+///
+/// Errors that `retry::is_transient` recognizes leave the block to be retried on the next
+/// poll, as does `StaleResponse`; other errors skip the block.
+pub trait ChainAdapter: Send {
+    /// Height of the chain's latest block
+    fn latest_height(&mut self) -> BoxFuture<'_, Result<u64>>;
+
+    /// Header of the block at `height`
+    fn block_at(&mut self, height: u64) -> BoxFuture<'_, Result<BlockHeader>>;
+
+    /// Headers of the blocks `first` through `last` from a single round trip. Adapters that can't batch keep the default, and blocks are
+    /// then fetched one at a time.
+    fn blocks_in(&mut self, first: u64, last: u64) -> BoxFuture<'_, Result<BlockBatch>> {
+        let _ = (first, last);
+        Box::pin(async { Err(anyhow!("Batch requests not supported")) })
+    }
+
+    /// Height and header of the block a tag such as "safe", "finalized" or "pending" points
+    /// at. Adapters for chains without such heads keep the default, which turns tracking off.
+    fn tagged_block(&mut self, tag: &'static str) -> BoxFuture<'_, Result<(u64, BlockHeader)>> {
+        Box::pin(async move { Err(anyhow!("No {} head on this chain", tag)) })
+    }
+
+    /// L1 origin of the block at `height`, on chains derived from L1 such as the OP stack.
+    /// Adapters for other chains keep the default, which turns tracking off.
+    fn l1_origin(&mut self, height: u64) -> BoxFuture<'_, Result<L1Origin>> {
+        Box::pin(async move { Err(anyhow!("Block {} has no L1 origin on this chain", height)) })
+    }
+
+    /// Chain ID the endpoint serves, to check it against the configured one. Adapters for
+    /// chains without one keep the default, which skips the check.
+    fn chain_id(&mut self) -> BoxFuture<'_, Result<u64>> {
+        Box::pin(async { Err(anyhow!("No chain ID on this chain")) })
+    }
+
+    /// Whether the node behind the endpoint is syncing, and its peer count. Adapters for
+    /// chains without such a query keep the default, which turns health checks off.
+    fn node_health(&mut self) -> BoxFuture<'_, Result<NodeHealth>> {
+        Box::pin(async { Err(anyhow!("No node health on this chain")) })
+    }
+
+    /// Counters accumulated since the previous call
+    fn take_counters(&mut self) -> AdapterCounters {
+        AdapterCounters::default()
+    }
+
+    /// Drop connection state after repeated failures or a panic
+    fn reset(&mut self) {}
+}
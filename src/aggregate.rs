@@ -0,0 +1,282 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use clap::{Args, ValueEnum};
+use log::info;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::analyze::AnalysisFormat;
+use crate::dataset::DataFormat;
+use crate::histogram::DeltaHistogram;
+use crate::replay;
+
+/// What the observations of the runs are grouped into before trends are computed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AggregatePeriod {
+    /// ISO weeks, starting on Monday (UTC)
+    Week,
+    /// Calendar days (UTC)
+    Day,
+    /// Each input on its own, in the order given, for comparing runs before and after a
+    /// change whatever their dates
+    Run,
+}
+
+/// Arguments for the `aggregate` subcommand
+#[derive(Debug, Args)]
+pub struct AggregateArgs {
+    /// Datasets of the runs to aggregate: run directories, NDJSON files, SQLite or DuckDB databases
+    #[arg(required = true)]
+    pub inputs: Vec<PathBuf>,
+
+    /// Format of the inputs; detected from each path when omitted
+    #[arg(long, value_enum)]
+    pub from: Option<DataFormat>,
+
+    /// Group blocks by the week or day they were received in, or by run
+    #[arg(long, value_enum, default_value_t = AggregatePeriod::Week)]
+    pub period: AggregatePeriod,
+
+    /// Only aggregate these chains, comma-separated
+    #[arg(long, value_delimiter = ',')]
+    pub chain: Vec<String>,
+
+    /// Only blocks observed from these vantage points, comma-separated
+    #[arg(long, value_delimiter = ',')]
+    pub vantage: Vec<String>,
+
+    /// Slopes of the average delta smaller than this, in milliseconds per period, count as
+    /// steady rather than increasing or decreasing
+    #[arg(long, default_value_t = 5.0)]
+    pub steady_ms: f64,
+
+    /// Print the trends as text or as JSON
+    #[arg(long, value_enum, default_value_t = AnalysisFormat::Text)]
+    pub format: AnalysisFormat,
+}
+
+/// One chain's deltas over one period
+struct PeriodDeltas {
+    label: String,
+    // Position on the trend's x axis: periods since the first, or the run's index
+    index: f64,
+    deltas: DeltaHistogram,
+    first_received: DateTime<Utc>,
+    last_received: DateTime<Utc>,
+}
+
+impl PeriodDeltas {
+    fn future_pct(&self) -> f64 {
+        self.deltas.future_count() as f64 * 100.0 / self.deltas.len() as f64
+    }
+
+    fn summary(&self, previous: Option<&PeriodDeltas>) -> serde_json::Value {
+        serde_json::json!({
+            "period": self.label,
+            "first_received": self.first_received.to_rfc3339(),
+            "last_received": self.last_received.to_rfc3339(),
+            "blocks": self.deltas.len(),
+            "future_timestamp_blocks": self.deltas.future_count(),
+            "future_pct": self.future_pct(),
+            "delta_ms": {
+                "avg": self.deltas.mean_ms(),
+                "stddev": self.deltas.stddev_ms(),
+                "p50": self.deltas.percentile(50.0),
+                "p99": self.deltas.percentile(99.0),
+            },
+            "avg_change_ms": previous.map(|previous| self.deltas.mean_ms() - previous.deltas.mean_ms()),
+        })
+    }
+}
+
+/// Which way a chain's average delta is heading
+struct Trend {
+    // Least-squares slope of the average delta, in milliseconds per period
+    slope_ms: f64,
+    // Average delta of the last period minus that of the first
+    change_ms: i64,
+    direction: &'static str,
+}
+
+impl Trend {
+    // `None` with fewer than two periods, which have no trend
+    fn of(periods: &[PeriodDeltas], steady_ms: f64) -> Option<Self> {
+        let (first, last) = (periods.first()?, periods.last()?);
+        if periods.len() < 2 {
+            return None;
+        }
+        let n = periods.len() as f64;
+        let mean_x = periods.iter().map(|period| period.index).sum::<f64>() / n;
+        let mean_y = periods.iter().map(|period| period.deltas.mean_ms() as f64).sum::<f64>() / n;
+        let (covariance, variance) = periods.iter().fold((0.0, 0.0), |(covariance, variance), period| {
+            let dx = period.index - mean_x;
+            (covariance + dx * (period.deltas.mean_ms() as f64 - mean_y), variance + dx * dx)
+        });
+        let slope_ms = covariance / variance;
+        Some(Self {
+            slope_ms,
+            change_ms: last.deltas.mean_ms() - first.deltas.mean_ms(),
+            direction: if slope_ms.abs() < steady_ms {
+                "steady"
+            } else if slope_ms > 0.0 {
+                "increasing"
+            } else {
+                "decreasing"
+            },
+        })
+    }
+}
+
+// The period a block received at `received_at` falls in, as a sortable key and the
+// number of periods since the epoch
+fn period_of(period: AggregatePeriod, received_at: DateTime<Utc>) -> (String, i64) {
+    let date = received_at.date_naive();
+    let epoch = NaiveDate::default();
+    match period {
+        AggregatePeriod::Week => {
+            let monday = date - Duration::days(date.weekday().num_days_from_monday() as i64);
+            let week = monday.iso_week();
+            (format!("{}-W{:02}", week.year(), week.week()), (monday - epoch).num_days() / 7)
+        }
+        AggregatePeriod::Day | AggregatePeriod::Run => (date.to_string(), (date - epoch).num_days()),
+    }
+}
+
+// Runs are labelled after the file or directory they came from
+fn run_label(input: &Path) -> String {
+    input
+        .file_name()
+        .map_or_else(|| input.display().to_string(), |name| name.to_string_lossy().into_owned())
+}
+
+/// Aggregate the observations of several runs per chain and period, and show whether each
+/// chain's average delta is increasing or decreasing from one period to the next, e.g. to
+/// see whether a sequencer upgrade changed how its blocks are stamped
+pub fn run(args: &AggregateArgs) -> Result<()> {
+    // Chain -> (sort key, period key) -> deltas
+    let mut chains: BTreeMap<String, BTreeMap<(i64, String), PeriodDeltas>> = BTreeMap::new();
+    // Blocks already counted, so runs that overlap count a block once, in the first run
+    // that recorded it
+    let mut seen: HashSet<(Option<String>, String, u64)> = HashSet::new();
+    for (run, input) in args.inputs.iter().enumerate() {
+        let mut observations = replay::load_observations(input, args.from)?;
+        info!("Loaded {} observations from {}", observations.len(), input.display());
+        observations.sort_by_key(|observation| observation.received_at);
+        for observation in observations {
+            if !(args.chain.is_empty() || args.chain.contains(&observation.chain))
+                || !(args.vantage.is_empty()
+                    || observation.vantage.as_ref().is_some_and(|vantage| args.vantage.contains(vantage)))
+            {
+                continue;
+            }
+            let key = (observation.vantage.clone(), observation.chain.clone(), observation.block_number);
+            if !seen.insert(key) {
+                continue;
+            }
+            let (label, index) = match args.period {
+                AggregatePeriod::Run => (run_label(input), run as i64),
+                period => period_of(period, observation.received_at),
+            };
+            let period = chains
+                .entry(observation.chain)
+                .or_default()
+                .entry((index, label.clone()))
+                .or_insert_with(|| PeriodDeltas {
+                    label,
+                    index: index as f64,
+                    deltas: DeltaHistogram::default(),
+                    first_received: observation.received_at,
+                    last_received: observation.received_at,
+                });
+            period.deltas.record(observation.delta_ms);
+            period.first_received = period.first_received.min(observation.received_at);
+            period.last_received = period.last_received.max(observation.received_at);
+        }
+    }
+    if chains.is_empty() {
+        return Err(anyhow!("No block observations in the inputs match the filters"));
+    }
+
+    let mut json_chains = Vec::new();
+    for (chain, periods) in chains {
+        let mut periods: Vec<PeriodDeltas> = periods.into_values().collect();
+        // Trends are in periods since the first, so gaps between runs stretch the x axis
+        let origin = periods[0].index;
+        for period in &mut periods {
+            period.index -= origin;
+        }
+        let trend = Trend::of(&periods, args.steady_ms);
+        match args.format {
+            AnalysisFormat::Text => {
+                for line in text_report(&chain, args.period, &periods, trend.as_ref()) {
+                    println!("{}", line);
+                }
+            }
+            AnalysisFormat::Json => json_chains.push(serde_json::json!({
+                "chain": chain,
+                "periods": periods
+                    .iter()
+                    .enumerate()
+                    .map(|(i, period)| period.summary(i.checked_sub(1).map(|previous| &periods[previous])))
+                    .collect::<Vec<_>>(),
+                "trend": trend.map(|trend| serde_json::json!({
+                    "avg_slope_ms_per_period": trend.slope_ms,
+                    "avg_change_ms": trend.change_ms,
+                    "direction": trend.direction,
+                })),
+            })),
+        }
+    }
+    if args.format == AnalysisFormat::Json {
+        let period = args.period.to_possible_value().map(|value| value.get_name().to_string());
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({ "period": period, "chains": json_chains }))?
+        );
+    }
+    Ok(())
+}
+
+fn text_report(chain: &str, period: AggregatePeriod, periods: &[PeriodDeltas], trend: Option<&Trend>) -> Vec<String> {
+    let unit = match period {
+        AggregatePeriod::Week => "week",
+        AggregatePeriod::Day => "day",
+        AggregatePeriod::Run => "run",
+    };
+    let width = periods.iter().map(|period| period.label.len()).max().unwrap_or(0).max("Period".len());
+    let mut lines = vec![
+        format!("{}: {} {}{}", chain, periods.len(), unit, if periods.len() == 1 { "" } else { "s" }),
+        format!(
+            "  {:<width$} | {:>8} | {:>7} | {:>7} | {:>8} | {:>7} | {:>7} | {:>7}",
+            "Period", "Blocks", "Future", "Avg", "Change", "StdDev", "P50", "P99",
+            width = width
+        ),
+    ];
+    for (i, current) in periods.iter().enumerate() {
+        let change = i
+            .checked_sub(1)
+            .map_or("-".to_string(), |previous| {
+                format!("{:+}ms", current.deltas.mean_ms() - periods[previous].deltas.mean_ms())
+            });
+        lines.push(format!(
+            "  {:<width$} | {:>8} | {:>6.1}% | {:>5}ms | {:>8} | {:>5.0}ms | {:>5}ms | {:>5}ms",
+            current.label,
+            current.deltas.len(),
+            current.future_pct(),
+            current.deltas.mean_ms(),
+            change,
+            current.deltas.stddev_ms(),
+            current.deltas.percentile(50.0),
+            current.deltas.percentile(99.0),
+            width = width
+        ));
+    }
+    lines.push(match trend {
+        Some(trend) => format!(
+            "  - Average delta {}: {:+.1}ms per {}, {:+}ms from first to last",
+            trend.direction, trend.slope_ms, unit, trend.change_ms
+        ),
+        None => format!("  - No trend: observed in only one {}", unit),
+    });
+    lines
+}
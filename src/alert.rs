@@ -0,0 +1,121 @@
+use anyhow::{anyhow, Result};
+use log::{error, warn};
+use serde_json::json;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+use crate::monitor::BlockObservation;
+use crate::notify::Notifiers;
+
+/// POSTs a JSON alert to a webhook, and/or sends a message to the notifiers, for every
+/// block whose delta, past or future, exceeds a threshold, and optionally for every block
+/// whose delta is anomalous for its chain. Clones share the threshold, so it can be changed
+/// while the alert runs.
+#[derive(Debug, Clone)]
+pub struct DeltaAlert {
+    client: reqwest::Client,
+    url: Option<String>,
+    threshold_ms: Arc<AtomicU64>,
+    notify: bool,
+    anomalies: bool,
+}
+
+impl DeltaAlert {
+    /// Alert on deltas beyond `threshold_ms`, and with `anomalies` on anomalous deltas too
+    pub fn new(url: Option<String>, threshold_ms: u64, notify: bool, anomalies: bool) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+            url,
+            threshold_ms: Arc::new(AtomicU64::new(threshold_ms)),
+            notify,
+            anomalies,
+        }
+    }
+
+    pub fn threshold_ms(&self) -> u64 {
+        self.threshold_ms.load(Ordering::Relaxed)
+    }
+
+    pub fn set_threshold_ms(&self, threshold_ms: u64) {
+        self.threshold_ms.store(threshold_ms, Ordering::Relaxed);
+    }
+
+    pub fn alerts_on_anomalies(&self) -> bool {
+        self.anomalies
+    }
+
+    // Why `observation` calls for an alert, if it does, with the alert's message
+    fn reason(&self, observation: &BlockObservation) -> Option<(&'static str, String)> {
+        if observation.delta_ms.unsigned_abs() > self.threshold_ms() {
+            return Some((
+                "threshold",
+                format!(
+                    "{}: Block {} delta of {}ms exceeds the {}ms alert threshold",
+                    observation.chain, observation.block_number, observation.delta_ms, self.threshold_ms()
+                ),
+            ));
+        }
+        let z_score = observation.anomaly_z.filter(|_| self.anomalies)?;
+        Some((
+            "anomaly",
+            format!(
+                "{}: Block {} delta of {}ms is {:+.1} standard deviations from its recent baseline",
+                observation.chain, observation.block_number, observation.delta_ms, z_score
+            ),
+        ))
+    }
+
+    async fn send(&self, url: &str, observation: &BlockObservation, reason: &str) -> Result<()> {
+        let payload = json!({
+            "chain": observation.chain,
+            "block_number": observation.block_number,
+            "delta_ms": observation.delta_ms,
+            "direction": observation.timestamp_delta().direction,
+            "reason": reason,
+            "threshold_ms": self.threshold_ms(),
+            "z_score": observation.anomaly_z,
+            "block_timestamp_ms": observation.block_timestamp_ms,
+            "received_at": observation.received_at.to_rfc3339(),
+        });
+        let response = self.client.post(url).json(&payload).send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Alert webhook returned {}", response.status()));
+        }
+        Ok(())
+    }
+
+    /// Check every observation on its way to `next`, alerting on those over the threshold or
+    /// anomalous. Alerts are sent in order before the observation is passed on, so none are
+    /// cut off at shutdown.
+    pub async fn run(
+        self,
+        mut receiver: UnboundedReceiver<BlockObservation>,
+        next: Option<UnboundedSender<BlockObservation>>,
+        notifiers: Notifiers,
+    ) {
+        while let Some(observation) = receiver.recv().await {
+            if let Some((reason, message)) = self.reason(&observation) {
+                // The monitor already logs anomalies
+                if reason == "threshold" {
+                    warn!("{}", message);
+                }
+                if let Some(url) = &self.url {
+                    if let Err(e) = self.send(url, &observation, reason).await {
+                        error!("Failed to send delta alert: {}", e);
+                    }
+                }
+                if self.notify {
+                    notifiers.notify("Block timestamp delta alert", &message).await;
+                }
+            }
+            if let Some(next) = &next {
+                let _ = next.send(observation);
+            }
+        }
+    }
+}
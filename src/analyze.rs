@@ -0,0 +1,230 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use clap::{Args, Subcommand, ValueEnum};
+use log::info;
+use std::path::PathBuf;
+
+use crate::dataset::DataFormat;
+use crate::distribution::{self, CompareChainsArgs};
+use crate::histogram::HistogramBuckets;
+use crate::replay::{self, ReplaySettings};
+use crate::report;
+use crate::stats::{ChainStats, GapSummary};
+use crate::vantage::{self, CompareVantageArgs};
+
+/// How the analysis is printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AnalysisFormat {
+    Text,
+    Json,
+}
+
+/// Analyses other than the default per-chain one
+#[derive(Debug, Subcommand)]
+pub enum AnalyzeCommand {
+    /// Compare each chain's deltas between the vantage points it was observed from
+    CompareVantage(CompareVantageArgs),
+    /// Test whether two chains' delta distributions differ, and by how much at each percentile
+    Compare(CompareChainsArgs),
+}
+
+/// Arguments for the `analyze` subcommand
+#[derive(Debug, Args)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct AnalyzeArgs {
+    #[command(subcommand)]
+    pub command: Option<AnalyzeCommand>,
+
+    /// Stored observations: a run directory, NDJSON file, SQLite database or DuckDB database
+    #[arg(env = "OUTPUT_DIR", default_value = "./logs")]
+    pub input: PathBuf,
+
+    /// Format of the input; detected from the path when omitted
+    #[arg(long, value_enum)]
+    pub from: Option<DataFormat>,
+
+    /// Only analyze these chains, comma-separated
+    #[arg(long, value_delimiter = ',')]
+    pub chain: Vec<String>,
+
+    /// Only blocks observed from these vantage points, comma-separated
+    #[arg(long, value_delimiter = ',')]
+    pub vantage: Vec<String>,
+
+    /// Only blocks received at or after this time (RFC 3339)
+    #[arg(long)]
+    pub since: Option<DateTime<Utc>>,
+
+    /// Only blocks received before this time (RFC 3339)
+    #[arg(long)]
+    pub until: Option<DateTime<Utc>>,
+
+    /// Delta percentiles to report, comma-separated
+    #[arg(long, value_delimiter = ',', default_value = "50,90,99,99.9")]
+    pub percentiles: Vec<f64>,
+
+    /// Upper bounds of the delta histogram buckets in ms, comma-separated
+    /// [default: HISTOGRAM_BUCKETS_MS, or -2000,-1000,-500,-250,0,250,500,1000,2000,4000,8000]
+    #[arg(long, value_parser = HistogramBuckets::parse)]
+    pub buckets: Option<HistogramBuckets>,
+
+    /// Print the analysis as text or as JSON
+    #[arg(long, value_enum, default_value_t = AnalysisFormat::Text)]
+    pub format: AnalysisFormat,
+}
+
+fn gap_line(label: &str, gaps: Option<GapSummary>) -> Option<String> {
+    gaps.map(|gaps| {
+        format!(
+            "  - {}: min {}ms | avg {}ms | max {}ms | p50 {}ms | p90 {}ms | p99 {}ms",
+            label, gaps.min_ms, gaps.avg_ms, gaps.max_ms, gaps.p50_ms, gaps.p90_ms, gaps.p99_ms
+        )
+    })
+}
+
+fn gap_json(gaps: Option<GapSummary>) -> serde_json::Value {
+    gaps.map_or(serde_json::Value::Null, |gaps| {
+        serde_json::json!({
+            "min": gaps.min_ms,
+            "avg": gaps.avg_ms,
+            "max": gaps.max_ms,
+            "p50": gaps.p50_ms,
+            "p90": gaps.p90_ms,
+            "p99": gaps.p99_ms,
+        })
+    })
+}
+
+// Percentile labels such as "p50" and "p99.9"
+pub(crate) fn percentile_label(p: f64) -> String {
+    format!("p{}", p)
+}
+
+fn text_report(stats: &ChainStats, percentiles: &[f64]) -> Vec<String> {
+    let deltas = &stats.delta_histogram;
+    let mut lines = vec![
+        format!("{}: {} blocks", stats.chain_name, stats.total_blocks),
+        format!(
+            "  - Delta: min {}ms | avg {}ms | max {}ms | stddev {:.1}ms",
+            deltas.min_ms(),
+            deltas.mean_ms(),
+            deltas.max_ms(),
+            deltas.stddev_ms()
+        ),
+        format!(
+            "  - Delta percentiles: {}",
+            percentiles
+                .iter()
+                .map(|p| format!("{} {}ms", percentile_label(*p), deltas.percentile(*p)))
+                .collect::<Vec<_>>()
+                .join(" | ")
+        ),
+        format!(
+            "  - Past timestamps: {} | Exact timestamps: {} | Future timestamps: {} | Leap tagged: {}",
+            stats.timestamp_past_blocks,
+            stats.timestamp_exact_blocks,
+            stats.timestamp_future_blocks,
+            stats.leap_tagged_blocks
+        ),
+        format!(
+            "  - Non-monotonic timestamps: {} | worst regression {}ms",
+            stats.non_monotonic_blocks, stats.max_timestamp_regression_ms
+        ),
+        "  - Delta histogram (ms):".to_string(),
+    ];
+    lines.extend(report::histogram_chart(&stats.delta_buckets()));
+    lines.extend(gap_line("Block interval", stats.block_gap_summary()));
+    lines.extend(gap_line("Arrival interval", stats.arrival_gap_summary()));
+    lines
+}
+
+fn json_report(stats: &ChainStats, percentiles: &[f64]) -> serde_json::Value {
+    let deltas = &stats.delta_histogram;
+    let percentiles: serde_json::Map<String, serde_json::Value> = percentiles
+        .iter()
+        .map(|p| (percentile_label(*p), deltas.percentile(*p).into()))
+        .collect();
+    let histogram: Vec<serde_json::Value> = stats
+        .delta_buckets()
+        .iter()
+        .map(|bucket| {
+            serde_json::json!({
+                "lower_ms": bucket.lower_ms,
+                "upper_ms": bucket.upper_ms,
+                "count": bucket.count,
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "chain": stats.chain_name,
+        "blocks": stats.total_blocks,
+        "past_timestamp_blocks": stats.timestamp_past_blocks,
+        "future_timestamp_blocks": stats.timestamp_future_blocks,
+        "exact_timestamp_blocks": stats.timestamp_exact_blocks,
+        "leap_tagged_blocks": stats.leap_tagged_blocks,
+        "non_monotonic_blocks": stats.non_monotonic_blocks,
+        "max_timestamp_regression_ms": stats.max_timestamp_regression_ms,
+        "delta_ms": {
+            "min": deltas.min_ms(),
+            "avg": deltas.mean_ms(),
+            "max": deltas.max_ms(),
+            "stddev": deltas.stddev_ms(),
+            "percentiles": percentiles,
+        },
+        "histogram": histogram,
+        "block_interval_ms": gap_json(stats.block_gap_summary()),
+        "arrival_interval_ms": gap_json(stats.arrival_gap_summary()),
+    })
+}
+
+/// Print delta percentiles, a histogram and block intervals for each chain in a stored
+/// dataset, optionally narrowed to some chains or a time range
+pub fn run(args: &AnalyzeArgs) -> Result<()> {
+    if let Some(AnalyzeCommand::CompareVantage(compare)) = &args.command {
+        return vantage::compare(compare);
+    }
+    if let Some(AnalyzeCommand::Compare(compare)) = &args.command {
+        return distribution::compare(compare);
+    }
+    if let Some(p) = args.percentiles.iter().find(|p| !(0.0..=100.0).contains(*p)) {
+        return Err(anyhow!("Percentile {} is not between 0 and 100", p));
+    }
+
+    let mut observations = replay::load_observations(&args.input, args.from)?;
+    observations.retain(|observation| {
+        (args.chain.is_empty() || args.chain.contains(&observation.chain))
+            && (args.vantage.is_empty() || observation.vantage.as_ref().is_some_and(|vantage| args.vantage.contains(vantage)))
+            && args.since.is_none_or(|since| observation.received_at >= since)
+            && args.until.is_none_or(|until| observation.received_at < until)
+    });
+    if observations.is_empty() {
+        return Err(anyhow!("No block observations in {} match the filters", args.input.display()));
+    }
+    info!("Analyzing {} observations from {}", observations.len(), args.input.display());
+
+    let mut chain_stats: Vec<ChainStats> = replay::replay(&observations, &ReplaySettings::default(), None)?
+        .into_values()
+        .collect();
+    chain_stats.sort_by(|a, b| a.chain_name.cmp(&b.chain_name));
+    if let Some(buckets) = &args.buckets {
+        for stats in &mut chain_stats {
+            stats.histogram_buckets = buckets.clone();
+        }
+    }
+
+    match args.format {
+        AnalysisFormat::Text => {
+            for stats in &chain_stats {
+                for line in text_report(stats, &args.percentiles) {
+                    println!("{}", line);
+                }
+            }
+        }
+        AnalysisFormat::Json => {
+            let chains: Vec<serde_json::Value> =
+                chain_stats.iter().map(|stats| json_report(stats, &args.percentiles)).collect();
+            println!("{}", serde_json::to_string_pretty(&serde_json::json!({ "chains": chains }))?);
+        }
+    }
+    Ok(())
+}
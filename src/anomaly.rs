@@ -0,0 +1,183 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+
+use crate::get_env_as_u64;
+
+/// How far a delta must stray from its chain's recent deltas to count as an anomaly, from
+/// the `ANOMALY_*` variables
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnomalySettings {
+    /// Standard deviations from the baseline beyond which a delta is anomalous
+    pub z_threshold: f64,
+    /// Blocks after which a delta's weight in the baseline has halved
+    pub half_life_blocks: u64,
+    /// Blocks the baseline is built from before any delta is flagged
+    pub warmup_blocks: u64,
+    /// Least distance from the baseline that is flagged, so a chain with very steady deltas
+    /// isn't flagged for a few milliseconds of jitter
+    pub min_deviation_ms: u64,
+}
+
+impl AnomalySettings {
+    /// The settings, or `None` with `ANOMALY_Z_SCORE=0` to turn detection off
+    pub fn from_env() -> Option<Self> {
+        let z_threshold = env::var("ANOMALY_Z_SCORE")
+            .ok()
+            .and_then(|val| val.parse::<f64>().ok())
+            .filter(|z| z.is_finite())
+            .unwrap_or(4.0);
+        (z_threshold > 0.0).then(|| Self {
+            z_threshold,
+            half_life_blocks: get_env_as_u64("ANOMALY_HALF_LIFE_BLOCKS", 100).max(1),
+            warmup_blocks: get_env_as_u64("ANOMALY_WARMUP_BLOCKS", 50),
+            min_deviation_ms: get_env_as_u64("ANOMALY_MIN_DEVIATION_MS", 100),
+        })
+    }
+
+    // Weight of each new delta in the moving average
+    fn alpha(&self) -> f64 {
+        1.0 - 0.5f64.powf(1.0 / self.half_life_blocks as f64)
+    }
+}
+
+/// Deltas far from their chain's recent baseline, an exponentially weighted moving average
+/// and variance of the deltas before them. Unlike the alert threshold, this adapts to each
+/// chain: a delta is anomalous when it lies more than `z_threshold` standard deviations
+/// from the baseline, whatever the chain's usual deltas are.
+///
+/// Every delta feeds the baseline, anomalous or not, so a lasting shift stops being flagged
+/// once the baseline has caught up with it.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct DeltaAnomalies {
+    #[serde(skip)]
+    pub settings: Option<AnomalySettings>, // `None` when detection is off; not checkpointed
+    mean_ms: f64,
+    variance: f64,
+    samples: u64,
+    pub anomalies: usize,           // Deltas flagged as anomalous
+    pub above_baseline: usize,      // Of those, deltas later than the baseline
+    pub below_baseline: usize,      // Of those, deltas earlier than the baseline
+    pub max_z_score: f64,           // Furthest a flagged delta was from the baseline, in standard deviations
+    pub last_anomaly_block: Option<u64>,
+}
+
+impl DeltaAnomalies {
+    pub fn new(settings: Option<AnomalySettings>) -> Self {
+        Self {
+            settings,
+            ..Self::default()
+        }
+    }
+
+    /// The baseline's mean and standard deviation, once it is built
+    pub fn baseline_ms(&self) -> Option<(f64, f64)> {
+        let settings = self.settings?;
+        (self.samples >= settings.warmup_blocks.max(1)).then(|| (self.mean_ms, self.variance.sqrt()))
+    }
+
+    /// Record the delta of `block_number`, returning its z-score against the baseline if it
+    /// is anomalous
+    pub(crate) fn record(&mut self, block_number: u64, delta_ms: i64) -> Option<f64> {
+        let settings = self.settings?;
+        let delta_ms = delta_ms as f64;
+        let deviation = delta_ms - self.mean_ms;
+        // Deltas are whole milliseconds, so a spread under one is no spread at all
+        let z_score = deviation / self.variance.sqrt().max(1.0);
+        let anomalous = self.baseline_ms().is_some()
+            && deviation.abs() >= settings.min_deviation_ms as f64
+            && z_score.abs() > settings.z_threshold;
+
+        if self.samples == 0 {
+            self.mean_ms = delta_ms;
+        } else {
+            let alpha = settings.alpha();
+            let increment = alpha * deviation;
+            self.mean_ms += increment;
+            self.variance = (1.0 - alpha) * (self.variance + deviation * increment);
+        }
+        self.samples += 1;
+
+        if !anomalous {
+            return None;
+        }
+        self.anomalies += 1;
+        if z_score > 0.0 {
+            self.above_baseline += 1;
+        } else {
+            self.below_baseline += 1;
+        }
+        self.max_z_score = self.max_z_score.max(z_score.abs());
+        self.last_anomaly_block = Some(block_number);
+        Some(z_score)
+    }
+
+    /// The detector's columns in `SUMMARY_HEADERS` order, empty when detection is off
+    pub(crate) fn record_columns(&self) -> Vec<String> {
+        if self.settings.is_none() {
+            return vec![String::new(); 4];
+        }
+        vec![
+            self.anomalies.to_string(),
+            self.above_baseline.to_string(),
+            self.below_baseline.to_string(),
+            format!("{:.2}", self.max_z_score),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SETTINGS: AnomalySettings = AnomalySettings {
+        z_threshold: 4.0,
+        half_life_blocks: 100,
+        warmup_blocks: 50,
+        min_deviation_ms: 100,
+    };
+
+    // Deltas alternating 100ms either side of 500ms, so the baseline spread is about 100ms
+    fn steady(anomalies: &mut DeltaAnomalies, blocks: std::ops::Range<u64>) {
+        for block in blocks {
+            let delta_ms = if block % 2 == 0 { 400 } else { 600 };
+            assert_eq!(anomalies.record(block, delta_ms), None);
+        }
+    }
+
+    #[test]
+    fn nothing_is_flagged_during_warmup() {
+        let mut anomalies = DeltaAnomalies::new(Some(SETTINGS));
+        steady(&mut anomalies, 0..10);
+        assert_eq!(anomalies.record(10, 60_000), None);
+        assert_eq!(anomalies.baseline_ms(), None);
+        assert_eq!(anomalies.anomalies, 0);
+    }
+
+    #[test]
+    fn a_constant_series_is_never_flagged() {
+        let settings = AnomalySettings {
+            min_deviation_ms: 0,
+            ..SETTINGS
+        };
+        let mut anomalies = DeltaAnomalies::new(Some(settings));
+        for block in 0..200 {
+            assert_eq!(anomalies.record(block, 500), None);
+        }
+        assert_eq!(anomalies.baseline_ms(), Some((500.0, 0.0)));
+        // Without any spread, a millisecond off counts as one standard deviation, not infinitely many
+        assert_eq!(anomalies.record(200, 501), None);
+        assert_eq!(anomalies.anomalies, 0);
+        assert!(anomalies.max_z_score.is_finite());
+    }
+
+    #[test]
+    fn a_clear_outlier_is_flagged() {
+        let mut anomalies = DeltaAnomalies::new(Some(SETTINGS));
+        steady(&mut anomalies, 0..100);
+        let z_score = anomalies.record(100, 5_000).expect("outlier flagged");
+        assert!(z_score > SETTINGS.z_threshold, "z-score {}", z_score);
+        assert_eq!(anomalies.anomalies, 1);
+        assert_eq!(anomalies.above_baseline, 1);
+        assert_eq!(anomalies.last_anomaly_block, Some(100));
+    }
+}
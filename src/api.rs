@@ -0,0 +1,298 @@
+use chrono::Utc;
+use log::warn;
+use std::collections::BTreeMap;
+use std::env;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::oneshot;
+
+use crate::health::HealthCheck;
+use crate::report::chain_summary;
+use crate::server::{self, Request, Response};
+use crate::stats::ChainStats;
+
+/// Each chain's latest stats as JSON, served to dashboards and scripts while the run is going,
+/// along with liveness and readiness probes, and endpoints pausing and resuming a chain's
+/// polling
+#[derive(Debug)]
+pub struct StatsApi {
+    chains: Mutex<BTreeMap<String, ChainSnapshot>>,
+    health: HealthCheck,
+    control: UnboundedSender<ControlRequest>,
+    // Bearer token pause and resume requests must carry, from `API_TOKEN`
+    token: Option<String>,
+}
+
+#[derive(Debug)]
+struct ChainSnapshot {
+    summary: serde_json::Value,
+    degraded: bool,
+    paused: bool,
+    latest_block_ms: Option<i64>,
+}
+
+/// What a control request does to a chain's polling
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlAction {
+    Pause,
+    Resume,
+}
+
+/// A request to pause or resume a chain, answered with the response's status and body once
+/// it has been carried out
+#[derive(Debug)]
+pub struct ControlRequest {
+    /// The chain as named in the request path
+    pub chain: String,
+    pub action: ControlAction,
+    pub reply: oneshot::Sender<(&'static str, serde_json::Value)>,
+}
+
+impl StatsApi {
+    /// Serve stats checked against `health`, passing pause and resume requests to `control`;
+    /// those have to carry `API_TOKEN` as a bearer token if it is set
+    pub fn new(health: HealthCheck, control: UnboundedSender<ControlRequest>) -> Self {
+        Self {
+            chains: Mutex::default(),
+            health,
+            control,
+            token: env::var("API_TOKEN").ok().filter(|token| !token.is_empty()),
+        }
+    }
+
+    /// Take over a chain's latest stats, summarized as in `summary.json`
+    pub fn update(&self, stats: &ChainStats) {
+        let mut summary = chain_summary(stats);
+        summary["updated_at"] = Utc::now().to_rfc3339().into();
+        self.chains.lock().unwrap().insert(
+            stats.chain_name.clone(),
+            ChainSnapshot {
+                summary,
+                degraded: stats.degraded,
+                paused: stats.paused,
+                latest_block_ms: stats.previous.as_ref().map(|block| block.receipt_time_ms),
+            },
+        );
+    }
+
+    /// Stop serving a chain that is no longer monitored
+    pub fn remove(&self, chain: &str) {
+        self.chains.lock().unwrap().remove(chain);
+    }
+
+    // Status and JSON body for a request path
+    fn route(&self, path: &str) -> (&'static str, serde_json::Value) {
+        let chains = self.chains.lock().unwrap();
+        let health = || {
+            self.health
+                .check(|name| chains.get(name).and_then(|chain| chain.latest_block_ms), Utc::now())
+        };
+        match path.trim_end_matches('/') {
+            // Liveness: fails once a chain has stopped receiving blocks
+            "/healthz" => {
+                let health = health();
+                let degraded: Vec<&String> = chains
+                    .iter()
+                    .filter(|(_, chain)| chain.degraded)
+                    .map(|(name, _)| name)
+                    .collect();
+                let paused: Vec<&String> = chains
+                    .iter()
+                    .filter(|(_, chain)| chain.paused)
+                    .map(|(name, _)| name)
+                    .collect();
+                let status = match (health.healthy, degraded.is_empty()) {
+                    (false, _) => "stale",
+                    (true, true) => "ok",
+                    (true, false) => "degraded",
+                };
+                let body = serde_json::json!({
+                    "status": status,
+                    "chains": chains.len(),
+                    "degraded_chains": degraded,
+                    "paused_chains": paused,
+                    "stale_chains": health.stale_chains,
+                });
+                (if health.healthy { "200 OK" } else { "503 Service Unavailable" }, body)
+            }
+            // Readiness: passes once every chain has received a block
+            "/readyz" => {
+                let health = health();
+                let body = serde_json::json!({
+                    "status": if health.ready { "ready" } else { "waiting" },
+                    "waiting_chains": health.waiting_chains,
+                });
+                (if health.ready { "200 OK" } else { "503 Service Unavailable" }, body)
+            }
+            "/stats" => {
+                let summaries: Vec<&serde_json::Value> = chains.values().map(|chain| &chain.summary).collect();
+                ("200 OK", serde_json::json!({ "chains": summaries }))
+            }
+            path => {
+                // Chain names are matched case-insensitively, so `/stats/base` finds "Base"
+                let chain = path
+                    .strip_prefix("/stats/")
+                    .and_then(|name| chains.iter().find(|(chain, _)| chain.eq_ignore_ascii_case(name)));
+                match chain {
+                    Some((_, chain)) => ("200 OK", chain.summary.clone()),
+                    None => ("404 Not Found", serde_json::json!({ "error": "not found" })),
+                }
+            }
+        }
+    }
+
+    // Whether a pause or resume request carries the API token, if there is one
+    fn authorized(&self, request: &Request) -> bool {
+        self.token.as_ref().is_none_or(|token| {
+            request
+                .header("Authorization")
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .is_some_and(|given| tokens_match(given.trim(), token))
+        })
+    }
+
+    // Status and JSON body for a pause or resume request, once the main loop has handled it
+    async fn control(&self, chain: &str, action: ControlAction) -> (&'static str, serde_json::Value) {
+        let (reply, response) = oneshot::channel();
+        let request = ControlRequest {
+            chain: chain.to_string(),
+            action,
+            reply,
+        };
+        if self.control.send(request).is_err() {
+            return ("503 Service Unavailable", serde_json::json!({ "error": "shutting down" }));
+        }
+        response
+            .await
+            .unwrap_or(("503 Service Unavailable", serde_json::json!({ "error": "shutting down" })))
+    }
+
+    /// Answer requests for `/stats`, `/stats/{chain}`, `/healthz` and `/readyz`, and `POST`s to
+    /// `/chains/{chain}/pause` and `/chains/{chain}/resume`, on `listener` until the process exits
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) {
+        server::log_listening(&listener, "live stats", "/stats");
+        // Without a token, only clients on the same host may pause and resume chains
+        let loopback = listener.local_addr().is_ok_and(|addr| addr.ip().is_loopback());
+        if self.token.is_none() && !loopback {
+            warn!("API_TOKEN not set; refusing pause and resume requests on a non-loopback address");
+        }
+        server::serve(listener, "Stats API", move |request: Request| {
+            let api = self.clone();
+            async move { api.respond(request, loopback).await }
+        })
+        .await
+    }
+
+    // The JSON reply to a request, on a listener bound to a loopback address or not
+    async fn respond(&self, request: Request, loopback: bool) -> Response {
+        let (status, body) = match control_path(&request.path) {
+            // Only POSTs change anything, so a crawler or prefetch can't pause a chain
+            Some(_) if request.method != "POST" => ("405 Method Not Allowed", serde_json::json!({ "error": "use POST" })),
+            Some(_) if self.token.is_none() && !loopback => (
+                "403 Forbidden",
+                serde_json::json!({ "error": "set API_TOKEN to pause and resume chains on a non-loopback address" }),
+            ),
+            Some(_) if !self.authorized(&request) => {
+                ("401 Unauthorized", serde_json::json!({ "error": "missing or wrong API token" }))
+            }
+            Some((chain, action)) => self.control(chain, action).await,
+            None => self.route(&request.path),
+        };
+        Response {
+            status,
+            content_type: "application/json",
+            body: serde_json::to_string_pretty(&body).unwrap_or_default() + "\n",
+        }
+    }
+}
+
+// Compare a given token with the expected one in time that doesn't depend on where they
+// first differ, so the token can't be guessed a byte at a time from response times
+fn tokens_match(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+// The chain and action of a `/chains/{chain}/pause` or `/chains/{chain}/resume` path
+fn control_path(path: &str) -> Option<(&str, ControlAction)> {
+    let rest = path.trim_end_matches('/').strip_prefix("/chains/")?;
+    if let Some(chain) = rest.strip_suffix("/pause") {
+        Some((chain, ControlAction::Pause))
+    } else {
+        rest.strip_suffix("/resume").map(|chain| (chain, ControlAction::Resume))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    fn api(token: Option<&str>) -> StatsApi {
+        let (control, mut requests) = mpsc::unbounded_channel::<ControlRequest>();
+        // Carry out every control request at once
+        tokio::spawn(async move {
+            while let Some(request) = requests.recv().await {
+                let _ = request.reply.send(("200 OK", serde_json::json!({ "chain": request.chain })));
+            }
+        });
+        StatsApi {
+            chains: Mutex::default(),
+            health: HealthCheck::from_env(Vec::new(), Utc::now()),
+            control,
+            token: token.map(str::to_string),
+        }
+    }
+
+    fn post(path: &str, authorization: Option<&str>) -> Request {
+        let headers = authorization.map(|value| ("Authorization", value)).into_iter().collect::<Vec<_>>();
+        Request::new("POST", path, &headers)
+    }
+
+    #[test]
+    fn control_paths_name_the_chain_and_action() {
+        assert_eq!(control_path("/chains/base/pause"), Some(("base", ControlAction::Pause)));
+        assert_eq!(control_path("/chains/base/resume/"), Some(("base", ControlAction::Resume)));
+        assert_eq!(control_path("/chains/base"), None);
+        assert_eq!(control_path("/stats/base"), None);
+    }
+
+    #[test]
+    fn tokens_must_match_exactly() {
+        assert!(tokens_match("secret", "secret"));
+        assert!(!tokens_match("secreT", "secret"));
+        assert!(!tokens_match("secret2", "secret"));
+        assert!(!tokens_match("", "secret"));
+    }
+
+    #[tokio::test]
+    async fn control_requests_need_the_token() {
+        let api = api(Some("secret"));
+        assert!(api.authorized(&post("/chains/base/pause", Some("Bearer secret"))));
+        assert!(!api.authorized(&post("/chains/base/pause", Some("Bearer wrong"))));
+        assert!(!api.authorized(&post("/chains/base/pause", Some("secret"))));
+        assert!(!api.authorized(&post("/chains/base/pause", None)));
+
+        let response = api.respond(post("/chains/base/pause", Some("Bearer wrong")), false).await;
+        assert_eq!(response.status, "401 Unauthorized");
+        let response = api.respond(post("/chains/base/pause?reason=deploy", Some("Bearer secret")), false).await;
+        assert_eq!(response.status, "200 OK");
+    }
+
+    #[tokio::test]
+    async fn without_a_token_only_loopback_may_pause() {
+        let api = api(None);
+        assert!(api.authorized(&post("/chains/base/pause", None)));
+        let response = api.respond(post("/chains/base/pause", None), false).await;
+        assert_eq!(response.status, "403 Forbidden");
+        let response = api.respond(post("/chains/base/pause", None), true).await;
+        assert_eq!(response.status, "200 OK");
+        let response = api.respond(Request::new("GET", "/chains/base/pause", &[]), true).await;
+        assert_eq!(response.status, "405 Method Not Allowed");
+    }
+}
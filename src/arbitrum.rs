@@ -0,0 +1,237 @@
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use std::collections::VecDeque;
+
+use crate::adapter::{AdapterCounters, BlockBatch, BlockHeader, ChainAdapter, L1Origin, NodeHealth};
+use crate::evm::{http_client, EvmAdapter};
+use crate::http::{HttpSettings, RpcAuth};
+use crate::monitor::{ChainInfo, MonitorOptions};
+use crate::timestamp::{self, TimestampDecoding};
+
+/// L1 block timestamps kept, so the many L2 blocks sharing an L1 block cost one L1 request
+const L1_TIMESTAMP_CACHE: usize = 64;
+
+/// Arbitrum Nitro chains (Arbitrum One, Nova and their testnets): Ethereum JSON-RPC, except
+/// that L1 origins come from the `l1BlockNumber` field of the block headers instead of an
+/// L1 attributes deposit, with the L1 block's timestamp read from an L1 endpoint.
+///
+/// The sequencer stamps blocks in whole seconds from its own clock, so with blocks every
+/// 250ms most timestamps repeat, and the L1 block a block names trails the L1 head by
+/// however long the sequencer takes to see it.
+pub struct ArbitrumAdapter {
+    l2: EvmAdapter,
+    // Where L1 block timestamps are read from; `None` without an L1 RPC URL
+    l1: Option<EvmAdapter>,
+    // Number and timestamp (ms) of recently read L1 blocks
+    l1_timestamps: VecDeque<(u64, u64)>,
+}
+
+impl ArbitrumAdapter {
+    pub fn new(chain_info: &ChainInfo, options: &MonitorOptions, client: reqwest::Client) -> Self {
+        let l1 = chain_info.l1_rpc_url.as_ref().map(|rpc_url| {
+            let info = ChainInfo {
+                name: format!("{} L1", chain_info.name),
+                rpc_url: rpc_url.clone(),
+                timestamp_decoding: TimestampDecoding::Seconds,
+                // The chain's credentials are for its own provider
+                http: HttpSettings {
+                    auth: RpcAuth::default(),
+                    ..chain_info.http.clone()
+                },
+                ..chain_info.clone()
+            };
+            EvmAdapter::new(&info, options, http_client(rpc_url, &info.http))
+        });
+        Self {
+            l2: EvmAdapter::new(chain_info, options, client),
+            l1,
+            l1_timestamps: VecDeque::new(),
+        }
+    }
+
+    async fn get_l1_origin(&mut self, height: u64) -> Result<L1Origin> {
+        let block = self.l2.block_fields(height).await?;
+        let number = block
+            .get("l1BlockNumber")
+            .map(timestamp::parse_number)
+            .transpose()?
+            .ok_or_else(|| anyhow!("Block {} has no l1BlockNumber; not an Arbitrum chain", height))?;
+        if let Some((_, timestamp_ms)) = self.l1_timestamps.iter().find(|(cached, _)| *cached == number) {
+            return Ok(L1Origin {
+                number,
+                timestamp_ms: *timestamp_ms,
+            });
+        }
+
+        let l1 = self
+            .l1
+            .as_mut()
+            .ok_or_else(|| anyhow!("No L1 endpoint to read L1 block timestamps from; set l1_rpc_url or L1_RPC_URL"))?;
+        let result = l1.block_fields(number).await;
+        // Latencies of the L1 endpoint would be mistaken for the chain's own
+        l1.take_counters();
+        let seconds = result?
+            .get("timestamp")
+            .map(timestamp::parse_number)
+            .transpose()?
+            .ok_or_else(|| anyhow!("L1 block {} has no timestamp", number))?;
+        let timestamp_ms = seconds * 1000;
+        if self.l1_timestamps.len() >= L1_TIMESTAMP_CACHE {
+            self.l1_timestamps.pop_front();
+        }
+        self.l1_timestamps.push_back((number, timestamp_ms));
+        Ok(L1Origin { number, timestamp_ms })
+    }
+}
+
+impl ChainAdapter for ArbitrumAdapter {
+    fn latest_height(&mut self) -> BoxFuture<'_, Result<u64>> {
+        self.l2.latest_height()
+    }
+
+    fn block_at(&mut self, height: u64) -> BoxFuture<'_, Result<BlockHeader>> {
+        self.l2.block_at(height)
+    }
+
+    fn blocks_in(&mut self, first: u64, last: u64) -> BoxFuture<'_, Result<BlockBatch>> {
+        self.l2.blocks_in(first, last)
+    }
+
+    fn tagged_block(&mut self, tag: &'static str) -> BoxFuture<'_, Result<(u64, BlockHeader)>> {
+        self.l2.tagged_block(tag)
+    }
+
+    fn l1_origin(&mut self, height: u64) -> BoxFuture<'_, Result<L1Origin>> {
+        Box::pin(self.get_l1_origin(height))
+    }
+
+    fn chain_id(&mut self) -> BoxFuture<'_, Result<u64>> {
+        self.l2.chain_id()
+    }
+
+    fn node_health(&mut self) -> BoxFuture<'_, Result<NodeHealth>> {
+        self.l2.node_health()
+    }
+
+    fn take_counters(&mut self) -> AdapterCounters {
+        self.l2.take_counters()
+    }
+
+    fn reset(&mut self) {
+        self.l2.reset();
+        if let Some(l1) = &mut self.l1 {
+            l1.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use serde_json::{json, Value};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    // A JSON-RPC node over HTTP, which answers every eth_getBlockByNumber with the fields
+    // `block` gives for the requested height and closes the connection, so counting its
+    // connections counts its requests
+    async fn serve_blocks(listener: TcpListener, requests: Arc<AtomicUsize>, block: fn(u64) -> Value) {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            requests.fetch_add(1, Ordering::Relaxed);
+            tokio::spawn(async move {
+                let mut reader = BufReader::new(stream);
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).await.unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).await.unwrap();
+                let request: Value = serde_json::from_slice(&body).unwrap();
+                let height = timestamp::parse_number(&request["params"][0]).unwrap();
+                let reply = json!({"jsonrpc": "2.0", "id": request["id"], "result": block(height)}).to_string();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    reply.len(),
+                    reply
+                );
+                reader.get_mut().write_all(response.as_bytes()).await.unwrap();
+            });
+        }
+    }
+
+    // Every fourth L2 block moves to the next L1 block; block 99 predates the field
+    fn l2_block(height: u64) -> Value {
+        let mut block = json!({"number": format!("0x{:x}", height), "timestamp": "0x6553f100"});
+        if height != 99 {
+            block["l1BlockNumber"] = json!(format!("0x{:x}", 1000 + height / 4));
+        }
+        block
+    }
+
+    fn l1_block(height: u64) -> Value {
+        json!({"number": format!("0x{:x}", height), "timestamp": format!("0x{:x}", 1_700_000_000 + height * 12)})
+    }
+
+    // An adapter for a chain served by an L2 node and, with `with_l1`, an L1 node, along
+    // with the count of requests the L1 node got
+    async fn scripted_adapter(with_l1: bool) -> (ArbitrumAdapter, Arc<AtomicUsize>) {
+        let serve = |block: fn(u64) -> Value| async move {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let requests = Arc::new(AtomicUsize::new(0));
+            tokio::spawn(serve_blocks(listener, requests.clone(), block));
+            (url, requests)
+        };
+        let (l2_url, _) = serve(l2_block).await;
+        let (l1_url, l1_requests) = serve(l1_block).await;
+
+        let config = Config::simulated(None).unwrap();
+        let chain_info = ChainInfo {
+            rpc_url: l2_url.clone(),
+            l1_rpc_url: with_l1.then_some(l1_url),
+            ..config.chains[0].clone()
+        };
+        let client = http_client(&l2_url, &chain_info.http);
+        (ArbitrumAdapter::new(&chain_info, &config.options, client), l1_requests)
+    }
+
+    #[tokio::test]
+    async fn l1_origins_come_from_the_header_with_cached_l1_timestamps() {
+        let (mut adapter, l1_requests) = scripted_adapter(true).await;
+        let origin = |number: u64| L1Origin {
+            number,
+            timestamp_ms: (1_700_000_000 + number * 12) * 1000,
+        };
+        assert_eq!(adapter.l1_origin(8).await.unwrap(), origin(1002));
+        // Blocks on the same L1 block are answered from the cache
+        assert_eq!(adapter.l1_origin(9).await.unwrap(), origin(1002));
+        assert_eq!(adapter.l1_origin(11).await.unwrap(), origin(1002));
+        assert_eq!(l1_requests.load(Ordering::Relaxed), 1);
+        assert_eq!(adapter.l1_origin(12).await.unwrap(), origin(1003));
+        assert_eq!(l1_requests.load(Ordering::Relaxed), 2);
+
+        let error = adapter.l1_origin(99).await.unwrap_err();
+        assert!(error.to_string().contains("no l1BlockNumber"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn l1_origins_need_an_l1_endpoint() {
+        let (mut adapter, l1_requests) = scripted_adapter(false).await;
+        let error = adapter.l1_origin(8).await.unwrap_err();
+        assert!(error.to_string().contains("No L1 endpoint"), "{}", error);
+        assert_eq!(l1_requests.load(Ordering::Relaxed), 0);
+    }
+}
@@ -0,0 +1,237 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use clap::Args;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::{info, warn};
+use std::env;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::manifest::MANIFEST_FILE;
+use crate::redact::{self, origin};
+use crate::timestamp::TimestampDecoding;
+
+/// Snapshot of the settings a run was started with
+pub const CONFIG_SNAPSHOT_FILE: &str = "run_config.json";
+
+/// Settings recorded in the config snapshot
+const CONFIG_KEYS: &[&str] = &[
+    "DURATION_MINUTES",
+    "POLL_INTERVAL_MS",
+    "ADAPTIVE_POLLING",
+    "ADAPTIVE_POLL_MIN_MS",
+    "LEAP_POLICY",
+    "LEAP_SECOND_DATES",
+    "REPORT_TIMEZONE",
+    "STRICT_RPC_SCHEMA",
+    "SHARE_CONNECTIONS",
+    "BATCH_REQUESTS",
+    "SINGLE_CALL_POLLING",
+    "REORG_REMEASURE",
+    "RPC_RETRY_ATTEMPTS",
+    "RPC_RETRY_BASE_MS",
+    "RPC_RETRY_MAX_MS",
+    "RPC_RETRY_JITTER_MS",
+    "MAX_RAW_SAMPLES",
+    "ROTATE_INTERVAL_MINUTES",
+    "ROTATE_SIZE_MB",
+    "ROTATE_GZIP",
+    "SIMULATE",
+    "SIMULATE_SKEW_MS",
+    "SIMULATE_DELAY_MS",
+    "SIMULATE_JITTER_MS",
+    "SIMULATE_LATENCY_MS",
+    "SIMULATE_REORG_EVERY",
+    "SIMULATE_REORG_DEPTH",
+    "SIMULATE_BLOCK_TIME_MS",
+    "SIMULATE_SEED",
+    "SAFE_HEAD_INTERVAL_MS",
+    "NODE_HEALTH_INTERVAL_MS",
+    "TRACK_L1_ORIGIN",
+    "NTP_SERVERS",
+    "NTP_INTERVAL_SECS",
+    "NTP_APPLY_OFFSET",
+    "HTTP_TIMEOUT_MS",
+    "HTTP_CONNECT_TIMEOUT_MS",
+    "HTTP_POOL_MAX_IDLE",
+    "HTTP_POOL_IDLE_SECS",
+    "HTTP_KEEPALIVE_SECS",
+    "HTTP2_PRIOR_KNOWLEDGE",
+    "WEDGE_TIMEOUT_SECS",
+    "HEAD_CONSISTENCY_WINDOW_MS",
+    "SUPERVISOR_MAX_FAILURES",
+    "SUPERVISOR_BACKOFF_MS",
+    "SUPERVISOR_MAX_BACKOFF_MS",
+    "CRASH_LOOP_RESTARTS",
+    "STALL_RESTART_SECS",
+    "BREAKER_FAILURES",
+    "BREAKER_COOLDOWN_SECS",
+    "DIGEST_AT",
+    "DIGEST_INTERVAL_MINUTES",
+    "REPORT_INTERVAL_SECS",
+    "REPORT_SINKS",
+    "HEALTH_MAX_BLOCK_AGE_SECS",
+    "API_TOKEN",
+    "COMPARE_MAX_HEAD_DIFF",
+    "COMPARE_MAX_TIMESTAMP_DIFF_MS",
+    "HISTOGRAM_BUCKETS_MS",
+    "MAX_CATCH_UP_BLOCKS",
+    "SCHEDULE_TOLERANCE_MS",
+    "ANOMALY_Z_SCORE",
+    "ANOMALY_HALF_LIFE_BLOCKS",
+    "ANOMALY_WARMUP_BLOCKS",
+    "ANOMALY_MIN_DEVIATION_MS",
+    "OUTAGE_BLOCK_TIMES",
+    "VANTAGE_LABEL",
+    "VANTAGE_REGION",
+];
+
+/// Arguments for the `archive` subcommand
+#[derive(Debug, Args)]
+pub struct ArchiveArgs {
+    /// Completed run directory to package
+    #[arg(long, env = "OUTPUT_DIR", default_value = "./logs")]
+    pub output_dir: PathBuf,
+
+    /// Tarball to write; defaults to `<run directory name>.tar.gz` next to the run directory
+    #[arg(long)]
+    pub archive: Option<PathBuf>,
+
+    /// Also upload the tarball with an HTTP PUT, e.g. to a pre-signed object store URL
+    #[arg(long, env = "ARCHIVE_UPLOAD_URL")]
+    pub upload_url: Option<String>,
+}
+
+/// Record the configuration of the run started at `started_at` (the original start when it
+/// resumed from a checkpoint), for inclusion in its archive: the settings given, and the
+/// effective poll intervals and clock setup, so the run can be interpreted long after its
+/// defaults have changed
+pub fn write_config_snapshot(dir: &Path, config: &Config, started_at: DateTime<Utc>) -> Result<()> {
+    let poll_interval_ms = config.poll_interval.as_millis() as u64;
+    // Credentials such as `API_TOKEN` are only recorded as set, masked as in the logs
+    let settings: serde_json::Map<String, serde_json::Value> = CONFIG_KEYS
+        .iter()
+        .filter_map(|key| env::var(key).ok().map(|value| (key.to_string(), redact::text(&value).into())))
+        .collect();
+    let chains: Vec<serde_json::Value> = config
+        .chains
+        .iter()
+        .map(|chain| {
+            serde_json::json!({
+                "name": chain.name,
+                "rpc_origin": origin(&chain.rpc_url),
+                "ws_origin": chain.ws_url.as_deref().map(origin),
+                "compare_rpc_origin": chain.compare_rpc_url.as_deref().map(origin),
+                "flashblocks_origin": chain.flashblocks_url.as_deref().map(origin),
+                "l1_rpc_origin": chain.l1_rpc_url.as_deref().map(origin),
+                "kind": chain.kind,
+                "block_tag": chain.block_tag,
+                "chain_id": chain.chain_id,
+                "poll_interval_ms": chain.poll_interval_ms.unwrap_or(poll_interval_ms),
+                "expected_block_time_ms": chain.expected_block_time_ms,
+                "schedule_tolerance_ms": chain.schedule_tolerance_ms,
+                "timestamp_ms_field": match &chain.timestamp_decoding {
+                    TimestampDecoding::Seconds => None,
+                    TimestampDecoding::MillisecondsField(field) => Some(field),
+                },
+            })
+        })
+        .collect();
+    let snapshot = serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "started_at": started_at.to_rfc3339(),
+        "platform": format!("{}-{}", env::consts::OS, env::consts::ARCH),
+        "vantage": config.options.vantage.summary(),
+        "clock": config.options.clock.summary(),
+        "poll_interval_ms": poll_interval_ms,
+        "adaptive_polling": config.adaptive_polling.is_some(),
+        "chains": chains,
+        "settings": settings,
+    });
+    std::fs::write(dir.join(CONFIG_SNAPSHOT_FILE), serde_json::to_string_pretty(&snapshot)? + "\n")?;
+    Ok(())
+}
+
+// Every file under `dir`, as sorted relative paths
+fn run_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for entry in std::fs::read_dir(&current)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else {
+                files.push(path.strip_prefix(dir)?.to_path_buf());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Package a run directory into `<name>/...` inside a gzipped tarball. Files keep their
+/// paths within the run, so the bundled checksum manifest verifies after extraction.
+fn build(dir: &Path, archive: &Path) -> Result<usize> {
+    let name = dir
+        .canonicalize()?
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| anyhow!("Cannot name an archive after {}", dir.display()))?;
+
+    let files = run_files(dir)?;
+    if files.is_empty() {
+        return Err(anyhow!("{} contains no files", dir.display()));
+    }
+    for required in [MANIFEST_FILE, CONFIG_SNAPSHOT_FILE] {
+        if !files.iter().any(|f| f == Path::new(required)) {
+            warn!("{} has no {}; the archive will lack it", dir.display(), required);
+        }
+    }
+
+    let encoder = GzEncoder::new(File::create(archive)?, Compression::default());
+    let mut tar = tar::Builder::new(encoder);
+    tar.mode(tar::HeaderMode::Deterministic);
+    for relative in &files {
+        tar.append_path_with_name(dir.join(relative), Path::new(&name).join(relative))?;
+    }
+    tar.into_inner()?.finish()?;
+    Ok(files.len())
+}
+
+async fn upload(archive: &Path, url: &str) -> Result<()> {
+    let body = tokio::fs::read(archive).await?;
+    let response = reqwest::Client::new()
+        .put(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/gzip")
+        .body(body)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Upload failed with status {}", response.status()));
+    }
+    Ok(())
+}
+
+pub async fn run(args: &ArchiveArgs) -> Result<()> {
+    let archive = match &args.archive {
+        Some(path) => path.clone(),
+        None => {
+            let dir = args.output_dir.canonicalize()?;
+            let name = dir.file_name().unwrap_or_default().to_string_lossy();
+            dir.with_file_name(format!("{}.tar.gz", name))
+        }
+    };
+
+    let count = build(&args.output_dir, &archive)
+        .with_context(|| format!("Failed to archive {}", args.output_dir.display()))?;
+    info!("Archived {} files to {}", count, archive.display());
+
+    if let Some(url) = &args.upload_url {
+        upload(&archive, url).await?;
+        info!("Uploaded {}", archive.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,345 @@
+use anyhow::{anyhow, Result};
+use clap::Args;
+use futures::StreamExt;
+use log::info;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::analyze::AnalysisFormat;
+use crate::config::{ChainsConfig, Config};
+use crate::daemon;
+use crate::http::HttpSettings;
+use crate::monitor::{BlockObservation, BlockTag, ChainInfo, ChainKind};
+use crate::presets::{ChainPreset, PRESETS};
+use crate::redact;
+use crate::timestamp::TimestampDecoding;
+
+/// Arguments for the `provider-bench` subcommand
+#[derive(Debug, Args)]
+pub struct ProviderBenchArgs {
+    /// RPC endpoints serving the same chain, each as a URL or as label=URL; unlabelled
+    /// endpoints are named after their host
+    #[arg(required = true, num_args = 2..)]
+    pub providers: Vec<String>,
+
+    /// Built-in chain the providers serve (see `--chain`), for its block time and the
+    /// chain ID every provider is checked against
+    #[arg(long)]
+    pub chain: Option<String>,
+
+    /// How long to poll the providers, in seconds; Ctrl-C stops early with the results so far
+    #[arg(long, default_value_t = 300, value_parser = clap::value_parser!(u64).range(1..))]
+    pub duration_secs: u64,
+
+    /// Interval between each provider's polls
+    #[arg(long, env = "POLL_INTERVAL_MS", default_value_t = 250)]
+    pub poll_interval_ms: u64,
+
+    /// Print the results as text or as JSON
+    #[arg(long, value_enum, default_value_t = AnalysisFormat::Text)]
+    pub format: AnalysisFormat,
+}
+
+/// One provider's answer for a block
+struct Arrival {
+    provider: usize,
+    // When the block's response arrived, or its request was sent when that is unknown
+    at_ms: i64,
+    timestamp_ms: u64,
+    delta_ms: i64,
+    round_trip_ms: Option<i64>,
+}
+
+impl Arrival {
+    fn of(provider: usize, observation: &BlockObservation) -> Self {
+        Self {
+            provider,
+            at_ms: observation.responded_at.unwrap_or(observation.received_at).timestamp_millis(),
+            timestamp_ms: observation.block_timestamp_ms,
+            delta_ms: observation.delta_ms,
+            round_trip_ms: observation.round_trip_ms(),
+        }
+    }
+}
+
+/// How one provider fared against the others
+#[derive(Default)]
+struct ProviderResult {
+    label: String,
+    blocks: usize,
+    // Blocks, among those more than one provider delivered, this one delivered first
+    first: usize,
+    // Blocks other providers delivered while this one was running but it never did
+    missed: usize,
+    // How long after the fastest provider this one delivered each block
+    lag_ms: Vec<i64>,
+    delta_ms: Vec<i64>,
+    round_trip_ms: Vec<i64>,
+    // Blocks whose timestamp differed from the fastest provider's
+    timestamp_mismatches: usize,
+}
+
+// Nearest-rank percentile of a sorted sample
+fn percentile(sorted: &[i64], p: f64) -> Option<i64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+// `label=URL`, or a bare URL named after its host
+fn parse_provider(spec: &str) -> (String, String) {
+    if let Some((label, rpc_url)) = spec.split_once('=') {
+        if !label.is_empty() && !label.contains([':', '/']) {
+            return (label.trim().to_string(), rpc_url.trim().to_string());
+        }
+    }
+    let label = reqwest::Url::parse(spec)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| redact::url(spec).into_owned());
+    (label, spec.trim().to_string())
+}
+
+/// Poll one chain through several RPC providers at once and report which delivers new
+/// blocks first, and how their latencies and block timestamps compare
+pub async fn run(args: &ProviderBenchArgs) -> Result<()> {
+    let preset = match &args.chain {
+        Some(id) => Some(ChainPreset::find(id).ok_or_else(|| {
+            let known: Vec<&str> = PRESETS.iter().map(|preset| preset.id).collect();
+            anyhow!("Unknown chain '{}'; the presets are {}", id, known.join(", "))
+        })?),
+        None => None,
+    };
+    let chain_name = preset.map_or("Chain", |preset| preset.name);
+
+    let mut chains: Vec<ChainInfo> = Vec::new();
+    for spec in &args.providers {
+        let (mut label, rpc_url) = parse_provider(spec);
+        let base = label.clone();
+        let mut n = 1;
+        while chains.iter().any(|chain| chain.name == label) {
+            n += 1;
+            label = format!("{}-{}", base, n);
+        }
+        let chain = match preset {
+            Some(preset) => ChainInfo {
+                name: label,
+                ..preset.chain_info(&rpc_url)
+            },
+            None => ChainInfo {
+                name: label,
+                rpc_url,
+                ws_url: None,
+                compare_rpc_url: None,
+                flashblocks_url: None,
+                expected_block_time_ms: None,
+                schedule_tolerance_ms: None,
+                poll_interval_ms: None,
+                chain_id: None,
+                timestamp_decoding: TimestampDecoding::Seconds,
+                http: HttpSettings::from_env(),
+                kind: ChainKind::Standard,
+                block_tag: BlockTag::Latest,
+                l1_rpc_url: None,
+            },
+        };
+        chains.push(chain);
+    }
+    let labels: Vec<String> = chains.iter().map(|chain| chain.name.clone()).collect();
+
+    // Every provider is monitored as a chain of its own, over its own connections
+    let mut config = Config::with_chains(ChainsConfig {
+        chains,
+        ..ChainsConfig::default()
+    })?;
+    config.poll_interval = Duration::from_millis(args.poll_interval_ms);
+    config.adaptive_polling = None;
+    config.share_connections = false;
+    config.duration = Some(Duration::from_secs(args.duration_secs));
+    info!(
+        "Benchmarking {} providers of {} for {}s: {}",
+        labels.len(),
+        chain_name,
+        args.duration_secs,
+        labels.join(", ")
+    );
+
+    let mut blocks: BTreeMap<u64, Vec<Arrival>> = BTreeMap::new();
+    let mut observations = Box::pin(crate::run_monitoring(config));
+    let shutdown = daemon::shutdown_signal();
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            observation = observations.next() => {
+                let Some(observation) = observation else {
+                    break;
+                };
+                let Some(provider) = labels.iter().position(|label| *label == observation.chain) else {
+                    continue;
+                };
+                let arrivals = blocks.entry(observation.block_number).or_default();
+                // Blocks remeasured after a reorg keep their first arrival
+                if !arrivals.iter().any(|arrival| arrival.provider == provider) {
+                    arrivals.push(Arrival::of(provider, &observation));
+                }
+            }
+            _ = &mut shutdown => {
+                info!("Stop signal received, reporting the results so far");
+                break;
+            }
+        }
+    }
+
+    let (contested, results) = compare(&labels, &blocks);
+    match args.format {
+        AnalysisFormat::Text => {
+            for line in text_report(chain_name, contested, &results) {
+                println!("{}", line);
+            }
+        }
+        AnalysisFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&json_report(chain_name, args.duration_secs, contested, &results))?);
+        }
+    }
+    Ok(())
+}
+
+// Each provider's results, and how many blocks more than one provider delivered
+fn compare(labels: &[String], blocks: &BTreeMap<u64, Vec<Arrival>>) -> (usize, Vec<ProviderResult>) {
+    let mut results: Vec<ProviderResult> = labels
+        .iter()
+        .map(|label| ProviderResult {
+            label: label.clone(),
+            ..ProviderResult::default()
+        })
+        .collect();
+
+    // Blocks count as missed only between the first and last block of the slowest-starting
+    // and earliest-stopping providers, when all of them were running
+    let mut first_blocks = vec![None; labels.len()];
+    let mut last_blocks = vec![None; labels.len()];
+    for (block, arrivals) in blocks {
+        for arrival in arrivals {
+            first_blocks[arrival.provider].get_or_insert(*block);
+            last_blocks[arrival.provider] = Some(*block);
+        }
+    }
+    let window_start = first_blocks.iter().flatten().max().copied().unwrap_or(0);
+    let window_end = last_blocks.iter().flatten().min().copied().unwrap_or(0);
+
+    let mut contested = 0;
+    for (block, arrivals) in blocks {
+        for arrival in arrivals {
+            let result = &mut results[arrival.provider];
+            result.blocks += 1;
+            result.delta_ms.push(arrival.delta_ms);
+            result.round_trip_ms.extend(arrival.round_trip_ms);
+        }
+        if (window_start..=window_end).contains(block) {
+            for (provider, result) in results.iter_mut().enumerate() {
+                if !arrivals.iter().any(|arrival| arrival.provider == provider) {
+                    result.missed += 1;
+                }
+            }
+        }
+        if arrivals.len() < 2 {
+            continue;
+        }
+        contested += 1;
+        let Some(fastest) = arrivals.iter().min_by_key(|arrival| arrival.at_ms) else {
+            continue;
+        };
+        results[fastest.provider].first += 1;
+        for arrival in arrivals {
+            let result = &mut results[arrival.provider];
+            result.lag_ms.push(arrival.at_ms - fastest.at_ms);
+            if arrival.timestamp_ms != fastest.timestamp_ms {
+                result.timestamp_mismatches += 1;
+            }
+        }
+    }
+
+    for result in &mut results {
+        result.lag_ms.sort_unstable();
+        result.delta_ms.sort_unstable();
+        result.round_trip_ms.sort_unstable();
+    }
+    (contested, results)
+}
+
+fn ms(value: Option<i64>) -> String {
+    value.map_or("-".to_string(), |ms| format!("{}ms", ms))
+}
+
+fn text_report(chain: &str, contested: usize, results: &[ProviderResult]) -> Vec<String> {
+    let width = results.iter().map(|result| result.label.len()).max().unwrap_or(0).max("Provider".len());
+    let mut lines = vec![
+        format!("{}: {} providers, {} blocks delivered by more than one", chain, results.len(), contested),
+        format!(
+            "  {:<width$} | {:>7} | {:>7} | {:>6} | {:>7} | {:>7} | {:>7} | {:>7} | {:>7} | {:>10}",
+            "Provider", "Blocks", "First", "Missed", "Lag P50", "Lag P90", "RTT P50", "RTT P90", "Delta", "Mismatches",
+            width = width
+        ),
+    ];
+    for result in results {
+        let first_pct = if contested > 0 { result.first as f64 * 100.0 / contested as f64 } else { 0.0 };
+        lines.push(format!(
+            "  {:<width$} | {:>7} | {:>6.1}% | {:>6} | {:>7} | {:>7} | {:>7} | {:>7} | {:>7} | {:>10}",
+            result.label,
+            result.blocks,
+            first_pct,
+            result.missed,
+            ms(percentile(&result.lag_ms, 50.0)),
+            ms(percentile(&result.lag_ms, 90.0)),
+            ms(percentile(&result.round_trip_ms, 50.0)),
+            ms(percentile(&result.round_trip_ms, 90.0)),
+            ms(percentile(&result.delta_ms, 50.0)),
+            result.timestamp_mismatches,
+            width = width
+        ));
+    }
+    if let Some(fastest) = results.iter().filter(|result| result.first > 0).max_by_key(|result| result.first) {
+        lines.push(format!(
+            "  Fastest: {}, first with {} of {} blocks",
+            fastest.label, fastest.first, contested
+        ));
+    }
+    lines
+}
+
+fn json_report(chain: &str, duration_secs: u64, contested: usize, results: &[ProviderResult]) -> serde_json::Value {
+    serde_json::json!({
+        "chain": chain,
+        "duration_secs": duration_secs,
+        "contested_blocks": contested,
+        "fastest": results
+            .iter()
+            .filter(|result| result.first > 0)
+            .max_by_key(|result| result.first)
+            .map(|result| result.label.clone()),
+        "providers": results.iter().map(|result| serde_json::json!({
+            "provider": result.label,
+            "blocks": result.blocks,
+            "first": result.first,
+            "missed": result.missed,
+            "timestamp_mismatches": result.timestamp_mismatches,
+            "lag_ms": {
+                "p50": percentile(&result.lag_ms, 50.0),
+                "p90": percentile(&result.lag_ms, 90.0),
+                "p99": percentile(&result.lag_ms, 99.0),
+            },
+            "round_trip_ms": {
+                "p50": percentile(&result.round_trip_ms, 50.0),
+                "p90": percentile(&result.round_trip_ms, 90.0),
+                "p99": percentile(&result.round_trip_ms, 99.0),
+            },
+            "delta_ms": {
+                "p50": percentile(&result.delta_ms, 50.0),
+                "p90": percentile(&result.delta_ms, 90.0),
+                "p99": percentile(&result.delta_ms, 99.0),
+            },
+        })).collect::<Vec<_>>(),
+    })
+}
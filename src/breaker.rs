@@ -0,0 +1,137 @@
+use std::time::{Duration, Instant};
+
+use crate::get_env_as_u64;
+
+/// Failure threshold and cool-down shared by every endpoint's breaker
+#[derive(Debug, Clone, Copy)]
+pub struct BreakerConfig {
+    failure_threshold: u32,
+    cool_down: Duration,
+}
+
+impl BreakerConfig {
+    pub fn from_env() -> Self {
+        Self {
+            failure_threshold: get_env_as_u64("BREAKER_FAILURES", 10).max(1) as u32,
+            cool_down: Duration::from_secs(get_env_as_u64("BREAKER_COOLDOWN_SECS", 60)),
+        }
+    }
+}
+
+/// Stops polling an endpoint that keeps failing until a cool-down has passed.
+///
+/// Unlike the supervisor's failure count, the breaker's survives monitor restarts, so an
+/// endpoint that is simply down trips it instead of being restarted forever.
+pub struct CircuitBreaker {
+    config: BreakerConfig,
+    consecutive_failures: u32,
+    // Set while open; once it passes, a single trial request is let through
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: BreakerConfig) -> Self {
+        Self {
+            config,
+            consecutive_failures: 0,
+            open_until: None,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open_until.is_some()
+    }
+
+    /// Whether the endpoint may be called now: closed, or open with the cool-down elapsed
+    pub fn allows(&self, now: Instant) -> bool {
+        self.open_until.is_none_or(|until| now >= until)
+    }
+
+    /// Record a successful call. Returns true if this closes the breaker.
+    pub fn record_success(&mut self) -> bool {
+        self.consecutive_failures = 0;
+        self.open_until.take().is_some()
+    }
+
+    /// Record a failed call. Returns true if this opens a closed breaker; a failed trial
+    /// while open just restarts the cool-down.
+    pub fn record_failure(&mut self, now: Instant) -> bool {
+        self.consecutive_failures += 1;
+        if self.is_open() {
+            self.open_until = Some(now + self.config.cool_down);
+            return false;
+        }
+        if self.consecutive_failures >= self.config.failure_threshold {
+            self.open_until = Some(now + self.config.cool_down);
+            return true;
+        }
+        false
+    }
+
+    pub fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures
+    }
+
+    pub fn cool_down(&self) -> Duration {
+        self.config.cool_down
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOL_DOWN: Duration = Duration::from_secs(60);
+
+    fn breaker() -> CircuitBreaker {
+        CircuitBreaker::new(BreakerConfig {
+            failure_threshold: 3,
+            cool_down: COOL_DOWN,
+        })
+    }
+
+    #[test]
+    fn opens_after_the_threshold_and_blocks_until_the_cool_down() {
+        let start = Instant::now();
+        let mut breaker = breaker();
+        assert!(!breaker.record_failure(start));
+        assert!(!breaker.record_failure(start));
+        assert!(breaker.allows(start));
+        assert!(breaker.record_failure(start));
+        assert!(breaker.is_open());
+        assert!(!breaker.allows(start + COOL_DOWN / 2));
+        // Half open: one trial request goes through once the cool-down has passed
+        assert!(breaker.allows(start + COOL_DOWN));
+    }
+
+    #[test]
+    fn a_failed_trial_restarts_the_cool_down() {
+        let start = Instant::now();
+        let mut breaker = breaker();
+        for _ in 0..3 {
+            breaker.record_failure(start);
+        }
+        let trial = start + COOL_DOWN;
+        assert!(!breaker.record_failure(trial));
+        assert!(breaker.is_open());
+        assert!(!breaker.allows(trial + COOL_DOWN / 2));
+        assert!(breaker.allows(trial + COOL_DOWN));
+        assert_eq!(breaker.consecutive_failures(), 4);
+    }
+
+    #[test]
+    fn a_successful_trial_closes_it() {
+        let start = Instant::now();
+        let mut breaker = breaker();
+        for _ in 0..3 {
+            breaker.record_failure(start);
+        }
+        assert!(breaker.record_success());
+        assert!(!breaker.is_open());
+        assert!(breaker.allows(start));
+        assert_eq!(breaker.consecutive_failures(), 0);
+        // Closing again is not news, and the count starts over
+        assert!(!breaker.record_success());
+        assert!(!breaker.record_failure(start));
+    }
+}
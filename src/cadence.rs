@@ -0,0 +1,142 @@
+use log::{debug, info};
+use std::collections::VecDeque;
+use std::env;
+use std::time::{Duration, Instant};
+
+use crate::get_env_as_u64;
+
+/// Intervals between new heads the learned block time is the median of
+const CADENCE_SAMPLES: usize = 16;
+
+/// Settings of adaptive polling, shared by every chain's poller
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdaptivePolling {
+    /// Interval of the tight polling around each expected block
+    tight_interval: Duration,
+}
+
+impl AdaptivePolling {
+    /// Adaptive polling if `ADAPTIVE_POLLING` is set, otherwise `None` to poll on a fixed interval
+    pub fn from_env() -> Option<Self> {
+        env::var("ADAPTIVE_POLLING")
+            .is_ok_and(|val| val == "true" || val == "1")
+            .then(|| Self {
+                tight_interval: Duration::from_millis(get_env_as_u64("ADAPTIVE_POLL_MIN_MS", 50).max(10)),
+            })
+    }
+}
+
+/// Schedules a chain's polls from its learned block cadence: one call just before each
+/// block is expected, then tight polling until it shows up.
+///
+/// The block time is the median interval between the last few new heads, starting from
+/// the chain's expected block time. How far ahead of the expected time polling starts
+/// adapts too: it grows when the first poll already finds the block, and shrinks when
+/// the block keeps the poller waiting. A block that misses its window is looked for
+/// around the following expected block instead. Until the block time is known, polls
+/// are made at the regular interval.
+pub struct CadencePoller {
+    settings: AdaptivePolling,
+    chain: String,
+    // Regular poll interval, used until the block time is known
+    fallback: Duration,
+    expected_block_time: Option<Duration>,
+    intervals: VecDeque<Duration>,
+    // Latest head, and the start of the poll that first saw it
+    last_head: Option<(u64, Instant)>,
+    lead: Duration,
+    // Polls since the latest head was first seen
+    polls: u32,
+    learned: bool,
+}
+
+impl CadencePoller {
+    pub fn new(settings: AdaptivePolling, chain: &str, fallback: Duration, expected_block_time_ms: Option<u64>) -> Self {
+        Self {
+            settings,
+            chain: chain.to_string(),
+            fallback,
+            expected_block_time: expected_block_time_ms.map(Duration::from_millis),
+            intervals: VecDeque::with_capacity(CADENCE_SAMPLES),
+            last_head: None,
+            lead: settings.tight_interval * 2,
+            polls: 0,
+            learned: false,
+        }
+    }
+
+    /// Learned block time, or the expected one until a new head has been seen
+    pub fn block_time(&self) -> Option<Duration> {
+        if self.intervals.is_empty() {
+            return self.expected_block_time;
+        }
+        let mut sorted: Vec<Duration> = self.intervals.iter().copied().collect();
+        sorted.sort_unstable();
+        Some(sorted[sorted.len() / 2])
+    }
+
+    /// Note the head after a poll that started at `polled_at`
+    pub fn record(&mut self, head: Option<u64>, polled_at: Instant) {
+        self.polls += 1;
+        let Some(head) = head else {
+            return;
+        };
+        match self.last_head {
+            Some((last, _)) if head <= last => return,
+            Some((last, seen)) => {
+                if self.intervals.len() == CADENCE_SAMPLES {
+                    self.intervals.pop_front();
+                }
+                self.intervals.push_back(polled_at.saturating_duration_since(seen) / (head - last) as u32);
+                self.adjust_lead();
+            }
+            None => {}
+        }
+        self.last_head = Some((head, polled_at));
+        self.polls = 0;
+    }
+
+    // The first poll finding the block may have been late, so start earlier; more than a
+    // couple of tight polls before it arrived were wasted, so start later
+    fn adjust_lead(&mut self) {
+        let Some(block_time) = self.block_time() else {
+            return;
+        };
+        if !self.learned && self.intervals.len() == CADENCE_SAMPLES {
+            self.learned = true;
+            info!("{}: Learned block time of {}ms; polling around each expected block", self.chain, block_time.as_millis());
+        }
+        let tight = self.settings.tight_interval;
+        let lead = match self.polls {
+            1 => (self.lead + tight).min(block_time / 2),
+            polls if polls > 2 => self.lead.saturating_sub(tight).max(tight),
+            _ => self.lead,
+        };
+        if lead != self.lead {
+            debug!("{}: Polling {}ms ahead of each expected block", self.chain, lead.as_millis());
+            self.lead = lead;
+        }
+    }
+
+    /// How long to wait before the next poll
+    pub fn next_poll_in(&self, now: Instant) -> Duration {
+        if self.polls == 0 && self.last_head.is_none() {
+            return Duration::ZERO;
+        }
+        let (Some(block_time), Some((_, seen))) = (self.block_time(), self.last_head) else {
+            return self.fallback;
+        };
+        // Tight polling lasts from the lead before an expected block until a quarter of a
+        // block time after it; past that, the next block is expected a block time later
+        let window = (block_time / 4).max(self.lead);
+        let elapsed = now.saturating_duration_since(seen).as_millis();
+        let block_ms = block_time.as_millis().max(1);
+        let slots = (elapsed.saturating_sub(window.as_millis()) / block_ms + 1) as u32;
+        let wake = (seen + block_time * slots).checked_sub(self.lead).unwrap_or(seen);
+        if now < wake {
+            wake - now
+        } else {
+            self.settings.tight_interval
+        }
+    }
+}
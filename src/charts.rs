@@ -0,0 +1,113 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use plotters::prelude::*;
+use std::path::Path;
+
+use crate::stats::ChainStats;
+use crate::timezone::ReportTimezone;
+
+/// Size of every chart, in pixels
+const CHART_SIZE: (u32, u32) = (1000, 400);
+
+const LINE_COLOR: RGBColor = RGBColor(0x33, 0x66, 0xcc);
+
+/// Most bars that get their own label; longer histograms only label every few bars
+const MAX_BAR_LABELS: usize = 16;
+
+/// Render a chain's `{Chain}_delta_chart.png`, its delta against receipt time, and
+/// `{Chain}_histogram_chart.png`, its delta histogram, to `dir`. Receipt times are labelled
+/// in `timezone`.
+///
+/// Like the charts of `report.html`, the delta over time covers the per-block records kept
+/// for the detailed CSV and the histogram covers every block. Labels are drawn with the
+/// system's sans-serif font, so a host without fonts fails here.
+pub(crate) fn write_charts(dir: &Path, stats: &ChainStats, timezone: ReportTimezone) -> Result<()> {
+    if !stats.raw_samples.is_empty() {
+        let path = dir.join(format!("{}_delta_chart.png", stats.chain_name));
+        delta_chart(&path, stats, timezone).with_context(|| format!("Failed to render {}", path.display()))?;
+    }
+    if !stats.delta_histogram.is_empty() {
+        let path = dir.join(format!("{}_histogram_chart.png", stats.chain_name));
+        histogram_chart(&path, stats).with_context(|| format!("Failed to render {}", path.display()))?;
+    }
+    Ok(())
+}
+
+// Each kept block's delta against its receipt time, with a line at zero: past (positive)
+// deltas plot above it and future ones below
+fn delta_chart(path: &Path, stats: &ChainStats, timezone: ReportTimezone) -> Result<()> {
+    let mut points: Vec<(DateTime<Utc>, i64)> = stats
+        .raw_samples
+        .iter()
+        .filter_map(|sample| Some((DateTime::from_timestamp_millis(sample.receipt_time_ms)?, sample.delta_ms)))
+        .collect();
+    points.sort_unstable();
+    let (Some(&(first, _)), Some(&(last, _))) = (points.first(), points.last()) else {
+        return Ok(());
+    };
+    // A single block, or a burst within one millisecond, still gets an axis
+    let last = last.max(first + Duration::seconds(1));
+    // Zero is always on the axis, and the extremes keep some room from the edges
+    let min_delta = points.iter().map(|&(_, delta)| delta).min().unwrap_or(0).min(0);
+    let max_delta = points.iter().map(|&(_, delta)| delta).max().unwrap_or(0).max(0);
+    let pad = (max_delta - min_delta) / 20 + 1;
+    let (min_delta, max_delta) = (if min_delta < 0 { min_delta - pad } else { 0 }, max_delta + pad);
+
+    let root = BitMapBackend::new(path, CHART_SIZE).into_drawing_area();
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption(format!("{}: delta over time (ms)", stats.chain_name), ("sans-serif", 20))
+        .margin(12)
+        .x_label_area_size(36)
+        .y_label_area_size(64)
+        .build_cartesian_2d(first..last, min_delta..max_delta)?;
+    let x_desc = format!("Received ({})", timezone.name());
+    chart
+        .configure_mesh()
+        .x_labels(8)
+        .x_label_formatter(&|at| timezone.format(*at, "%H:%M:%S"))
+        .x_desc(x_desc)
+        .y_desc("Delta (ms)")
+        .draw()?;
+    chart.draw_series(LineSeries::new([(first, 0), (last, 0)], BLACK.mix(0.4)))?;
+    chart.draw_series(LineSeries::new(points, &LINE_COLOR))?;
+    root.present()?;
+    Ok(())
+}
+
+// One bar per `HISTOGRAM_BUCKETS_MS` bucket, labelled with its bounds
+fn histogram_chart(path: &Path, stats: &ChainStats) -> Result<()> {
+    let buckets = stats.delta_buckets();
+    let tallest = buckets.iter().map(|bucket| bucket.count).max().unwrap_or(0).max(1);
+    let label_every = buckets.len().div_ceil(MAX_BAR_LABELS).max(1);
+
+    let root = BitMapBackend::new(path, CHART_SIZE).into_drawing_area();
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption(format!("{}: delta histogram (ms)", stats.chain_name), ("sans-serif", 20))
+        .margin(12)
+        .x_label_area_size(36)
+        .y_label_area_size(64)
+        .build_cartesian_2d((0..buckets.len()).into_segmented(), 0..tallest + tallest / 20 + 1)?;
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .x_labels(buckets.len())
+        .x_label_formatter(&|segment| match segment {
+            SegmentValue::CenterOf(i) if i % label_every == 0 => {
+                buckets.get(*i).map(|bucket| bucket.label()).unwrap_or_default()
+            }
+            _ => String::new(),
+        })
+        .x_desc("Delta (ms)")
+        .y_desc("Blocks")
+        .draw()?;
+    chart.draw_series(
+        Histogram::vertical(&chart)
+            .style(LINE_COLOR.filled())
+            .margin(4)
+            .data(buckets.iter().enumerate().map(|(i, bucket)| (i, bucket.count))),
+    )?;
+    root.present()?;
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::stats::ChainStats;
+
+/// Latest accumulated stats of a run, so `--resume` can continue it after a crash
+pub const CHECKPOINT_FILE: &str = "checkpoint.json";
+
+/// Everything a resumed run needs: each chain's stats, including the last block its
+/// monitor processed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub started_at: DateTime<Utc>,
+    pub saved_at: DateTime<Utc>,
+    pub chains: HashMap<String, ChainStats>,
+}
+
+// Serializes like `Checkpoint` without copying the stats
+#[derive(Serialize)]
+struct CheckpointRef<'a> {
+    started_at: DateTime<Utc>,
+    saved_at: DateTime<Utc>,
+    chains: &'a HashMap<String, ChainStats>,
+}
+
+impl Checkpoint {
+    /// Load the checkpoint in `dir`, if the run has written one
+    pub fn load(dir: &Path) -> Result<Option<Self>> {
+        let path = dir.join(CHECKPOINT_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = std::fs::read_to_string(&path)?;
+        let checkpoint = serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse checkpoint {}", path.display()))?;
+        Ok(Some(checkpoint))
+    }
+
+    /// Replace the checkpoint in `dir`. The new one is written next to it and renamed into
+    /// place, so a crash mid-write leaves the previous checkpoint intact.
+    pub fn save(dir: &Path, started_at: DateTime<Utc>, chain_stats: &HashMap<String, ChainStats>) -> Result<()> {
+        let checkpoint = CheckpointRef {
+            started_at,
+            saved_at: Utc::now(),
+            chains: chain_stats,
+        };
+        let path = dir.join(CHECKPOINT_FILE);
+        let partial = path.with_extension("json.tmp");
+        std::fs::write(&partial, serde_json::to_vec(&checkpoint)?)?;
+        std::fs::rename(&partial, &path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,290 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use log::{info, warn};
+use std::env;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::time;
+
+use crate::get_env_as_u64;
+
+/// Seconds between the NTP epoch (1900) and the Unix epoch
+const NTP_UNIX_OFFSET_SECS: i64 = 2_208_988_800;
+
+/// How long to wait for each server's answer
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Stored while no check has succeeded yet
+const UNKNOWN_OFFSET: i64 = i64::MIN;
+
+/// A moment read from the wall clock and the monotonic clock together. Deltas compare the
+/// wall clock with block timestamps, while intervals between moments (arrival gaps, round
+/// trips) come from the monotonic clock, which NTP steps and offset corrections don't move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stamp {
+    pub wall: DateTime<Utc>,
+    /// `None` for moments read back from stored observations, whose intervals can only come
+    /// from the wall clock
+    pub monotonic: Option<Instant>,
+}
+
+impl Stamp {
+    pub fn now() -> Self {
+        Self {
+            monotonic: Some(Instant::now()),
+            wall: Utc::now(),
+        }
+    }
+
+    /// A moment known only by its wall-clock time
+    pub fn wall(wall: DateTime<Utc>) -> Self {
+        Self { wall, monotonic: None }
+    }
+
+    /// Milliseconds from `earlier` to this moment, by the monotonic clock when both have it
+    pub fn ms_since(&self, earlier: &Stamp) -> i64 {
+        match (self.monotonic, earlier.monotonic) {
+            (Some(now), Some(then)) if now >= then => now.duration_since(then).as_millis() as i64,
+            (Some(now), Some(then)) => -(then.duration_since(now).as_millis() as i64),
+            _ => self.wall.timestamp_millis() - earlier.wall.timestamp_millis(),
+        }
+    }
+}
+
+/// Offset of the local clock from NTP time, checked at startup and periodically during the
+/// run, so a "future" block can be told apart from a local clock running slow.
+///
+/// With several servers the median offset is used. Unless `NTP_APPLY_OFFSET=false`, the
+/// offset is added to every receipt time before its delta is computed.
+#[derive(Debug, Clone)]
+pub struct ClockSync {
+    servers: Vec<String>,
+    interval: Duration,
+    apply: bool,
+    // True time minus local time in milliseconds, shared with every monitor
+    offset_ms: Arc<AtomicI64>,
+}
+
+impl Default for ClockSync {
+    fn default() -> Self {
+        Self {
+            servers: Vec::new(),
+            interval: Duration::from_secs(600),
+            apply: true,
+            offset_ms: Arc::new(AtomicI64::new(UNKNOWN_OFFSET)),
+        }
+    }
+}
+
+impl ClockSync {
+    pub fn from_env() -> Self {
+        let servers = env::var("NTP_SERVERS")
+            .map(|servers| {
+                servers
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|server| !server.is_empty())
+                    // The NTP port is implied when a server is given by name only
+                    .map(|server| if server.contains(':') { server.to_string() } else { format!("{}:123", server) })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            servers,
+            interval: Duration::from_secs(get_env_as_u64("NTP_INTERVAL_SECS", 600).max(1)),
+            apply: env::var("NTP_APPLY_OFFSET").map_or(true, |val| val != "false"),
+            ..Self::default()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.servers.is_empty()
+    }
+
+    /// Latest measured offset (true time minus local time), once a check has succeeded
+    pub fn offset_ms(&self) -> Option<i64> {
+        let offset = self.offset_ms.load(Ordering::Relaxed);
+        (offset != UNKNOWN_OFFSET).then_some(offset)
+    }
+
+    /// Where receipt times come from, as recorded in `run_config.json`: the kernel's clock
+    /// source, and the NTP servers and startup offset they are corrected by, if any
+    pub fn summary(&self) -> serde_json::Value {
+        serde_json::json!({
+            "clocksource": clocksource(),
+            "ntp_servers": self.servers,
+            "ntp_interval_secs": self.is_enabled().then_some(self.interval.as_secs()),
+            "offset_ms": self.offset_ms(),
+            "corrected": self.is_enabled() && self.apply,
+        })
+    }
+
+    /// `local`, corrected by the measured offset when compensation is on
+    pub fn correct(&self, local: DateTime<Utc>) -> DateTime<Utc> {
+        match self.offset_ms() {
+            Some(offset) if self.apply => local + ChronoDuration::milliseconds(offset),
+            _ => local,
+        }
+    }
+
+    /// Query every server and take the median of the offsets that came back
+    pub async fn check(&self) -> Result<i64> {
+        let mut offsets = Vec::new();
+        for server in &self.servers {
+            match query(server).await {
+                Ok(offset) => offsets.push(offset),
+                Err(e) => warn!("Clock check against {} failed: {}", server, e),
+            }
+        }
+        if offsets.is_empty() {
+            return Err(anyhow!("No NTP server answered"));
+        }
+        offsets.sort_unstable();
+        let offset = offsets[offsets.len() / 2];
+        self.offset_ms.store(offset, Ordering::Relaxed);
+        Ok(offset)
+    }
+
+    /// Re-check the offset every `NTP_INTERVAL_SECS` until the process exits
+    pub async fn run(self) {
+        let mut ticker = time::interval(self.interval);
+        // The first tick completes immediately; the startup check already covered it
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            match self.check().await {
+                Ok(offset) => info!("Local clock offset: {:+}ms", offset),
+                Err(e) => warn!("Clock check failed, keeping the previous offset: {}", e),
+            }
+        }
+    }
+}
+
+// The hardware clock the kernel keeps time with (e.g. `tsc`, or `kvm-clock` in a VM), where
+// the platform says
+fn clocksource() -> Option<String> {
+    std::fs::read_to_string("/sys/devices/system/clocksource/clocksource0/current_clocksource")
+        .ok()
+        .map(|source| source.trim().to_string())
+        .filter(|source| !source.is_empty())
+}
+
+// One SNTP exchange: the offset is the mean of the outbound and return differences
+// between the server's clock and ours
+async fn query(server: &str) -> Result<i64> {
+    // Bind to the family of the server's address, so IPv6-only servers can be reached
+    let address = tokio::net::lookup_host(server)
+        .await?
+        .next()
+        .ok_or_else(|| anyhow!("no address for {}", server))?;
+    let local: SocketAddr = match address {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(address).await?;
+
+    // LI 0, version 4, mode 3 (client)
+    let mut request = [0u8; 48];
+    request[0] = 0x23;
+    let sent = Utc::now().timestamp_millis();
+    socket.send(&request).await?;
+
+    let mut response = [0u8; 48];
+    let len = time::timeout(QUERY_TIMEOUT, socket.recv(&mut response))
+        .await
+        .map_err(|_| anyhow!("timed out"))??;
+    let received = Utc::now().timestamp_millis();
+    if len < response.len() {
+        return Err(anyhow!("short response ({} bytes)", len));
+    }
+    offset(&response, sent, received)
+}
+
+// Offset of the server's clock from ours, given its response to a request sent and
+// answered at `sent` and `received` by our clock
+fn offset(response: &[u8; 48], sent: i64, received: i64) -> Result<i64> {
+    // Only a server's answer (mode 4) with a synchronized clock (LI other than 3) is usable
+    let mode = response[0] & 0x07;
+    if mode != 4 {
+        return Err(anyhow!("unexpected mode {} in the response", mode));
+    }
+    if response[0] >> 6 == 3 {
+        return Err(anyhow!("server clock not synchronized"));
+    }
+    // Stratum 0 is a "kiss-o'-death" telling us to back off
+    if response[1] == 0 {
+        return Err(anyhow!("server refused the request"));
+    }
+
+    let server_received = ntp_millis(&response[32..40]);
+    let server_sent = ntp_millis(&response[40..48]);
+    Ok(((server_received - sent) + (server_sent - received)) / 2)
+}
+
+// NTP timestamps are 32 bits of seconds since 1900 and 32 bits of fraction
+fn ntp_millis(bytes: &[u8]) -> i64 {
+    let secs = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as i64;
+    let fraction = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as i64;
+    (secs - NTP_UNIX_OFFSET_SECS) * 1000 + ((fraction * 1000) >> 32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An NTP timestamp for `millis` since the Unix epoch
+    fn ntp_timestamp(millis: i64) -> [u8; 8] {
+        let secs = (millis.div_euclid(1000) + NTP_UNIX_OFFSET_SECS) as u32;
+        // Rounded up, so that truncating it back to milliseconds gives `millis` again
+        let fraction = (((millis.rem_euclid(1000) << 32) + 999) / 1000) as u32;
+        let mut bytes = [0u8; 8];
+        bytes[..4].copy_from_slice(&secs.to_be_bytes());
+        bytes[4..].copy_from_slice(&fraction.to_be_bytes());
+        bytes
+    }
+
+    // A stratum 2 server's answer, received and sent at the given Unix milliseconds
+    fn response(first_byte: u8, server_received: i64, server_sent: i64) -> [u8; 48] {
+        let mut response = [0u8; 48];
+        response[0] = first_byte;
+        response[1] = 2;
+        response[32..40].copy_from_slice(&ntp_timestamp(server_received));
+        response[40..48].copy_from_slice(&ntp_timestamp(server_sent));
+        response
+    }
+
+    #[test]
+    fn ntp_timestamps_convert_to_unix_millis() {
+        assert_eq!(ntp_millis(&[0x83, 0xaa, 0x7e, 0x80, 0, 0, 0, 0]), 0);
+        // 2024-01-01T00:00:00.500Z: half of the fraction's range
+        assert_eq!(ntp_millis(&[0xe9, 0x3c, 0x7f, 0x00, 0x80, 0, 0, 0]), 1_704_067_200_500);
+        assert_eq!(ntp_millis(&ntp_timestamp(1_704_067_200_250)), 1_704_067_200_250);
+    }
+
+    #[test]
+    fn offset_is_the_mean_of_both_legs() {
+        // Sent at 1000 and answered at 1100 by our clock; the server, 300ms ahead, received
+        // it 20ms in and answered 60ms later
+        let ahead = response(0x24, 1_320, 1_380);
+        assert_eq!(offset(&ahead, 1_000, 1_100).unwrap(), 300);
+        let behind = response(0x24, 820, 880);
+        assert_eq!(offset(&behind, 1_000, 1_100).unwrap(), -200);
+    }
+
+    #[test]
+    fn unusable_responses_are_rejected() {
+        // A client request (mode 3) echoed back
+        assert!(offset(&response(0x23, 1_320, 1_380), 1_000, 1_100).is_err());
+        // Leap indicator 3: the server's clock is unsynchronized
+        assert!(offset(&response(0xe4, 1_320, 1_380), 1_000, 1_100).is_err());
+        // A leap second warning (LI 1) is still a valid time
+        assert!(offset(&response(0x64, 1_320, 1_380), 1_000, 1_100).is_ok());
+        let mut kiss = response(0x24, 1_320, 1_380);
+        kiss[1] = 0;
+        assert!(offset(&kiss, 1_000, 1_100).is_err());
+    }
+}
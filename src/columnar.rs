@@ -0,0 +1,88 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+
+use crate::stats::RawSample;
+
+/// File format of the per-block (`{Chain}_detailed`) and `{Chain}_deltas` datasets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    /// Snappy-compressed Parquet, with the snake_case column names `query` uses; needs a
+    /// build with the `parquet` feature
+    Parquet,
+}
+
+impl OutputFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Parquet => "parquet",
+        }
+    }
+
+    /// Fail at startup rather than at the first report when this build can't write the format
+    pub fn check_supported(self) -> Result<()> {
+        if self == OutputFormat::Parquet && !cfg!(feature = "parquet") {
+            return Err(anyhow::anyhow!("--format parquet needs a build with the `parquet` feature"));
+        }
+        Ok(())
+    }
+}
+
+/// Write `samples` to `{chain}_detailed.parquet` and their deltas to `{chain}_deltas.parquet`,
+/// returning the path of the detailed file
+#[cfg(feature = "parquet")]
+pub fn write_samples(output_dir: &Path, chain: &str, samples: &VecDeque<RawSample>) -> Result<PathBuf> {
+    use arrow_array::{ArrayRef, BooleanArray, Int64Array, RecordBatch, StringArray, UInt64Array};
+    use std::sync::Arc;
+
+    let deltas: ArrayRef = Arc::new(samples.iter().map(|s| s.delta_ms).collect::<Int64Array>());
+    let detailed = RecordBatch::try_from_iter([
+        ("block_number", Arc::new(samples.iter().map(|s| s.block_number).collect::<UInt64Array>()) as ArrayRef),
+        ("block_timestamp_s", Arc::new(samples.iter().map(|s| s.block_timestamp_ms / 1000).collect::<UInt64Array>())),
+        ("receipt_time_ms", Arc::new(samples.iter().map(|s| s.receipt_time_ms).collect::<Int64Array>())),
+        ("delta_ms", deltas.clone()),
+        ("leap_second_window", Arc::new(samples.iter().map(|s| Some(s.leap_affected)).collect::<BooleanArray>())),
+        ("block_timestamp_ms", Arc::new(samples.iter().map(|s| s.block_timestamp_ms).collect::<UInt64Array>())),
+        ("response_time_ms", Arc::new(samples.iter().map(|s| s.response_time_ms()).collect::<Int64Array>())),
+        ("rpc_round_trip_ms", Arc::new(samples.iter().map(|s| s.round_trip_ms).collect::<Int64Array>())),
+        ("l1_origin_block", Arc::new(samples.iter().map(|s| s.l1_origin_block).collect::<UInt64Array>())),
+        ("l1_origin_drift_ms", Arc::new(samples.iter().map(|s| s.l1_drift_ms).collect::<Int64Array>())),
+        ("delta_direction", Arc::new(samples.iter().map(|s| Some(s.timestamp_delta().direction.as_str())).collect::<StringArray>())),
+        ("delta_magnitude_ms", Arc::new(samples.iter().map(|s| s.timestamp_delta().magnitude_ms).collect::<UInt64Array>())),
+        ("block_hash", Arc::new(samples.iter().map(|s| s.hash.as_deref()).collect::<StringArray>())),
+        ("parent_hash", Arc::new(samples.iter().map(|s| s.parent_hash.as_deref()).collect::<StringArray>())),
+        ("gas_used", Arc::new(samples.iter().map(|s| s.gas_used).collect::<UInt64Array>())),
+        ("tx_count", Arc::new(samples.iter().map(|s| s.tx_count).collect::<UInt64Array>())),
+        ("node_syncing", Arc::new(samples.iter().map(|s| s.node_syncing).collect::<BooleanArray>())),
+    ])?;
+    let detailed_path = output_dir.join(format!("{}_detailed.parquet", chain));
+    write_batch(&detailed_path, &detailed)?;
+
+    let deltas = RecordBatch::try_from_iter([("delta_ms", deltas)])?;
+    write_batch(&output_dir.join(format!("{}_deltas.parquet", chain)), &deltas)?;
+    Ok(detailed_path)
+}
+
+#[cfg(feature = "parquet")]
+fn write_batch(path: &Path, batch: &arrow_array::RecordBatch) -> Result<()> {
+    use parquet::arrow::ArrowWriter;
+    use parquet::basic::Compression;
+    use parquet::file::properties::WriterProperties;
+
+    let props = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer = ArrowWriter::try_new(std::fs::File::create(path)?, batch.schema(), Some(props))?;
+    writer.write(batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+#[cfg(not(feature = "parquet"))]
+pub fn write_samples(_output_dir: &Path, _chain: &str, _samples: &VecDeque<RawSample>) -> Result<PathBuf> {
+    Err(anyhow::anyhow!("This build lacks the `parquet` feature"))
+}
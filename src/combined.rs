@@ -0,0 +1,77 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::dataset;
+use crate::report;
+use crate::stats::{ChainStats, SUMMARY_HEADERS};
+
+/// Every chain's stats in one CSV table, one row per chain
+pub const COMBINED_STATS_CSV: &str = "combined_stats.csv";
+
+/// The same table as a JSON array of rows, keyed by the snake-case column names
+pub const COMBINED_STATS_JSON: &str = "combined_stats.json";
+
+/// Run metadata leading every row: the column, and where it is found in the JSON summary
+const RUN_COLUMNS: &[(&str, &[&str])] = &[
+    ("Version", &["version"]),
+    ("Status", &["status"]),
+    ("Started At", &["started_at"]),
+    ("Updated At", &["updated_at"]),
+    ("Elapsed (s)", &["elapsed_secs"]),
+    ("Poll Interval (ms)", &["poll_interval_ms"]),
+    ("Vantage", &["vantage", "label"]),
+];
+
+/// Write every chain's `{Chain}_stats.csv` row, behind the run metadata of `summary`, to
+/// `combined_stats.csv` and `combined_stats.json` in `dir`, so one file covers the run
+pub fn write(dir: &Path, summary: &serde_json::Value, chain_stats: &HashMap<String, ChainStats>) -> Result<()> {
+    let run: Vec<String> = RUN_COLUMNS
+        .iter()
+        .map(|(_, path)| {
+            let value = path.iter().fold(summary, |value, key| &value[*key]);
+            match value {
+                serde_json::Value::Null => String::new(),
+                serde_json::Value::String(text) => text.clone(),
+                other => other.to_string(),
+            }
+        })
+        .collect();
+    let headers: Vec<&str> = RUN_COLUMNS.iter().map(|(header, _)| *header).chain(SUMMARY_HEADERS.iter().copied()).collect();
+
+    let mut wtr = csv::Writer::from_path(dir.join(COMBINED_STATS_CSV))?;
+    wtr.write_record(&headers)?;
+    let mut rows = Vec::new();
+    for stats in report::sorted(chain_stats) {
+        let record: Vec<String> = run.iter().cloned().chain(stats.summary_record()).collect();
+        wtr.write_record(&record)?;
+        let row: serde_json::Map<String, serde_json::Value> = headers
+            .iter()
+            .zip(record)
+            .map(|(header, value)| (dataset::column_name(header), json_value(value)))
+            .collect();
+        rows.push(serde_json::Value::Object(row));
+    }
+    wtr.flush()?;
+
+    std::fs::write(dir.join(COMBINED_STATS_JSON), serde_json::to_string_pretty(&rows)? + "\n")?;
+    Ok(())
+}
+
+// A CSV cell as JSON: empty cells are null, and numbers and booleans keep their type
+fn json_value(cell: String) -> serde_json::Value {
+    if cell.is_empty() {
+        return serde_json::Value::Null;
+    }
+    if let Ok(number) = cell.parse::<i64>() {
+        return number.into();
+    }
+    if let Some(number) = cell.parse::<f64>().ok().filter(|number| number.is_finite()) {
+        return number.into();
+    }
+    match cell.as_str() {
+        "true" => true.into(),
+        "false" => false.into(),
+        _ => cell.into(),
+    }
+}
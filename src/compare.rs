@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+
+use crate::get_env_as_u64;
+
+/// How far a chain's primary and comparison providers may drift apart before they count as
+/// disagreeing
+#[derive(Debug, Clone, Copy)]
+pub struct CompareThresholds {
+    /// Blocks between the two heads
+    pub max_head_diff: u64,
+    /// Milliseconds between the two heads' timestamps
+    pub max_timestamp_diff_ms: u64,
+}
+
+impl Default for CompareThresholds {
+    fn default() -> Self {
+        Self {
+            max_head_diff: 5,
+            max_timestamp_diff_ms: 5000,
+        }
+    }
+}
+
+impl CompareThresholds {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            max_head_diff: get_env_as_u64("COMPARE_MAX_HEAD_DIFF", defaults.max_head_diff),
+            max_timestamp_diff_ms: get_env_as_u64("COMPARE_MAX_TIMESTAMP_DIFF_MS", defaults.max_timestamp_diff_ms),
+        }
+    }
+}
+
+/// How well a chain's two providers agreed on the latest head over the run
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ProviderDivergence {
+    pub checks: usize,                // Polls where both providers answered
+    pub disagreements: usize,         // Times the providers started disagreeing
+    pub disagreeing: bool,            // Whether they disagreed at the latest check
+    pub max_head_diff: u64,           // Most blocks between the two heads
+    pub max_timestamp_diff_ms: u64,   // Largest gap between the two head timestamps
+}
+
+impl ProviderDivergence {
+    /// Record the latest head (number and timestamp in ms) from each provider, returning
+    /// whether they disagree by more than `thresholds`
+    pub(crate) fn record(&mut self, primary: (u64, u64), comparison: (u64, u64), thresholds: &CompareThresholds) -> bool {
+        let head_diff = primary.0.abs_diff(comparison.0);
+        let timestamp_diff_ms = primary.1.abs_diff(comparison.1);
+        self.checks += 1;
+        self.max_head_diff = self.max_head_diff.max(head_diff);
+        self.max_timestamp_diff_ms = self.max_timestamp_diff_ms.max(timestamp_diff_ms);
+
+        let disagreeing = head_diff > thresholds.max_head_diff || timestamp_diff_ms > thresholds.max_timestamp_diff_ms;
+        if disagreeing && !self.disagreeing {
+            self.disagreements += 1;
+        }
+        self.disagreeing = disagreeing;
+        disagreeing
+    }
+
+    /// The comparison's columns in `SUMMARY_HEADERS` order, empty for chains with a single
+    /// provider
+    pub(crate) fn record_columns(&self) -> Vec<String> {
+        if self.checks == 0 {
+            return vec![String::new(); 4];
+        }
+        vec![
+            self.checks.to_string(),
+            self.disagreements.to_string(),
+            self.max_head_diff.to_string(),
+            self.max_timestamp_diff_ms.to_string(),
+        ]
+    }
+}
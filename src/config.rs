@@ -0,0 +1,446 @@
+use anyhow::{anyhow, Context, Result};
+use log::{error, info};
+use serde::Deserialize;
+use std::env;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::anomaly::AnomalySettings;
+use crate::breaker::BreakerConfig;
+use crate::cadence::AdaptivePolling;
+use crate::clock::ClockSync;
+use crate::compare::CompareThresholds;
+use crate::get_env_as_u64;
+use crate::histogram::HistogramBuckets;
+use crate::http::{AuthOverrides, HttpOverrides, HttpSettings};
+use crate::leap::LeapSeconds;
+use crate::monitor::{BlockTag, ChainInfo, ChainKind, MonitorOptions};
+use crate::presets::ChainPreset;
+use crate::retry::RetryPolicy;
+use crate::simulate::{self, SimulationSettings};
+use crate::stats::DEFAULT_MAX_RAW_SAMPLES;
+use crate::supervisor::SupervisorConfig;
+use crate::timestamp::TimestampDecoding;
+use crate::timezone::ReportTimezone;
+use crate::vantage::Vantage;
+
+/// Everything needed to monitor a set of chains
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub chains: Vec<ChainInfo>,
+    pub options: MonitorOptions,
+    pub poll_interval: Duration,
+    /// Poll around each chain's expected blocks instead of on `poll_interval`; `None` polls
+    /// on the fixed interval
+    pub adaptive_polling: Option<AdaptivePolling>,
+    /// How long to monitor; `None` runs until stopped
+    pub duration: Option<Duration>,
+    /// Share one HTTP client between chains served from the same provider origin
+    pub share_connections: bool,
+    pub supervisor: SupervisorConfig,
+    pub breaker: BreakerConfig,
+    /// Delta alert threshold from the chains file, overriding `--alert-threshold-ms`
+    pub alert_threshold_ms: Option<u64>,
+}
+
+impl Config {
+    /// Read the configuration from environment variables, taking the chains from
+    /// `chains_file` when given and from the `*_RPC_URL` variables otherwise
+    pub fn from_env(chains_file: Option<&Path>) -> Result<Self> {
+        let file = match chains_file {
+            Some(path) => {
+                let file = load_chains(path)?;
+                info!("Loaded {} chains from {}", file.chains.len(), path.display());
+                file
+            }
+            None => ChainsConfig {
+                chains: chains_from_env(),
+                ..ChainsConfig::default()
+            },
+        };
+        Self::with_chains(file)
+    }
+
+    /// Like `from_env`, but monitoring the named chain presets (see `presets::PRESETS`), each
+    /// given as its id or as `id=URL` to use another RPC endpoint
+    pub fn from_presets(presets: &[String]) -> Result<Self> {
+        let mut chains: Vec<ChainInfo> = Vec::new();
+        for spec in presets {
+            let chain = ChainPreset::parse(spec)?;
+            if chains.iter().any(|known| known.name == chain.name) {
+                return Err(anyhow!("Chain '{}' is given more than once", chain.name));
+            }
+            info!("Added {} to monitoring", chain.name);
+            chains.push(chain);
+        }
+        Self::with_chains(ChainsConfig {
+            chains,
+            ..ChainsConfig::default()
+        })
+    }
+
+    /// Like `from_env`, but with every chain simulated (see `SIMULATE_*`) instead of
+    /// reached over RPC. Without a chains file, Optimism, Base and Unichain are simulated.
+    pub fn simulated(chains_file: Option<&Path>) -> Result<Self> {
+        let mut config = match chains_file {
+            Some(_) => Self::from_env(chains_file)?,
+            None => Self::with_chains(ChainsConfig {
+                chains: [("Optimism", 2000), ("Base", 2000), ("Unichain", 1000)]
+                    .into_iter()
+                    .map(|(name, block_time_ms)| ChainInfo {
+                        name: name.to_string(),
+                        rpc_url: "sim://".to_string(),
+                        ws_url: None,
+                        compare_rpc_url: None,
+                        flashblocks_url: None,
+                        expected_block_time_ms: Some(block_time_ms),
+                        schedule_tolerance_ms: None,
+                        poll_interval_ms: None,
+                        chain_id: None,
+                        timestamp_decoding: TimestampDecoding::Seconds,
+                        http: HttpSettings::from_env(),
+                        kind: ChainKind::Standard,
+                        block_tag: BlockTag::Latest,
+                        l1_rpc_url: None,
+                    })
+                    .collect(),
+                ..ChainsConfig::default()
+            })?,
+        };
+        config.simulate();
+        config.check_simulations()?;
+        Ok(config)
+    }
+
+    /// Simulate every chain instead of reaching it over RPC. Chains already given a `sim://`
+    /// URL keep its settings; subscriptions, comparison providers and flashblocks feeds are
+    /// dropped.
+    pub fn simulate(&mut self) {
+        for chain in &mut self.chains {
+            if !simulate::is_simulated(&chain.rpc_url) {
+                chain.rpc_url = "sim://".to_string();
+            }
+            chain.ws_url = None;
+            chain.compare_rpc_url = None;
+            chain.flashblocks_url = None;
+        }
+        info!("Simulating {} chains; no RPC endpoints are contacted", self.chains.len());
+    }
+
+    pub(crate) fn with_chains(file: ChainsConfig) -> Result<Self> {
+        let mut chains = file.chains;
+        if chains.is_empty() {
+            return Err(anyhow!("No chains configured. Set at least OP_RPC_URL and BASE_RPC_URL, or pass --config with a chains file."));
+        }
+        // Arbitrum chains without an L1 endpoint of their own share L1_RPC_URL
+        if let Ok(l1_rpc_url) = env::var("L1_RPC_URL") {
+            for chain in chains.iter_mut().filter(|chain| chain.kind == ChainKind::Arbitrum) {
+                chain.l1_rpc_url.get_or_insert_with(|| l1_rpc_url.clone());
+            }
+        }
+
+        let duration_minutes = get_env_as_u64("DURATION_MINUTES", 60);
+        let config = Self {
+            chains,
+            options: MonitorOptions {
+                leap_seconds: LeapSeconds::from_env()?,
+                timezone: ReportTimezone::from_env()?,
+                strict_schema: env::var("STRICT_RPC_SCHEMA").is_ok_and(|val| val == "true" || val == "1"),
+                wedge_timeout: Duration::from_secs(get_env_as_u64("WEDGE_TIMEOUT_SECS", 30).max(1)),
+                retry: RetryPolicy::from_env(),
+                batch_requests: env::var("BATCH_REQUESTS").map_or(true, |val| val != "false"),
+                single_call_polling: env::var("SINGLE_CALL_POLLING").is_ok_and(|val| val == "true" || val == "1"),
+                remeasure_reorgs: env::var("REORG_REMEASURE").is_ok_and(|val| val == "true" || val == "1"),
+                max_raw_samples: DEFAULT_MAX_RAW_SAMPLES,
+                clock: ClockSync::from_env(),
+                safe_head_interval: match get_env_as_u64("SAFE_HEAD_INTERVAL_MS", 0) {
+                    0 => None,
+                    ms => Some(Duration::from_millis(ms)),
+                },
+                node_health_interval: match get_env_as_u64("NODE_HEALTH_INTERVAL_MS", 30_000) {
+                    0 => None,
+                    ms => Some(Duration::from_millis(ms)),
+                },
+                track_l1_origin: env::var("TRACK_L1_ORIGIN").is_ok_and(|val| val == "true" || val == "1"),
+                compare: CompareThresholds::from_env(),
+                histogram_buckets: HistogramBuckets::from_env()?,
+                max_catch_up_blocks: get_env_as_u64("MAX_CATCH_UP_BLOCKS", 100),
+                schedule_tolerance_ms: get_env_as_u64("SCHEDULE_TOLERANCE_MS", 250),
+                anomaly: AnomalySettings::from_env(),
+                outage_block_times: get_env_as_u64("OUTAGE_BLOCK_TIMES", 10),
+                vantage: Vantage::detect(env::var("VANTAGE_LABEL").ok().as_deref()),
+                head_consistency_window_ms: get_env_as_u64("HEAD_CONSISTENCY_WINDOW_MS", 0),
+            },
+            poll_interval: Duration::from_millis(
+                file.poll_interval_ms.unwrap_or_else(|| get_env_as_u64("POLL_INTERVAL_MS", 500)),
+            ),
+            adaptive_polling: AdaptivePolling::from_env(),
+            duration: (duration_minutes > 0).then(|| Duration::from_secs(duration_minutes * 60)),
+            share_connections: env::var("SHARE_CONNECTIONS").map_or(true, |val| val != "false"),
+            supervisor: SupervisorConfig::from_env(),
+            breaker: BreakerConfig::from_env(),
+            alert_threshold_ms: file.alert_threshold_ms,
+        };
+        config.check_simulations()?;
+        Ok(config)
+    }
+
+    // Catch mistakes in simulated chains' settings before any monitor starts
+    fn check_simulations(&self) -> Result<()> {
+        for chain in &self.chains {
+            for rpc_url in std::iter::once(&chain.rpc_url).chain(&chain.compare_rpc_url) {
+                if simulate::is_simulated(rpc_url) {
+                    SimulationSettings::parse(rpc_url, chain)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A chains file: one `[[chain]]` table per monitored chain, after any settings that
+/// apply to all of them
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChainsFile {
+    /// Overrides POLL_INTERVAL_MS
+    poll_interval_ms: Option<u64>,
+    /// Overrides `--alert-threshold-ms`
+    alert_threshold_ms: Option<u64>,
+    #[serde(rename = "chain", default)]
+    chains: Vec<ChainEntry>,
+}
+
+/// The chains and settings read from a chains file
+#[derive(Debug, Default)]
+pub struct ChainsConfig {
+    pub chains: Vec<ChainInfo>,
+    pub poll_interval_ms: Option<u64>,
+    pub alert_threshold_ms: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChainEntry {
+    name: String,
+    rpc_url: String,
+    ws_url: Option<String>,
+    /// Second provider to check the first against
+    compare_rpc_url: Option<String>,
+    /// Flashblocks feed to compare the canonical blocks with
+    flashblocks_url: Option<String>,
+    expected_block_time_ms: Option<u64>,
+    /// Overrides SCHEDULE_TOLERANCE_MS
+    schedule_tolerance_ms: Option<u64>,
+    poll_interval_ms: Option<u64>,
+    /// Chain ID the RPC endpoint must report
+    chain_id: Option<u64>,
+    /// Header field with a millisecond timestamp, for chains that provide one
+    timestamp_ms_field: Option<String>,
+    /// Overrides the HTTP_* client settings for this chain
+    #[serde(default)]
+    http: HttpOverrides,
+    /// Credentials and headers for the chain's RPC endpoint
+    #[serde(default)]
+    auth: AuthOverrides,
+    /// `arbitrum` for Arbitrum Nitro chains
+    #[serde(default)]
+    kind: ChainKind,
+    /// `pending`, `safe` or `finalized` to measure the blocks under that tag instead of
+    /// the latest
+    #[serde(default)]
+    block_tag: BlockTag,
+    /// L1 endpoint for an Arbitrum chain's L1 origin timestamps; overrides L1_RPC_URL
+    l1_rpc_url: Option<String>,
+}
+
+// Substitute `${VAR}` references so API keys can stay out of the file
+fn expand_env(value: &str) -> Result<String> {
+    let mut expanded = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow!("Unterminated ${{...}} in '{}'", value))?;
+        let name = &rest[start + 2..start + end];
+        let var = env::var(name).map_err(|_| anyhow!("Environment variable {} is not set", name))?;
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&var);
+        rest = &rest[start + end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Load the chains to monitor, and the settings that apply to them, from a TOML file
+pub fn load_chains(path: &Path) -> Result<ChainsConfig> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read chain config {}", path.display()))?;
+    let file: ChainsFile = toml::from_str(&contents)
+        .with_context(|| format!("Invalid chain config {}", path.display()))?;
+
+    let mut chains: Vec<ChainInfo> = Vec::new();
+    for entry in file.chains {
+        // Chain names become output file names, so they must be unique
+        if chains.iter().any(|chain| chain.name == entry.name) {
+            return Err(anyhow!("Chain '{}' is defined more than once", entry.name));
+        }
+        let rpc_url = expand_env(&entry.rpc_url).with_context(|| format!("Chain '{}'", entry.name))?;
+        let ws_url = entry
+            .ws_url
+            .map(|url| expand_env(&url))
+            .transpose()
+            .with_context(|| format!("Chain '{}'", entry.name))?;
+        let compare_rpc_url = entry
+            .compare_rpc_url
+            .map(|url| expand_env(&url))
+            .transpose()
+            .with_context(|| format!("Chain '{}'", entry.name))?;
+        let flashblocks_url = entry
+            .flashblocks_url
+            .map(|url| expand_env(&url))
+            .transpose()
+            .with_context(|| format!("Chain '{}'", entry.name))?;
+        // Subscriptions push the latest heads, which would be measured instead of the tagged ones
+        if ws_url.is_some() && entry.block_tag != BlockTag::Latest {
+            return Err(anyhow!(
+                "Chain '{}': ws_url only applies to block_tag = \"latest\", not \"{}\"",
+                entry.name,
+                entry.block_tag.as_str()
+            ));
+        }
+        if entry.l1_rpc_url.is_some() && entry.kind != ChainKind::Arbitrum {
+            return Err(anyhow!("Chain '{}': l1_rpc_url only applies to kind = \"arbitrum\"", entry.name));
+        }
+        let l1_rpc_url = entry
+            .l1_rpc_url
+            .map(|url| expand_env(&url))
+            .transpose()
+            .with_context(|| format!("Chain '{}'", entry.name))?;
+        let http = HttpSettings {
+            auth: entry.auth.resolve(expand_env).with_context(|| format!("Chain '{}' auth", entry.name))?,
+            ..HttpSettings::from_env().with_overrides(&entry.http)
+        };
+        chains.push(ChainInfo {
+            name: entry.name,
+            rpc_url,
+            ws_url,
+            compare_rpc_url,
+            flashblocks_url,
+            expected_block_time_ms: entry.expected_block_time_ms,
+            schedule_tolerance_ms: entry.schedule_tolerance_ms,
+            poll_interval_ms: entry.poll_interval_ms,
+            chain_id: entry.chain_id,
+            timestamp_decoding: entry
+                .timestamp_ms_field
+                .map_or(TimestampDecoding::Seconds, TimestampDecoding::MillisecondsField),
+            http,
+            kind: entry.kind,
+            block_tag: entry.block_tag,
+            l1_rpc_url,
+        });
+    }
+    Ok(ChainsConfig {
+        chains,
+        poll_interval_ms: file.poll_interval_ms,
+        alert_threshold_ms: file.alert_threshold_ms,
+    })
+}
+
+// Build the chain list from the OP/BASE/UNI_RPC_URL environment variables
+fn chains_from_env() -> Vec<ChainInfo> {
+    info!("Using RPC URLs from environment variables");
+
+    // Create chain monitors from environment variables
+    let mut chains = Vec::new();
+
+    // Add Optimism if URL is in environment
+    if let Ok(op_url) = env::var("OP_RPC_URL") {
+        chains.push(ChainInfo {
+            name: "Optimism".to_string(),
+            rpc_url: op_url,
+            ws_url: env::var("OP_WS_URL").ok(),
+            compare_rpc_url: env::var("OP_COMPARE_RPC_URL").ok(),
+            flashblocks_url: None,
+            expected_block_time_ms: Some(2000),
+            schedule_tolerance_ms: None,
+            poll_interval_ms: None,
+            chain_id: None,
+            timestamp_decoding: TimestampDecoding::Seconds,
+            http: HttpSettings::from_env(),
+            kind: ChainKind::Standard,
+            block_tag: BlockTag::Latest,
+            l1_rpc_url: None,
+        });
+        info!("Added Optimism to monitoring");
+    } else {
+        error!("OP_RPC_URL environment variable is missing");
+    }
+
+    // Add Base if URL is in environment
+    if let Ok(base_url) = env::var("BASE_RPC_URL") {
+        chains.push(ChainInfo {
+            name: "Base".to_string(),
+            rpc_url: base_url,
+            ws_url: env::var("BASE_WS_URL").ok(),
+            compare_rpc_url: env::var("BASE_COMPARE_RPC_URL").ok(),
+            flashblocks_url: env::var("BASE_FLASHBLOCKS_URL").ok(),
+            expected_block_time_ms: Some(2000),
+            schedule_tolerance_ms: None,
+            poll_interval_ms: None,
+            chain_id: None,
+            timestamp_decoding: TimestampDecoding::Seconds,
+            http: HttpSettings::from_env(),
+            kind: ChainKind::Standard,
+            block_tag: BlockTag::Latest,
+            l1_rpc_url: None,
+        });
+        info!("Added Base to monitoring");
+    } else {
+        error!("BASE_RPC_URL environment variable is missing");
+    }
+
+    // Add Unichain if URL is in environment
+    if let Ok(uni_url) = env::var("UNI_RPC_URL") {
+        chains.push(ChainInfo {
+            name: "Unichain".to_string(),
+            rpc_url: uni_url,
+            ws_url: env::var("UNI_WS_URL").ok(),
+            compare_rpc_url: env::var("UNI_COMPARE_RPC_URL").ok(),
+            flashblocks_url: env::var("UNI_FLASHBLOCKS_URL").ok(),
+            expected_block_time_ms: Some(1000),
+            schedule_tolerance_ms: None,
+            poll_interval_ms: None,
+            chain_id: None,
+            timestamp_decoding: TimestampDecoding::Seconds,
+            http: HttpSettings::from_env(),
+            kind: ChainKind::Standard,
+            block_tag: BlockTag::Latest,
+            l1_rpc_url: None,
+        });
+        info!("Added Unichain to monitoring");
+    }
+
+    // Add Arbitrum One if URL is in environment
+    if let Ok(arb_url) = env::var("ARB_RPC_URL") {
+        chains.push(ChainInfo {
+            name: "Arbitrum".to_string(),
+            rpc_url: arb_url,
+            ws_url: env::var("ARB_WS_URL").ok(),
+            compare_rpc_url: env::var("ARB_COMPARE_RPC_URL").ok(),
+            flashblocks_url: None,
+            expected_block_time_ms: Some(250),
+            schedule_tolerance_ms: None,
+            poll_interval_ms: None,
+            chain_id: None,
+            timestamp_decoding: TimestampDecoding::Seconds,
+            http: HttpSettings::from_env(),
+            kind: ChainKind::Arbitrum,
+            block_tag: BlockTag::Latest,
+            l1_rpc_url: None,
+        });
+        info!("Added Arbitrum to monitoring");
+    }
+
+    chains
+}
@@ -0,0 +1,281 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::clock::Stamp;
+use crate::histogram::DeltaHistogram;
+use crate::stats::GapSummary;
+
+/// Most recent mismatches kept for the final report and `summary.json`
+const MAX_RECENT_MISMATCHES: usize = 20;
+
+/// Which of the two ways of learning about heads saw one that the other didn't
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeadSource {
+    /// Announced over the `newHeads` subscription
+    NewHeads,
+    /// Returned by polling `eth_blockNumber`
+    Polling,
+}
+
+impl HeadSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HeadSource::NewHeads => "newHeads",
+            HeadSource::Polling => "eth_blockNumber",
+        }
+    }
+
+    pub fn other(&self) -> HeadSource {
+        match self {
+            HeadSource::NewHeads => HeadSource::Polling,
+            HeadSource::Polling => HeadSource::NewHeads,
+        }
+    }
+}
+
+/// A head only one source reported within the window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeadMismatch {
+    pub block_number: u64,
+    pub seen_by: HeadSource,
+    pub seen_at_ms: i64, // When that source reported it
+}
+
+/// Cross-check of a chain's `newHeads` subscription against polling `eth_blockNumber`
+/// while both run (`HEAD_CONSISTENCY_WINDOW_MS`).
+///
+/// A pushed head counts as reflected by polling once a poll returns it or a higher one, and
+/// a polled head counts as announced once `newHeads` pushes that very block. Heads still
+/// unmatched after the window are provider-consistency mismatches. The lag is when polling
+/// first reflected a pushed head minus when it was pushed, so it is negative for heads
+/// polling saw first.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct HeadConsistency {
+    #[serde(skip)]
+    pub window_ms: Option<u64>, // `None` for chains without a subscription or with the audit off; not checkpointed
+    pub pushed_heads: usize,    // Heads announced over `newHeads` while auditing
+    pub polled_heads: usize,    // New heads returned by `eth_blockNumber` while auditing
+    pub pushed_only: usize,     // Pushed heads no poll reflected within the window
+    pub polled_only: usize,     // Polled heads `newHeads` didn't announce within the window
+    pub poll_lags: DeltaHistogram, // Poll reflecting a head minus its push, in ms
+    pub recent: VecDeque<HeadMismatch>, // Most recent mismatches, oldest first
+    #[serde(skip)]
+    pending: PendingHeads,
+}
+
+// Heads waiting for the other source, cleared whenever the subscription drops
+#[derive(Debug, Default, Clone)]
+struct PendingHeads {
+    // First head pushed since the subscription (re)connected; polls below it predate it
+    first_pushed: Option<u64>,
+    polled_height: Option<u64>,
+    // Heads pushed within the window, and those no poll has reflected yet
+    pushed: BTreeMap<u64, Stamp>,
+    unpolled: BTreeMap<u64, Stamp>,
+    // Polls within the window that raised the height, the highest head among the polls
+    // since dropped, and the polled heads not yet pushed
+    polls: VecDeque<(u64, Stamp)>,
+    dropped_height: Option<u64>,
+    unpushed: BTreeMap<u64, Stamp>,
+}
+
+impl HeadConsistency {
+    pub fn new(window_ms: Option<u64>) -> Self {
+        Self {
+            window_ms,
+            ..Self::default()
+        }
+    }
+
+    pub fn is_audited(&self) -> bool {
+        self.window_ms.is_some()
+    }
+
+    /// These counts with only the poll lags recorded since the previous call, which are
+    /// moved out; the heads still waiting for the other source stay here
+    pub(crate) fn take_update(&mut self) -> Self {
+        Self {
+            window_ms: self.window_ms,
+            pushed_heads: self.pushed_heads,
+            polled_heads: self.polled_heads,
+            pushed_only: self.pushed_only,
+            polled_only: self.polled_only,
+            poll_lags: std::mem::take(&mut self.poll_lags),
+            recent: self.recent.clone(),
+            pending: PendingHeads::default(),
+        }
+    }
+
+    /// Note that `newHeads` announced `block_number` at `at`
+    pub(crate) fn record_pushed(&mut self, block_number: u64, at: Stamp) {
+        if !self.is_audited() || self.pending.pushed.contains_key(&block_number) {
+            return;
+        }
+        self.pushed_heads += 1;
+        let pending = &mut self.pending;
+        pending.first_pushed.get_or_insert(block_number);
+        pending.pushed.insert(block_number, at);
+        pending.unpushed.remove(&block_number);
+        if pending.polled_height.is_some_and(|height| height >= block_number) {
+            // Polling got there first; the lag is from the first poll that did, unless that
+            // was more than a window ago
+            let first_poll = pending.polls.iter().find(|(height, _)| *height >= block_number);
+            if let Some((_, polled)) = first_poll.filter(|_| pending.dropped_height.is_none_or(|height| height < block_number)) {
+                self.poll_lags.record(polled.ms_since(&at));
+            }
+        } else {
+            pending.unpolled.insert(block_number, at);
+        }
+    }
+
+    /// Note that a poll sent at `at` returned `height` as the head
+    pub(crate) fn record_polled(&mut self, height: u64, at: Stamp) {
+        if !self.is_audited() || self.pending.polled_height.is_some_and(|known| height <= known) {
+            return;
+        }
+        let pending = &mut self.pending;
+        pending.polled_height = Some(height);
+        pending.polls.push_back((height, at));
+        let reflected: Vec<u64> = pending.unpolled.range(..=height).map(|(number, _)| *number).collect();
+        for number in reflected {
+            if let Some(pushed) = pending.unpolled.remove(&number) {
+                self.poll_lags.record(at.ms_since(&pushed));
+            }
+        }
+        // Heads from before the subscription's first push can't be expected from it
+        if pending.first_pushed.is_some_and(|first| height >= first) {
+            self.polled_heads += 1;
+            if !pending.pushed.contains_key(&height) {
+                pending.unpushed.insert(height, at);
+            }
+        }
+    }
+
+    /// Count the heads still unmatched a window after they were seen as of `now`,
+    /// returning them
+    pub(crate) fn expire(&mut self, now: Stamp) -> Vec<HeadMismatch> {
+        let Some(window_ms) = self.window_ms else {
+            return Vec::new();
+        };
+        let window_ms = window_ms as i64;
+        let pending = &mut self.pending;
+        let mut mismatches = Vec::new();
+        for (seen_by, heads) in [(HeadSource::NewHeads, &mut pending.unpolled), (HeadSource::Polling, &mut pending.unpushed)] {
+            let expired: Vec<u64> = heads
+                .iter()
+                .filter(|(_, seen)| now.ms_since(seen) > window_ms)
+                .map(|(number, _)| *number)
+                .collect();
+            for number in expired {
+                if let Some(seen) = heads.remove(&number) {
+                    mismatches.push(HeadMismatch {
+                        block_number: number,
+                        seen_by,
+                        seen_at_ms: seen.wall.timestamp_millis(),
+                    });
+                }
+            }
+        }
+        pending.pushed.retain(|_, pushed| now.ms_since(pushed) <= window_ms);
+        // The latest poll stays, since it still tells which heads polling has reflected
+        while pending.polls.len() > 1 && pending.polls.front().is_some_and(|(_, polled)| now.ms_since(polled) > window_ms) {
+            pending.dropped_height = pending.polls.pop_front().map(|(height, _)| height);
+        }
+
+        for mismatch in &mismatches {
+            match mismatch.seen_by {
+                HeadSource::NewHeads => self.pushed_only += 1,
+                HeadSource::Polling => self.polled_only += 1,
+            }
+            if self.recent.len() >= MAX_RECENT_MISMATCHES {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(mismatch.clone());
+        }
+        mismatches
+    }
+
+    /// Forget the heads waiting for a match, when the subscription drops and pushes stop
+    pub(crate) fn reset(&mut self) {
+        self.pending = PendingHeads::default();
+    }
+
+    /// How long after a head was pushed polling reflected it
+    pub fn poll_lag_summary(&self) -> Option<GapSummary> {
+        GapSummary::of(&self.poll_lags)
+    }
+
+    /// The audit's columns in `SUMMARY_HEADERS` order, empty for chains that aren't audited
+    pub(crate) fn record_columns(&self) -> Vec<String> {
+        if !self.is_audited() {
+            return vec![String::new(); 10];
+        }
+        let mut record: Vec<String> = [self.pushed_heads, self.polled_heads, self.pushed_only, self.polled_only]
+            .iter()
+            .map(|count| count.to_string())
+            .collect();
+        record.extend(GapSummary::record(self.poll_lag_summary()));
+        record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Utc};
+
+    fn at(ms: i64) -> Stamp {
+        Stamp::wall(DateTime::<Utc>::from_timestamp_millis(1_700_000_000_000 + ms).unwrap())
+    }
+
+    #[test]
+    fn heads_seen_by_both_record_the_poll_lag() {
+        let mut audit = HeadConsistency::new(Some(1000));
+        audit.record_pushed(100, at(0));
+        audit.record_polled(100, at(150));
+        // Polling saw 101 before it was pushed, and skipped 102 for 103
+        audit.record_polled(101, at(2000));
+        audit.record_pushed(101, at(2040));
+        audit.record_pushed(102, at(4000));
+        assert!(audit.expire(at(4200)).is_empty());
+        audit.record_pushed(103, at(4300));
+        audit.record_polled(103, at(4400));
+        assert!(audit.expire(at(9000)).is_empty());
+        assert_eq!((audit.pushed_heads, audit.polled_heads), (4, 3));
+        assert_eq!((audit.pushed_only, audit.polled_only), (0, 0));
+        let lag = audit.poll_lag_summary().unwrap();
+        assert_eq!((lag.min_ms, lag.max_ms), (-40, 400));
+    }
+
+    #[test]
+    fn heads_only_one_source_reported_are_mismatches() {
+        let mut audit = HeadConsistency::new(Some(1000));
+        audit.record_pushed(100, at(0));
+        audit.record_polled(100, at(100));
+        // Polling stalls at 100 while 101 is pushed, then returns 102, which is never pushed
+        audit.record_pushed(101, at(2000));
+        assert!(audit.expire(at(2500)).is_empty());
+        let mismatches = audit.expire(at(3100));
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!((mismatches[0].block_number, mismatches[0].seen_by), (101, HeadSource::NewHeads));
+        audit.record_polled(102, at(4000));
+        let mismatches = audit.expire(at(5100));
+        assert_eq!((mismatches[0].block_number, mismatches[0].seen_by), (102, HeadSource::Polling));
+        assert_eq!((audit.pushed_only, audit.polled_only), (1, 1));
+        assert_eq!(audit.recent.len(), 2);
+    }
+
+    #[test]
+    fn polls_before_the_first_push_are_not_expected_from_it() {
+        let mut audit = HeadConsistency::new(Some(1000));
+        audit.record_polled(99, at(0));
+        audit.record_pushed(100, at(500));
+        audit.record_polled(100, at(600));
+        assert!(audit.expire(at(5000)).is_empty());
+        audit.reset();
+        audit.record_polled(105, at(6000));
+        assert!(audit.expire(at(9000)).is_empty());
+        assert_eq!(audit.polled_heads, 1);
+    }
+}
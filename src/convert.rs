@@ -0,0 +1,264 @@
+use anyhow::{anyhow, Result};
+use clap::Args;
+use log::info;
+use rusqlite::types::ValueRef;
+use rusqlite::Connection;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::dataset::{self, DataFormat};
+
+/// Arguments for the `convert` subcommand
+#[derive(Debug, Args)]
+pub struct ConvertArgs {
+    /// Existing dataset: a run directory, NDJSON file, SQLite database, Parquet file or
+    /// DuckDB database
+    pub input: PathBuf,
+
+    /// Destination: a directory for CSV, otherwise a file
+    pub output: PathBuf,
+
+    /// Format of the input; detected from the path when omitted
+    #[arg(long, value_enum)]
+    pub from: Option<DataFormat>,
+
+    /// Format of the output; detected from the extension when omitted (no extension means CSV)
+    #[arg(long, value_enum)]
+    pub to: Option<DataFormat>,
+}
+
+/// Older column spellings and the names they were upgraded to, per table
+const COLUMN_UPGRADES: &[(&str, &str, &str)] = &[
+    ("stats", "timestamp_past_blocks", "past_timestamp_blocks"),
+    ("stats", "timestamp_future_blocks", "future_timestamp_blocks"),
+    ("stats", "avg_time_delta_ms", "avg_delta_ms"),
+    ("detailed", "block_timestamp", "block_timestamp_s"),
+    ("detailed", "receipt_time", "receipt_time_ms"),
+];
+
+/// Transcode a dataset between formats, upgrading old column names on the way
+pub fn run(args: &ConvertArgs) -> Result<()> {
+    let from = match args.from {
+        Some(format) => format,
+        None => DataFormat::detect(&args.input)?,
+    };
+    let to = match args.to {
+        Some(format) => format,
+        None => output_format(&args.output),
+    };
+
+    let conn = load(&args.input, from)?;
+    upgrade(&conn)?;
+
+    let tables = dataset::table_names(&conn)?;
+    if tables.is_empty() {
+        return Err(anyhow!("No data found in {}", args.input.display()));
+    }
+
+    match to {
+        DataFormat::Csv => write_csv(&conn, &tables, &args.output)?,
+        DataFormat::Ndjson => write_ndjson(&conn, &tables, &args.output)?,
+        DataFormat::Sqlite => write_sqlite(&conn, &args.output)?,
+        DataFormat::Parquet | DataFormat::Duckdb => {
+            return Err(anyhow!("Converting to {:?} isn't supported; convert to csv, ndjson or sqlite", to));
+        }
+    }
+
+    info!(
+        "Converted {} ({:?}) to {} ({:?}): {}",
+        args.input.display(),
+        from,
+        args.output.display(),
+        to,
+        tables.join(", ")
+    );
+    Ok(())
+}
+
+fn output_format(path: &Path) -> DataFormat {
+    if path.extension().is_none() {
+        DataFormat::Csv
+    } else {
+        DataFormat::detect(path).unwrap_or(DataFormat::Csv)
+    }
+}
+
+// Load into a writable in-memory database so upgrades never touch the input
+fn load(path: &Path, format: DataFormat) -> Result<Connection> {
+    if format != DataFormat::Sqlite {
+        return dataset::open(path, format);
+    }
+
+    let conn = Connection::open_in_memory()?;
+    conn.execute(
+        "ATTACH DATABASE ?1 AS source",
+        [path.to_string_lossy().as_ref()],
+    )?;
+    let source_tables: Vec<String> = {
+        let mut stmt = conn.prepare(
+            "SELECT name FROM source.sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'",
+        )?;
+        let names = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        names
+    };
+    for table in source_tables {
+        conn.execute(
+            &format!("CREATE TABLE \"{0}\" AS SELECT * FROM source.\"{0}\"", table),
+            [],
+        )?;
+    }
+    conn.execute("DETACH DATABASE source", [])?;
+    Ok(conn)
+}
+
+fn columns_of(conn: &Connection, table: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!("SELECT name FROM pragma_table_info('{}')", table))?;
+    let columns = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    Ok(columns)
+}
+
+fn upgrade(conn: &Connection) -> Result<()> {
+    let tables = dataset::table_names(conn)?;
+    for (table, old, new) in COLUMN_UPGRADES {
+        if !tables.iter().any(|t| t == table) {
+            continue;
+        }
+        let columns = columns_of(conn, table)?;
+        if columns.iter().any(|c| c == old) && !columns.iter().any(|c| c == new) {
+            conn.execute(
+                &format!("ALTER TABLE \"{}\" RENAME COLUMN \"{}\" TO \"{}\"", table, old, new),
+                [],
+            )?;
+            info!("Upgraded column {}.{} to {}", table, old, new);
+        }
+    }
+    Ok(())
+}
+
+fn value_to_string(value: ValueRef<'_>) -> String {
+    match value {
+        ValueRef::Null => String::new(),
+        ValueRef::Integer(i) => i.to_string(),
+        ValueRef::Real(f) => f.to_string(),
+        ValueRef::Text(t) => String::from_utf8_lossy(t).into_owned(),
+        ValueRef::Blob(b) => String::from_utf8_lossy(b).into_owned(),
+    }
+}
+
+fn value_to_json(value: ValueRef<'_>) -> serde_json::Value {
+    match value {
+        ValueRef::Null => serde_json::Value::Null,
+        ValueRef::Integer(i) => i.into(),
+        ValueRef::Real(f) => f.into(),
+        ValueRef::Text(t) => String::from_utf8_lossy(t).into_owned().into(),
+        ValueRef::Blob(b) => String::from_utf8_lossy(b).into_owned().into(),
+    }
+}
+
+// Write one `{Chain}_{table}.csv` per chain and table, using the logger's own headers
+fn write_csv(conn: &Connection, tables: &[String], dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+
+    for table in tables {
+        let available = columns_of(conn, table)?;
+        let layout = dataset::CSV_LAYOUTS
+            .iter()
+            .find(|(name, _)| name == table)
+            .map(|(_, columns)| *columns)
+            .unwrap_or(&[]);
+
+        // Known columns first in their usual order, then anything else except the chain
+        let mut columns: Vec<(String, String)> = layout
+            .iter()
+            .map(|header| (dataset::column_name(header), header.to_string()))
+            .filter(|(column, _)| available.contains(column))
+            .collect();
+        for column in &available {
+            if column != "chain" && !columns.iter().any(|(c, _)| c == column) {
+                columns.push((column.clone(), column.clone()));
+            }
+        }
+
+        let select = columns
+            .iter()
+            .map(|(c, _)| format!("\"{}\"", c))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let chains: Vec<String> = {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT DISTINCT chain FROM \"{}\" WHERE chain IS NOT NULL ORDER BY chain",
+                table
+            ))?;
+            let chains = stmt
+                .query_map([], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+            chains
+        };
+
+        for chain in chains {
+            let file_path = dir.join(format!("{}_{}.csv", chain, table));
+            let file = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&file_path)?;
+            let mut wtr = csv::Writer::from_writer(file);
+            wtr.write_record(columns.iter().map(|(_, header)| header))?;
+
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM \"{}\" WHERE chain = ?1 ORDER BY rowid",
+                select, table
+            ))?;
+            let mut rows = stmt.query([&chain])?;
+            while let Some(row) = rows.next()? {
+                let mut record = Vec::with_capacity(columns.len());
+                for i in 0..columns.len() {
+                    record.push(value_to_string(row.get_ref(i)?));
+                }
+                wtr.write_record(&record)?;
+            }
+            wtr.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_ndjson(conn: &Connection, tables: &[String], path: &Path) -> Result<()> {
+    if path.exists() {
+        return Err(anyhow!("{} already exists", path.display()));
+    }
+    let mut out = BufWriter::new(File::create(path)?);
+
+    for table in tables {
+        let columns = columns_of(conn, table)?;
+        let mut stmt = conn.prepare(&format!("SELECT * FROM \"{}\" ORDER BY rowid", table))?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let mut object = serde_json::Map::new();
+            object.insert("table".to_string(), table.clone().into());
+            for (i, column) in columns.iter().enumerate() {
+                object.insert(column.clone(), value_to_json(row.get_ref(i)?));
+            }
+            serde_json::to_writer(&mut out, &object)?;
+            out.write_all(b"\n")?;
+        }
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+fn write_sqlite(conn: &Connection, path: &Path) -> Result<()> {
+    if path.exists() {
+        return Err(anyhow!("{} already exists", path.display()));
+    }
+    conn.execute("VACUUM INTO ?1", [path.to_string_lossy().as_ref()])?;
+    Ok(())
+}
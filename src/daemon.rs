@@ -0,0 +1,139 @@
+use anyhow::{anyhow, Result};
+use ::notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use log::warn;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use tokio::time::{self, Instant};
+
+/// How long a burst of file events has to settle before the file is read again; editors
+/// often write a file in several steps
+const SETTLE_DELAY: Duration = Duration::from_millis(250);
+
+/// Watches the chains file for changes while the logger runs as a daemon. The watch ends
+/// when this is dropped.
+pub struct ConfigWatcher {
+    path: PathBuf,
+    _watcher: RecommendedWatcher,
+    changes: UnboundedReceiver<()>,
+    // When the latest burst of events counts as settled; kept here so `changed` can be
+    // cancelled without losing a change
+    settles_at: Option<Instant>,
+}
+
+impl ConfigWatcher {
+    /// Watch `path`. Its directory is what is actually watched, so a file that an editor
+    /// replaces rather than rewrites keeps being followed.
+    pub fn start(path: &Path) -> Result<Self> {
+        let path = std::path::absolute(path)?;
+        let dir = path
+            .parent()
+            .ok_or_else(|| anyhow!("{} has no parent directory to watch", path.display()))?
+            .to_path_buf();
+        let (sender, changes) = mpsc::unbounded_channel();
+        let watched = path.clone();
+        let mut watcher = ::notify::recommended_watcher(move |event: ::notify::Result<Event>| match event {
+            Ok(event) if touches(&event, &watched) && !event.kind.is_access() => {
+                let _ = sender.send(());
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Config watch error: {}", e),
+        })?;
+        watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+        Ok(Self {
+            path,
+            _watcher: watcher,
+            changes,
+            settles_at: None,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Wait until the file has changed and the writes have settled. Cancel-safe, so it can
+    /// be used in `select!`.
+    pub async fn changed(&mut self) -> Option<()> {
+        loop {
+            match self.settles_at {
+                None => {
+                    self.changes.recv().await?;
+                    self.settles_at = Some(Instant::now() + SETTLE_DELAY);
+                }
+                Some(settles_at) => {
+                    tokio::select! {
+                        event = self.changes.recv() => {
+                            event?;
+                            self.settles_at = Some(Instant::now() + SETTLE_DELAY);
+                        }
+                        _ = time::sleep_until(settles_at) => {
+                            self.settles_at = None;
+                            return Some(());
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn touches(event: &Event, path: &Path) -> bool {
+    event.paths.iter().any(|changed| changed == path)
+}
+
+/// Resolves on Ctrl-C or, on Unix, SIGTERM, so a run (a daemon's included) can be stopped
+/// early, by hand or by its service manager, and still write its final report
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(e) => warn!("Can't listen for SIGTERM: {}", e),
+        }
+    }
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        warn!("Can't listen for Ctrl-C: {}", e);
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Listens for SIGUSR1, which asks for a report right away instead of at the next report
+/// tick, e.g. while debugging a live incident. Never fires where there are no Unix signals.
+pub struct ReportSignal {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl ReportSignal {
+    pub fn listen() -> Self {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            let signal = signal(SignalKind::user_defined1())
+                .map_err(|e| warn!("Can't listen for SIGUSR1: {}", e))
+                .ok();
+            Self { signal }
+        }
+        #[cfg(not(unix))]
+        Self {}
+    }
+
+    /// Resolves when the next SIGUSR1 arrives
+    pub async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.signal {
+            if signal.recv().await.is_some() {
+                return;
+            }
+        }
+        std::future::pending::<()>().await
+    }
+}
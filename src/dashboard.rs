@@ -0,0 +1,133 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph, Sparkline};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::HashMap;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+use crate::stats::ChainStats;
+
+/// Width of each panel's counter column
+const COUNTERS_WIDTH: u16 = 46;
+
+/// Live terminal dashboard with one panel per chain, shown instead of the periodic log
+/// report. The terminal is restored when it is dropped.
+pub struct Dashboard {
+    terminal: DefaultTerminal,
+    started: DateTime<Utc>,
+}
+
+impl Dashboard {
+    /// Take over the terminal. The receiver yields once the user presses `q`, `Esc` or
+    /// Ctrl-C, since raw mode keeps Ctrl-C from interrupting the process.
+    pub fn start() -> Result<(Self, UnboundedReceiver<()>)> {
+        let terminal = ratatui::try_init()?;
+        let (sender, receiver) = mpsc::unbounded_channel();
+        // Reading keys blocks, so it gets its own thread; it ends with the process
+        std::thread::spawn(move || {
+            while let Ok(event) = event::read() {
+                if let Event::Key(key) = event {
+                    let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                    if key.kind == KeyEventKind::Press && (ctrl_c || matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)) {
+                        let _ = sender.send(());
+                        break;
+                    }
+                }
+            }
+        });
+        Ok((Self { terminal, started: Utc::now() }, receiver))
+    }
+
+    /// Redraw the panels from the latest stats of each chain
+    pub fn draw(&mut self, chain_stats: &HashMap<String, ChainStats>) -> Result<()> {
+        let started = self.started;
+        self.terminal.draw(|frame| render(frame, chain_stats, started))?;
+        Ok(())
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        ratatui::restore();
+    }
+}
+
+fn render(frame: &mut Frame, chain_stats: &HashMap<String, ChainStats>, started: DateTime<Utc>) {
+    let [header, body] = Layout::vertical([Constraint::Length(1), Constraint::Fill(1)]).areas(frame.area());
+    let elapsed = (Utc::now() - started).num_seconds();
+    frame.render_widget(
+        Line::from(format!(
+            " Block Timestamp Logger | running {}h{:02}m{:02}s | q to quit",
+            elapsed / 3600,
+            elapsed / 60 % 60,
+            elapsed % 60
+        )),
+        header,
+    );
+
+    if chain_stats.is_empty() {
+        frame.render_widget(Paragraph::new(" Waiting for the first blocks..."), body);
+        return;
+    }
+
+    let mut names: Vec<&String> = chain_stats.keys().collect();
+    names.sort();
+    let panels = Layout::vertical(names.iter().map(|_| Constraint::Fill(1))).split(body);
+    for (name, area) in names.iter().zip(panels.iter()) {
+        render_chain(frame, &chain_stats[*name], *area);
+    }
+}
+
+fn render_chain(frame: &mut Frame, stats: &ChainStats, area: Rect) {
+    let (title, border) = if stats.degraded {
+        (format!(" {} [degraded] ", stats.chain_name), Style::default().fg(Color::Red))
+    } else if stats.paused {
+        (format!(" {} [paused] ", stats.chain_name), Style::default().fg(Color::DarkGray))
+    } else {
+        (format!(" {} ", stats.chain_name), Style::default())
+    };
+    let block = Block::bordered().title(title).border_style(border);
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let [counters, chart] = Layout::horizontal([Constraint::Length(COUNTERS_WIDTH), Constraint::Fill(1)]).areas(inner);
+    let head = stats
+        .previous
+        .as_ref()
+        .map_or("-".to_string(), |block| format!("#{}", block.block_number));
+    let p99 = stats.delta_percentiles()[2];
+    let lines = vec![
+        Line::from(format!("Head: {} (backlog {}, {:+.2})", head, stats.backlog_depth, stats.backlog_trend())),
+        Line::from(format!("Blocks: {} | last delta {}ms", stats.total_blocks, stats.last_delta_ms)),
+        Line::from(format!("Avg delta: {}ms | p99 {}ms", stats.avg_time_delta_ms, p99)),
+        Line::from(format!("Past: {} (max {}ms)", stats.timestamp_past_blocks, stats.max_past_delta_ms)),
+        Line::from(format!("Future: {} (max {}ms)", stats.timestamp_future_blocks, stats.max_future_delta_ms)),
+        Line::from(format!("Exact: {}", stats.timestamp_exact_blocks)),
+        Line::from(format!(
+            "RPC errors: {} | retries {} | reorgs {}",
+            stats.rpc_errors, stats.rpc_retries, stats.reorgs
+        )),
+    ];
+    frame.render_widget(Paragraph::new(lines), counters);
+
+    // Latest deltas as magnitudes, one per column, in yellow while the newest is a future timestamp
+    let width = chart.width.saturating_sub(2) as usize;
+    let recent: Vec<i64> = stats
+        .raw_samples
+        .iter()
+        .rev()
+        .take(width)
+        .rev()
+        .map(|sample| sample.delta_ms)
+        .collect();
+    let future_last = recent.last().is_some_and(|delta| *delta <= 0);
+    let sparkline = Sparkline::default()
+        .block(Block::bordered().title(" |delta| (ms) "))
+        .data(recent.iter().map(|delta| delta.unsigned_abs()).collect::<Vec<u64>>())
+        .style(Style::default().fg(if future_last { Color::Yellow } else { Color::Cyan }));
+    frame.render_widget(sparkline, chart);
+}
@@ -0,0 +1,518 @@
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use rusqlite::types::Value;
+use rusqlite::Connection;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use crate::combined::COMBINED_STATS_CSV;
+use crate::stats::{
+    DELTAS_HEADERS, DETAILED_HEADERS, GAPS_HEADERS, HEAD_LAG_HEADERS, HISTOGRAM_HEADERS, HOURLY_HEADERS,
+    OUTAGES_HEADERS, PRECONF_HEADERS, SUMMARY_HEADERS,
+};
+use crate::timeseries::TIMESERIES_HEADERS;
+
+/// The `{Chain}_{kind}.csv` files the logger writes, with their CSV headers in column order;
+/// each kind loads into a table of the same name, its columns the headers as `column_name`
+/// turns them into columns
+pub const CSV_LAYOUTS: &[(&str, &[&str])] = &[
+    ("stats", SUMMARY_HEADERS),
+    ("detailed", DETAILED_HEADERS),
+    ("deltas", DELTAS_HEADERS),
+    ("head_lag", HEAD_LAG_HEADERS),
+    ("preconf", PRECONF_HEADERS),
+    ("gaps", GAPS_HEADERS),
+    ("outages", OUTAGES_HEADERS),
+    ("histogram", HISTOGRAM_HEADERS),
+    ("hourly", HOURLY_HEADERS),
+    ("timeseries", TIMESERIES_HEADERS),
+];
+
+/// Turn a CSV header such as "Block Timestamp (s)" into "block_timestamp_s"
+pub fn column_name(header: &str) -> String {
+    let mut name = String::new();
+    for c in header.chars() {
+        if c.is_ascii_alphanumeric() {
+            name.push(c.to_ascii_lowercase());
+        } else if !name.ends_with('_') {
+            name.push('_');
+        }
+    }
+    name.trim_matches('_').to_string()
+}
+
+/// Tracks the tables (and their columns) created while loading a dataset into SQLite
+#[derive(Default)]
+pub struct TableSet {
+    columns: HashMap<String, Vec<String>>,
+}
+
+impl TableSet {
+    /// Create `table` if needed and add any of `columns` it doesn't have yet.
+    /// Every table starts with a `chain` column.
+    pub fn ensure(&mut self, conn: &Connection, table: &str, columns: &[String]) -> Result<()> {
+        let existing = self.columns.entry(table.to_string()).or_default();
+        if existing.is_empty() {
+            conn.execute(&format!("CREATE TABLE \"{}\" (chain TEXT)", table), [])?;
+            existing.push("chain".to_string());
+        }
+        for column in columns {
+            if !existing.contains(column) {
+                // NUMERIC affinity stores numeric-looking text as numbers
+                conn.execute(
+                    &format!("ALTER TABLE \"{}\" ADD COLUMN \"{}\" NUMERIC", table, column),
+                    [],
+                )?;
+                existing.push(column.clone());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn insert_statement(table: &str, columns: &[String]) -> String {
+    let column_list = columns
+        .iter()
+        .map(|c| format!("\"{}\"", c))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let placeholders = vec!["?"; columns.len()].join(", ");
+    format!("INSERT INTO \"{}\" ({}) VALUES ({})", table, column_list, placeholders)
+}
+
+// The chain a `{Chain}_{kind}` file stem names and the table its rows go to. Chain names
+// may contain underscores, so the stem is matched against the kinds the logger writes
+// first, falling back to the last underscore for any other kind.
+fn file_table(stem: &str) -> Option<(&str, String)> {
+    let stem = strip_rotation(stem);
+    // The daily logs, `observations_{date}`, all go to `observations`
+    if let Some(date) = stem.strip_prefix("observations_") {
+        if date.starts_with(|c: char| c.is_ascii_digit()) {
+            return Some(("observations", "observations".to_string()));
+        }
+    }
+    let known = CSV_LAYOUTS.iter().find_map(|(kind, _)| {
+        let chain = stem.strip_suffix(kind)?.strip_suffix('_')?;
+        (!chain.is_empty()).then(|| (chain, kind.to_string()))
+    });
+    if known.is_some() {
+        return known;
+    }
+    let (chain, kind) = stem.rsplit_once('_')?;
+    (!chain.is_empty() && !kind.is_empty()).then(|| (chain, column_name(kind)))
+}
+
+// A rotated file's stem without its `.{start}` suffix, e.g. `.20250101T120000Z-1`
+fn strip_rotation(stem: &str) -> &str {
+    match stem.rsplit_once('.') {
+        Some((base, start))
+            if start.starts_with(|c: char| c.is_ascii_digit())
+                && start.chars().all(|c| c.is_ascii_digit() || matches!(c, 'T' | 'Z' | '-')) =>
+        {
+            base
+        }
+        _ => stem,
+    }
+}
+
+/// Load every `{Chain}_{kind}.csv` in `dir` into a table named `kind`.
+///
+/// Rotated files (`{Chain}_{kind}.{start}.csv`, gzipped or not) load into the same table,
+/// and the daily CSV observation logs (`observations_{date}.csv`) into `observations`.
+/// The `{Chain}_detailed.parquet` and `{Chain}_deltas.parquet` of `--format parquet` load
+/// into `detailed` and `deltas` as well, when built with the `parquet` feature.
+/// Rows get a `chain` column taken from the file name unless the file has its own.
+pub fn load_csv_dir(conn: &Connection, dir: &Path, tables: &mut TableSet) -> Result<()> {
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .collect();
+    entries.sort();
+
+    for path in entries {
+        let Some(name) = path.file_name().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        // The combined stats only repeat every chain's stats file
+        if name == COMBINED_STATS_CSV {
+            continue;
+        }
+        if let Some((chain, table)) = name.strip_suffix(".parquet").and_then(file_table) {
+            load_parquet(conn, &path, chain, &table, tables)?;
+            continue;
+        }
+        let (stem, gzipped) = match (name.strip_suffix(".csv.gz"), name.strip_suffix(".csv")) {
+            (Some(stem), _) => (stem, true),
+            (None, Some(stem)) => (stem, false),
+            (None, None) => continue,
+        };
+        let Some((chain, table)) = file_table(stem) else {
+            continue;
+        };
+
+        let file = File::open(&path)?;
+        let reader: Box<dyn Read> = if gzipped {
+            Box::new(flate2::read::GzDecoder::new(file))
+        } else {
+            Box::new(file)
+        };
+        let mut rdr = csv::Reader::from_reader(reader);
+        let mut columns: Vec<String> = rdr.headers()?.iter().map(column_name).collect();
+
+        // Files such as the stats summary already carry their own chain column
+        let has_chain = columns.iter().any(|c| c == "chain");
+        if !has_chain {
+            columns.insert(0, "chain".to_string());
+        }
+        tables.ensure(conn, &table, &columns)?;
+
+        let tx = conn.unchecked_transaction()?;
+        {
+            let mut stmt = tx.prepare(&insert_statement(&table, &columns))?;
+            for record in rdr.records() {
+                let record = record?;
+                let mut values: Vec<&str> = Vec::with_capacity(columns.len());
+                if !has_chain {
+                    values.push(chain);
+                }
+                values.extend(record.iter());
+                stmt.execute(rusqlite::params_from_iter(values))?;
+            }
+        }
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+/// Load the Parquet file at `path` into `table`, every row getting `chain`
+#[cfg(feature = "parquet")]
+fn load_parquet(conn: &Connection, path: &Path, chain: &str, table: &str, tables: &mut TableSet) -> Result<()> {
+    use arrow_array::RecordBatchReader;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?.build()?;
+    let mut columns = vec!["chain".to_string()];
+    columns.extend(reader.schema().fields().iter().map(|field| column_name(field.name())));
+    tables.ensure(conn, table, &columns)?;
+
+    let tx = conn.unchecked_transaction()?;
+    {
+        let mut stmt = tx.prepare(&insert_statement(table, &columns))?;
+        for batch in reader {
+            let batch = batch?;
+            for row in 0..batch.num_rows() {
+                let mut values = vec![Value::Text(chain.to_string())];
+                for array in batch.columns() {
+                    values.push(arrow_to_sql(array.as_ref(), row)?);
+                }
+                stmt.execute(rusqlite::params_from_iter(values))?;
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+#[cfg(not(feature = "parquet"))]
+fn load_parquet(_conn: &Connection, path: &Path, _chain: &str, _table: &str, _tables: &mut TableSet) -> Result<()> {
+    log::warn!("Skipping {}: reading Parquet needs a build with the `parquet` feature", path.display());
+    Ok(())
+}
+
+/// Load a single `{Chain}_{kind}.parquet` file into the table named `kind`
+#[cfg(feature = "parquet")]
+fn load_parquet_file(conn: &Connection, path: &Path, tables: &mut TableSet) -> Result<()> {
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+    let (chain, table) = file_table(stem).ok_or_else(|| {
+        anyhow!("Can't tell the chain and table of {}; name it {{Chain}}_{{kind}}.parquet", path.display())
+    })?;
+    load_parquet(conn, path, chain, &table, tables)
+}
+
+#[cfg(not(feature = "parquet"))]
+fn load_parquet_file(_conn: &Connection, path: &Path, _tables: &mut TableSet) -> Result<()> {
+    Err(anyhow!("Reading {} needs a build with the `parquet` feature", path.display()))
+}
+
+// One cell of the Parquet columns `columnar::write_samples` writes, as a SQLite value;
+// booleans become integers as in NDJSON
+#[cfg(feature = "parquet")]
+fn arrow_to_sql(array: &dyn arrow_array::Array, row: usize) -> Result<Value> {
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Float64Type, Int64Type, UInt64Type};
+
+    if array.is_null(row) {
+        return Ok(Value::Null);
+    }
+    if let Some(values) = array.as_primitive_opt::<Int64Type>() {
+        return Ok(Value::Integer(values.value(row)));
+    }
+    if let Some(values) = array.as_primitive_opt::<UInt64Type>() {
+        return Ok(Value::Integer(values.value(row) as i64));
+    }
+    if let Some(values) = array.as_primitive_opt::<Float64Type>() {
+        return Ok(Value::Real(values.value(row)));
+    }
+    if let Some(values) = array.as_boolean_opt() {
+        return Ok(Value::Integer(values.value(row) as i64));
+    }
+    if let Some(values) = array.as_string_opt::<i32>() {
+        return Ok(Value::Text(values.value(row).to_string()));
+    }
+    Err(anyhow!("Unsupported Parquet column type {}", array.data_type()))
+}
+
+/// Load every table of a DuckDB database, such as the `observations` of `DUCKDB_PATH`, into
+/// a table of the same name. The database is opened read-only, so a run still appending to
+/// it only has to be between batches.
+#[cfg(feature = "duckdb")]
+fn load_duckdb(conn: &Connection, path: &Path, tables: &mut TableSet) -> Result<()> {
+    use duckdb::{AccessMode, Config};
+
+    let source = duckdb::Connection::open_with_flags(path, Config::default().access_mode(AccessMode::ReadOnly)?)?;
+    let source_tables: Vec<String> = {
+        let mut stmt = source.prepare(
+            "SELECT table_name FROM information_schema.tables WHERE table_schema = 'main' ORDER BY table_name",
+        )?;
+        let names = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<duckdb::Result<Vec<String>>>()?;
+        names
+    };
+    for source_table in source_tables {
+        let source_columns: Vec<String> = {
+            let mut stmt = source.prepare(
+                "SELECT column_name FROM information_schema.columns WHERE table_schema = 'main' AND table_name = ? ORDER BY ordinal_position",
+            )?;
+            let names = stmt
+                .query_map([&source_table], |row| row.get(0))?
+                .collect::<duckdb::Result<Vec<String>>>()?;
+            names
+        };
+        let table = column_name(&source_table);
+        let columns: Vec<String> = source_columns.iter().map(|column| column_name(column)).collect();
+        tables.ensure(conn, &table, &columns)?;
+
+        let select = format!(
+            "SELECT {} FROM \"{}\"",
+            source_columns
+                .iter()
+                .map(|column| format!("\"{}\"", column.replace('"', "\"\"")))
+                .collect::<Vec<_>>()
+                .join(", "),
+            source_table.replace('"', "\"\"")
+        );
+        let tx = conn.unchecked_transaction()?;
+        {
+            let mut insert = tx.prepare(&insert_statement(&table, &columns))?;
+            let mut stmt = source.prepare(&select)?;
+            let mut rows = stmt.query([])?;
+            while let Some(row) = rows.next()? {
+                let values = (0..columns.len())
+                    .map(|i| duckdb_to_sql(row.get_ref(i)?))
+                    .collect::<Result<Vec<Value>>>()?;
+                insert.execute(rusqlite::params_from_iter(values))?;
+            }
+        }
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "duckdb"))]
+fn load_duckdb(_conn: &Connection, path: &Path, _tables: &mut TableSet) -> Result<()> {
+    Err(anyhow!("Reading {} needs a build with the `duckdb` feature", path.display()))
+}
+
+// One DuckDB cell as a SQLite value. Booleans become integers as in NDJSON, and timestamps
+// RFC 3339 text as in the SQLite store.
+#[cfg(feature = "duckdb")]
+fn duckdb_to_sql(value: duckdb::types::ValueRef) -> Result<Value> {
+    use duckdb::types::ValueRef;
+
+    Ok(match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Boolean(b) => Value::Integer(b as i64),
+        ValueRef::TinyInt(i) => Value::Integer(i.into()),
+        ValueRef::SmallInt(i) => Value::Integer(i.into()),
+        ValueRef::Int(i) => Value::Integer(i.into()),
+        ValueRef::BigInt(i) => Value::Integer(i),
+        ValueRef::HugeInt(i) => Value::Integer(i as i64),
+        ValueRef::UTinyInt(i) => Value::Integer(i.into()),
+        ValueRef::USmallInt(i) => Value::Integer(i.into()),
+        ValueRef::UInt(i) => Value::Integer(i.into()),
+        ValueRef::UBigInt(i) => Value::Integer(i as i64),
+        ValueRef::Float(f) => Value::Real(f.into()),
+        ValueRef::Double(f) => Value::Real(f),
+        ValueRef::Decimal(d) => Value::Text(d.to_string()),
+        ValueRef::Text(text) => Value::Text(String::from_utf8_lossy(text).into_owned()),
+        ValueRef::Timestamp(unit, t) => {
+            let at = chrono::DateTime::from_timestamp_micros(unit.to_micros(t))
+                .ok_or_else(|| anyhow!("Invalid DuckDB timestamp {}", t))?;
+            Value::Text(at.to_rfc3339())
+        }
+        other => return Err(anyhow!("Unsupported DuckDB column type {}", other.data_type())),
+    })
+}
+
+/// Load an NDJSON file where each line is an object with a `table` field plus column values
+pub fn load_ndjson(conn: &Connection, path: &Path, tables: &mut TableSet) -> Result<()> {
+    let reader = BufReader::new(File::open(path)?);
+    let tx = conn.unchecked_transaction()?;
+
+    for (line_no, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut object = match serde_json::from_str::<serde_json::Value>(&line)? {
+            serde_json::Value::Object(object) => object,
+            _ => return Err(anyhow!("Line {} is not a JSON object", line_no + 1)),
+        };
+        let table = match object.remove("table") {
+            Some(serde_json::Value::String(table)) => column_name(&table),
+            _ => return Err(anyhow!("Line {} has no \"table\" field", line_no + 1)),
+        };
+
+        let columns: Vec<String> = object.keys().map(|k| column_name(k)).collect();
+        tables.ensure(&tx, &table, &columns)?;
+
+        let values: Vec<Value> = object.into_values().map(json_to_sql).collect();
+        tx.execute(
+            &insert_statement(&table, &columns),
+            rusqlite::params_from_iter(values),
+        )?;
+    }
+
+    tx.commit()?;
+    Ok(())
+}
+
+fn json_to_sql(value: serde_json::Value) -> Value {
+    match value {
+        serde_json::Value::Null => Value::Null,
+        serde_json::Value::Bool(b) => Value::Integer(b as i64),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => Value::Real(n.as_f64().unwrap_or_default()),
+        },
+        serde_json::Value::String(s) => Value::Text(s),
+        other => Value::Text(other.to_string()),
+    }
+}
+
+/// Names of the user tables in a SQLite database
+pub fn table_names(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+    )?;
+    let names = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    Ok(names)
+}
+
+/// On-disk layouts a run's data can be stored in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DataFormat {
+    /// A run directory of `{Chain}_{kind}.csv` files
+    Csv,
+    /// A single file with one JSON object per row
+    Ndjson,
+    /// A SQLite database file
+    Sqlite,
+    /// A single `{Chain}_{kind}.parquet` file of `--format parquet`
+    Parquet,
+    /// A DuckDB database file, such as the one `DUCKDB_PATH` writes
+    Duckdb,
+}
+
+impl DataFormat {
+    /// Guess the format from a path: directories are CSV runs, files go by extension
+    pub fn detect(path: &Path) -> Result<Self> {
+        if path.is_dir() {
+            return Ok(DataFormat::Csv);
+        }
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("ndjson") | Some("jsonl") => Ok(DataFormat::Ndjson),
+            Some("db") | Some("sqlite") | Some("sqlite3") => Ok(DataFormat::Sqlite),
+            Some("parquet") => Ok(DataFormat::Parquet),
+            Some("duckdb") => Ok(DataFormat::Duckdb),
+            _ => Err(anyhow!(
+                "Can't tell the format of {}; pass it explicitly",
+                path.display()
+            )),
+        }
+    }
+}
+
+/// Open a dataset as a SQLite connection, loading the other formats into memory
+pub fn open(path: &Path, format: DataFormat) -> Result<Connection> {
+    if format == DataFormat::Sqlite {
+        return Ok(Connection::open_with_flags(
+            path,
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+        )?);
+    }
+    let conn = Connection::open_in_memory()?;
+    let mut tables = TableSet::default();
+    match format {
+        DataFormat::Csv => load_csv_dir(&conn, path, &mut tables)?,
+        DataFormat::Ndjson => load_ndjson(&conn, path, &mut tables)?,
+        DataFormat::Parquet => load_parquet_file(&conn, path, &mut tables)?,
+        DataFormat::Duckdb => load_duckdb(&conn, path, &mut tables)?,
+        DataFormat::Sqlite => unreachable!(),
+    }
+    Ok(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_table_keeps_underscores_in_chain_names() {
+        assert_eq!(file_table("op_mainnet_detailed"), Some(("op_mainnet", "detailed".to_string())));
+        assert_eq!(file_table("op_mainnet_head_lag"), Some(("op_mainnet", "head_lag".to_string())));
+        assert_eq!(file_table("Base_head_lag"), Some(("Base", "head_lag".to_string())));
+        // Kinds the logger doesn't write split at the last underscore
+        assert_eq!(file_table("op_mainnet_extra"), Some(("op_mainnet", "extra".to_string())));
+        assert_eq!(file_table("detailed"), None);
+    }
+
+    #[test]
+    fn file_table_strips_rotation_suffixes() {
+        assert_eq!(
+            file_table("op_mainnet_deltas.20250101T120000Z"),
+            Some(("op_mainnet", "deltas".to_string()))
+        );
+        assert_eq!(
+            file_table("op_mainnet_detailed.20250101T125930Z-1"),
+            Some(("op_mainnet", "detailed".to_string()))
+        );
+        assert_eq!(file_table("observations_2025-01-01"), Some(("observations", "observations".to_string())));
+    }
+
+    #[test]
+    fn loads_a_chain_with_underscores_under_its_full_name() {
+        let dir = std::env::temp_dir().join(format!("dataset-underscore-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("op_mainnet_deltas.csv"), "Delta (ms)\n250\n").unwrap();
+        std::fs::write(dir.join("op_mainnet_deltas.20250101T120000Z.csv"), "Delta (ms)\n300\n").unwrap();
+
+        let conn = open(&dir, DataFormat::Csv).unwrap();
+        assert_eq!(table_names(&conn).unwrap(), ["deltas"]);
+        let chains: Vec<(String, i64)> = conn
+            .prepare("SELECT chain, COUNT(*) FROM deltas GROUP BY chain")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(chains, [("op_mainnet".to_string(), 2)]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Which side of the receipt time a block's timestamp falls on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeltaDirection {
+    /// Stamped before the block was received: the usual case
+    Past,
+    /// Stamped at exactly the millisecond it was received
+    Exact,
+    /// Stamped after the block was received, i.e. ahead of the local clock
+    Future,
+}
+
+impl DeltaDirection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeltaDirection::Past => "past",
+            DeltaDirection::Exact => "exact",
+            DeltaDirection::Future => "future",
+        }
+    }
+}
+
+impl fmt::Display for DeltaDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A block's delta, receipt time minus block timestamp, split into its direction and its
+/// magnitude, so no consumer has to remember which sign means what
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimestampDelta {
+    pub direction: DeltaDirection,
+    pub magnitude_ms: u64,
+}
+
+impl TimestampDelta {
+    /// The delta of a signed `delta_ms`: positive in the past, negative in the future
+    pub fn from_ms(delta_ms: i64) -> Self {
+        let direction = match delta_ms {
+            0 => DeltaDirection::Exact,
+            ms if ms > 0 => DeltaDirection::Past,
+            _ => DeltaDirection::Future,
+        };
+        Self {
+            direction,
+            magnitude_ms: delta_ms.unsigned_abs(),
+        }
+    }
+
+    /// Back to the signed form the datasets store
+    pub fn signed_ms(&self) -> i64 {
+        match self.direction {
+            DeltaDirection::Future => -(self.magnitude_ms as i64),
+            _ => self.magnitude_ms as i64,
+        }
+    }
+}
+
+impl fmt::Display for TimestampDelta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.direction {
+            DeltaDirection::Exact => f.write_str("0ms"),
+            direction => write!(f, "{}ms {}", self.magnitude_ms, direction),
+        }
+    }
+}
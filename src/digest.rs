@@ -0,0 +1,266 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, NaiveDateTime, NaiveTime, Utc};
+use std::collections::HashMap;
+use std::env;
+
+use crate::timezone::ReportTimezone;
+use crate::get_env_as_u64;
+use crate::histogram::DeltaHistogram;
+use crate::stats::ChainStats;
+
+/// Position in a chain's stats at the time of the previous digest
+#[derive(Debug, Default, Clone)]
+struct DigestMark {
+    deltas: DeltaHistogram,
+    arrival_gaps: DeltaHistogram,
+    anomalies: usize,
+    stale_responses: usize,
+    breaker_trips: usize,
+    rpc_errors: usize,
+}
+
+/// Schedules periodic digests and tracks what each one has already covered
+pub struct DigestSchedule {
+    interval: Duration,
+    timezone: ReportTimezone,
+    // Kept as local wall-clock time so a daily digest stays at the same hour across DST
+    next_local: NaiveDateTime,
+    marks: HashMap<String, DigestMark>,
+}
+
+impl DigestSchedule {
+    /// Build a schedule from `DIGEST_AT` (HH:MM in the report timezone) and
+    /// `DIGEST_INTERVAL_MINUTES`. Returns `None` when no digest time is configured.
+    pub fn from_env(now: DateTime<Utc>, timezone: ReportTimezone) -> Result<Option<Self>> {
+        let at = match env::var("DIGEST_AT") {
+            Ok(val) => NaiveTime::parse_from_str(&val, "%H:%M")
+                .map_err(|e| anyhow!("Invalid DIGEST_AT '{}' (expected HH:MM): {}", val, e))?,
+            Err(_) => return Ok(None),
+        };
+        let interval_minutes = get_env_as_u64("DIGEST_INTERVAL_MINUTES", 24 * 60).max(1);
+
+        Ok(Some(Self {
+            interval: Duration::minutes(interval_minutes as i64),
+            timezone,
+            next_local: Self::first_due(at, timezone.now_local(now)),
+            marks: HashMap::new(),
+        }))
+    }
+
+    // Next local occurrence of `at`, today if it hasn't passed yet, otherwise tomorrow
+    fn first_due(at: NaiveTime, now_local: NaiveDateTime) -> NaiveDateTime {
+        let today = now_local.date().and_time(at);
+        if today > now_local {
+            today
+        } else {
+            today + Duration::days(1)
+        }
+    }
+
+    pub fn next_due(&self) -> DateTime<Utc> {
+        self.timezone.to_utc(self.next_local)
+    }
+
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        now >= self.next_due()
+    }
+
+    /// Summarize everything observed since the previous digest and advance the schedule
+    pub fn build(&mut self, chain_stats: &HashMap<String, ChainStats>, now: DateTime<Utc>) -> String {
+        let mut names: Vec<&String> = chain_stats.keys().collect();
+        names.sort();
+
+        let mut lines = Vec::new();
+        for name in names {
+            let stats = &chain_stats[name];
+            let mark = self.marks.get(name).cloned().unwrap_or_default();
+            lines.push(Self::summarize(stats, &mark));
+            self.marks.insert(
+                name.clone(),
+                DigestMark {
+                    deltas: stats.delta_histogram.clone(),
+                    arrival_gaps: stats.arrival_gaps.clone(),
+                    anomalies: stats.anomalies.anomalies,
+                    stale_responses: stats.stale_responses,
+                    breaker_trips: stats.breaker_trips,
+                    rpc_errors: stats.rpc_errors,
+                },
+            );
+        }
+
+        while self.next_due() <= now {
+            self.next_local += self.interval;
+        }
+
+        if lines.is_empty() {
+            "No chains reported any data".to_string()
+        } else {
+            lines.join("\n")
+        }
+    }
+
+    fn summarize(stats: &ChainStats, mark: &DigestMark) -> String {
+        let deltas = stats.delta_histogram.since(&mark.deltas);
+        // Counters cover the period since the previous digest; the breaker's state is current
+        let mut endpoint = format!(
+            "Stale responses: {} | Breaker trips: {} | RPC errors: {}",
+            stats.stale_responses.saturating_sub(mark.stale_responses),
+            stats.breaker_trips.saturating_sub(mark.breaker_trips),
+            stats.rpc_errors.saturating_sub(mark.rpc_errors)
+        );
+        if stats.degraded {
+            endpoint.push_str(" | Degraded: breaker open");
+        }
+
+        // Longest wait between consecutive blocks within the period, or the outage still
+        // going on if it is longer
+        let arrival_gaps = stats.arrival_gaps.since(&mark.arrival_gaps);
+        let longest_gap_ms = if arrival_gaps.is_empty() { 0 } else { arrival_gaps.max_ms() };
+        let ongoing_ms = stats.outages.ongoing().map_or(0, |outage| outage.duration_ms);
+        let longest_stall_ms = longest_gap_ms.max(ongoing_ms);
+
+        if deltas.is_empty() {
+            if ongoing_ms > 0 {
+                return format!("{}: no blocks seen | Stalled for: {}ms | {}", stats.chain_name, ongoing_ms, endpoint);
+            }
+            return format!("{}: no blocks seen | {}", stats.chain_name, endpoint);
+        }
+
+        format!(
+            "{}: {} blocks | p95 delta: {}ms | Future timestamps: {} | Anomalies: {} | Longest stall: {}ms | {}",
+            stats.chain_name,
+            deltas.len(),
+            deltas.percentile(95.0),
+            deltas.future_count(),
+            stats.anomalies.anomalies.saturating_sub(mark.anomalies),
+            longest_stall_ms,
+            endpoint
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapter::BlockHeader;
+    use crate::clock::Stamp;
+    use crate::outage::Outages;
+
+    // 2025-03-28T06:00:00Z
+    const START_MS: i64 = 1_743_141_600_000;
+
+    fn schedule(at: &str, timezone: &str, now: DateTime<Utc>) -> DigestSchedule {
+        let timezone = ReportTimezone::parse(timezone).unwrap();
+        DigestSchedule {
+            interval: Duration::days(1),
+            timezone,
+            next_local: DigestSchedule::first_due(NaiveTime::parse_from_str(at, "%H:%M").unwrap(), timezone.now_local(now)),
+            marks: HashMap::new(),
+        }
+    }
+
+    // Record a block stamped `delta_ms` before it was received at `START_MS + received_ms`
+    fn record(stats: &mut ChainStats, block_number: u64, received_ms: i64, delta_ms: i64) {
+        let received = START_MS + received_ms;
+        let header = BlockHeader {
+            timestamp_ms: (received - delta_ms) as u64,
+            hash: None,
+            parent_hash: None,
+            gas_used: None,
+            tx_count: None,
+        };
+        let stamp = Stamp::wall(DateTime::from_timestamp_millis(received).unwrap());
+        stats.update(block_number, &header, stamp, None, None, false);
+    }
+
+    #[test]
+    fn first_digest_is_today_unless_the_time_has_passed() {
+        let at = NaiveTime::from_hms_opt(9, 0, 0).unwrap();
+        let day = chrono::NaiveDate::from_ymd_opt(2025, 3, 28).unwrap();
+        assert_eq!(DigestSchedule::first_due(at, day.and_hms_opt(8, 30, 0).unwrap()), day.and_time(at));
+        assert_eq!(
+            DigestSchedule::first_due(at, day.and_hms_opt(9, 0, 0).unwrap()),
+            day.succ_opt().unwrap().and_time(at)
+        );
+    }
+
+    #[test]
+    fn daily_digest_keeps_its_local_hour_across_dst() {
+        let now = DateTime::from_timestamp_millis(START_MS).unwrap();
+        let mut digest = schedule("09:00", "Europe/Berlin", now);
+        // 09:00 CET
+        assert_eq!(digest.next_due().to_rfc3339(), "2025-03-28T08:00:00+00:00");
+        assert!(!digest.is_due(now));
+
+        digest.build(&HashMap::new(), digest.next_due());
+        digest.build(&HashMap::new(), digest.next_due());
+        // Clocks went forward on the 30th: 09:00 CEST
+        assert_eq!(digest.next_due().to_rfc3339(), "2025-03-30T07:00:00+00:00");
+    }
+
+    #[test]
+    fn each_digest_covers_only_what_came_since_the_last() {
+        let now = DateTime::from_timestamp_millis(START_MS).unwrap();
+        let mut digest = schedule("09:00", "UTC", now);
+        assert_eq!(digest.build(&HashMap::new(), now), "No chains reported any data");
+
+        let mut stats = ChainStats::new("Base");
+        record(&mut stats, 1, 0, 200);
+        record(&mut stats, 2, 2_000, 400);
+        record(&mut stats, 3, 5_000, -100);
+        stats.anomalies.anomalies = 1;
+        stats.stale_responses = 3;
+        let mut chains = HashMap::from([("Base".to_string(), stats)]);
+        // Stalls come from the arrival gap histogram, precise to 0.1% above 2048ms
+        assert_eq!(
+            digest.build(&chains, now),
+            "Base: 3 blocks | p95 delta: 400ms | Future timestamps: 1 | Anomalies: 1 | Longest stall: 3001ms \
+             | Stale responses: 3 | Breaker trips: 0 | RPC errors: 0"
+        );
+
+        let stats = chains.get_mut("Base").unwrap();
+        record(stats, 4, 6_000, 300);
+        record(stats, 5, 10_000, 300);
+        stats.rpc_errors += 2;
+        stats.breaker_trips += 1;
+        stats.degraded = true;
+        assert_eq!(
+            digest.build(&chains, now),
+            "Base: 2 blocks | p95 delta: 300ms | Future timestamps: 0 | Anomalies: 0 | Longest stall: 4001ms \
+             | Stale responses: 0 | Breaker trips: 1 | RPC errors: 2 | Degraded: breaker open"
+        );
+        chains.get_mut("Base").unwrap().degraded = false;
+        assert_eq!(
+            digest.build(&chains, now),
+            "Base: no blocks seen | Stale responses: 0 | Breaker trips: 0 | RPC errors: 0"
+        );
+    }
+
+    #[test]
+    fn longest_stall_includes_the_ongoing_outage() {
+        let now = DateTime::from_timestamp_millis(START_MS).unwrap();
+        let mut digest = schedule("09:00", "UTC", now);
+        let at = |ms: i64| Stamp::wall(DateTime::from_timestamp_millis(START_MS + ms).unwrap());
+        let mut stats = ChainStats {
+            max_raw_samples: 0,
+            outages: Outages::new(Some(5_000)),
+            ..ChainStats::new("Base")
+        };
+        record(&mut stats, 1, 0, 200);
+        record(&mut stats, 2, 2_000, 200);
+        stats.outages.record_head(2, at(2_000));
+        stats.outages.check(at(9_000), 10);
+        let mut chains = HashMap::from([("Base".to_string(), stats)]);
+        assert_eq!(
+            digest.build(&chains, now),
+            "Base: 2 blocks | p95 delta: 200ms | Future timestamps: 0 | Anomalies: 0 | Longest stall: 7000ms \
+             | Stale responses: 0 | Breaker trips: 0 | RPC errors: 0"
+        );
+
+        chains.get_mut("Base").unwrap().outages.check(at(12_000), 10);
+        assert_eq!(
+            digest.build(&chains, now),
+            "Base: no blocks seen | Stalled for: 10000ms | Stale responses: 0 | Breaker trips: 0 | RPC errors: 0"
+        );
+    }
+}
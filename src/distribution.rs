@@ -0,0 +1,312 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use clap::Args;
+use log::info;
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+
+use crate::analyze::{percentile_label, AnalysisFormat};
+use crate::dataset::DataFormat;
+use crate::delta::{DeltaDirection, TimestampDelta};
+use crate::replay;
+use crate::vantage::ks_distance;
+
+/// Arguments for `analyze compare`
+#[derive(Debug, Args)]
+pub struct CompareChainsArgs {
+    /// Stored observations: a run directory, NDJSON file, SQLite database or DuckDB database
+    #[arg(env = "OUTPUT_DIR", default_value = "./logs")]
+    pub input: PathBuf,
+
+    /// Format of the input; detected from the path when omitted
+    #[arg(long, value_enum)]
+    pub from: Option<DataFormat>,
+
+    /// Chains to compare, comma-separated; every pair of them is compared [default: every
+    /// chain in the input]
+    #[arg(long, value_delimiter = ',')]
+    pub chain: Vec<String>,
+
+    /// Only blocks observed from these vantage points, comma-separated
+    #[arg(long, value_delimiter = ',')]
+    pub vantage: Vec<String>,
+
+    /// Only blocks received at or after this time (RFC 3339)
+    #[arg(long)]
+    pub since: Option<DateTime<Utc>>,
+
+    /// Only blocks received before this time (RFC 3339)
+    #[arg(long)]
+    pub until: Option<DateTime<Utc>>,
+
+    /// Delta percentiles whose differences are reported, comma-separated
+    #[arg(long, value_delimiter = ',', default_value = "10,25,50,75,90,99")]
+    pub percentiles: Vec<f64>,
+
+    /// Significance level of the Kolmogorov-Smirnov test
+    #[arg(long, default_value_t = 0.05)]
+    pub alpha: f64,
+
+    /// Print the comparison as text or as JSON
+    #[arg(long, value_enum, default_value_t = AnalysisFormat::Text)]
+    pub format: AnalysisFormat,
+}
+
+/// One chain's deltas, sorted
+struct ChainDeltas {
+    sorted: Vec<i64>,
+    future: usize,
+}
+
+impl ChainDeltas {
+    fn percentile(&self, p: f64) -> i64 {
+        let rank = ((p / 100.0) * self.sorted.len() as f64).ceil() as usize;
+        self.sorted[rank.clamp(1, self.sorted.len()) - 1]
+    }
+
+    fn mean(&self) -> i64 {
+        (self.sorted.iter().map(|delta| *delta as i128).sum::<i128>() / self.sorted.len() as i128) as i64
+    }
+
+    fn future_pct(&self) -> f64 {
+        self.future as f64 * 100.0 / self.sorted.len() as f64
+    }
+}
+
+/// How a chain's delta distribution differs from another's
+struct DistributionComparison {
+    // Each percentile with the first chain's delta and the second's
+    quantiles: Vec<(f64, i64, i64)>,
+    mean_diff_ms: i64,
+    future_pct_diff: f64,
+    // Largest gap between the two cumulative delta distributions, from 0 (identical) to 1
+    ks_distance: f64,
+    // Chance of a gap at least this large if both chains' deltas came from one distribution
+    p_value: f64,
+}
+
+impl DistributionComparison {
+    fn of(a: &ChainDeltas, b: &ChainDeltas, percentiles: &[f64]) -> Self {
+        let distance = ks_distance(&a.sorted, &b.sorted);
+        Self {
+            quantiles: percentiles.iter().map(|&p| (p, a.percentile(p), b.percentile(p))).collect(),
+            mean_diff_ms: b.mean() - a.mean(),
+            future_pct_diff: b.future_pct() - a.future_pct(),
+            ks_distance: distance,
+            p_value: ks_p_value(distance, a.sorted.len(), b.sorted.len()),
+        }
+    }
+}
+
+/// Asymptotic p-value of the two-sample Kolmogorov-Smirnov statistic `distance` for samples
+/// of `n` and `m` deltas, with Stephens' small-sample correction
+fn ks_p_value(distance: f64, n: usize, m: usize) -> f64 {
+    let effective = ((n * m) as f64 / (n + m) as f64).sqrt();
+    let lambda = (effective + 0.12 + 0.11 / effective) * distance;
+    if lambda < 0.2 {
+        // 1 to within 1e-8, where the series below converges slowly
+        return 1.0;
+    }
+    let mut sum = 0.0;
+    for j in 1..=100 {
+        let term = (-2.0 * (j * j) as f64 * lambda * lambda).exp();
+        sum += if j % 2 == 1 { term } else { -term };
+        if term < 1e-12 {
+            break;
+        }
+    }
+    (2.0 * sum).clamp(0.0, 1.0)
+}
+
+/// Compare the delta distributions of every pair of chains in a stored dataset: a
+/// two-sample Kolmogorov-Smirnov test of whether they differ, and the difference at each
+/// percentile showing where
+pub fn compare(args: &CompareChainsArgs) -> Result<()> {
+    if let Some(p) = args.percentiles.iter().find(|p| !(0.0..=100.0).contains(*p)) {
+        return Err(anyhow!("Percentile {} is not between 0 and 100", p));
+    }
+    if !(args.alpha > 0.0 && args.alpha < 1.0) {
+        return Err(anyhow!("--alpha {} is not between 0 and 1", args.alpha));
+    }
+
+    let observations = replay::load_observations(&args.input, args.from)?;
+    info!("Loaded {} observations from {}", observations.len(), args.input.display());
+    let mut chains: BTreeMap<String, ChainDeltas> = BTreeMap::new();
+    // A block recorded twice from the same vantage point counts as first received
+    let mut seen: HashSet<(Option<String>, String, u64)> = HashSet::new();
+    for observation in observations {
+        if !(args.chain.is_empty() || args.chain.contains(&observation.chain))
+            || !(args.vantage.is_empty()
+                || observation.vantage.as_ref().is_some_and(|vantage| args.vantage.contains(vantage)))
+            || args.since.is_some_and(|since| observation.received_at < since)
+            || args.until.is_some_and(|until| observation.received_at >= until)
+        {
+            continue;
+        }
+        if !seen.insert((observation.vantage.clone(), observation.chain.clone(), observation.block_number)) {
+            continue;
+        }
+        let deltas = chains.entry(observation.chain).or_insert_with(|| ChainDeltas {
+            sorted: Vec::new(),
+            future: 0,
+        });
+        deltas.sorted.push(observation.delta_ms);
+        deltas.future += (TimestampDelta::from_ms(observation.delta_ms).direction == DeltaDirection::Future) as usize;
+    }
+    if let Some(missing) = args.chain.iter().find(|chain| !chains.contains_key(*chain)) {
+        return Err(anyhow!("No block observations of {} in {} match the filters", missing, args.input.display()));
+    }
+    if chains.len() < 2 {
+        return Err(anyhow!(
+            "Comparing needs at least two chains, but only {} in {} match the filters",
+            chains.len(),
+            args.input.display()
+        ));
+    }
+    for deltas in chains.values_mut() {
+        deltas.sorted.sort_unstable();
+    }
+
+    let names: Vec<&String> = chains.keys().collect();
+    let mut json_comparisons = Vec::new();
+    for (i, a) in names.iter().enumerate() {
+        for b in &names[i + 1..] {
+            let comparison = DistributionComparison::of(&chains[*a], &chains[*b], &args.percentiles);
+            match args.format {
+                AnalysisFormat::Text => {
+                    for line in text_report(a, b, &chains, &comparison, args.alpha) {
+                        println!("{}", line);
+                    }
+                }
+                AnalysisFormat::Json => json_comparisons.push(json_report(a, b, &chains, &comparison, args.alpha)),
+            }
+        }
+    }
+    if args.format == AnalysisFormat::Json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({ "alpha": args.alpha, "comparisons": json_comparisons }))?
+        );
+    }
+    Ok(())
+}
+
+fn verdict(comparison: &DistributionComparison, alpha: f64) -> String {
+    if comparison.p_value < alpha {
+        format!("delta distributions differ significantly (p < {})", alpha)
+    } else {
+        format!("no significant difference at p < {}", alpha)
+    }
+}
+
+fn text_report(
+    a: &str,
+    b: &str,
+    chains: &BTreeMap<String, ChainDeltas>,
+    comparison: &DistributionComparison,
+    alpha: f64,
+) -> Vec<String> {
+    let (deltas_a, deltas_b) = (&chains[a], &chains[b]);
+    let width = a.len().max(b.len()).max(7);
+    let mut lines = vec![
+        format!("{} vs {}: {} and {} blocks", a, b, deltas_a.sorted.len(), deltas_b.sorted.len()),
+        format!("  {:<10} | {:>width$} | {:>width$} | {:>8}", "Percentile", a, b, "Diff", width = width),
+    ];
+    let mut rows: Vec<(String, i64, i64)> = vec![("avg".to_string(), deltas_a.mean(), deltas_b.mean())];
+    rows.extend(comparison.quantiles.iter().map(|&(p, da, db)| (percentile_label(p), da, db)));
+    for (label, da, db) in rows {
+        lines.push(format!(
+            "  {:<10} | {:>w$}ms | {:>w$}ms | {:>+6}ms",
+            label,
+            da,
+            db,
+            db - da,
+            w = width - 2
+        ));
+    }
+    lines.push(format!(
+        "  - Future timestamps: {:.1}% vs {:.1}% ({:+.1} points)",
+        deltas_a.future_pct(),
+        deltas_b.future_pct(),
+        comparison.future_pct_diff
+    ));
+    lines.push(format!(
+        "  - KS distance {:.3}, p = {:.3e}: {}",
+        comparison.ks_distance,
+        comparison.p_value,
+        verdict(comparison, alpha)
+    ));
+    lines
+}
+
+fn json_report(
+    a: &str,
+    b: &str,
+    chains: &BTreeMap<String, ChainDeltas>,
+    comparison: &DistributionComparison,
+    alpha: f64,
+) -> serde_json::Value {
+    serde_json::json!({
+        "chains": [a, b],
+        "blocks": [chains[a].sorted.len(), chains[b].sorted.len()],
+        "future_pct": [chains[a].future_pct(), chains[b].future_pct()],
+        "future_pct_diff": comparison.future_pct_diff,
+        "avg_delta_ms": [chains[a].mean(), chains[b].mean()],
+        "avg_diff_ms": comparison.mean_diff_ms,
+        "percentiles": comparison.quantiles.iter().map(|&(p, da, db)| serde_json::json!({
+            "percentile": p,
+            "delta_ms": [da, db],
+            "diff_ms": db - da,
+        })).collect::<Vec<_>>(),
+        "ks_distance": comparison.ks_distance,
+        "p_value": comparison.p_value,
+        "significant": comparison.p_value < alpha,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The statistic at which the test's sample sizes give a Kolmogorov lambda of exactly `lambda`
+    fn distance_for(lambda: f64, n: usize, m: usize) -> f64 {
+        let effective = ((n * m) as f64 / (n + m) as f64).sqrt();
+        lambda / (effective + 0.12 + 0.11 / effective)
+    }
+
+    #[test]
+    fn identical_samples_do_not_differ() {
+        let deltas: Vec<i64> = (0..50).map(|i| i * 40 - 300).collect();
+        let distance = ks_distance(&deltas, &deltas);
+        assert_eq!(distance, 0.0);
+        assert_eq!(ks_p_value(distance, deltas.len(), deltas.len()), 1.0);
+    }
+
+    #[test]
+    fn disjoint_samples_differ_completely() {
+        let early: Vec<i64> = (0..30).collect();
+        let late: Vec<i64> = (1_000..1_030).collect();
+        assert_eq!(ks_distance(&early, &late), 1.0);
+        assert_eq!(ks_distance(&late, &early), 1.0);
+        // 2 * exp(-2 * lambda^2) with lambda = sqrt(15) + 0.12 + 0.11 / sqrt(15)
+        let p_value = ks_p_value(1.0, early.len(), late.len());
+        assert!((p_value - 1.797e-14).abs() < 1e-16, "p = {}", p_value);
+    }
+
+    #[test]
+    fn overlapping_samples_differ_by_the_largest_cdf_gap() {
+        // After 2 the first sample's CDF is 2/4 and the second's 0; they meet again at 4
+        assert_eq!(ks_distance(&[1, 2, 3, 4], &[3, 4, 5, 6]), 0.5);
+        // Unequal sizes and ties: after 2, 3/5 against 1/3
+        let distance = ks_distance(&[1, 2, 2, 7, 9], &[2, 5, 8]);
+        assert!((distance - (3.0 / 5.0 - 1.0 / 3.0)).abs() < 1e-12, "D = {}", distance);
+    }
+
+    #[test]
+    fn p_value_follows_the_kolmogorov_distribution() {
+        // Tabulated Q_KS(1.0) = 0.27000 and the 5% critical value Q_KS(1.358) = 0.0500
+        assert!((ks_p_value(distance_for(1.0, 400, 600), 400, 600) - 0.27000).abs() < 1e-4);
+        assert!((ks_p_value(distance_for(1.358, 400, 600), 400, 600) - 0.0500).abs() < 1e-3);
+        assert_eq!(ks_p_value(distance_for(0.1, 400, 600), 400, 600), 1.0);
+    }
+}
@@ -0,0 +1,154 @@
+use anyhow::{anyhow, Context, Result};
+use duckdb::types::{TimeUnit, Value};
+use duckdb::{params_from_iter, Connection};
+use futures::future::{self, BoxFuture};
+use log::info;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::get_env_as_u64;
+use crate::monitor::BlockObservation;
+use crate::sink::ObservationSink;
+use crate::store::{ColumnValue, OBSERVATION_COLUMNS};
+
+/// Rows buffered before they are written without waiting for the flush interval
+const MAX_BATCH_ROWS: usize = 1000;
+
+/// Most rows kept while the database can't be written; beyond it the oldest are dropped
+const MAX_PENDING_ROWS: usize = 100_000;
+
+/// Appends every observation to the `observations` table of a DuckDB database file, so it
+/// can be opened and queried with SQL as it is, while the run goes on or after it.
+///
+/// Rows are appended in batches, each in one transaction. The file is only opened while a
+/// batch is written, so between batches it can be opened read-only (`duckdb -readonly`) by
+/// other processes. A batch that can't be written, e.g. while another process holds the
+/// file, stays buffered and is retried with the next one.
+pub struct DuckdbSink {
+    path: PathBuf,
+    flush_interval: Duration,
+    buffer: Vec<BlockObservation>,
+    failing: bool, // Whether the last batch failed, so the buffer holds rows kept from it
+}
+
+impl DuckdbSink {
+    /// Build the sink from `DUCKDB_PATH` and `DUCKDB_FLUSH_SECS`, creating the database and
+    /// its table; `None` when `DUCKDB_PATH` is not set
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(path) = env::var("DUCKDB_PATH") else {
+            return Ok(None);
+        };
+        let sink = Self {
+            path: PathBuf::from(path),
+            flush_interval: Duration::from_secs(get_env_as_u64("DUCKDB_FLUSH_SECS", 5).max(1)),
+            buffer: Vec::new(),
+            failing: false,
+        };
+        // Databases from older versions may predate some of the columns
+        let (first, first_value) = OBSERVATION_COLUMNS[0];
+        let mut schema = format!("CREATE TABLE IF NOT EXISTS observations ({} {});\n", first, duckdb_type(first_value));
+        for (column, value) in &OBSERVATION_COLUMNS[1..] {
+            schema.push_str(&format!(
+                "ALTER TABLE observations ADD COLUMN IF NOT EXISTS {} {};\n",
+                column,
+                duckdb_type(*value)
+            ));
+        }
+        Connection::open(&sink.path)
+            .and_then(|conn| conn.execute_batch(&schema))
+            .with_context(|| format!("Failed to open DuckDB database {}", sink.path.display()))?;
+        info!("Writing observations to DuckDB database {}", sink.path.display());
+        Ok(Some(sink))
+    }
+
+    // Append the buffered rows in one transaction, keeping them for the next attempt when
+    // it fails
+    fn send(&mut self) -> Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let result = append(&self.path, &self.buffer);
+        self.failing = result.is_err();
+        match result {
+            Ok(()) => {
+                self.buffer.clear();
+                Ok(())
+            }
+            Err(e) => {
+                let dropped = self.buffer.len().saturating_sub(MAX_PENDING_ROWS);
+                self.buffer.drain(..dropped);
+                let kept = format!("{} observations are kept for the next attempt", self.buffer.len());
+                match dropped {
+                    0 => Err(e.context(kept)),
+                    _ => Err(e.context(format!("{}; the oldest {} were dropped", kept, dropped))),
+                }
+            }
+        }
+    }
+}
+
+impl ObservationSink for DuckdbSink {
+    fn name(&self) -> &str {
+        "DuckDB"
+    }
+
+    fn write<'a>(&'a mut self, observation: &'a BlockObservation) -> BoxFuture<'a, Result<()>> {
+        self.buffer.push(observation.clone());
+        // Rows kept after a failure wait for the flush interval or the next full batch, and
+        // buffering more doesn't count as the database working again
+        if self.buffer.len().is_multiple_of(MAX_BATCH_ROWS) {
+            return Box::pin(future::ready(self.send()));
+        }
+        Box::pin(future::ready(match self.failing {
+            true => Err(anyhow!("{} observations are waiting for the database to be writable", self.buffer.len())),
+            false => Ok(()),
+        }))
+    }
+
+    fn flush(&mut self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(future::ready(self.send()))
+    }
+
+    /// Batches are written every `DUCKDB_FLUSH_SECS`
+    fn flush_interval(&self) -> Option<Duration> {
+        Some(self.flush_interval)
+    }
+}
+
+// Append the observations to the database at `path` in one transaction
+fn append(path: &Path, observations: &[BlockObservation]) -> Result<()> {
+    let mut conn = Connection::open(path)?;
+    let tx = conn.transaction()?;
+    {
+        let columns: Vec<&str> = OBSERVATION_COLUMNS.iter().map(|(column, _)| *column).collect();
+        let mut appender = tx.appender_with_columns("observations", &columns)?;
+        for observation in observations {
+            appender.append_row(params_from_iter(
+                OBSERVATION_COLUMNS.iter().map(|(_, value)| duckdb_value(*value, observation)),
+            ))?;
+        }
+        appender.flush()?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+fn duckdb_type(value: ColumnValue) -> &'static str {
+    match value {
+        ColumnValue::Text(_) => "VARCHAR",
+        ColumnValue::Integer(_) => "BIGINT",
+        ColumnValue::Boolean(_) => "BOOLEAN",
+        ColumnValue::Timestamp(_) => "TIMESTAMPTZ",
+    }
+}
+
+fn duckdb_value(value: ColumnValue, observation: &BlockObservation) -> Value {
+    let value = match value {
+        ColumnValue::Text(text) => text(observation).map(|text| Value::Text(text.to_string())),
+        ColumnValue::Integer(integer) => integer(observation).map(Value::BigInt),
+        ColumnValue::Boolean(boolean) => boolean(observation).map(Value::Boolean),
+        ColumnValue::Timestamp(time) => Some(Value::Timestamp(TimeUnit::Microsecond, time(observation).timestamp_micros())),
+    };
+    value.unwrap_or(Value::Null)
+}
@@ -0,0 +1,562 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use futures::future::BoxFuture;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Instant;
+use tokio::time;
+use tracing::{info_span, Instrument};
+
+use crate::adapter::{AdapterCounters, BlockBatch, BlockHeader, ChainAdapter, L1Origin, NodeHealth, StaleResponse};
+use crate::clock::Stamp;
+use crate::http::HttpSettings;
+use crate::ipc::{self, IpcConnection};
+use crate::monitor::{ChainInfo, MonitorOptions};
+use crate::retry::{is_transient, RetryPolicy, TransientStatus};
+use crate::schema::SchemaValidator;
+use crate::telemetry;
+use crate::timestamp::TimestampDecoding;
+
+// JSON-RPC request
+#[derive(Serialize)]
+struct JsonRpcRequest {
+    jsonrpc: String,
+    method: String,
+    params: Vec<serde_json::Value>,
+    id: u64,
+}
+
+// JSON-RPC response
+#[derive(Deserialize, Debug)]
+struct JsonRpcResponse<T> {
+    jsonrpc: String,
+    id: u64,
+    result: Option<T>,
+    error: Option<JsonRpcError>,
+}
+
+// JSON-RPC error
+#[derive(Deserialize, Debug)]
+struct JsonRpcError {
+    code: i64,
+    message: String,
+}
+
+// Block structure for JSON-RPC responses
+#[derive(Deserialize, Debug)]
+struct Block {
+    number: String,  // Hex-encoded block number
+    timestamp: String, // Hex-encoded timestamp
+    hash: Option<String>,
+    #[serde(rename = "parentHash")]
+    parent_hash: Option<String>,
+    #[serde(rename = "gasUsed")]
+    gas_used: Option<String>, // Hex-encoded
+    // Hashes only, as blocks are fetched without transaction bodies
+    transactions: Option<Vec<serde_json::Value>>,
+    // Everything else, for chains with extended timestamp fields
+    #[serde(flatten)]
+    fields: serde_json::Map<String, serde_json::Value>,
+}
+
+// Transaction fields needed to read an OP-stack L1 attributes deposit
+#[derive(Deserialize, Debug)]
+struct Transaction {
+    #[serde(rename = "type")]
+    tx_type: Option<String>,
+    input: String,
+}
+
+/// Type of OP-stack deposit transactions
+const DEPOSIT_TX_TYPE: &str = "0x7e";
+
+/// Selectors of the L1 attributes call each OP-stack block starts with: Bedrock's ABI-encoded
+/// `setL1BlockValues`, and the packed `setL1BlockValuesEcotone` and `setL1BlockValuesIsthmus`
+/// that replaced it
+const SET_L1_BLOCK_VALUES: &str = "015d8eb9";
+const SET_L1_BLOCK_VALUES_ECOTONE: &str = "440a5e20";
+const SET_L1_BLOCK_VALUES_ISTHMUS: &str = "098999be";
+
+/// How requests reach the node
+enum Transport {
+    Http(reqwest::Client),
+    /// A local node's IPC socket, which skips the HTTP stack entirely
+    Ipc(IpcConnection),
+}
+
+/// Ethereum JSON-RPC over HTTP or a local node's IPC socket: `eth_blockNumber` and
+/// `eth_getBlockByNumber` (plus `eth_getTransactionByBlockNumberAndIndex` for OP-stack L1
+/// origins), with batch requests, retries and, in strict mode, schema
+/// validation
+pub struct EvmAdapter {
+    name: String,
+    rpc_url: String,
+    http: HttpSettings,
+    transport: Transport,
+    decoding: TimestampDecoding,
+    retry: RetryPolicy,
+    strict_schema: bool,
+    schema: SchemaValidator,
+    request_id: u64,
+    counters: AdapterCounters,
+}
+
+impl EvmAdapter {
+    /// An adapter for `chain_info.rpc_url`; `client` is used unless the URL names an IPC
+    /// socket
+    pub fn new(chain_info: &ChainInfo, options: &MonitorOptions, client: reqwest::Client) -> Self {
+        let transport = match ipc::socket_path(&chain_info.rpc_url) {
+            Some(path) => Transport::Ipc(IpcConnection::new(path)),
+            None => Transport::Http(client),
+        };
+        Self {
+            name: chain_info.name.clone(),
+            rpc_url: chain_info.rpc_url.clone(),
+            http: chain_info.http.clone(),
+            transport,
+            decoding: chain_info.timestamp_decoding.clone(),
+            retry: options.retry,
+            strict_schema: options.strict_schema,
+            schema: SchemaValidator::new(&chain_info.name),
+            request_id: 1,
+            counters: AdapterCounters::default(),
+        }
+    }
+
+    // Send a JSON-RPC request and return its raw result, validating the envelope in strict mode
+    async fn call(&mut self, method: &str, params: Vec<serde_json::Value>) -> Result<serde_json::Value> {
+        let request = self.request(method, params);
+        let raw = self.post(method, &request).await?;
+        self.parse_response(raw, request.id)
+    }
+
+    // Send a JSON-RPC batch of `method` calls and return each call's result in request order
+    async fn call_batch(
+        &mut self,
+        method: &str,
+        batch: Vec<Vec<serde_json::Value>>,
+    ) -> Result<Vec<Result<serde_json::Value>>> {
+        let requests: Vec<JsonRpcRequest> = batch
+            .into_iter()
+            .map(|params| self.request(method, params))
+            .collect();
+        let raw = self.post(method, &requests).await?;
+        
+        // Providers without batch support answer with a single error object instead
+        let serde_json::Value::Array(responses) = raw else {
+            return Err(anyhow!("Batch requests not supported: {}", raw));
+        };
+        
+        // Responses in a batch may come back in any order
+        let mut responses: HashMap<u64, serde_json::Value> = responses
+            .into_iter()
+            .filter_map(|response| Some((response.get("id")?.as_u64()?, response)))
+            .collect();
+        Ok(requests
+            .iter()
+            .map(|request| match responses.remove(&request.id) {
+                Some(raw) => self.parse_response(raw, request.id),
+                None => Err(anyhow!("No response to request {} in batch", request.id)),
+            })
+            .collect())
+    }
+
+    fn request(&mut self, method: &str, params: Vec<serde_json::Value>) -> JsonRpcRequest {
+        let id = self.request_id;
+        self.request_id += 1;
+        JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: method.to_string(),
+            params,
+            id,
+        }
+    }
+
+    // Send a request body, retrying transient failures with backoff so a brief 429, 5xx or
+    // node restart doesn't drop blocks from the stats
+    async fn post<T: Serialize>(&mut self, method: &str, body: &T) -> Result<serde_json::Value> {
+        let retry = self.retry;
+        let mut attempt = 1;
+        loop {
+            let sent = Instant::now();
+            let sent_at = Utc::now();
+            let result = async {
+                match &mut self.transport {
+                    Transport::Http(client) => {
+                        let response = client
+                            .post(&self.rpc_url)
+                            .headers(telemetry::trace_headers())
+                            .json(body)
+                            .send()
+                            .await?;
+                        if let Some(status) = TransientStatus::check(response.status()) {
+                            return Err(status.into());
+                        }
+                        let server_date = response
+                            .headers()
+                            .get(reqwest::header::DATE)
+                            .and_then(|date| date.to_str().ok())
+                            .and_then(|date| DateTime::parse_from_rfc2822(date).ok());
+                        Ok::<_, anyhow::Error>((response.json().await?, server_date))
+                    }
+                    Transport::Ipc(connection) => Ok((connection.request(&serde_json::to_vec(body)?).await?, None)),
+                }
+            }
+            .instrument(info_span!("rpc", method))
+            .await;
+            
+            match result {
+                Ok((raw, server_date)) => {
+                    // Each attempt is timed on its own, so retry backoff doesn't count as latency
+                    let round_trip = sent.elapsed().as_millis() as u64;
+                    if method == "eth_blockNumber" {
+                        self.counters.head_latencies_ms.push(round_trip);
+                    } else {
+                        self.counters.block_latencies_ms.push(round_trip);
+                    }
+                    // The provider stamped the header some time during the round trip
+                    if let Some(date) = server_date {
+                        let midway = sent_at + Duration::milliseconds(round_trip as i64 / 2);
+                        self.counters.server_dates.push((date.timestamp_millis() + 500, midway));
+                    }
+                    return Ok(raw);
+                }
+                Err(e) if attempt < retry.max_attempts() && is_transient(&e) => {
+                    let delay = retry.delay(attempt);
+                    debug!(
+                        "{}: {} failed ({}), retrying in {}ms",
+                        self.name, method, e, delay.as_millis()
+                    );
+                    self.counters.retries += 1;
+                    time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    // Unwrap one JSON-RPC response, validating its envelope in strict mode
+    fn parse_response(&mut self, raw: serde_json::Value, id: u64) -> Result<serde_json::Value> {
+        let response: JsonRpcResponse<serde_json::Value> = serde_json::from_value(raw.clone())?;
+        
+        if self.strict_schema {
+            self.counters.schema_deviations +=
+                self.schema.check_envelope(&raw, &response.jsonrpc, response.id, id);
+        }
+        
+        if let Some(err) = response.error {
+            return Err(anyhow!("RPC error {}: {}", err.code, err.message));
+        }
+        
+        response.result.ok_or_else(|| anyhow!("Missing result"))
+    }
+
+    async fn get_block_number(&mut self) -> Result<u64> {
+        let result = self.call("eth_blockNumber", vec![]).await?;
+        
+        if self.strict_schema {
+            self.counters.schema_deviations += self.schema.check_block_number(&result);
+        }
+        
+        let block_number_hex: String = serde_json::from_value(result)?;
+        let block_number = u64::from_str_radix(block_number_hex.trim_start_matches("0x"), 16)?;
+        
+        Ok(block_number)
+    }
+
+    async fn get_block_by_number(&mut self, block_number: u64) -> Result<BlockHeader> {
+        let result = self.call("eth_getBlockByNumber", block_params(block_number)).await?;
+        let block = self.parse_block(block_number, result)?;
+        block_header(block_number, block, &self.decoding)
+    }
+
+    // Fetch blocks `first` through `last` in a single batch, returning when the batch arrived
+    // along with each block's header
+    async fn get_block_batch(&mut self, first: u64, last: u64) -> Result<BlockBatch> {
+        let received = Stamp::now();
+        let batch = (first..=last).map(block_params).collect();
+        let results = self.call_batch("eth_getBlockByNumber", batch).await?;
+        let decoding = self.decoding.clone();
+        let headers = (first..=last)
+            .zip(results)
+            .map(|(block_number, result)| {
+                let block = self.parse_block(block_number, result?)?;
+                block_header(block_number, block, &decoding)
+            })
+            .collect();
+        Ok(BlockBatch { received, headers })
+    }
+
+    async fn get_chain_id(&mut self) -> Result<u64> {
+        let result = self.call("eth_chainId", vec![]).await?;
+        let chain_id_hex: String = serde_json::from_value(result)?;
+        Ok(u64::from_str_radix(chain_id_hex.trim_start_matches("0x"), 16)?)
+    }
+
+    // `eth_syncing`, with `net_peerCount` when the node answers it; many gateways don't
+    async fn get_node_health(&mut self) -> Result<NodeHealth> {
+        let syncing = self.call("eth_syncing", vec![]).await?;
+        let height = |field: &str| {
+            syncing
+                .get(field)
+                .and_then(|value| value.as_str())
+                .and_then(|hex| u64::from_str_radix(hex.trim_start_matches("0x"), 16).ok())
+        };
+        let (current_block, highest_block) = (height("currentBlock"), height("highestBlock"));
+        let peer_count = match self.call("net_peerCount", vec![]).await {
+            Ok(count) => count
+                .as_str()
+                .and_then(|hex| u64::from_str_radix(hex.trim_start_matches("0x"), 16).ok()),
+            Err(e) => {
+                debug!("{}: No peer count: {}", self.name, e);
+                None
+            }
+        };
+        Ok(NodeHealth {
+            // `false` when synced, an object describing the progress otherwise
+            syncing: syncing.as_bool() != Some(false),
+            current_block,
+            highest_block,
+            peer_count,
+        })
+    }
+
+    /// Every field of the block at `block_number`, for chains whose headers carry more than
+    /// `BlockHeader` keeps
+    pub(crate) async fn block_fields(&mut self, block_number: u64) -> Result<serde_json::Map<String, serde_json::Value>> {
+        let result = self.call("eth_getBlockByNumber", block_params(block_number)).await?;
+        match result {
+            serde_json::Value::Object(fields) => Ok(fields),
+            serde_json::Value::Null => Err(StaleResponse(block_number).into()),
+            other => Err(anyhow!("Expected block {}, got {}", block_number, other)),
+        }
+    }
+
+    // Number and header of the block a tag such as "safe" currently points at
+    async fn get_tagged_block(&mut self, tag: &str) -> Result<(u64, BlockHeader)> {
+        let params = vec![serde_json::Value::String(tag.to_string()), serde_json::Value::Bool(false)];
+        let result = self.call("eth_getBlockByNumber", params).await?;
+        if result.is_null() {
+            return Err(anyhow!("No {} block", tag));
+        }
+        let block: Block = serde_json::from_value(result)?;
+        let number = u64::from_str_radix(block.number.trim_start_matches("0x"), 16)?;
+        Ok((number, block_header(number, block, &self.decoding)?))
+    }
+
+    // The L1 origin recorded by the L1 attributes deposit, the first transaction of every
+    // OP-stack block
+    async fn get_l1_origin(&mut self, block_number: u64) -> Result<L1Origin> {
+        let params = vec![
+            serde_json::Value::String(format!("0x{:x}", block_number)),
+            serde_json::Value::String("0x0".to_string()),
+        ];
+        let result = self.call("eth_getTransactionByBlockNumberAndIndex", params).await?;
+        if result.is_null() {
+            return Err(StaleResponse(block_number).into());
+        }
+        let tx: Transaction = serde_json::from_value(result)?;
+        if tx.tx_type.as_deref() != Some(DEPOSIT_TX_TYPE) {
+            return Err(anyhow!("Block {} doesn't start with an L1 attributes deposit", block_number));
+        }
+        l1_attributes(&tx.input).map_err(|e| anyhow!("Block {}: {}", block_number, e))
+    }
+
+    fn parse_block(&mut self, block_number: u64, result: serde_json::Value) -> Result<Block> {
+        // A null result for a block the head already covers means a lagging backend answered
+        if result.is_null() {
+            return Err(StaleResponse(block_number).into());
+        }
+        
+        if self.strict_schema {
+            self.counters.schema_deviations += self.schema.check_block(&result);
+        }
+        
+        Ok(serde_json::from_value(result)?)
+    }
+}
+
+impl ChainAdapter for EvmAdapter {
+    fn latest_height(&mut self) -> BoxFuture<'_, Result<u64>> {
+        Box::pin(self.get_block_number())
+    }
+
+    fn block_at(&mut self, height: u64) -> BoxFuture<'_, Result<BlockHeader>> {
+        Box::pin(self.get_block_by_number(height))
+    }
+
+    fn blocks_in(&mut self, first: u64, last: u64) -> BoxFuture<'_, Result<BlockBatch>> {
+        Box::pin(self.get_block_batch(first, last))
+    }
+
+    fn tagged_block(&mut self, tag: &'static str) -> BoxFuture<'_, Result<(u64, BlockHeader)>> {
+        Box::pin(self.get_tagged_block(tag))
+    }
+
+    fn l1_origin(&mut self, height: u64) -> BoxFuture<'_, Result<L1Origin>> {
+        Box::pin(self.get_l1_origin(height))
+    }
+
+    fn chain_id(&mut self) -> BoxFuture<'_, Result<u64>> {
+        Box::pin(self.get_chain_id())
+    }
+
+    fn node_health(&mut self) -> BoxFuture<'_, Result<NodeHealth>> {
+        Box::pin(self.get_node_health())
+    }
+
+    fn take_counters(&mut self) -> AdapterCounters {
+        std::mem::take(&mut self.counters)
+    }
+
+    fn reset(&mut self) {
+        match &mut self.transport {
+            Transport::Http(client) => *client = http_client(&self.rpc_url, &self.http),
+            Transport::Ipc(connection) => connection.disconnect(),
+        }
+    }
+}
+
+/// An HTTP client for `rpc_url` with `settings`. A node on the same machine is always
+/// reached directly, never through a proxy configured in the environment, which would add
+/// a hop to every measured arrival.
+pub(crate) fn http_client(rpc_url: &str, settings: &HttpSettings) -> reqwest::Client {
+    let local = reqwest::Url::parse(rpc_url).is_ok_and(|url| match url.host_str() {
+        Some("localhost") => true,
+        Some(host) => host.trim_matches(['[', ']']).parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback()),
+        None => false,
+    });
+    let builder = settings.client_builder();
+    let builder = if local { builder.no_proxy() } else { builder };
+    builder.build().unwrap_or_default()
+}
+
+// eth_getBlockByNumber params for a block header, without transaction bodies
+fn block_params(block_number: u64) -> Vec<serde_json::Value> {
+    vec![
+        serde_json::Value::String(format!("0x{:x}", block_number)),
+        serde_json::Value::Bool(false),
+    ]
+}
+
+// Make sure the provider returned the block we asked for and decode its timestamp in milliseconds
+fn block_header(block_number: u64, block: Block, decoding: &TimestampDecoding) -> Result<BlockHeader> {
+    info_span!("parse").in_scope(|| {
+        let returned_number = u64::from_str_radix(block.number.trim_start_matches("0x"), 16)?;
+        if returned_number != block_number {
+            return Err(anyhow!("Requested block {} but received block {}", block_number, returned_number));
+        }
+        
+        let timestamp_hex = block.timestamp.trim_start_matches("0x");
+        let gas_used = match &block.gas_used {
+            Some(gas_used) => Some(u64::from_str_radix(gas_used.trim_start_matches("0x"), 16)?),
+            None => None,
+        };
+        Ok(BlockHeader {
+            timestamp_ms: decoding.to_millis(u64::from_str_radix(timestamp_hex, 16)?, &block.fields)?,
+            hash: block.hash,
+            parent_hash: block.parent_hash,
+            gas_used,
+            tx_count: block.transactions.map(|transactions| transactions.len() as u64),
+        })
+    })
+}
+
+// Decode the L1 block number and timestamp from the calldata of an L1 attributes deposit
+fn l1_attributes(input: &str) -> Result<L1Origin> {
+    let input = input.trim_start_matches("0x");
+    let (selector, args) = input.split_at_checked(8).ok_or_else(|| anyhow!("Empty L1 attributes calldata"))?;
+    // A big-endian uint64 `len` bytes long, `offset` bytes into the arguments
+    let uint = |offset: usize, len: usize| -> Result<u64> {
+        let hex = args
+            .get(offset * 2..(offset + len) * 2)
+            .ok_or_else(|| anyhow!("Truncated L1 attributes calldata"))?;
+        Ok(u64::from_str_radix(hex, 16)?)
+    };
+    let (number, timestamp) = match selector {
+        // One 32-byte word per argument: number, timestamp, ...
+        SET_L1_BLOCK_VALUES => (uint(24, 8)?, uint(56, 8)?),
+        // Packed: two uint32 fee scalars, the uint64 sequence number, then timestamp and number
+        SET_L1_BLOCK_VALUES_ECOTONE | SET_L1_BLOCK_VALUES_ISTHMUS => (uint(24, 8)?, uint(16, 8)?),
+        _ => return Err(anyhow!("Unknown L1 attributes call 0x{}", selector)),
+    };
+    Ok(L1Origin {
+        number,
+        timestamp_ms: timestamp * 1000,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // L1 attributes deposits as the sequencer posts them, one argument per line
+    const BEDROCK: &str = concat!(
+        "0x015d8eb9",
+        "000000000000000000000000000000000000000000000000000000000109d8fe", // number
+        "00000000000000000000000000000000000000000000000000000000647f5ea7", // timestamp
+        "0000000000000000000000000000000000000000000000000000000573120ae0", // base fee
+        "4cbb4d3c4a8d1e0fa1b0e2ddd5d1b6b1b36c1c2f64ab8c0a7e51f0d3c2a9b871", // hash
+        "0000000000000000000000000000000000000000000000000000000000000003", // sequence number
+        "0000000000000000000000006887246668a3b87f54deb3b94ba47a6f63f32985", // batcher hash
+        "00000000000000000000000000000000000000000000000000000000000000bc", // fee overhead
+        "00000000000000000000000000000000000000000000000000000000000a6fe0", // fee scalar
+    );
+    const ECOTONE: &str = concat!(
+        "0x440a5e20",
+        "00000558000c5fc5", // base fee scalar, blob base fee scalar
+        "0000000000000001", // sequence number
+        "0000000065f1b483", // timestamp
+        "0000000001285748", // number
+        "0000000000000000000000000000000000000000000000000000000745c7b77b", // base fee
+        "0000000000000000000000000000000000000000000000000000000000000001", // blob base fee
+        "a1b6e8f2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5c6d7e", // hash
+        "0000000000000000000000006887246668a3b87f54deb3b94ba47a6f63f32985", // batcher hash
+    );
+    const ISTHMUS: &str = concat!(
+        "0x098999be",
+        "0000146b000f79c5", // base fee scalar, blob base fee scalar
+        "0000000000000004", // sequence number
+        "00000000681b004b", // timestamp
+        "0000000001564518", // number
+        "000000000000000000000000000000000000000000000000000000005aa009cf", // base fee
+        "0000000000000000000000000000000000000000000000000000000000000003", // blob base fee
+        "5f2c1e8b7a69d4c3b2a1f0e9d8c7b6a5f4e3d2c1b0a9f8e7d6c5b4a3f2e1d0c9", // hash
+        "0000000000000000000000005050f69a9786f081509234f1a7f4684b5e5b76c9", // batcher hash
+        "000000000000000000000000", // operator fee scalar, operator fee constant
+    );
+
+    #[test]
+    fn l1_attributes_decode_every_upgrade() {
+        assert_eq!(
+            l1_attributes(BEDROCK).unwrap(),
+            L1Origin { number: 17_422_590, timestamp_ms: 1_686_068_903_000 }
+        );
+        assert_eq!(
+            l1_attributes(ECOTONE).unwrap(),
+            L1Origin { number: 19_421_000, timestamp_ms: 1_710_339_203_000 }
+        );
+        assert_eq!(
+            l1_attributes(ISTHMUS).unwrap(),
+            L1Origin { number: 22_431_000, timestamp_ms: 1_746_600_011_000 }
+        );
+    }
+
+    #[test]
+    fn malformed_l1_attributes_are_rejected() {
+        assert!(l1_attributes("").is_err());
+        assert!(l1_attributes("0x440a5e").is_err());
+        // Cut off inside the L1 block number
+        assert!(l1_attributes(&BEDROCK[..10 + 60]).is_err());
+        assert!(l1_attributes(&ECOTONE[..10 + 56]).is_err());
+        // Anything past the fields read is ignored
+        assert!(l1_attributes(&ECOTONE[..10 + 64]).is_ok());
+
+        let unknown = format!("0xdeadbeef{}", &ECOTONE[10..]);
+        let error = l1_attributes(&unknown).unwrap_err();
+        assert_eq!(error.to_string(), "Unknown L1 attributes call 0xdeadbeef");
+    }
+}
@@ -0,0 +1,364 @@
+use anyhow::{anyhow, Result};
+use futures::StreamExt;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::clock::Stamp;
+use crate::histogram::DeltaHistogram;
+use crate::stats::GapSummary;
+
+/// Longest wait between reconnection attempts
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Blocks below the latest recorded one whose flashblocks are still kept waiting for it,
+/// e.g. while a catch-up fetches them
+const MAX_PENDING_BLOCKS: u64 = 64;
+
+/// One flashblock: a partial block the builder streams ahead of the canonical block,
+/// stamped the moment its message arrived
+#[derive(Debug, Clone)]
+pub struct Flashblock {
+    pub block_number: u64,
+    /// Position within the block; 0 opens it and carries its timestamp
+    pub index: u64,
+    /// Block timestamp in milliseconds, from the opening flashblock
+    pub timestamp_ms: Option<u64>,
+    /// Hash of the block as built so far
+    pub block_hash: Option<String>,
+    pub received: Stamp,
+}
+
+// The parts of a flashblock message (rollup-boost's `FlashblocksPayloadV1`) that are used
+#[derive(Deserialize)]
+struct FlashblockMessage {
+    payload_id: Option<String>,
+    index: u64,
+    base: Option<FlashblockBase>,
+    diff: Option<FlashblockDiff>,
+    metadata: Option<FlashblockMetadata>,
+}
+
+#[derive(Deserialize)]
+struct FlashblockBase {
+    block_number: String,
+    timestamp: String,
+}
+
+#[derive(Deserialize)]
+struct FlashblockDiff {
+    block_hash: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct FlashblockMetadata {
+    block_number: Option<u64>,
+}
+
+/// A background connection to a chain's flashblocks feed that reconnects on failure
+pub struct FlashblockFeed {
+    receiver: mpsc::UnboundedReceiver<Flashblock>,
+    connected: Arc<AtomicBool>,
+    task: JoinHandle<()>,
+}
+
+impl FlashblockFeed {
+    pub fn spawn(chain: String, url: String) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let connected = Arc::new(AtomicBool::new(false));
+        let task = tokio::spawn(feed_loop(chain, url, sender, connected.clone()));
+        Self {
+            receiver,
+            connected,
+            task,
+        }
+    }
+
+    /// Whether the feed is currently connected
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    /// Next flashblock received since the last call, if any
+    pub fn try_next(&mut self) -> Option<Flashblock> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+impl Drop for FlashblockFeed {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn parse_quantity(hex: &str) -> Result<u64> {
+    Ok(u64::from_str_radix(hex.trim_start_matches("0x"), 16)?)
+}
+
+async fn feed_loop(chain: String, url: String, sender: mpsc::UnboundedSender<Flashblock>, connected: Arc<AtomicBool>) {
+    let mut delay = Duration::from_secs(1);
+    loop {
+        let result = stream_flashblocks(&chain, &url, &sender, &connected).await;
+        if sender.is_closed() {
+            return;
+        }
+
+        // Back off only while connections keep failing outright
+        if connected.swap(false, Ordering::Relaxed) {
+            delay = Duration::from_secs(1);
+        }
+        match result {
+            Ok(()) => warn!("{}: Flashblocks feed closed; reconnecting", chain),
+            Err(e) => warn!("{}: Flashblocks feed failed: {}; reconnecting", chain, e),
+        }
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+async fn stream_flashblocks(
+    chain: &str,
+    url: &str,
+    sender: &mpsc::UnboundedSender<Flashblock>,
+    connected: &AtomicBool,
+) -> Result<()> {
+    let (mut ws, _) = tokio_tungstenite::connect_async(url).await?;
+    connected.store(true, Ordering::Relaxed);
+    info!("{}: Connected to the flashblocks feed", chain);
+
+    // Later flashblocks may only name their payload, so remember which block it builds
+    let mut payload_block: Option<(String, u64)> = None;
+    while let Some(message) = ws.next().await {
+        // Stamp arrival before any parsing so it reflects when the flashblock reached us
+        let received = Stamp::now();
+        let message: FlashblockMessage = match message? {
+            Message::Text(text) => serde_json::from_str(text.as_str())?,
+            Message::Binary(data) => serde_json::from_slice(&data)
+                .map_err(|e| anyhow!("Binary flashblock messages must be uncompressed JSON ({})", e))?,
+            Message::Close(_) => return Ok(()),
+            _ => continue,
+        };
+
+        let (block_number, timestamp_ms) = match &message.base {
+            Some(base) => (parse_quantity(&base.block_number)?, Some(parse_quantity(&base.timestamp)? * 1000)),
+            None => {
+                let from_payload = payload_block
+                    .as_ref()
+                    .filter(|(payload_id, _)| message.payload_id.as_ref() == Some(payload_id))
+                    .map(|(_, block_number)| *block_number);
+                match message.metadata.as_ref().and_then(|metadata| metadata.block_number).or(from_payload) {
+                    Some(block_number) => (block_number, None),
+                    // A flashblock for a payload whose opening one we missed
+                    None => continue,
+                }
+            }
+        };
+        if let Some(payload_id) = &message.payload_id {
+            payload_block = Some((payload_id.clone(), block_number));
+        }
+        let flashblock = Flashblock {
+            block_number,
+            index: message.index,
+            timestamp_ms,
+            block_hash: message.diff.and_then(|diff| diff.block_hash),
+            received,
+        };
+        if sender.send(flashblock).is_err() {
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+/// The flashblocks received for a block that hasn't been recorded yet
+#[derive(Debug, Clone)]
+struct PendingBlock {
+    first_received: Stamp,
+    timestamp_ms: Option<u64>,
+    // Hash as of the highest index seen
+    last_index: u64,
+    block_hash: Option<String>,
+    flashblocks: usize,
+}
+
+/// Collects flashblocks by block until the monitor records the canonical block
+#[derive(Debug, Default)]
+pub(crate) struct PreconfTracker {
+    pending: BTreeMap<u64, PendingBlock>,
+    // Latest canonical block compared, so flashblocks arriving after it count as late
+    latest_recorded: Option<u64>,
+}
+
+impl PreconfTracker {
+    /// Hold `flashblock` until its block is recorded. Returns false if the block was already
+    /// recorded, i.e. the flashblock arrived too late to be a preconfirmation.
+    pub(crate) fn add(&mut self, flashblock: Flashblock) -> bool {
+        if self.latest_recorded.is_some_and(|latest| flashblock.block_number <= latest) {
+            return false;
+        }
+        let pending = self.pending.entry(flashblock.block_number).or_insert(PendingBlock {
+            first_received: flashblock.received,
+            timestamp_ms: None,
+            last_index: flashblock.index,
+            block_hash: None,
+            flashblocks: 0,
+        });
+        pending.flashblocks += 1;
+        if flashblock.received.ms_since(&pending.first_received) < 0 {
+            pending.first_received = flashblock.received;
+        }
+        if flashblock.timestamp_ms.is_some() {
+            pending.timestamp_ms = flashblock.timestamp_ms;
+        }
+        if flashblock.index >= pending.last_index && flashblock.block_hash.is_some() {
+            pending.last_index = flashblock.index;
+            pending.block_hash = flashblock.block_hash;
+        }
+        true
+    }
+
+    /// Compare the flashblocks of `block_number` with the canonical block, stamped
+    /// `timestamp_ms` with hash `hash` and received at `received`. `None` if no flashblock
+    /// arrived for it.
+    pub(crate) fn settle(
+        &mut self,
+        block_number: u64,
+        timestamp_ms: u64,
+        hash: Option<&str>,
+        received: Stamp,
+    ) -> Option<PreconfSample> {
+        self.latest_recorded = Some(self.latest_recorded.map_or(block_number, |latest| latest.max(block_number)));
+        let pending = self.pending.remove(&block_number);
+        // Blocks that were skipped will never be recorded
+        let oldest = block_number.saturating_sub(MAX_PENDING_BLOCKS);
+        self.pending = self.pending.split_off(&oldest);
+
+        let pending = pending?;
+        // The lead is an interval, so the first flashblock's arrival is dated back from the
+        // canonical block's by the monotonic clock
+        let receipt_time_ms = received.wall.timestamp_millis();
+        Some(PreconfSample {
+            block_number,
+            flashblocks: pending.flashblocks,
+            block_timestamp_ms: timestamp_ms,
+            first_received_ms: receipt_time_ms - received.ms_since(&pending.first_received),
+            receipt_time_ms,
+            timestamp_matches: pending.timestamp_ms.map(|preconf_ms| preconf_ms / 1000 == timestamp_ms / 1000),
+            hash_matches: pending.block_hash.zip(hash).map(|(preconf, canonical)| preconf.eq_ignore_ascii_case(canonical)),
+        })
+    }
+}
+
+/// A canonical block compared with its preconfirmation, kept for `{Chain}_preconf.csv`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PreconfSample {
+    pub block_number: u64,
+    pub flashblocks: usize,
+    pub block_timestamp_ms: u64,
+    /// When the block's first flashblock arrived
+    pub first_received_ms: i64,
+    /// When the canonical block was received
+    pub receipt_time_ms: i64,
+    /// Whether the opening flashblock carried the canonical timestamp (to the second)
+    pub timestamp_matches: Option<bool>,
+    /// Whether the last flashblock's hash is the canonical block's
+    pub hash_matches: Option<bool>,
+}
+
+impl PreconfSample {
+    /// First flashblock arrival minus the block timestamp
+    pub fn delta_ms(&self) -> i64 {
+        self.first_received_ms - self.block_timestamp_ms as i64
+    }
+
+    /// How long before the canonical block was received its first flashblock arrived
+    pub fn lead_ms(&self) -> i64 {
+        self.receipt_time_ms - self.first_received_ms
+    }
+}
+
+/// How a chain's preconfirmations compared with its canonical blocks over the run
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PreconfStats {
+    pub blocks: usize,                // Canonical blocks with at least one flashblock
+    pub missed_blocks: usize,         // Canonical blocks recorded while the feed was up, without any
+    pub flashblocks: usize,           // Flashblocks matched to a canonical block
+    pub late_flashblocks: usize,      // Flashblocks for blocks that were already recorded
+    pub timestamp_mismatches: usize,  // Opening flashblocks whose timestamp the canonical block didn't keep
+    pub hash_mismatches: usize,       // Blocks whose last flashblock wasn't the canonical block
+    pub deltas: DeltaHistogram,       // First flashblock arrival minus block timestamp
+    pub leads: DeltaHistogram,        // Canonical receipt time minus first flashblock arrival
+}
+
+impl PreconfStats {
+    /// These stats with only the deltas and leads recorded since the previous call, which
+    /// are moved out, leaving empty histograms to record the next ones in
+    pub(crate) fn take_update(&mut self) -> Self {
+        Self {
+            blocks: self.blocks,
+            missed_blocks: self.missed_blocks,
+            flashblocks: self.flashblocks,
+            late_flashblocks: self.late_flashblocks,
+            timestamp_mismatches: self.timestamp_mismatches,
+            hash_mismatches: self.hash_mismatches,
+            deltas: std::mem::take(&mut self.deltas),
+            leads: std::mem::take(&mut self.leads),
+        }
+    }
+
+    pub(crate) fn record(&mut self, sample: &PreconfSample) {
+        self.blocks += 1;
+        self.flashblocks += sample.flashblocks;
+        if sample.timestamp_matches == Some(false) {
+            self.timestamp_mismatches += 1;
+        }
+        if sample.hash_matches == Some(false) {
+            self.hash_mismatches += 1;
+        }
+        self.deltas.record(sample.delta_ms());
+        self.leads.record(sample.lead_ms());
+    }
+
+    /// Whether the chain has a flashblocks feed that was up while blocks were recorded
+    pub fn is_tracked(&self) -> bool {
+        self.blocks > 0 || self.missed_blocks > 0
+    }
+
+    /// First flashblock arrivals relative to block timestamps
+    pub fn delta_summary(&self) -> Option<GapSummary> {
+        GapSummary::of(&self.deltas)
+    }
+
+    /// How far ahead of the canonical blocks their first flashblocks arrived
+    pub fn lead_summary(&self) -> Option<GapSummary> {
+        GapSummary::of(&self.leads)
+    }
+
+    /// The preconfirmation columns in `SUMMARY_HEADERS` order, empty for chains without a
+    /// flashblocks feed
+    pub(crate) fn record_columns(&self) -> Vec<String> {
+        if !self.is_tracked() {
+            return vec![String::new(); 18];
+        }
+        let mut record: Vec<String> = [
+            self.blocks,
+            self.missed_blocks,
+            self.flashblocks,
+            self.late_flashblocks,
+            self.timestamp_mismatches,
+            self.hash_mismatches,
+        ]
+        .iter()
+        .map(|count| count.to_string())
+        .collect();
+        record.extend(GapSummary::record(self.delta_summary()));
+        record.extend(GapSummary::record(self.lead_summary()));
+        record
+    }
+}
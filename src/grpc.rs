@@ -0,0 +1,185 @@
+use anyhow::Result;
+use chrono::Utc;
+use futures::future::{self, BoxFuture};
+use futures::stream::{self, Stream};
+use log::{error, info, warn};
+use std::collections::{BTreeMap, HashSet};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+
+use crate::delta::DeltaDirection;
+use crate::monitor::BlockObservation;
+use crate::report::chain_summary;
+use crate::sink::ObservationSink;
+use crate::stats::ChainStats;
+
+/// Types generated from `proto/observations.proto`
+pub mod proto {
+    tonic::include_proto!("block_timestamp_logger.v1");
+}
+
+use proto::observation_service_server::{ObservationService, ObservationServiceServer};
+
+/// Observations held for each subscriber before the slowest start skipping some
+const SUBSCRIBER_BUFFER: usize = 4096;
+
+/// Streams every observation to gRPC subscribers as it is recorded and serves each chain's
+/// latest stats, with the typed schema in `proto/observations.proto`
+#[derive(Debug)]
+pub struct GrpcService {
+    observations: broadcast::Sender<proto::Observation>,
+    chains: Mutex<BTreeMap<String, proto::ChainStats>>,
+}
+
+impl Default for GrpcService {
+    fn default() -> Self {
+        Self {
+            observations: broadcast::channel(SUBSCRIBER_BUFFER).0,
+            chains: Mutex::default(),
+        }
+    }
+}
+
+impl GrpcService {
+    /// Take over a chain's latest stats
+    pub fn update(&self, stats: &ChainStats) {
+        let [p50, p90, p99, p999] = stats.delta_percentiles();
+        let snapshot = proto::ChainStats {
+            chain: stats.chain_name.clone(),
+            updated_at_ms: Utc::now().timestamp_millis(),
+            total_blocks: stats.total_blocks as u64,
+            past_blocks: stats.timestamp_past_blocks as u64,
+            exact_blocks: stats.timestamp_exact_blocks as u64,
+            future_blocks: stats.timestamp_future_blocks as u64,
+            avg_delta_ms: stats.avg_time_delta_ms,
+            stddev_delta_ms: stats.delta_stddev_ms(),
+            p50_delta_ms: p50,
+            p90_delta_ms: p90,
+            p99_delta_ms: p99,
+            p999_delta_ms: p999,
+            max_past_delta_ms: stats.max_past_delta_ms,
+            max_future_delta_ms: stats.max_future_delta_ms,
+            last_block: stats.last_block_number,
+            degraded: stats.degraded,
+            paused: stats.paused,
+            summary_json: chain_summary(stats).to_string(),
+        };
+        self.chains.lock().unwrap().insert(stats.chain_name.clone(), snapshot);
+    }
+
+    /// Stop serving a chain that is no longer monitored
+    pub fn remove(&self, chain: &str) {
+        self.chains.lock().unwrap().remove(chain);
+    }
+
+    /// Serve subscribers and stats requests on `listener`
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) {
+        if let Ok(addr) = listener.local_addr() {
+            info!("Serving observations over gRPC on {}", addr);
+        }
+        let served = Server::builder()
+            .add_service(ObservationServiceServer::from_arc(self))
+            .serve_with_incoming(TcpIncoming::from(listener))
+            .await;
+        if let Err(e) = served {
+            error!("gRPC server stopped: {}", e);
+        }
+    }
+}
+
+/// Observations are dropped when nobody is subscribed
+impl ObservationSink for Arc<GrpcService> {
+    fn name(&self) -> &str {
+        "gRPC subscribers"
+    }
+
+    fn write<'a>(&'a mut self, observation: &'a BlockObservation) -> BoxFuture<'a, Result<()>> {
+        let _ = self.observations.send(message(observation));
+        Box::pin(future::ready(Ok(())))
+    }
+}
+
+type ObservationStream = Pin<Box<dyn Stream<Item = Result<proto::Observation, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl ObservationService for GrpcService {
+    type StreamObservationsStream = ObservationStream;
+
+    async fn stream_observations(
+        &self,
+        request: Request<proto::StreamObservationsRequest>,
+    ) -> Result<Response<Self::StreamObservationsStream>, Status> {
+        let chains: HashSet<String> = request.into_inner().chains.into_iter().collect();
+        let receiver = self.observations.subscribe();
+        let observations = stream::unfold((receiver, chains), |(mut receiver, chains)| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(observation) if chains.is_empty() || chains.contains(&observation.chain) => {
+                        return Some((Ok(observation), (receiver, chains)));
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("gRPC subscriber fell behind and skipped {} observations", skipped);
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(observations)))
+    }
+
+    async fn get_stats(
+        &self,
+        request: Request<proto::GetStatsRequest>,
+    ) -> Result<Response<proto::GetStatsResponse>, Status> {
+        let requested = request.into_inner().chains;
+        let chains = self.chains.lock().unwrap();
+        let snapshots = if requested.is_empty() {
+            chains.values().cloned().collect()
+        } else {
+            requested
+                .iter()
+                .map(|name| {
+                    chains
+                        .get(name)
+                        .cloned()
+                        .ok_or_else(|| Status::not_found(format!("No stats for chain '{}'", name)))
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        Ok(Response::new(proto::GetStatsResponse { chains: snapshots }))
+    }
+}
+
+// The observation as a message, with the same fields as the observation records
+fn message(observation: &BlockObservation) -> proto::Observation {
+    let direction = match observation.timestamp_delta().direction {
+        DeltaDirection::Past => proto::DeltaDirection::Past,
+        DeltaDirection::Exact => proto::DeltaDirection::Exact,
+        DeltaDirection::Future => proto::DeltaDirection::Future,
+    };
+    proto::Observation {
+        chain: observation.chain.clone(),
+        block_number: observation.block_number,
+        block_timestamp_ms: observation.block_timestamp_ms,
+        block_hash: observation.block_hash.clone(),
+        parent_hash: observation.parent_hash.clone(),
+        gas_used: observation.gas_used,
+        tx_count: observation.tx_count,
+        received_at_ms: observation.received_at.timestamp_millis(),
+        responded_at_ms: observation.responded_at.map(|responded| responded.timestamp_millis()),
+        delta_ms: observation.delta_ms,
+        delta_direction: direction.into(),
+        leap_second_window: observation.leap_affected,
+        l1_origin_block: observation.l1_origin.map(|origin| origin.number),
+        l1_origin_drift_ms: observation.l1_drift_ms(),
+        node_syncing: observation.node_syncing,
+        anomaly_z: observation.anomaly_z,
+        vantage: observation.vantage.clone(),
+    }
+}
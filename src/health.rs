@@ -0,0 +1,104 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::get_env_as_u64;
+
+/// Whether each chain monitor is still collecting blocks, for liveness and readiness probes.
+///
+/// A chain is ready once it has received a block since it started being monitored, and stale
+/// when its latest block is older than `HEALTH_MAX_BLOCK_AGE_SECS`. A chain still waiting for
+/// its first block only counts as stale once that long has passed since it started.
+#[derive(Debug, Clone)]
+pub struct HealthCheck {
+    // Each chain with when its monitoring started (ms); clones share the list
+    chains: Arc<Mutex<Vec<(String, i64)>>>,
+    max_block_age_ms: i64,
+}
+
+/// Outcome of a health check
+#[derive(Debug, Clone)]
+pub struct Health {
+    /// No chain is stale
+    pub healthy: bool,
+    /// Every chain has received a block since startup
+    pub ready: bool,
+    pub stale_chains: Vec<String>,
+    pub waiting_chains: Vec<String>,
+}
+
+impl HealthCheck {
+    pub fn from_env(chains: Vec<String>, started: DateTime<Utc>) -> Self {
+        let started_ms = started.timestamp_millis();
+        Self {
+            chains: Arc::new(Mutex::new(chains.into_iter().map(|chain| (chain, started_ms)).collect())),
+            max_block_age_ms: get_env_as_u64("HEALTH_MAX_BLOCK_AGE_SECS", 120).max(1) as i64 * 1000,
+        }
+    }
+
+    /// Check `chains` from now on; chains not checked before count as started at `now`
+    pub fn set_chains(&self, chains: Vec<String>, now: DateTime<Utc>) {
+        let mut checked = self.chains.lock().unwrap();
+        let started: Vec<(String, i64)> = chains
+            .into_iter()
+            .map(|chain| {
+                let started_ms = checked
+                    .iter()
+                    .find(|(name, _)| *name == chain)
+                    .map_or(now.timestamp_millis(), |(_, started_ms)| *started_ms);
+                (chain, started_ms)
+            })
+            .collect();
+        *checked = started;
+    }
+
+    /// Check every chain given the receipt time (ms) of its latest block
+    pub fn check(&self, latest_block_ms: impl Fn(&str) -> Option<i64>, now: DateTime<Utc>) -> Health {
+        let now_ms = now.timestamp_millis();
+        let mut stale_chains = Vec::new();
+        let mut waiting_chains = Vec::new();
+        for (chain, started_ms) in self.chains.lock().unwrap().iter() {
+            // A block restored from a checkpoint says nothing about this run
+            let since = match latest_block_ms(chain) {
+                Some(received_ms) if received_ms >= *started_ms => received_ms,
+                _ => {
+                    waiting_chains.push(chain.clone());
+                    *started_ms
+                }
+            };
+            if now_ms - since > self.max_block_age_ms {
+                stale_chains.push(chain.clone());
+            }
+        }
+        Health {
+            healthy: stale_chains.is_empty(),
+            ready: waiting_chains.is_empty(),
+            stale_chains,
+            waiting_chains,
+        }
+    }
+}
+
+impl Health {
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "healthy": self.healthy,
+            "ready": self.ready,
+            "stale_chains": self.stale_chains,
+            "waiting_chains": self.waiting_chains,
+        })
+    }
+
+    /// Rewrite the heartbeat file while healthy. Once a chain goes stale (or the process
+    /// hangs) the file stops being touched, so a probe can check its age.
+    pub fn write_heartbeat(&self, path: &Path, now: DateTime<Utc>) -> Result<()> {
+        if !self.healthy {
+            return Ok(());
+        }
+        let mut status = self.to_json();
+        status["updated_at"] = now.to_rfc3339().into();
+        std::fs::write(path, serde_json::to_string(&status)? + "\n")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,318 @@
+use anyhow::{anyhow, Result};
+use hdrhistogram::serialization::{Deserializer, Serializer, V2Serializer};
+use hdrhistogram::Histogram;
+use serde::{Deserialize, Serialize};
+
+/// Significant decimal digits the histograms keep; deltas under 2s are recorded exactly
+const SIGNIFICANT_FIGURES: u8 = 3;
+
+/// Distribution of signed deltas in bounded memory, however long the run.
+///
+/// HDR histograms only hold non-negative values, so past (positive) deltas and the rest
+/// are kept as magnitudes in separate histograms, exact (zero) deltas sharing the second
+/// with future (negative) ones. Sums are exact; percentiles are exact below 2048ms and
+/// within 0.1% above.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "EncodedHistogram", into = "EncodedHistogram")]
+pub struct DeltaHistogram {
+    past: Histogram<u64>,
+    // Future and exact deltas
+    future: Histogram<u64>,
+    sum: i128,
+    sum_of_squares: i128,
+}
+
+impl Default for DeltaHistogram {
+    fn default() -> Self {
+        Self {
+            past: Histogram::new(SIGNIFICANT_FIGURES).expect("valid histogram precision"),
+            future: Histogram::new(SIGNIFICANT_FIGURES).expect("valid histogram precision"),
+            sum: 0,
+            sum_of_squares: 0,
+        }
+    }
+}
+
+/// A `DeltaHistogram` as stored in checkpoints: each half in the HDR V2 format, hex-encoded
+#[derive(Serialize, Deserialize)]
+struct EncodedHistogram {
+    past: String,
+    future: String,
+    sum: i128,
+    sum_of_squares: i128,
+}
+
+impl From<DeltaHistogram> for EncodedHistogram {
+    fn from(histogram: DeltaHistogram) -> Self {
+        Self {
+            past: encode(&histogram.past),
+            future: encode(&histogram.future),
+            sum: histogram.sum,
+            sum_of_squares: histogram.sum_of_squares,
+        }
+    }
+}
+
+impl TryFrom<EncodedHistogram> for DeltaHistogram {
+    type Error = anyhow::Error;
+
+    fn try_from(encoded: EncodedHistogram) -> Result<Self> {
+        Ok(Self {
+            past: decode(&encoded.past)?,
+            future: decode(&encoded.future)?,
+            sum: encoded.sum,
+            sum_of_squares: encoded.sum_of_squares,
+        })
+    }
+}
+
+fn encode(histogram: &Histogram<u64>) -> String {
+    let mut bytes = Vec::new();
+    // Writing to a Vec only fails on counts the format can't hold, which u64 histograms can't reach
+    let _ = V2Serializer::new().serialize(histogram, &mut bytes);
+    hex::encode(bytes)
+}
+
+fn decode(encoded: &str) -> Result<Histogram<u64>> {
+    let bytes = hex::decode(encoded)?;
+    let mut histogram: Histogram<u64> = Deserializer::new()
+        .deserialize(&mut bytes.as_slice())
+        .map_err(|e| anyhow!("Invalid histogram: {:?}", e))?;
+    // Deserialized histograms have a fixed range; keep accepting any delta
+    histogram.auto(true);
+    Ok(histogram)
+}
+
+impl DeltaHistogram {
+    pub fn record(&mut self, delta_ms: i64) {
+        // Auto-resizing histograms accept any u64, so recording can't fail
+        if delta_ms > 0 {
+            let _ = self.past.record(delta_ms as u64);
+        } else {
+            let _ = self.future.record(delta_ms.unsigned_abs());
+        }
+        self.sum += delta_ms as i128;
+        self.sum_of_squares += (delta_ms as i128).pow(2);
+    }
+
+    pub fn len(&self) -> u64 {
+        self.past.len() + self.future.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn sum_ms(&self) -> i64 {
+        self.sum as i64
+    }
+
+    pub fn mean_ms(&self) -> i64 {
+        if self.is_empty() {
+            return 0;
+        }
+        (self.sum / self.len() as i128) as i64
+    }
+
+    /// Population standard deviation in milliseconds
+    pub fn stddev_ms(&self) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let n = self.len() as f64;
+        let mean = self.sum as f64 / n;
+        (self.sum_of_squares as f64 / n - mean * mean).max(0.0).sqrt()
+    }
+
+    /// Smallest delta recorded, to the histogram's precision
+    pub fn min_ms(&self) -> i64 {
+        if !self.future.is_empty() {
+            -(self.future.max() as i64)
+        } else {
+            self.past.min() as i64
+        }
+    }
+
+    /// Largest delta recorded, to the histogram's precision
+    pub fn max_ms(&self) -> i64 {
+        if !self.past.is_empty() {
+            self.past.max() as i64
+        } else {
+            -(self.future.min() as i64)
+        }
+    }
+
+    /// Nearest-rank percentile, as `stats::percentile` computes over a sorted slice
+    pub fn percentile(&self, p: f64) -> i64 {
+        let total = self.len();
+        if total == 0 {
+            return 0;
+        }
+        let rank = (((p / 100.0) * total as f64).ceil() as u64).clamp(1, total);
+        let future = self.future.len();
+        if rank <= future {
+            // Future deltas sort first, largest magnitude first
+            let magnitude_rank = future - rank + 1;
+            -(self.future.value_at_quantile(magnitude_rank as f64 / future as f64) as i64)
+        } else {
+            let past_rank = rank - future;
+            self.past.value_at_quantile(past_rank as f64 / self.past.len() as f64) as i64
+        }
+    }
+
+    /// Deltas less than or equal to `bound_ms`
+    pub fn count_at_most(&self, bound_ms: i64) -> u64 {
+        if bound_ms < 0 {
+            self.future.len() - self.future.count_between(0, bound_ms.unsigned_abs() - 1)
+        } else {
+            self.future.len() + self.past.count_between(1, bound_ms as u64)
+        }
+    }
+
+    /// Future deltas whose magnitude is below `magnitude_ms`
+    pub fn future_within(&self, magnitude_ms: u64) -> u64 {
+        if magnitude_ms <= 1 {
+            return 0;
+        }
+        self.future.count_between(1, magnitude_ms - 1)
+    }
+
+    /// Future deltas whose magnitude is at least `magnitude_ms`, i.e. the future deltas left
+    /// once those within one timestamp step of `magnitude_ms` count as exact
+    pub fn future_beyond(&self, magnitude_ms: u64) -> u64 {
+        self.future_count() - self.future_within(magnitude_ms)
+    }
+
+    /// Future (negative) deltas
+    pub fn future_count(&self) -> u64 {
+        self.future.len() - self.exact_count()
+    }
+
+    /// Exact (zero) deltas
+    pub fn exact_count(&self) -> u64 {
+        self.future.count_at(0)
+    }
+
+    /// Add the deltas recorded in `other`, e.g. those another copy recorded since it was
+    /// last added
+    pub fn add(&mut self, other: &Self) {
+        // Auto-resizing histograms take any counts, so adding can't fail
+        let _ = self.past.add(&other.past);
+        let _ = self.future.add(&other.future);
+        self.sum += other.sum;
+        self.sum_of_squares += other.sum_of_squares;
+    }
+
+    /// The deltas recorded since `earlier`, a previous copy of this histogram
+    pub fn since(&self, earlier: &Self) -> Self {
+        let mut recent = self.clone();
+        // Only fails if `earlier` isn't an ancestor, in which case everything is recent
+        if recent.past.subtract(&earlier.past).is_err() || recent.future.subtract(&earlier.future).is_err() {
+            return self.clone();
+        }
+        recent.sum -= earlier.sum;
+        recent.sum_of_squares -= earlier.sum_of_squares;
+        recent
+    }
+}
+
+/// Upper bounds (ms) of the buckets the final report's chart and `{chain}_histogram.csv`
+/// count deltas in; a last bucket holds everything above the final bound
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramBuckets(Vec<i64>);
+
+impl Default for HistogramBuckets {
+    fn default() -> Self {
+        Self(vec![-2000, -1000, -500, -250, 0, 250, 500, 1000, 2000, 4000, 8000])
+    }
+}
+
+/// Deltas in `lower < delta <= upper`; an open end is `None`
+#[derive(Debug, Clone, Copy)]
+pub struct BucketCount {
+    pub lower_ms: Option<i64>,
+    pub upper_ms: Option<i64>,
+    pub count: u64,
+}
+
+impl BucketCount {
+    /// Short label for the chart, e.g. "<= -2000", "1..250" or "> 8000"
+    pub fn label(&self) -> String {
+        match (self.lower_ms, self.upper_ms) {
+            (None, Some(upper)) => format!("<= {}", upper),
+            (Some(lower), Some(upper)) => format!("{}..{}", lower + 1, upper),
+            (Some(lower), None) => format!("> {}", lower),
+            (None, None) => "all".to_string(),
+        }
+    }
+}
+
+impl HistogramBuckets {
+    /// Read the bounds from `HISTOGRAM_BUCKETS_MS` (comma-separated, ascending)
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("HISTOGRAM_BUCKETS_MS") {
+            Ok(bounds) => Self::parse(&bounds),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn parse(bounds: &str) -> Result<Self> {
+        let bounds = bounds
+            .split(',')
+            .map(str::trim)
+            .filter(|bound| !bound.is_empty())
+            .map(|bound| bound.parse::<i64>().map_err(|_| anyhow!("Invalid histogram bucket bound '{}'", bound)))
+            .collect::<Result<Vec<i64>>>()?;
+        if bounds.is_empty() {
+            return Err(anyhow!("HISTOGRAM_BUCKETS_MS lists no bounds"));
+        }
+        if bounds.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(anyhow!("Histogram bucket bounds must be strictly ascending"));
+        }
+        Ok(Self(bounds))
+    }
+
+    /// How many of `histogram`'s deltas fall in each bucket, lowest first. A future delta
+    /// smaller than the chain's timestamp step `granularity_ms` can't be told apart from the
+    /// chain stamping blocks with their slot time, so it is counted as a delta of zero.
+    pub fn counts(&self, histogram: &DeltaHistogram, granularity_ms: u64) -> Vec<BucketCount> {
+        let within_step = histogram.future_within(granularity_ms);
+        let mut counts = Vec::with_capacity(self.0.len() + 1);
+        let mut lower = None;
+        let mut below = 0;
+        for &upper in &self.0 {
+            let mut at_most = histogram.count_at_most(upper);
+            if upper < 0 {
+                // The deltas from -granularity_ms exclusive up to this bound move up to zero
+                at_most -= within_step - histogram.future_within(upper.unsigned_abs()).min(within_step);
+            }
+            counts.push(BucketCount { lower_ms: lower, upper_ms: Some(upper), count: at_most - below });
+            lower = Some(upper);
+            below = at_most;
+        }
+        counts.push(BucketCount { lower_ms: lower, upper_ms: None, count: histogram.len() - below });
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn future_deltas_within_one_step_count_as_zero() {
+        let mut histogram = DeltaHistogram::default();
+        for delta_ms in [-2500, -1500, -900, -300, -1, 0, 100, 600] {
+            histogram.record(delta_ms);
+        }
+        let buckets = HistogramBuckets::parse("-1000,-500,0,500").unwrap();
+        let counts = |granularity_ms| -> Vec<u64> {
+            buckets.counts(&histogram, granularity_ms).iter().map(|bucket| bucket.count).collect()
+        };
+        assert_eq!(counts(0), [2, 1, 3, 1, 1]);
+        // -900, -300 and -1 are less than a 1s step from zero
+        assert_eq!(counts(1000), [2, 0, 4, 1, 1]);
+        assert_eq!(counts(2000), [1, 0, 5, 1, 1]);
+        assert_eq!(histogram.future_beyond(1000), 2);
+    }
+}
@@ -0,0 +1,52 @@
+use chrono::{DateTime, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::histogram::DeltaHistogram;
+
+/// A chain's deltas split by the hour of the day (UTC) their blocks were received in, to
+/// see whether timestamps drift with the day's traffic peaks
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct HourlyDeltas {
+    // One histogram per hour from midnight; empty until the first block
+    hours: Vec<DeltaHistogram>,
+}
+
+impl HourlyDeltas {
+    pub(crate) fn record(&mut self, received_at: DateTime<Utc>, delta_ms: i64) {
+        if self.hours.is_empty() {
+            self.hours = vec![DeltaHistogram::default(); 24];
+        }
+        self.hours[received_at.hour() as usize].record(delta_ms);
+    }
+
+    /// Add the deltas recorded in `other`, hour by hour
+    pub(crate) fn add(&mut self, other: &Self) {
+        if self.hours.is_empty() {
+            self.hours = other.hours.clone();
+            return;
+        }
+        for (deltas, added) in self.hours.iter_mut().zip(&other.hours) {
+            deltas.add(added);
+        }
+    }
+
+    /// Each hour of the day with its deltas, from midnight; all empty before the first block
+    pub fn hours(&self) -> impl Iterator<Item = (u32, &DeltaHistogram)> {
+        self.hours.iter().enumerate().map(|(hour, deltas)| (hour as u32, deltas))
+    }
+
+    /// The hours with blocks whose average delta is highest and lowest, if there are two
+    pub fn extremes(&self) -> Option<((u32, i64), (u32, i64))> {
+        let averages: Vec<(u32, i64)> = self
+            .hours()
+            .filter(|(_, deltas)| !deltas.is_empty())
+            .map(|(hour, deltas)| (hour, deltas.mean_ms()))
+            .collect();
+        if averages.len() < 2 {
+            return None;
+        }
+        let highest = averages.iter().max_by_key(|(_, avg_ms)| *avg_ms)?;
+        let lowest = averages.iter().min_by_key(|(_, avg_ms)| *avg_ms)?;
+        Some((*highest, *lowest))
+    }
+}
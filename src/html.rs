@@ -0,0 +1,318 @@
+use anyhow::Result;
+use chrono::DateTime;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::report::sorted;
+use crate::stats::{ChainStats, RawSample};
+use crate::timezone::ReportTimezone;
+
+/// Self-contained report of the run, written alongside `summary.json` once the run has ended
+pub const HTML_REPORT_FILE: &str = "report.html";
+
+/// Size of every chart, and the margin left around its plot for the axis labels
+const CHART_WIDTH: u32 = 760;
+const CHART_HEIGHT: u32 = 240;
+const MARGIN_LEFT: u32 = 64;
+const MARGIN_RIGHT: u32 = 16;
+const MARGIN_TOP: u32 = 12;
+const MARGIN_BOTTOM: u32 = 40;
+
+/// Most bars that get their own label; longer series only label every few bars
+const MAX_BAR_LABELS: usize = 16;
+
+const STYLE: &str = "body{font-family:system-ui,sans-serif;margin:2em auto;max-width:820px;color:#222}\
+h1{font-size:1.5em}h2{margin-top:2em;border-bottom:1px solid #ccc}h3{font-size:1em;margin-bottom:.3em}\
+table{border-collapse:collapse}td,th{padding:2px 12px 2px 0;text-align:left}td.n{text-align:right}\
+.note{color:#666;font-size:.9em}svg text{font-size:11px;fill:#555}pre{background:#f6f6f6;padding:1em;overflow-x:auto}";
+
+/// Write `report.html` to `dir`: the run metadata from `summary`, each chain's key figures
+/// and charts of its delta over time, delta histogram and blocks per minute, followed by
+/// the text of the final report in `lines`. The charts label receipt times in `timezone`.
+///
+/// The charts are inline SVG and the styles are embedded, so the file can be attached to
+/// an issue or opened offline as it is.
+pub(crate) fn write_html_report(
+    dir: &Path,
+    summary: &serde_json::Value,
+    chain_stats: &HashMap<String, ChainStats>,
+    lines: &[String],
+    timezone: ReportTimezone,
+) -> Result<()> {
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<title>Block timestamp report</title>\n");
+    let _ = writeln!(html, "<style>{}</style>\n</head>\n<body>", STYLE);
+    html.push_str("<h1>Block timestamp report</h1>\n<table>\n");
+    for (label, key) in [
+        ("Status", "status"),
+        ("Source", "source"),
+        ("Started", "started_at"),
+        ("Updated", "updated_at"),
+        ("Elapsed (s)", "elapsed_secs"),
+        ("Poll interval (ms)", "poll_interval_ms"),
+    ] {
+        let value = match &summary[key] {
+            serde_json::Value::Null => continue,
+            serde_json::Value::String(value) => value.clone(),
+            value => value.to_string(),
+        };
+        let _ = writeln!(html, "<tr><th>{}</th><td>{}</td></tr>", label, escape(&value));
+    }
+    html.push_str("</table>\n");
+
+    for stats in sorted(chain_stats) {
+        chain_section(&mut html, stats, timezone);
+    }
+
+    html.push_str("<h2>Final report</h2>\n<pre>");
+    html.push_str(&escape(&lines.join("\n")));
+    html.push_str("</pre>\n</body>\n</html>\n");
+    std::fs::write(dir.join(HTML_REPORT_FILE), html)?;
+    Ok(())
+}
+
+fn chain_section(html: &mut String, stats: &ChainStats, timezone: ReportTimezone) {
+    let _ = writeln!(
+        html,
+        "<h2>{}{}</h2>",
+        escape(&stats.chain_name),
+        if stats.degraded { " (degraded)" } else { "" }
+    );
+    let [p50, p90, p99, p999] = stats.delta_percentiles();
+    let mut rows = vec![
+        ("Blocks", stats.total_blocks.to_string()),
+        ("Past timestamps", stats.timestamp_past_blocks.to_string()),
+        ("Future timestamps", stats.timestamp_future_blocks.to_string()),
+        ("Exact timestamps", stats.timestamp_exact_blocks.to_string()),
+        ("Average delta (ms)", stats.avg_time_delta_ms.to_string()),
+        ("Delta stddev (ms)", format!("{:.1}", stats.delta_stddev_ms())),
+        ("Delta p50 / p90 / p99 / p99.9 (ms)", format!("{} / {} / {} / {}", p50, p90, p99, p999)),
+        ("Max past delta (ms)", stats.max_past_delta_ms.to_string()),
+        ("Max future delta (ms)", stats.max_future_delta_ms.to_string()),
+        ("RPC errors", stats.rpc_errors.to_string()),
+        ("Non-monotonic timestamps", stats.non_monotonic_blocks.to_string()),
+        ("Max timestamp regression (ms)", stats.max_timestamp_regression_ms.to_string()),
+        ("Reorgs", stats.reorgs.to_string()),
+        ("Missed blocks", stats.missed_blocks.to_string()),
+    ];
+    if let Some(interval) = stats.avg_block_interval_ms() {
+        rows.push(("Avg block interval (ms)", format!("{:.1}", interval)));
+    }
+    html.push_str("<table>\n");
+    for (label, value) in rows {
+        let _ = writeln!(html, "<tr><th>{}</th><td class=\"n\">{}</td></tr>", label, value);
+    }
+    html.push_str("</table>\n");
+
+    if stats.raw_samples.is_empty() {
+        html.push_str("<p class=\"note\">No per-block records were kept, so there is nothing to chart.</p>\n");
+    } else {
+        let _ = writeln!(
+            html,
+            "<h3>Delta over time (ms; times in {})</h3>\n{}",
+            timezone.name(),
+            delta_chart(&stats.raw_samples, timezone)
+        );
+    }
+    if !stats.delta_histogram.is_empty() {
+        let bars: Vec<(String, u64)> = stats
+            .delta_buckets()
+            .iter()
+            .map(|bucket| (bucket.label(), bucket.count))
+            .collect();
+        let _ = writeln!(html, "<h3>Delta histogram (ms)</h3>\n{}", bar_chart(&bars));
+    }
+    if !stats.raw_samples.is_empty() {
+        let _ = writeln!(
+            html,
+            "<h3>Blocks per minute (times in {})</h3>\n{}",
+            timezone.name(),
+            bar_chart(&blocks_per_minute(stats, timezone))
+        );
+    }
+    if stats.raw_samples.len() < stats.total_blocks {
+        let _ = writeln!(
+            html,
+            "<p class=\"note\">The delta over time and blocks per minute charts cover the last {} blocks; the figures and histogram cover all {}.</p>",
+            stats.raw_samples.len(),
+            stats.total_blocks
+        );
+    }
+}
+
+// Blocks received in each minute from the first kept record to the last, empty minutes included
+fn blocks_per_minute(stats: &ChainStats, timezone: ReportTimezone) -> Vec<(String, u64)> {
+    let mut minutes: BTreeMap<i64, u64> = BTreeMap::new();
+    for sample in &stats.raw_samples {
+        *minutes.entry(sample.receipt_time_ms.div_euclid(60_000)).or_default() += 1;
+    }
+    let (Some(&first), Some(&last)) = (minutes.keys().next(), minutes.keys().next_back()) else {
+        return Vec::new();
+    };
+    (first..=last)
+        .map(|minute| (clock_time(minute * 60_000, timezone), minutes.get(&minute).copied().unwrap_or(0)))
+        .collect()
+}
+
+// Receipt time as `HH:MM` in the report timezone
+fn clock_time(ms: i64, timezone: ReportTimezone) -> String {
+    DateTime::from_timestamp_millis(ms).map_or_else(String::new, |time| timezone.format(time, "%H:%M"))
+}
+
+fn svg_open(html: &mut String) {
+    let _ = write!(
+        html,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">",
+        w = CHART_WIDTH,
+        h = CHART_HEIGHT
+    );
+    let _ = write!(
+        html,
+        "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"#ccc\"/>",
+        MARGIN_LEFT,
+        MARGIN_TOP,
+        plot_width(),
+        plot_height()
+    );
+}
+
+fn plot_width() -> f64 {
+    (CHART_WIDTH - MARGIN_LEFT - MARGIN_RIGHT) as f64
+}
+
+fn plot_height() -> f64 {
+    (CHART_HEIGHT - MARGIN_TOP - MARGIN_BOTTOM) as f64
+}
+
+// Label at the left of the plot, level with `y`
+fn y_label(svg: &mut String, y: f64, label: &str) {
+    let _ = write!(
+        svg,
+        "<text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\" dominant-baseline=\"middle\">{}</text>",
+        MARGIN_LEFT - 6,
+        y,
+        escape(label)
+    );
+}
+
+// Label under the plot, centred on `x`
+fn x_label(svg: &mut String, x: f64, label: &str) {
+    let _ = write!(
+        svg,
+        "<text x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+        x,
+        CHART_HEIGHT - MARGIN_BOTTOM + 16,
+        escape(label)
+    );
+}
+
+/// Each block's delta against its receipt time, with a line at zero. Past (positive)
+/// deltas plot above it and future ones below.
+///
+/// Long runs are reduced to the smallest and largest delta of each pixel column, which
+/// keeps every spike while bounding the size of the file.
+fn delta_chart(samples: &VecDeque<RawSample>, timezone: ReportTimezone) -> String {
+    let first_ms = samples.iter().map(|sample| sample.receipt_time_ms).min().unwrap_or(0);
+    let last_ms = samples.iter().map(|sample| sample.receipt_time_ms).max().unwrap_or(0);
+    let min_delta = samples.iter().map(|sample| sample.delta_ms).min().unwrap_or(0).min(0);
+    let max_delta = samples.iter().map(|sample| sample.delta_ms).max().unwrap_or(0).max(0);
+    let span_ms = (last_ms - first_ms).max(1) as f64;
+    let range = (max_delta - min_delta).max(1) as f64;
+    let x = |ms: i64| MARGIN_LEFT as f64 + (ms - first_ms) as f64 / span_ms * plot_width();
+    let y = |delta: i64| MARGIN_TOP as f64 + (max_delta - delta) as f64 / range * plot_height();
+
+    let columns = plot_width() as usize;
+    let mut points: Vec<(i64, i64)> = samples.iter().map(|sample| (sample.receipt_time_ms, sample.delta_ms)).collect();
+    points.sort_unstable();
+    if points.len() > 2 * columns {
+        // The lowest and highest point of each column, in time order
+        let mut reduced: BTreeMap<usize, [(i64, i64); 2]> = BTreeMap::new();
+        for &(ms, delta) in &points {
+            let column = ((x(ms) - MARGIN_LEFT as f64) as usize).min(columns - 1);
+            let [low, high] = reduced.entry(column).or_insert([(ms, delta); 2]);
+            if delta < low.1 {
+                *low = (ms, delta);
+            }
+            if delta > high.1 {
+                *high = (ms, delta);
+            }
+        }
+        points = reduced
+            .into_values()
+            .flat_map(|[low, high]| if low.0 <= high.0 { [low, high] } else { [high, low] })
+            .collect();
+    }
+
+    let mut svg = String::new();
+    svg_open(&mut svg);
+    let _ = write!(
+        svg,
+        "<line x1=\"{}\" x2=\"{}\" y1=\"{y:.1}\" y2=\"{y:.1}\" stroke=\"#999\" stroke-dasharray=\"4 3\"/>",
+        MARGIN_LEFT,
+        CHART_WIDTH - MARGIN_RIGHT,
+        y = y(0)
+    );
+    let path: Vec<String> = points
+        .iter()
+        .map(|&(ms, delta)| format!("{:.1},{:.1}", x(ms), y(delta)))
+        .collect();
+    let _ = write!(
+        svg,
+        "<polyline fill=\"none\" stroke=\"#3366cc\" stroke-width=\"1\" points=\"{}\"/>",
+        path.join(" ")
+    );
+    for delta in [max_delta, 0, min_delta] {
+        y_label(&mut svg, y(delta), &delta.to_string());
+    }
+    x_label(&mut svg, x(first_ms), &clock_time(first_ms, timezone));
+    x_label(&mut svg, x(last_ms), &clock_time(last_ms, timezone));
+    svg.push_str("</svg>");
+    svg
+}
+
+/// One bar per labelled count, scaled so the tallest fills the plot
+fn bar_chart(bars: &[(String, u64)]) -> String {
+    let tallest = bars.iter().map(|(_, count)| *count).max().unwrap_or(0).max(1);
+    let slot = plot_width() / bars.len().max(1) as f64;
+    let label_every = bars.len().div_ceil(MAX_BAR_LABELS).max(1);
+
+    let mut svg = String::new();
+    svg_open(&mut svg);
+    for (i, (label, count)) in bars.iter().enumerate() {
+        let height = *count as f64 / tallest as f64 * plot_height();
+        let left = MARGIN_LEFT as f64 + i as f64 * slot;
+        let _ = write!(
+            svg,
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"#3366cc\"><title>{}: {}</title></rect>",
+            left + slot * 0.1,
+            MARGIN_TOP as f64 + plot_height() - height,
+            slot * 0.8,
+            height,
+            escape(label),
+            count
+        );
+        if i % label_every == 0 {
+            x_label(&mut svg, left + slot / 2.0, label);
+        }
+    }
+    y_label(&mut svg, MARGIN_TOP as f64, &tallest.to_string());
+    y_label(&mut svg, MARGIN_TOP as f64 + plot_height(), "0");
+    svg.push_str("</svg>");
+    svg
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,203 @@
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::time::Duration;
+
+use crate::get_env_as_u64;
+
+/// How the HTTP client a chain is polled through connects and waits, from the `HTTP_*`
+/// variables and a chain's `[chain.http]` and `[chain.auth]` tables
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HttpSettings {
+    /// Longest a request may take, from connecting to reading the last byte of the response;
+    /// a stalled request fails and is retried instead of holding up the poll
+    pub request_timeout: Duration,
+    pub connect_timeout: Duration,
+    /// Idle connections kept per host; `None` keeps any number
+    pub pool_max_idle: Option<usize>,
+    /// How long an idle connection stays in the pool
+    pub pool_idle_timeout: Duration,
+    /// Interval of TCP keep-alive probes on open connections; `None` sends none
+    pub tcp_keepalive: Option<Duration>,
+    /// Speak HTTP/2 from the start instead of negotiating it, for endpoints known to support it
+    pub http2_prior_knowledge: bool,
+    /// Credentials and extra headers sent with every request
+    pub auth: RpcAuth,
+}
+
+impl HttpSettings {
+    pub fn from_env() -> Self {
+        Self {
+            request_timeout: Duration::from_millis(get_env_as_u64("HTTP_TIMEOUT_MS", 10_000).max(1)),
+            connect_timeout: Duration::from_millis(get_env_as_u64("HTTP_CONNECT_TIMEOUT_MS", 5000).max(1)),
+            pool_max_idle: env::var("HTTP_POOL_MAX_IDLE").ok().and_then(|val| val.parse().ok()),
+            pool_idle_timeout: Duration::from_secs(get_env_as_u64("HTTP_POOL_IDLE_SECS", 90)),
+            tcp_keepalive: Some(get_env_as_u64("HTTP_KEEPALIVE_SECS", 30))
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+            http2_prior_knowledge: env::var("HTTP2_PRIOR_KNOWLEDGE").is_ok_and(|val| val == "true" || val == "1"),
+            auth: RpcAuth::default(),
+        }
+    }
+
+    /// These settings with a chain's own overrides applied
+    pub(crate) fn with_overrides(self, overrides: &HttpOverrides) -> Self {
+        Self {
+            request_timeout: overrides
+                .timeout_ms
+                .map_or(self.request_timeout, |ms| Duration::from_millis(ms.max(1))),
+            connect_timeout: overrides
+                .connect_timeout_ms
+                .map_or(self.connect_timeout, |ms| Duration::from_millis(ms.max(1))),
+            pool_max_idle: overrides.pool_max_idle.or(self.pool_max_idle),
+            pool_idle_timeout: overrides.pool_idle_secs.map_or(self.pool_idle_timeout, Duration::from_secs),
+            tcp_keepalive: match overrides.keepalive_secs {
+                Some(0) => None,
+                Some(secs) => Some(Duration::from_secs(secs)),
+                None => self.tcp_keepalive,
+            },
+            http2_prior_knowledge: overrides.http2_prior_knowledge.unwrap_or(self.http2_prior_knowledge),
+            auth: self.auth,
+        }
+    }
+
+    /// A client builder configured with these settings
+    pub(crate) fn client_builder(&self) -> reqwest::ClientBuilder {
+        let mut builder = reqwest::Client::builder()
+            .timeout(self.request_timeout)
+            .connect_timeout(self.connect_timeout)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .tcp_keepalive(self.tcp_keepalive);
+        if let Some(max) = self.pool_max_idle {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        if self.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge();
+        }
+        if !self.auth.is_empty() {
+            builder = builder.default_headers(self.auth.header_map());
+        }
+        builder
+    }
+}
+
+/// A chain's `[chain.http]` table in a chains file; every setting falls back to its `HTTP_*`
+/// variable
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct HttpOverrides {
+    timeout_ms: Option<u64>,
+    connect_timeout_ms: Option<u64>,
+    pool_max_idle: Option<usize>,
+    pool_idle_secs: Option<u64>,
+    /// `0` turns TCP keep-alive off
+    keepalive_secs: Option<u64>,
+    http2_prior_knowledge: Option<bool>,
+}
+
+/// Headers sent with every request to a chain's RPC endpoint, for private endpoints that
+/// want an `Authorization` header or an API key header rather than a key in the URL.
+/// Values are kept out of logs and marked sensitive on the wire.
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct RpcAuth {
+    // Validated header names, lowercased, and their values
+    headers: BTreeMap<String, String>,
+}
+
+impl RpcAuth {
+    /// `Authorization: Bearer {token}`
+    pub fn bearer(token: &str) -> Result<Self> {
+        let mut auth = Self::default();
+        auth.insert("authorization", &format!("Bearer {}", token))?;
+        Ok(auth)
+    }
+
+    /// `Authorization: Basic ...` from a username and optional password
+    pub fn basic(username: &str, password: Option<&str>) -> Result<Self> {
+        let credentials = format!("{}:{}", username, password.unwrap_or_default());
+        let mut auth = Self::default();
+        auth.insert(
+            "authorization",
+            &format!("Basic {}", base64::engine::general_purpose::STANDARD.encode(credentials)),
+        )?;
+        Ok(auth)
+    }
+
+    /// Send `name: value` too, replacing any header of that name
+    pub fn insert(&mut self, name: &str, value: &str) -> Result<()> {
+        let name = reqwest::header::HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| anyhow!("'{}' is not a valid header name", name))?;
+        reqwest::header::HeaderValue::from_str(value)
+            .map_err(|_| anyhow!("The value of header '{}' holds characters a header can't", name))?;
+        self.headers.insert(name.as_str().to_string(), value.to_string());
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+
+    /// Header names and values, for clients other than reqwest's
+    pub fn headers(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.headers.iter().map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    fn header_map(&self) -> reqwest::header::HeaderMap {
+        let mut map = reqwest::header::HeaderMap::new();
+        for (name, value) in self.headers() {
+            // Both were checked on insertion
+            if let (Ok(name), Ok(mut value)) = (
+                reqwest::header::HeaderName::from_bytes(name.as_bytes()),
+                reqwest::header::HeaderValue::from_str(value),
+            ) {
+                value.set_sensitive(true);
+                map.insert(name, value);
+            }
+        }
+        map
+    }
+}
+
+// Only the header names, so settings can be logged without their credentials
+impl fmt::Debug for RpcAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.headers.keys()).finish()
+    }
+}
+
+/// A chain's `[chain.auth]` table in a chains file; values may hold `${VAR}` references
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct AuthOverrides {
+    bearer_token: Option<String>,
+    basic_username: Option<String>,
+    basic_password: Option<String>,
+    /// Further headers, by name
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+}
+
+impl AuthOverrides {
+    /// The headers the table describes, with its values passed through `expand`
+    pub(crate) fn resolve(&self, expand: impl Fn(&str) -> Result<String>) -> Result<RpcAuth> {
+        let mut auth = match (&self.bearer_token, &self.basic_username) {
+            (Some(_), Some(_)) => return Err(anyhow!("Give either bearer_token or basic_username, not both")),
+            (Some(token), None) => RpcAuth::bearer(&expand(token)?)?,
+            (None, Some(username)) => {
+                let password = self.basic_password.as_deref().map(&expand).transpose()?;
+                RpcAuth::basic(&expand(username)?, password.as_deref())?
+            }
+            (None, None) if self.basic_password.is_some() => {
+                return Err(anyhow!("basic_password needs a basic_username"));
+            }
+            (None, None) => RpcAuth::default(),
+        };
+        for (name, value) in &self.headers {
+            auth.insert(name, &expand(value)?)?;
+        }
+        Ok(auth)
+    }
+}
@@ -0,0 +1,187 @@
+use anyhow::{anyhow, Context, Result};
+use futures::future::{self, BoxFuture};
+use log::info;
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::time::Duration;
+
+use crate::monitor::BlockObservation;
+use crate::sink::ObservationSink;
+
+/// Lines buffered before a write is sent without waiting for the flush interval
+const MAX_BATCH_LINES: usize = 500;
+
+/// Longest an observation waits in the buffer
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// An InfluxDB v2 write endpoint
+#[derive(Debug, Clone)]
+struct InfluxHttp {
+    client: reqwest::Client,
+    write_url: String,
+    token: Option<String>,
+}
+
+/// Writes every observation as InfluxDB line protocol, to the v2 HTTP write API, a file,
+/// or both. Each point is tagged with the chain, block number and vantage point and stamped
+/// with its receipt time in milliseconds. Fetched blocks also carry the RPC round trip of their request,
+/// blocks with a tracked L1 origin its `l1_origin_block` and `l1_origin_drift_ms`, and blocks
+/// that report them their `gas_used` and `tx_count`, and blocks from nodes whose health is
+/// checked `node_syncing`:
+///
+/// `block_timestamp,chain=Base,block_number=123,vantage=eu-west delta_ms=412i,block_timestamp_ms=1700000000000i,leap_affected=false,rpc_round_trip_ms=38i,gas_used=1843200i,tx_count=12i,node_syncing=false 1700000000412`
+pub struct InfluxSink {
+    measurement: String,
+    http: Option<InfluxHttp>,
+    file: Option<File>,
+    buffer: Vec<String>,
+}
+
+impl InfluxSink {
+    /// Build the sink from `INFLUX_URL` (with `INFLUX_ORG`, `INFLUX_BUCKET` and
+    /// `INFLUX_TOKEN`) and `INFLUX_FILE`; `None` when neither is set
+    pub fn from_env() -> Result<Option<Self>> {
+        let http = match env::var("INFLUX_URL") {
+            Ok(url) => {
+                let bucket = env::var("INFLUX_BUCKET").map_err(|_| anyhow!("INFLUX_URL is set but INFLUX_BUCKET is not"))?;
+                let org = env::var("INFLUX_ORG").unwrap_or_default();
+                let mut write_url = reqwest::Url::parse(&url)?.join("api/v2/write")?;
+                write_url
+                    .query_pairs_mut()
+                    .append_pair("org", &org)
+                    .append_pair("bucket", &bucket)
+                    .append_pair("precision", "ms");
+                info!("Writing observations to InfluxDB bucket {}", bucket);
+                Some(InfluxHttp {
+                    client: reqwest::Client::builder()
+                        .timeout(Duration::from_secs(10))
+                        .build()
+                        .unwrap_or_default(),
+                    write_url: write_url.to_string(),
+                    token: env::var("INFLUX_TOKEN").ok(),
+                })
+            }
+            Err(_) => None,
+        };
+        let file = match env::var("INFLUX_FILE") {
+            Ok(path) => {
+                info!("Writing observations as line protocol to {}", path);
+                Some(OpenOptions::new().create(true).append(true).open(path)?)
+            }
+            Err(_) => None,
+        };
+        if http.is_none() && file.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            measurement: env::var("INFLUX_MEASUREMENT").unwrap_or_else(|_| "block_timestamp".to_string()),
+            http,
+            file,
+            buffer: Vec::new(),
+        }))
+    }
+
+    fn line(&self, observation: &BlockObservation) -> String {
+        let round_trip = observation
+            .round_trip_ms()
+            .map(|ms| format!(",rpc_round_trip_ms={}i", ms))
+            .unwrap_or_default();
+        let l1_origin = observation
+            .l1_origin
+            .zip(observation.l1_drift_ms())
+            .map(|(origin, drift_ms)| format!(",l1_origin_block={}i,l1_origin_drift_ms={}i", origin.number, drift_ms))
+            .unwrap_or_default();
+        let contents = [("gas_used", observation.gas_used), ("tx_count", observation.tx_count)]
+            .iter()
+            .filter_map(|(field, value)| value.map(|value| format!(",{}={}i", field, value)))
+            .collect::<String>();
+        let syncing = observation
+            .node_syncing
+            .map(|syncing| format!(",node_syncing={}", syncing))
+            .unwrap_or_default();
+        let vantage = observation
+            .vantage
+            .as_deref()
+            .map(|vantage| format!(",vantage={}", escape(vantage)))
+            .unwrap_or_default();
+        format!(
+            "{},chain={},block_number={}{} delta_ms={}i,block_timestamp_ms={}i,leap_affected={}{}{}{}{} {}",
+            escape(&self.measurement),
+            escape(&observation.chain),
+            observation.block_number,
+            vantage,
+            observation.delta_ms,
+            observation.block_timestamp_ms,
+            observation.leap_affected,
+            round_trip,
+            l1_origin,
+            contents,
+            syncing,
+            observation.received_at.timestamp_millis()
+        )
+    }
+
+    // Send the buffered lines; on failure they are dropped rather than held indefinitely.
+    // The file and the HTTP API are both written even if the other fails.
+    async fn send(&mut self) -> Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let body = self.buffer.join("\n") + "\n";
+        self.buffer.clear();
+        let written = match &mut self.file {
+            Some(file) => file.write_all(body.as_bytes()).context("Failed to write line protocol file"),
+            None => Ok(()),
+        };
+        if let Some(http) = &self.http {
+            http.write(body).await?;
+        }
+        written
+    }
+}
+
+impl ObservationSink for InfluxSink {
+    fn name(&self) -> &str {
+        "InfluxDB"
+    }
+
+    fn write<'a>(&'a mut self, observation: &'a BlockObservation) -> BoxFuture<'a, Result<()>> {
+        self.buffer.push(self.line(observation));
+        if self.buffer.len() < MAX_BATCH_LINES {
+            return Box::pin(future::ready(Ok(())));
+        }
+        Box::pin(self.send())
+    }
+
+    fn flush(&mut self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(self.send())
+    }
+
+    fn flush_interval(&self) -> Option<Duration> {
+        Some(FLUSH_INTERVAL)
+    }
+}
+
+impl InfluxHttp {
+    async fn write(&self, body: String) -> Result<()> {
+        let mut request = self
+            .client
+            .post(&self.write_url)
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .body(body);
+        if let Some(token) = &self.token {
+            request = request.header("Authorization", format!("Token {}", token));
+        }
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("InfluxDB returned {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+// Measurement names and tag values escape commas, spaces and equals signs
+fn escape(value: &str) -> String {
+    value.replace(',', "\\,").replace(' ', "\\ ").replace('=', "\\=")
+}
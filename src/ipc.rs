@@ -0,0 +1,97 @@
+use anyhow::Result;
+use std::io;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+#[cfg(unix)]
+type Stream = tokio::net::UnixStream;
+
+// Never constructed: opening fails on platforms without Unix domain sockets
+#[cfg(not(unix))]
+type Stream = tokio::io::DuplexStream;
+
+/// The node socket an RPC URL names: `ipc:///path/to/geth.ipc`, or a bare absolute path
+pub(crate) fn socket_path(rpc_url: &str) -> Option<PathBuf> {
+    if let Some(path) = rpc_url.strip_prefix("ipc://") {
+        return Some(PathBuf::from(path));
+    }
+    rpc_url.starts_with('/').then(|| PathBuf::from(rpc_url))
+}
+
+#[cfg(unix)]
+async fn open(path: &Path) -> io::Result<Stream> {
+    tokio::net::UnixStream::connect(path).await
+}
+
+#[cfg(not(unix))]
+async fn open(_path: &Path) -> io::Result<Stream> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "IPC endpoints need Unix domain sockets"))
+}
+
+/// JSON-RPC over a local node's IPC socket, one request at a time.
+///
+/// Nodes don't frame their IPC responses beyond writing one JSON value after another, so
+/// each response is read until a complete value has arrived. The socket is connected on
+/// first use and reconnected after any failure.
+pub(crate) struct IpcConnection {
+    path: PathBuf,
+    stream: Option<Stream>,
+    buffer: Vec<u8>,
+}
+
+impl IpcConnection {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            stream: None,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Drop the connection; the next request opens a new one
+    pub(crate) fn disconnect(&mut self) {
+        self.stream = None;
+    }
+
+    /// Send `body` and wait for the JSON value the node answers with
+    pub(crate) async fn request(&mut self, body: &[u8]) -> Result<serde_json::Value> {
+        // The stream is only put back once its response has been read, so a request that
+        // fails or is abandoned midway (e.g. by the wedge timeout) can't leave a late
+        // response behind for the next one
+        let mut stream = match self.stream.take() {
+            Some(stream) => stream,
+            None => {
+                self.buffer.clear();
+                // Still an io::Error, so retries treat it as transient
+                open(&self.path).await.map_err(|e| {
+                    io::Error::new(e.kind(), format!("Failed to connect to {}: {}", self.path.display(), e))
+                })?
+            }
+        };
+        stream.write_all(body).await?;
+        stream.write_all(b"\n").await?;
+
+        loop {
+            let mut values = serde_json::Deserializer::from_slice(&self.buffer).into_iter::<serde_json::Value>();
+            match values.next() {
+                Some(Ok(value)) => {
+                    let consumed = values.byte_offset();
+                    self.buffer.drain(..consumed);
+                    self.stream = Some(stream);
+                    return Ok(value);
+                }
+                // Only part of the response has arrived so far
+                Some(Err(e)) if e.is_eof() => {}
+                Some(Err(e)) => return Err(e.into()),
+                None => {}
+            }
+            if stream.read_buf(&mut self.buffer).await? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("{} closed the connection", self.path.display()),
+                )
+                .into());
+            }
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use anyhow::{anyhow, Result};
+use futures::future::{self, BoxFuture};
+use log::info;
+use rskafka::client::partition::{Compression, PartitionClient, UnknownTopicHandling};
+use rskafka::client::{Client, ClientBuilder};
+use rskafka::record::Record;
+use rskafka::BackoffConfig;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use crate::monitor::BlockObservation;
+use crate::sink::ObservationSink;
+
+/// Messages buffered before they are produced without waiting for the flush interval
+const MAX_BATCH_MESSAGES: usize = 500;
+
+/// Longest an observation waits in the buffer
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a request keeps being retried before its batch is dropped
+const REQUEST_DEADLINE: Duration = Duration::from_secs(10);
+
+/// Publishes every observation to a Kafka topic as a JSON message keyed by chain. Keys are
+/// assigned to partitions like Kafka's default partitioner, so each chain's observations
+/// stay in order on one partition:
+///
+/// `{"chain":"Base","block_number":123,"block_timestamp_ms":1700000000000,"block_hash":"0x9c1f...","parent_hash":"0x4b2e...","gas_used":1843200,"tx_count":12,"receipt_time_ms":1700000000412,"received_at":"2023-11-14T22:13:20.412+00:00","delta_ms":412,"delta_direction":"past","delta_magnitude_ms":412,"leap_second_window":false,"rpc_round_trip_ms":38,"l1_origin_block":null,"l1_origin_drift_ms":null,"node_syncing":false,"anomaly_z":null,"vantage":"eu-west"}`
+pub struct KafkaSink {
+    client: Client,
+    topic: String,
+    partitions: Vec<i32>,
+    producers: HashMap<i32, PartitionClient>,
+    buffer: Vec<(i32, Record)>,
+}
+
+impl KafkaSink {
+    /// Connect to `brokers` and look up the partitions of `topic`, which must already exist
+    pub async fn connect(brokers: &[String], topic: &str) -> Result<Self> {
+        let client = ClientBuilder::new(brokers.to_vec())
+            .client_id(env!("CARGO_PKG_NAME"))
+            .backoff_config(BackoffConfig {
+                deadline: Some(REQUEST_DEADLINE),
+                ..BackoffConfig::default()
+            })
+            .build()
+            .await
+            .map_err(|e| anyhow!("Failed to connect to Kafka at {}: {}", brokers.join(","), e))?;
+        let partitions: Vec<i32> = client
+            .list_topics()
+            .await?
+            .into_iter()
+            .find(|t| t.name == topic)
+            .ok_or_else(|| anyhow!("Kafka topic '{}' does not exist", topic))?
+            .partitions
+            .into_iter()
+            .collect();
+        if partitions.is_empty() {
+            return Err(anyhow!("Kafka topic '{}' has no partitions", topic));
+        }
+        info!("Publishing observations to Kafka topic {} ({} partitions)", topic, partitions.len());
+        Ok(Self {
+            client,
+            topic: topic.to_string(),
+            partitions,
+            producers: HashMap::new(),
+            buffer: Vec::new(),
+        })
+    }
+
+    fn record(observation: &BlockObservation) -> Record {
+        let value = json!({
+            "chain": observation.chain,
+            "block_number": observation.block_number,
+            "block_timestamp_ms": observation.block_timestamp_ms,
+            "block_hash": observation.block_hash,
+            "parent_hash": observation.parent_hash,
+            "gas_used": observation.gas_used,
+            "tx_count": observation.tx_count,
+            "receipt_time_ms": observation.received_at.timestamp_millis(),
+            "received_at": observation.received_at.to_rfc3339(),
+            "delta_ms": observation.delta_ms,
+            "delta_direction": observation.timestamp_delta().direction,
+            "delta_magnitude_ms": observation.timestamp_delta().magnitude_ms,
+            "leap_second_window": observation.leap_affected,
+            "rpc_round_trip_ms": observation.round_trip_ms(),
+            "l1_origin_block": observation.l1_origin.map(|origin| origin.number),
+            "l1_origin_drift_ms": observation.l1_drift_ms(),
+            "node_syncing": observation.node_syncing,
+            "anomaly_z": observation.anomaly_z,
+            "vantage": observation.vantage,
+        });
+        Record {
+            key: Some(observation.chain.as_bytes().to_vec()),
+            value: Some(value.to_string().into_bytes()),
+            headers: BTreeMap::new(),
+            timestamp: observation.received_at,
+        }
+    }
+
+    // Partition of a message keyed `key`, as Kafka's default partitioner picks it
+    fn partition_for(&self, key: &str) -> i32 {
+        let hash = (murmur2(key.as_bytes()) & 0x7fff_ffff) as usize;
+        self.partitions[hash % self.partitions.len()]
+    }
+
+    // Produce the buffered messages, one request per partition; on failure they are
+    // dropped rather than held indefinitely. A failing partition doesn't keep the others'
+    // messages from being produced.
+    async fn send(&mut self) -> Result<()> {
+        let mut batches: BTreeMap<i32, Vec<Record>> = BTreeMap::new();
+        for (partition, record) in self.buffer.drain(..) {
+            batches.entry(partition).or_default().push(record);
+        }
+        let (mut dropped, mut error) = (0, None);
+        for (partition, records) in batches {
+            let count = records.len();
+            if let Err(e) = self.produce(partition, records).await {
+                dropped += count;
+                error.get_or_insert(anyhow!("partition {}: {}", partition, e));
+            }
+        }
+        match error {
+            Some(e) => Err(e.context(format!("{} observations were dropped", dropped))),
+            None => Ok(()),
+        }
+    }
+
+    async fn produce(&mut self, partition: i32, records: Vec<Record>) -> Result<()> {
+        if !self.producers.contains_key(&partition) {
+            let producer = self
+                .client
+                .partition_client(self.topic.clone(), partition, UnknownTopicHandling::Retry)
+                .await?;
+            self.producers.insert(partition, producer);
+        }
+        self.producers[&partition].produce(records, Compression::NoCompression).await?;
+        Ok(())
+    }
+}
+
+impl ObservationSink for KafkaSink {
+    fn name(&self) -> &str {
+        "Kafka"
+    }
+
+    fn write<'a>(&'a mut self, observation: &'a BlockObservation) -> BoxFuture<'a, Result<()>> {
+        let partition = self.partition_for(&observation.chain);
+        self.buffer.push((partition, Self::record(observation)));
+        if self.buffer.len() < MAX_BATCH_MESSAGES {
+            return Box::pin(future::ready(Ok(())));
+        }
+        Box::pin(self.send())
+    }
+
+    fn flush(&mut self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(self.send())
+    }
+
+    fn flush_interval(&self) -> Option<Duration> {
+        Some(FLUSH_INTERVAL)
+    }
+}
+
+// The 32-bit murmur2 hash Kafka's clients partition keyed messages with
+fn murmur2(data: &[u8]) -> u32 {
+    const SEED: u32 = 0x9747_b28c;
+    const M: u32 = 0x5bd1_e995;
+    const R: u32 = 24;
+
+    let mut h = SEED ^ data.len() as u32;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h = h.wrapping_mul(M);
+        h ^= k;
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, byte) in tail.iter().enumerate() {
+            h ^= (*byte as u32) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> 13;
+    h = h.wrapping_mul(M);
+    h ^= h >> 15;
+    h
+}
@@ -0,0 +1,183 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use std::env;
+
+/// Days at whose end (23:59:60 UTC) a leap second was inserted
+const LEAP_SECOND_DAYS: &[(i32, u32, u32)] = &[
+    (1972, 6, 30),
+    (1972, 12, 31),
+    (1973, 12, 31),
+    (1974, 12, 31),
+    (1975, 12, 31),
+    (1976, 12, 31),
+    (1977, 12, 31),
+    (1978, 12, 31),
+    (1979, 12, 31),
+    (1981, 6, 30),
+    (1982, 6, 30),
+    (1983, 6, 30),
+    (1985, 6, 30),
+    (1987, 12, 31),
+    (1989, 12, 31),
+    (1990, 12, 31),
+    (1992, 6, 30),
+    (1993, 6, 30),
+    (1994, 6, 30),
+    (1995, 12, 31),
+    (1997, 6, 30),
+    (1998, 12, 31),
+    (2005, 12, 31),
+    (2008, 12, 31),
+    (2012, 6, 30),
+    (2015, 6, 30),
+    (2016, 12, 31),
+];
+
+/// How clocks (ours, the sequencer's, the provider's) are assumed to handle a leap second
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeapPolicy {
+    /// Don't tag anything
+    Ignore,
+    /// Clocks step or repeat a second at the leap instant
+    Step,
+    /// Clocks smear the second linearly from noon to noon (Google, AWS)
+    Smear24h,
+}
+
+impl LeapPolicy {
+    fn parse(value: &str) -> Result<Self> {
+        match value {
+            "none" => Ok(LeapPolicy::Ignore),
+            "step" => Ok(LeapPolicy::Step),
+            "smear-24h" => Ok(LeapPolicy::Smear24h),
+            other => Err(anyhow!(
+                "Invalid LEAP_POLICY '{}' (expected none, step or smear-24h)",
+                other
+            )),
+        }
+    }
+
+    // Half-width of the window around a leap instant where clocks can disagree
+    fn half_window(&self) -> Duration {
+        match self {
+            LeapPolicy::Ignore => Duration::zero(),
+            LeapPolicy::Step => Duration::seconds(2),
+            LeapPolicy::Smear24h => Duration::hours(12),
+        }
+    }
+}
+
+/// Known leap-second instants and the window around them where deltas are unreliable
+#[derive(Debug, Clone)]
+pub struct LeapSeconds {
+    policy: LeapPolicy,
+    instants: Vec<DateTime<Utc>>,
+}
+
+impl LeapSeconds {
+    /// Build from `LEAP_POLICY` (default `smear-24h`) and `LEAP_SECOND_DATES`, a
+    /// comma-separated list of additional announced leap days (YYYY-MM-DD)
+    pub fn from_env() -> Result<Self> {
+        let policy = match env::var("LEAP_POLICY") {
+            Ok(val) => LeapPolicy::parse(&val)?,
+            Err(_) => LeapPolicy::Smear24h,
+        };
+
+        let mut days = Vec::new();
+        if let Ok(extra) = env::var("LEAP_SECOND_DATES") {
+            for day in extra.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+                days.push(
+                    NaiveDate::parse_from_str(day, "%Y-%m-%d")
+                        .map_err(|e| anyhow!("Invalid leap second date '{}': {}", day, e))?,
+                );
+            }
+        }
+
+        Ok(Self::new(policy, days))
+    }
+
+    // The known leap seconds plus those at the end of each of `extra_days`
+    fn new(policy: LeapPolicy, extra_days: Vec<NaiveDate>) -> Self {
+        // The leap second sits between the end of the listed day and midnight
+        let instants = LEAP_SECOND_DAYS
+            .iter()
+            .filter_map(|(y, m, d)| NaiveDate::from_ymd_opt(*y, *m, *d))
+            .chain(extra_days)
+            .filter_map(|day| day.succ_opt())
+            .filter_map(|next| next.and_hms_opt(0, 0, 0))
+            .map(|midnight| midnight.and_utc())
+            .collect();
+        Self { policy, instants }
+    }
+
+    pub fn policy(&self) -> LeapPolicy {
+        self.policy
+    }
+
+    /// Whether an instant falls inside a leap-second window under the configured policy
+    pub fn affects(&self, instant: DateTime<Utc>) -> bool {
+        if self.policy == LeapPolicy::Ignore {
+            return false;
+        }
+        let half_window = self.policy.half_window();
+        self.instants
+            .iter()
+            .any(|leap| (instant - *leap).abs() <= half_window)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2016-12-31T00:00:00Z plus `secs`; the last leap second so far ends 2016-12-31
+    fn around_2016_leap(secs: i64) -> DateTime<Utc> {
+        NaiveDate::from_ymd_opt(2017, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap().and_utc() + Duration::seconds(secs)
+    }
+
+    #[test]
+    fn table_ends_with_the_2016_leap_second() {
+        let leaps = LeapSeconds::new(LeapPolicy::Step, Vec::new());
+        assert_eq!(leaps.instants.len(), 27);
+        assert_eq!(leaps.instants.last(), Some(&around_2016_leap(0)));
+        assert_eq!(
+            leaps.instants.first(),
+            Some(&NaiveDate::from_ymd_opt(1972, 7, 1).unwrap().and_hms_opt(0, 0, 0).unwrap().and_utc())
+        );
+    }
+
+    #[test]
+    fn step_window_is_two_seconds_either_side() {
+        let leaps = LeapSeconds::new(LeapPolicy::Step, Vec::new());
+        assert!(leaps.affects(around_2016_leap(-2)));
+        assert!(leaps.affects(around_2016_leap(0)));
+        assert!(leaps.affects(around_2016_leap(2)));
+        assert!(!leaps.affects(around_2016_leap(-3)));
+        assert!(!leaps.affects(around_2016_leap(3)));
+    }
+
+    #[test]
+    fn smear_window_runs_noon_to_noon() {
+        let leaps = LeapSeconds::new(LeapPolicy::Smear24h, Vec::new());
+        let twelve_hours = 12 * 3600;
+        assert!(leaps.affects(around_2016_leap(-twelve_hours)));
+        assert!(leaps.affects(around_2016_leap(-60)));
+        assert!(leaps.affects(around_2016_leap(twelve_hours)));
+        assert!(!leaps.affects(around_2016_leap(-twelve_hours - 1)));
+        assert!(!leaps.affects(around_2016_leap(twelve_hours + 1)));
+        // A day earlier or later is clear of the smear
+        assert!(!leaps.affects(around_2016_leap(-86_400)));
+    }
+
+    #[test]
+    fn ignore_tags_nothing_and_extra_days_are_added() {
+        assert!(!LeapSeconds::new(LeapPolicy::Ignore, Vec::new()).affects(around_2016_leap(0)));
+
+        let announced = NaiveDate::from_ymd_opt(2030, 6, 30).unwrap();
+        let leaps = LeapSeconds::new(LeapPolicy::Step, vec![announced]);
+        let midnight = NaiveDate::from_ymd_opt(2030, 7, 1).unwrap().and_hms_opt(0, 0, 0).unwrap().and_utc();
+        assert!(leaps.affects(midnight + Duration::seconds(1)));
+        assert_eq!(LeapPolicy::parse("smear-24h").unwrap(), LeapPolicy::Smear24h);
+        assert!(LeapPolicy::parse("smear").is_err());
+    }
+}
@@ -0,0 +1,164 @@
+//! Measure how block timestamps on EVM chains compare with the time blocks are observed.
+//!
+//! The `block-timestamp-logger` binary is a thin wrapper around this crate. To embed the
+//! monitoring in another tool, build a [`Config`] and consume [`run_monitoring`]:
+//!
+//! ```no_run
+//! use block_timestamp_logger::{run_monitoring, Config};
+//! use futures::StreamExt;
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let config = Config::from_env(None)?;
+//! let mut observations = Box::pin(run_monitoring(config));
+//! while let Some(observation) = observations.next().await {
+//!     println!("{} #{}: {}ms", observation.chain, observation.block_number, observation.delta_ms);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use futures::Stream;
+use std::collections::HashMap;
+use std::env;
+use tokio::sync::mpsc;
+use tokio::time;
+
+mod adapter;
+pub mod aggregate;
+mod alert;
+pub mod analyze;
+mod anomaly;
+mod api;
+mod arbitrum;
+pub mod archive;
+pub mod bench;
+mod breaker;
+mod cadence;
+#[cfg(feature = "charts")]
+mod charts;
+mod checkpoint;
+mod clock;
+mod columnar;
+mod combined;
+mod compare;
+mod config;
+mod consistency;
+pub mod convert;
+mod daemon;
+mod dashboard;
+mod dataset;
+mod delta;
+mod digest;
+mod distribution;
+#[cfg(feature = "duckdb")]
+mod duckdb;
+mod evm;
+mod flashblocks;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod health;
+mod histogram;
+mod hourly;
+mod html;
+mod http;
+mod influx;
+mod ipc;
+#[cfg(feature = "kafka")]
+mod kafka;
+mod leap;
+pub mod logger;
+pub mod manifest;
+pub mod merge;
+mod metrics;
+mod monitor;
+mod notify;
+mod observations;
+mod outage;
+#[cfg(feature = "postgres")]
+mod postgres;
+pub mod presets;
+pub mod query;
+pub mod redact;
+mod reorg;
+pub mod replay;
+mod report;
+mod retry;
+mod rotation;
+mod schedule;
+mod schema;
+mod server;
+mod simulate;
+mod sink;
+mod skew;
+mod slo;
+mod stats;
+mod statsd;
+mod store;
+mod subscription;
+mod supervisor;
+pub mod telemetry;
+mod timeseries;
+mod timestamp;
+mod timezone;
+mod upload;
+pub mod vantage;
+
+pub use adapter::{AdapterCounters, BlockBatch, BlockHeader, ChainAdapter, L1Origin, NodeHealth, StaleResponse};
+pub use alert::DeltaAlert;
+pub use anomaly::AnomalySettings;
+pub use breaker::BreakerConfig;
+pub use cadence::AdaptivePolling;
+pub use clock::{ClockSync, Stamp};
+pub use columnar::OutputFormat;
+pub use config::Config;
+pub use delta::{DeltaDirection, TimestampDelta};
+pub use histogram::DeltaHistogram;
+pub use http::{HttpSettings, RpcAuth};
+pub use leap::{LeapPolicy, LeapSeconds};
+pub use monitor::{BlockObservation, BlockTag, ChainInfo, ChainKind, ChainMonitor, MonitorOptions};
+pub use presets::ChainPreset;
+pub use retry::RetryPolicy;
+pub use rotation::RotationPolicy;
+pub use sink::{ObservationSink, ObservationSinkKind, ReportSink};
+pub use slo::SloThresholds;
+pub use statsd::StatsdFormat;
+pub use stats::{ChainStats, RawSample};
+pub use store::StorageBackend;
+pub use supervisor::SupervisorConfig;
+pub use timestamp::TimestampDecoding;
+pub use timezone::ReportTimezone;
+pub use vantage::Vantage;
+
+use crate::notify::Notifiers;
+
+/// Monitor the configured chains in a background task, yielding every block observation.
+///
+/// Monitoring stops once `config.duration` has elapsed or the stream is dropped. Must be
+/// called from within a Tokio runtime.
+pub fn run_monitoring(config: Config) -> impl Stream<Item = BlockObservation> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let duration_limit = config.duration;
+    let monitors = monitor::spawn_monitors(config, Notifiers::default(), Some(sender.clone()), None, HashMap::new());
+    tokio::spawn(async move {
+        match duration_limit {
+            Some(limit) => {
+                tokio::select! {
+                    _ = time::sleep(limit) => {}
+                    _ = sender.closed() => {}
+                }
+            }
+            None => sender.closed().await,
+        }
+        // Ends the stream once the monitors' senders are gone too
+        drop(monitors);
+    });
+    monitor::observation_stream(receiver)
+}
+
+// Load an environment variable as a u64 with a default value
+pub(crate) fn get_env_as_u64(key: &str, default: u64) -> u64 {
+    match env::var(key) {
+        Ok(val) => val.parse().unwrap_or(default),
+        Err(_) => default,
+    }
+}
@@ -0,0 +1,1002 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use log::{error, info, warn};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::env;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+use tokio::time;
+use tracing::info_span;
+
+use crate::alert::DeltaAlert;
+use crate::api::{ControlAction, ControlRequest, StatsApi};
+#[cfg(feature = "charts")]
+use crate::charts;
+use crate::checkpoint::Checkpoint;
+use crate::columnar::OutputFormat;
+use crate::config::Config;
+use crate::daemon::{self, ConfigWatcher, ReportSignal};
+use crate::dashboard::Dashboard;
+#[cfg(feature = "duckdb")]
+use crate::duckdb::DuckdbSink;
+use crate::health::HealthCheck;
+use crate::html;
+use crate::influx::InfluxSink;
+#[cfg(feature = "grpc")]
+use crate::grpc::GrpcService;
+#[cfg(feature = "kafka")]
+use crate::kafka::KafkaSink;
+#[cfg(feature = "postgres")]
+use crate::postgres::PostgresSink;
+use crate::digest::DigestSchedule;
+use crate::leap::LeapPolicy;
+use crate::metrics::Metrics;
+use crate::monitor::MonitorSet;
+use crate::notify::Notifiers;
+use crate::observations::ObservationLog;
+use crate::report::{self, RunInfo, StatsSnapshot};
+use crate::rotation::{CsvRotation, RotationPolicy};
+use crate::sink::{ObservationSink, ObservationSinkKind, ObservationSinks, ObservationWebhook, ReportSink, ReportSinks};
+use crate::skew::CrossChainSkew;
+use crate::slo::SloThresholds;
+use crate::statsd::{StatsdFormat, StatsdSink};
+use crate::stats::ChainStats;
+use crate::timeseries::DeltaTimeseries;
+use crate::timezone::ReportTimezone;
+use crate::upload::ObjectStore;
+use crate::store::{SqliteStore, StorageBackend};
+use crate::vantage::Vantage;
+use crate::{archive, combined, manifest, monitor};
+
+/// How often the health file is rewritten while healthy
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Command-line settings for a run, on top of the environment configuration
+pub struct RunOptions<'a> {
+    /// TOML file listing the chains, instead of the `*_RPC_URL` variables
+    pub chains_file: Option<&'a Path>,
+    /// Chain presets to monitor instead, each an id or `id=URL`
+    pub presets: &'a [String],
+    /// Serve Prometheus metrics on this address
+    pub metrics_addr: Option<SocketAddr>,
+    /// Serve the live stats as JSON on this address
+    pub api_addr: Option<SocketAddr>,
+    /// Stream observations and serve the live stats over gRPC on this address
+    pub grpc_addr: Option<SocketAddr>,
+    /// Heartbeat file, rewritten every few seconds while every chain is receiving blocks
+    pub health_file: Option<&'a Path>,
+    pub storage: StorageBackend,
+    /// File format of the per-block and delta datasets with CSV storage
+    pub format: OutputFormat,
+    /// Append the per-block and delta CSVs to rotated files instead of rewriting them
+    pub rotation: Option<RotationPolicy>,
+    /// Database for SQLite storage; defaults to `run.db` in `OUTPUT_DIR`
+    pub db_path: Option<&'a Path>,
+    pub alert: Option<DeltaAlert>,
+    /// Per-block records each chain keeps for its detailed CSV
+    pub max_raw_samples: Option<usize>,
+    /// Vantage point the observations are recorded under, instead of the hostname
+    pub vantage_label: Option<&'a str>,
+    /// Show a live dashboard instead of the periodic log report until the user quits
+    pub tui: bool,
+    /// Simulate every chain instead of reaching it over RPC
+    pub simulate: bool,
+    /// Continue from the checkpoint an earlier run left in `OUTPUT_DIR`
+    pub resume: bool,
+    /// How often to report and write the stats files
+    pub report_interval: Duration,
+    /// Render each chain's delta over time and histogram as PNGs with every report
+    pub charts: bool,
+    /// Where reports go
+    pub report_sinks: &'a [ReportSink],
+    /// Receives the JSON summary with every report when the webhook sink is selected
+    pub report_webhook_url: Option<String>,
+    /// Optional observation sinks to write every block to
+    pub observation_sinks: &'a [ObservationSinkKind],
+    /// Receives batches of observations when the webhook observation sink is selected
+    pub observation_webhook_url: Option<String>,
+    /// Sinks of an embedding program, which get every observation alongside the others
+    pub custom_sinks: Vec<Box<dyn ObservationSink>>,
+    /// Run until stopped, applying edits to `chains_file` as they are saved
+    pub daemon: bool,
+    /// Publish observations to Kafka through these brokers, unless empty
+    pub kafka_brokers: &'a [String],
+    /// Kafka topic the observations are published to
+    pub kafka_topic: &'a str,
+    /// Send every observation to the StatsD agent at this `host:port`
+    pub statsd_addr: Option<&'a str>,
+    /// Prefix of the StatsD metric names
+    pub statsd_prefix: &'a str,
+    pub statsd_format: StatsdFormat,
+    /// Also store the run in this PostgreSQL database
+    pub pg_url: Option<&'a str>,
+    /// Thresholds the final stats are checked against, failing the run if any is broken
+    pub slo: SloThresholds,
+}
+
+// Fold a monitor's stats update into the chain's stats, returning them
+fn absorb_stats(chain_stats: &mut HashMap<String, ChainStats>, update: ChainStats) -> &ChainStats {
+    match chain_stats.entry(update.chain_name.clone()) {
+        Entry::Occupied(entry) => {
+            let stats = entry.into_mut();
+            stats.absorb(update);
+            stats
+        }
+        Entry::Vacant(entry) => entry.insert(update),
+    }
+}
+
+// Write a chain's stats files, appending its new blocks to the rotated CSVs when rotating
+fn write_csv(
+    stats: &ChainStats,
+    output_path: &Path,
+    format: OutputFormat,
+    rotation: Option<&mut CsvRotation>,
+) -> Result<()> {
+    match rotation {
+        Some(rotation) => {
+            stats.write_summaries(output_path)?;
+            rotation.append(stats, Utc::now())
+        }
+        None => stats.write_to_csv(output_path, format),
+    }
+}
+
+// Queue the rotated CSVs completed since the last call for upload, when there is a bucket
+fn queue_rotated(rotation: Option<&mut CsvRotation>, uploads: Option<&UnboundedSender<PathBuf>>) {
+    let Some(rotation) = rotation else {
+        return;
+    };
+    for path in rotation.take_completed() {
+        if let Some(uploads) = uploads {
+            let _ = uploads.send(path);
+        }
+    }
+}
+
+// Render every chain's charts next to its stats files
+#[cfg(feature = "charts")]
+fn write_charts(chain_stats: &HashMap<String, ChainStats>, output_path: &Path, timezone: ReportTimezone) {
+    for (name, stats) in chain_stats {
+        if let Err(e) = charts::write_charts(output_path, stats, timezone) {
+            error!("Failed to write charts for {}: {:#}", name, e);
+        }
+    }
+}
+
+// Queue each chain's section of `summary` to be stored as a stats snapshot
+fn send_snapshots(
+    snapshots: Option<&UnboundedSender<StatsSnapshot>>,
+    summary: &serde_json::Value,
+    now: DateTime<Utc>,
+) {
+    let (Some(snapshots), Some(chains)) = (snapshots, summary["chains"].as_array()) else {
+        return;
+    };
+    for chain in chains {
+        let _ = snapshots.send((now, chain.clone()));
+    }
+}
+
+/// Monitor the configured chains until the duration limit is reached, writing reports
+/// to `OUTPUT_DIR` (or a SQLite database) as it goes, serving Prometheus metrics and
+/// live stats and sending delta alerts as `run_options` asks
+pub async fn run(run_options: RunOptions<'_>) -> Result<()> {
+    let RunOptions {
+        chains_file,
+        presets,
+        metrics_addr,
+        api_addr,
+        grpc_addr,
+        health_file,
+        storage,
+        format,
+        rotation,
+        db_path,
+        alert,
+        max_raw_samples,
+        vantage_label,
+        tui,
+        simulate,
+        resume,
+        report_interval,
+        charts,
+        report_sinks,
+        report_webhook_url,
+        observation_sinks: observation_sink_kinds,
+        observation_webhook_url,
+        custom_sinks,
+        daemon,
+        kafka_brokers,
+        kafka_topic,
+        statsd_addr,
+        statsd_prefix,
+        statsd_format,
+        pg_url,
+        slo,
+    } = run_options;
+
+    format.check_supported()?;
+    if rotation.is_some() && (storage != StorageBackend::Csv || format != OutputFormat::Csv) {
+        return Err(anyhow!("Rotating the per-block CSVs needs --storage csv and --format csv"));
+    }
+    #[cfg(not(feature = "kafka"))]
+    if !kafka_brokers.is_empty() {
+        return Err(anyhow!(
+            "Publishing to Kafka topic {} needs a build with the `kafka` feature",
+            kafka_topic
+        ));
+    }
+    #[cfg(not(feature = "grpc"))]
+    if let Some(addr) = grpc_addr {
+        return Err(anyhow!("Serving gRPC on {} needs a build with the `grpc` feature", addr));
+    }
+    #[cfg(not(feature = "postgres"))]
+    if pg_url.is_some() {
+        return Err(anyhow!("Storing the run in PostgreSQL needs a build with the `postgres` feature"));
+    }
+    #[cfg(not(feature = "duckdb"))]
+    if let Ok(path) = env::var("DUCKDB_PATH") {
+        return Err(anyhow!("Writing to DuckDB database {} needs a build with the `duckdb` feature", path));
+    }
+    #[cfg(not(feature = "charts"))]
+    if charts {
+        return Err(anyhow!("Rendering charts needs a build with the `charts` feature"));
+    }
+    
+    // Get configuration from environment variables
+    let output_dir = env::var("OUTPUT_DIR").unwrap_or_else(|_| "./logs".to_string());
+    
+    // Create output directory
+    let output_path = PathBuf::from(output_dir);
+    std::fs::create_dir_all(&output_path)?;
+    
+    info!("Block Timestamp Logger starting up");
+    let mut config = match (presets.is_empty(), simulate) {
+        (true, true) => Config::simulated(chains_file)?,
+        (true, false) => Config::from_env(chains_file)?,
+        (false, _) => {
+            let mut config = Config::from_presets(presets)?;
+            if simulate {
+                config.simulate();
+            }
+            config
+        }
+    };
+    if let Some(max_raw_samples) = max_raw_samples {
+        config.options.max_raw_samples = max_raw_samples;
+    }
+    if vantage_label.is_some() {
+        config.options.vantage = Vantage::detect(vantage_label);
+    }
+    info!("Observing from vantage point {}", config.options.vantage.label);
+    
+    // A daemon runs until stopped and follows edits to the chains file
+    let mut watcher = match (daemon, chains_file) {
+        (false, _) => None,
+        (true, Some(path)) => {
+            config.duration = None;
+            info!("Running as a daemon; changes to {} are applied as they are saved", path.display());
+            Some(ConfigWatcher::start(path)?)
+        }
+        (true, None) => return Err(anyhow!("Daemon mode needs a chains file (--config) to watch")),
+    };
+    // Pick up an interrupted run where its last checkpoint left off
+    let checkpoint = if resume { Checkpoint::load(&output_path)? } else { None };
+    let (started_at, resumed) = match checkpoint {
+        Some(checkpoint) => {
+            info!(
+                "Resuming run started {} from its checkpoint of {}",
+                config.options.timezone.format(checkpoint.started_at, "%Y-%m-%d %H:%M:%S %Z"),
+                config.options.timezone.format(checkpoint.saved_at, "%Y-%m-%d %H:%M:%S %Z")
+            );
+            (checkpoint.started_at, checkpoint.chains)
+        }
+        None => {
+            if resume {
+                warn!("No checkpoint in {}; starting a new run", output_path.display());
+            }
+            (Utc::now(), HashMap::new())
+        }
+    };
+    let run_info = RunInfo::new(&config, started_at);
+    let rotation = rotation.map(|policy| {
+        let mut rotation = CsvRotation::new(output_path.clone(), policy);
+        rotation.resume(&resumed);
+        rotation
+    });
+    
+    let options = &config.options;
+    if options.leap_seconds.policy() != LeapPolicy::Ignore {
+        info!("Leap-second policy: {:?}", options.leap_seconds.policy());
+    }
+    info!("Reporting times in {}", options.timezone.name());
+    if options.strict_schema {
+        info!("Strict RPC schema validation enabled");
+    }
+    if config.adaptive_polling.is_some() {
+        info!("Adaptive polling enabled; chains are polled around their expected blocks");
+    }
+    
+    // Measure our own clock before the first block is stamped with it
+    let clock = options.clock.clone();
+    if clock.is_enabled() {
+        match clock.check().await {
+            Ok(offset) => info!("Local clock offset: {:+}ms", offset),
+            Err(e) => warn!("Clock check failed, deltas are uncorrected until one succeeds: {}", e),
+        }
+        tokio::spawn(clock.run());
+    }
+    archive::write_config_snapshot(&output_path, &config, started_at)?;
+    let timezone = options.timezone;
+    let duration_limit = config.duration;
+    let sinks = ReportSinks::new(report_sinks, &output_path, report_webhook_url, timezone)?;
+    
+    // With SQLite storage, observations and stats snapshots are appended to a database
+    let db_path = match storage {
+        StorageBackend::Csv => None,
+        StorageBackend::Sqlite => Some(db_path.map_or_else(|| output_path.join("run.db"), Path::to_path_buf)),
+    };
+    let store = match &db_path {
+        Some(db_path) => {
+            info!("Storing observations and stats in {}", db_path.display());
+            Some(SqliteStore::open(db_path)?)
+        }
+        None => None,
+    };
+    
+    // Optional notifications and scheduled digests
+    let notifiers = Notifiers::from_env();
+    let digest = DigestSchedule::from_env(Utc::now(), timezone)?;
+    if let Some(schedule) = &digest {
+        if notifiers.is_empty() {
+            info!("Digest scheduled but no notifiers configured; digests will only be logged");
+        }
+        info!("Next digest at {}", timezone.format(schedule.next_due(), "%Y-%m-%d %H:%M %Z"));
+    }
+    
+    // Optional Prometheus endpoint, fed from the same stats updates as the reports and, as
+    // an observation sink, from every block
+    let metrics = match metrics_addr {
+        Some(addr) => {
+            let listener = TcpListener::bind(addr).await?;
+            let metrics = Arc::new(Metrics::default());
+            tokio::spawn(metrics.clone().serve(listener));
+            Some(metrics)
+        }
+        None => None,
+    };
+    
+    // Whether each chain is still receiving blocks, for the API's probes and the heartbeat file
+    let health = HealthCheck::from_env(config.chains.iter().map(|chain| chain.name.clone()).collect(), Utc::now());
+    
+    // Optional JSON API over the same stats, whose pause and resume requests the main loop
+    // carries out
+    let (control_sender, mut control_receiver) = mpsc::unbounded_channel();
+    let api = match api_addr {
+        Some(addr) => {
+            let listener = TcpListener::bind(addr).await?;
+            let api = Arc::new(StatsApi::new(health.clone(), control_sender));
+            tokio::spawn(api.clone().serve(listener));
+            Some(api)
+        }
+        None => None,
+    };
+    
+    // Every observation sink gets each block in its own task, so one that fails or falls
+    // behind holds up none of the others. Raw per-block records go to the database when
+    // there is one, otherwise to the observation logs selected.
+    let mut observation_sinks = ObservationSinks::default();
+    if let Some(db_path) = &db_path {
+        observation_sinks.push(Box::new(SqliteStore::open(db_path)?));
+    }
+    for kind in ObservationSinkKind::value_variants().iter().filter(|kind| observation_sink_kinds.contains(kind)) {
+        match kind {
+            ObservationSinkKind::Ndjson => {
+                if db_path.is_none() && env::var("OBSERVATION_LOG").map_or(true, |val| val != "false") {
+                    observation_sinks.push(Box::new(ObservationLog::new(output_path.clone())));
+                }
+            }
+            ObservationSinkKind::Csv => observation_sinks.push(Box::new(ObservationLog::csv(output_path.clone()))),
+            ObservationSinkKind::Webhook => {
+                let url = observation_webhook_url
+                    .clone()
+                    .ok_or_else(|| anyhow!("The webhook observation sink needs --observation-webhook-url"))?;
+                observation_sinks.push(Box::new(ObservationWebhook::new(url)));
+            }
+        }
+    }
+    if let Some(metrics) = &metrics {
+        observation_sinks.push(Box::new(metrics.clone()));
+    }
+    // Line protocol for InfluxDB
+    if let Some(influx) = InfluxSink::from_env()? {
+        observation_sinks.push(Box::new(influx));
+    }
+    // DuckDB gets the observations appended in batches, for SQL without a conversion step
+    #[cfg(feature = "duckdb")]
+    if let Some(duckdb) = DuckdbSink::from_env()? {
+        observation_sinks.push(Box::new(duckdb));
+    }
+    // StatsD gets each block's delta
+    if let Some(addr) = statsd_addr {
+        observation_sinks.push(Box::new(StatsdSink::connect(addr, statsd_prefix, statsd_format).await?));
+    }
+    // Kafka gets each observation keyed by chain
+    #[cfg(feature = "kafka")]
+    if !kafka_brokers.is_empty() {
+        observation_sinks.push(Box::new(KafkaSink::connect(kafka_brokers, kafka_topic).await?));
+    }
+    // gRPC subscribers get each observation, and the live stats with the API's
+    #[cfg(feature = "grpc")]
+    let grpc = match grpc_addr {
+        Some(addr) => {
+            let listener = TcpListener::bind(addr).await?;
+            let grpc = Arc::new(GrpcService::default());
+            tokio::spawn(grpc.clone().serve(listener));
+            observation_sinks.push(Box::new(grpc.clone()));
+            Some(grpc)
+        }
+        None => None,
+    };
+    for sink in custom_sinks {
+        observation_sinks.push(sink);
+    }
+    let (mut observer, observation_writer) = if observation_sinks.is_empty() {
+        (None, None)
+    } else {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (Some(sender), Some(tokio::spawn(observation_sinks.run(receiver))))
+    };
+    
+    // Delta alerts check each observation on its way to the sinks. The threshold is shared
+    // with the copy kept here, so a reloaded chains file can change it.
+    let alert_control = alert.clone();
+    if let Some(alert) = alert {
+        if let Some(threshold_ms) = config.alert_threshold_ms {
+            alert.set_threshold_ms(threshold_ms);
+        }
+        info!(
+            "Alerting on deltas beyond {}ms{}",
+            alert.threshold_ms(),
+            if alert.alerts_on_anomalies() { " and on anomalous deltas" } else { "" }
+        );
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(alert.run(receiver, observer.take(), notifiers.clone()));
+        observer = Some(sender);
+    }
+
+    // PostgreSQL gets each observation first, and each chain's summary with every report
+    #[cfg(feature = "postgres")]
+    let (postgres_writer, postgres_snapshots) = match pg_url {
+        Some(url) => {
+            let postgres = PostgresSink::connect(url, &config, started_at).await?;
+            let (sender, receiver) = mpsc::unbounded_channel();
+            let (snapshot_sender, snapshot_receiver) = mpsc::unbounded_channel();
+            let writer = tokio::spawn(postgres.run(receiver, observer.take(), snapshot_receiver));
+            observer = Some(sender);
+            (Some(writer), Some(snapshot_sender))
+        }
+        None => (None, None),
+    };
+    #[cfg(not(feature = "postgres"))]
+    let (postgres_writer, postgres_snapshots): (Option<tokio::task::JoinHandle<()>>, Option<UnboundedSender<StatsSnapshot>>) =
+        (None, None);
+    
+    // Rotated files are uploaded to an object store in the background, when one is set
+    let (uploads, upload_writer) = match ObjectStore::from_env(started_at)? {
+        Some(store) => {
+            let (sender, receiver) = mpsc::unbounded_channel();
+            (Some(sender), Some(tokio::spawn(store.run(receiver))))
+        }
+        None => (None, None),
+    };
+    
+    // Each chain polls in its own task and reports its stats back here
+    let (stats_sender, mut stats_receiver) = mpsc::unbounded_channel();
+    // A reloaded chains file keeps the vantage point the run started with
+    let vantage = config.options.vantage.clone();
+    let monitors = monitor::spawn_monitors(config, notifiers.clone(), observer, Some(stats_sender), resumed.clone());
+
+    // Set up intervals
+    let mut report_ticker = time::interval(report_interval);
+    let mut report_signal = ReportSignal::listen();
+
+    // Cross-chain skew is sampled on wall-clock minute boundaries
+    let skew = CrossChainSkew::new(&output_path);
+    let first_sample = (CrossChainSkew::first_sample_at(Utc::now()) - Utc::now()).to_std().unwrap_or_default();
+    let mut skew_ticker = time::interval_at(time::Instant::now() + first_sample, time::Duration::from_secs(60));
+    skew_ticker.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+
+    // Each chain's deltas over every report interval
+    let timeseries = DeltaTimeseries::new(&output_path, Utc::now());
+
+    // Duration limit
+    let deadline = async {
+        match duration_limit {
+            Some(limit) => time::sleep(limit).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(deadline);
+    let shutdown = daemon::shutdown_signal();
+    tokio::pin!(shutdown);
+
+    // Interactive sessions get a live dashboard, redrawn a few times a second
+    let (dashboard, mut quit) = if tui {
+        let (dashboard, quit) = Dashboard::start()?;
+        (Some(dashboard), Some(quit))
+    } else {
+        (None, None)
+    };
+    let mut redraw_ticker = time::interval(time::Duration::from_millis(250));
+    let mut heartbeat_ticker = time::interval(HEARTBEAT_INTERVAL);
+    // The dashboard stands in for the log report while it is up
+    let periodic_sinks = if dashboard.is_some() { sinks.without_log() } else { sinks.clone() };
+
+    let mut runner = Runner {
+        output_path,
+        format,
+        started_at,
+        timezone,
+        #[cfg(feature = "charts")]
+        charts,
+        simulate,
+        max_raw_samples,
+        vantage,
+        monitors,
+        // Stats for each chain start from any checkpoint's
+        chain_stats: resumed,
+        retired: HashMap::new(),
+        run_info,
+        health,
+        skew,
+        timeseries,
+        sinks,
+        periodic_sinks,
+        notifiers,
+        digest,
+        store,
+        rotation,
+        uploads,
+        postgres_snapshots,
+        metrics,
+        api,
+        #[cfg(feature = "grpc")]
+        grpc,
+        alert: alert_control,
+        dashboard,
+    };
+
+    // Main loop
+    loop {
+        tokio::select! {
+            // Latest stats from a chain's monitor
+            Some(update) = stats_receiver.recv() => runner.absorb(update),
+
+            _ = &mut deadline => {
+                info!("Monitoring duration complete, shutting down");
+                break;
+            }
+
+            // Any run stopped early still writes its final report and outputs
+            _ = &mut shutdown => {
+                info!("Stop signal received, shutting down");
+                break;
+            }
+
+            // A daemon's chains file was saved
+            Some(()) = async { watcher.as_mut()?.changed().await } => {
+                if let Some(watcher) = &watcher {
+                    runner.reload(watcher.path(), &mut stats_receiver).await;
+                }
+            }
+
+            // A chain paused or resumed through the API
+            Some(request) = control_receiver.recv() => {
+                let response = runner.control(&request, &mut stats_receiver).await;
+                let _ = request.reply.send(response);
+            }
+
+            // The user quit the dashboard
+            Some(()) = async { quit.as_mut()?.recv().await } => {
+                info!("Dashboard closed, shutting down");
+                break;
+            }
+
+            _ = redraw_ticker.tick(), if runner.dashboard.is_some() => runner.redraw(),
+
+            _ = heartbeat_ticker.tick(), if health_file.is_some() => {
+                if let Some(path) = health_file {
+                    runner.write_heartbeat(path);
+                }
+            }
+
+            _ = skew_ticker.tick() => runner.sample_skew(),
+
+            // SIGUSR1 brings the next report forward to now
+            _ = report_signal.recv() => {
+                info!("SIGUSR1 received, reporting now");
+                report_ticker.reset_immediately();
+            }
+
+            // Report stats periodically
+            _ = report_ticker.tick() => runner.report().await,
+        }
+    }
+
+    let chain_stats = runner
+        .finish(stats_receiver, observation_writer, postgres_writer, upload_writer)
+        .await;
+
+    // Last, so a failed SLO still leaves the complete run behind
+    slo.check(&chain_stats)
+}
+
+/// The main loop's state once a run is set up, with a method for each kind of event
+struct Runner {
+    output_path: PathBuf,
+    format: OutputFormat,
+    started_at: DateTime<Utc>,
+    timezone: ReportTimezone,
+    #[cfg(feature = "charts")]
+    charts: bool,
+    // How a reloaded chains file is read
+    simulate: bool,
+    max_raw_samples: Option<usize>,
+    vantage: Vantage,
+    monitors: MonitorSet,
+    chain_stats: HashMap<String, ChainStats>,
+    // Chains a daemon has stopped monitoring, in case they are listed again
+    retired: HashMap<String, ChainStats>,
+    run_info: RunInfo,
+    health: HealthCheck,
+    skew: CrossChainSkew,
+    timeseries: DeltaTimeseries,
+    sinks: ReportSinks,
+    periodic_sinks: ReportSinks,
+    notifiers: Notifiers,
+    digest: Option<DigestSchedule>,
+    store: Option<SqliteStore>,
+    rotation: Option<CsvRotation>,
+    uploads: Option<UnboundedSender<PathBuf>>,
+    postgres_snapshots: Option<UnboundedSender<StatsSnapshot>>,
+    metrics: Option<Arc<Metrics>>,
+    api: Option<Arc<StatsApi>>,
+    #[cfg(feature = "grpc")]
+    grpc: Option<Arc<GrpcService>>,
+    // Shares its threshold with the alert checking the observations
+    alert: Option<DeltaAlert>,
+    dashboard: Option<Dashboard>,
+}
+
+impl Runner {
+    // Take in a monitor's latest stats and pass them on to the live endpoints
+    fn absorb(&mut self, update: ChainStats) {
+        let stats = absorb_stats(&mut self.chain_stats, update);
+        if let Some(metrics) = &self.metrics {
+            metrics.update(stats);
+        }
+        if let Some(api) = &self.api {
+            api.update(stats);
+        }
+        #[cfg(feature = "grpc")]
+        if let Some(grpc) = &self.grpc {
+            grpc.update(stats);
+        }
+    }
+
+    // Stop a chain's monitor and take the last stats it reported
+    async fn stop_monitor(&mut self, name: &str, stats_receiver: &mut UnboundedReceiver<ChainStats>) -> Option<ChainStats> {
+        self.monitors.stop(name).await;
+        while let Ok(update) = stats_receiver.try_recv() {
+            absorb_stats(&mut self.chain_stats, update);
+        }
+        self.chain_stats.remove(name)
+    }
+
+    // Apply a daemon's edited chains file at `path`: stop the chains no longer listed,
+    // restart those whose settings changed and start the new ones
+    async fn reload(&mut self, path: &Path, stats_receiver: &mut UnboundedReceiver<ChainStats>) {
+        let loaded = if self.simulate { Config::simulated(Some(path)) } else { Config::from_env(Some(path)) };
+        let mut new_config = match loaded {
+            Ok(new_config) => new_config,
+            Err(e) => {
+                error!("Ignoring the edited {}: {:#}", path.display(), e);
+                return;
+            }
+        };
+        if let Some(max_raw_samples) = self.max_raw_samples {
+            new_config.options.max_raw_samples = max_raw_samples;
+        }
+        new_config.options.vantage = self.vantage.clone();
+        if let (Some(alert), Some(threshold_ms)) = (&self.alert, new_config.alert_threshold_ms) {
+            if alert.threshold_ms() != threshold_ms {
+                info!("Alert threshold changed to {}ms", threshold_ms);
+                alert.set_threshold_ms(threshold_ms);
+            }
+        }
+
+        let changes = self.monitors.reconfigure(&new_config);
+        if changes.is_empty() {
+            info!("Reloaded {}; no chains changed", path.display());
+        }
+        for name in &changes.removed {
+            let Some(stats) = self.stop_monitor(name, stats_receiver).await else {
+                continue;
+            };
+            // Its stats are written once more, and kept in case the chain is listed again
+            let written = match &self.store {
+                Some(store) => store.insert_stats(&stats, Utc::now()),
+                None => write_csv(&stats, &self.output_path, self.format, self.rotation.as_mut()),
+            };
+            if let Err(e) = written {
+                error!("Failed to write stats for {}: {}", name, e);
+            }
+            if let Some(metrics) = &self.metrics {
+                metrics.remove(name);
+            }
+            if let Some(api) = &self.api {
+                api.remove(name);
+            }
+            #[cfg(feature = "grpc")]
+            if let Some(grpc) = &self.grpc {
+                grpc.remove(name);
+            }
+            self.retired.insert(name.clone(), stats);
+            info!("{}: No longer listed; stopped monitoring", name);
+        }
+        for chain in changes.changed {
+            let stats = self.stop_monitor(&chain.name, stats_receiver).await;
+            info!("{}: Settings changed; restarted its monitor", chain.name);
+            // Keep the restarted chain in the reports until its first poll
+            if let Some(stats) = &stats {
+                self.chain_stats.insert(chain.name.clone(), stats.clone());
+            }
+            self.monitors.start(chain, stats);
+        }
+        for chain in changes.added {
+            let stats = self.retired.remove(&chain.name);
+            if let Some(metrics) = &self.metrics {
+                metrics.restore(&chain.name);
+            }
+            info!(
+                "{}: Added to monitoring{}",
+                chain.name,
+                if stats.is_some() { ", continuing from its earlier stats" } else { "" }
+            );
+            if let Some(stats) = &stats {
+                self.chain_stats.insert(chain.name.clone(), stats.clone());
+            }
+            self.monitors.start(chain, stats);
+        }
+        self.health.set_chains(self.monitors.polling(), Utc::now());
+        self.run_info.set_chains(&new_config);
+    }
+
+    // Answer a request to pause or resume a chain's polling. A paused chain keeps its stats, in
+    // the reports and the API, and is left out of the health checks until it is resumed.
+    async fn control(
+        &mut self,
+        request: &ControlRequest,
+        stats_receiver: &mut UnboundedReceiver<ChainStats>,
+    ) -> (&'static str, serde_json::Value) {
+        let Some((name, paused)) = self.monitors.find(&request.chain) else {
+            return ("404 Not Found", serde_json::json!({ "error": "not found" }));
+        };
+        match (request.action, paused) {
+            (ControlAction::Pause, false) => {
+                self.monitors.pause(&name).await;
+                while let Ok(update) = stats_receiver.try_recv() {
+                    absorb_stats(&mut self.chain_stats, update);
+                }
+                info!("{}: Polling paused", name);
+            }
+            (ControlAction::Resume, true) => {
+                // The paused stats tell the monitor to record the blocks it missed as a paused gap
+                self.monitors.resume(&name, self.chain_stats.get(&name).cloned());
+                info!("{}: Polling resumed", name);
+            }
+            // Already as requested
+            _ => {}
+        }
+        let paused = request.action == ControlAction::Pause;
+        if let Some(stats) = self.chain_stats.get_mut(&name) {
+            stats.paused = paused;
+            if let Some(api) = &self.api {
+                api.update(stats);
+            }
+        }
+        self.health.set_chains(self.monitors.polling(), Utc::now());
+        #[cfg(feature = "grpc")]
+        if let Some(grpc) = &self.grpc {
+            self.chain_stats.values().for_each(|stats| grpc.update(stats));
+        }
+        ("200 OK", serde_json::json!({ "chain": name, "paused": paused }))
+    }
+
+    fn redraw(&mut self) {
+        if let Some(dashboard) = &mut self.dashboard {
+            if let Err(e) = dashboard.draw(&self.chain_stats) {
+                error!("Failed to draw dashboard: {}", e);
+            }
+        }
+    }
+
+    // Rewrite the health file at `path` if every chain is receiving blocks
+    fn write_heartbeat(&self, path: &Path) {
+        let now = Utc::now();
+        let status = self.health.check(
+            |name| self.chain_stats.get(name).and_then(|stats| stats.previous.as_ref()).map(|block| block.receipt_time_ms),
+            now,
+        );
+        if let Err(e) = status.write_heartbeat(path, now) {
+            error!("Failed to write health file {}: {}", path.display(), e);
+        }
+    }
+
+    fn sample_skew(&mut self) {
+        if let Err(e) = self.skew.sample(&self.chain_stats, Utc::now()) {
+            error!("Failed to record cross-chain skew: {}", e);
+        }
+    }
+
+    // Publish the current stats and write every output file, sending the digest when it's due
+    async fn report(&mut self) {
+        let now = Utc::now();
+        let summary = self.run_info.summary(&self.chain_stats, &self.skew, now, false);
+        let mut lines = report::current_report(&self.chain_stats, now);
+        lines.extend(self.skew.current_report(&self.chain_stats));
+        self.periodic_sinks.publish("Current Stats", &lines, &summary, now).await;
+        for (name, stats) in &self.chain_stats {
+            // Write current stats to file, or snapshot them into the database
+            let written = info_span!("write_stats", chain = %name).in_scope(|| match &self.store {
+                Some(store) => store.insert_stats(stats, Utc::now()),
+                None => write_csv(stats, &self.output_path, self.format, self.rotation.as_mut()),
+            });
+            if let Err(e) = written {
+                error!("Failed to write stats for {}: {}", name, e);
+            }
+        }
+        queue_rotated(self.rotation.as_mut(), self.uploads.as_ref());
+        #[cfg(feature = "charts")]
+        if self.charts {
+            write_charts(&self.chain_stats, &self.output_path, self.timezone);
+        }
+        if let Err(e) = self.timeseries.append(&self.chain_stats, now) {
+            error!("Failed to append to the delta time series: {}", e);
+        }
+        if let Err(e) = report::write_summary(&self.output_path, &summary) {
+            error!("Failed to write JSON summary: {}", e);
+        }
+        if let Err(e) = combined::write(&self.output_path, &summary, &self.chain_stats) {
+            error!("Failed to write combined stats: {}", e);
+        }
+        send_snapshots(self.postgres_snapshots.as_ref(), &summary, now);
+        if let Err(e) = Checkpoint::save(&self.output_path, self.started_at, &self.chain_stats) {
+            error!("Failed to write checkpoint: {}", e);
+        }
+
+        // Send the scheduled digest once it's due
+        if let Some(schedule) = &mut self.digest {
+            let now = Utc::now();
+            if schedule.is_due(now) {
+                let body = schedule.build(&self.chain_stats, now);
+                info!("Digest:\n{}", body);
+                self.notifiers.notify("Block timestamp digest", &body).await;
+            }
+        }
+    }
+
+    // Stop the monitors, report the final stats and write, checksum and upload the outputs,
+    // returning the final stats
+    async fn finish(
+        mut self,
+        mut stats_receiver: UnboundedReceiver<ChainStats>,
+        observation_writer: Option<JoinHandle<()>>,
+        postgres_writer: Option<JoinHandle<()>>,
+        upload_writer: Option<JoinHandle<ObjectStore>>,
+    ) -> HashMap<String, ChainStats> {
+        // Give the terminal back before the final report
+        drop(self.dashboard);
+
+        // Stop the monitors and take the stats they reported before stopping
+        drop(self.monitors);
+        while let Ok(update) = stats_receiver.try_recv() {
+            absorb_stats(&mut self.chain_stats, update);
+        }
+
+        // The observation sinks finish once the stopped monitors release their senders
+        if let Some(writer) = observation_writer {
+            if let Err(e) = writer.await {
+                error!("Observation writer stopped unexpectedly: {}", e);
+            }
+        }
+
+        // Final stats reporting
+        let now = Utc::now();
+        let summary = self.run_info.summary(&self.chain_stats, &self.skew, now, true);
+        let mut lines = report::final_report(&self.chain_stats);
+        lines.extend(self.skew.final_report());
+        self.sinks.publish("Final Statistics", &lines, &summary, now).await;
+        self.notifiers.notify("Block timestamp run complete", &lines.join("\n")).await;
+        for (name, stats) in &self.chain_stats {
+            // Write final stats to file
+            let written = match &self.store {
+                Some(store) => store.insert_stats(stats, Utc::now()),
+                None => write_csv(stats, &self.output_path, self.format, self.rotation.as_mut()),
+            };
+            if let Err(e) = written {
+                error!("Failed to write final stats for {}: {}", name, e);
+            }
+        }
+        if let Some(rotation) = &mut self.rotation {
+            if let Err(e) = rotation.finish() {
+                error!("Failed to close the rotated CSVs: {}", e);
+            }
+        }
+        queue_rotated(self.rotation.as_mut(), self.uploads.as_ref());
+        if let Err(e) = self.timeseries.append(&self.chain_stats, now) {
+            error!("Failed to append to the delta time series: {}", e);
+        }
+        if let Err(e) = report::write_summary(&self.output_path, &summary) {
+            error!("Failed to write final JSON summary: {}", e);
+        }
+        if let Err(e) = combined::write(&self.output_path, &summary, &self.chain_stats) {
+            error!("Failed to write final combined stats: {}", e);
+        }
+        if let Err(e) = html::write_html_report(&self.output_path, &summary, &self.chain_stats, &lines, self.timezone) {
+            error!("Failed to write HTML report: {}", e);
+        }
+        #[cfg(feature = "charts")]
+        if self.charts {
+            write_charts(&self.chain_stats, &self.output_path, self.timezone);
+        }
+
+        // The database run ends with the final snapshots
+        send_snapshots(self.postgres_snapshots.as_ref(), &summary, now);
+        drop(self.postgres_snapshots);
+        if let Some(writer) = postgres_writer {
+            if let Err(e) = writer.await {
+                error!("PostgreSQL writer stopped unexpectedly: {}", e);
+            }
+        }
+        if let Err(e) = Checkpoint::save(&self.output_path, self.started_at, &self.chain_stats) {
+            error!("Failed to write final checkpoint: {}", e);
+        }
+
+        // Close the database before it is checksummed
+        drop(self.store);
+
+        // Let the rotated files' uploads finish first, as they may be removed locally
+        drop(self.uploads);
+        let object_store = match upload_writer {
+            Some(writer) => match writer.await {
+                Ok(object_store) => Some(object_store),
+                Err(e) => {
+                    error!("Uploader stopped unexpectedly: {}", e);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        // Checksum (and optionally sign) the final outputs so published datasets can be verified
+        if let Err(e) = manifest::write(&self.output_path) {
+            error!("Failed to write checksum manifest: {}", e);
+        }
+
+        // Then upload everything the run left behind
+        if let Some(mut object_store) = object_store {
+            object_store.upload_outputs(&self.output_path).await;
+        }
+        self.chain_stats
+    }
+}
@@ -0,0 +1,159 @@
+use anyhow::{anyhow, Context, Result};
+use clap::Args;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// Checksum manifest, in `sha256sum` format so `sha256sum -c` can verify it too
+pub const MANIFEST_FILE: &str = "SHA256SUMS";
+
+/// Detached hex-encoded Ed25519 signature of the manifest
+pub const SIGNATURE_FILE: &str = "SHA256SUMS.sig";
+
+/// Arguments for the `verify` subcommand
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+    /// Run directory containing the manifest
+    #[arg(long, env = "OUTPUT_DIR", default_value = "./logs")]
+    pub output_dir: PathBuf,
+
+    /// Hex-encoded Ed25519 public key; when given, the manifest signature must be valid
+    #[arg(long)]
+    pub public_key: Option<String>,
+}
+
+// Every file under `dir` except the manifest itself, as sorted relative paths
+fn dataset_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for entry in std::fs::read_dir(&current)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else {
+                let relative = path.strip_prefix(dir)?.to_path_buf();
+                if relative != Path::new(MANIFEST_FILE) && relative != Path::new(SIGNATURE_FILE) {
+                    files.push(relative);
+                }
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+// Manifest paths always use forward slashes so it verifies the same on every platform
+fn manifest_path(relative: &Path) -> String {
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn decode_key<const N: usize>(hex_key: &str, what: &str) -> Result<[u8; N]> {
+    hex::decode(hex_key.trim())
+        .map_err(|e| anyhow!("Invalid {}: {}", what, e))?
+        .try_into()
+        .map_err(|_| anyhow!("Invalid {}: expected {} bytes", what, N))
+}
+
+/// Load the signing key from the file named by `MANIFEST_SIGNING_KEY` (a hex-encoded
+/// 32-byte Ed25519 seed). Returns `None` when signing isn't configured.
+fn signing_key_from_env() -> Result<Option<SigningKey>> {
+    let path = match env::var("MANIFEST_SIGNING_KEY") {
+        Ok(path) => path,
+        Err(_) => return Ok(None),
+    };
+    let contents = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read MANIFEST_SIGNING_KEY file {}", path))?;
+    Ok(Some(SigningKey::from_bytes(&decode_key(&contents, "signing key")?)))
+}
+
+/// Write checksums of every file in the run directory, signing them when a key is configured
+pub fn write(dir: &Path) -> Result<()> {
+    let mut manifest = String::new();
+    for relative in dataset_files(dir)? {
+        let digest = sha256_file(&dir.join(&relative))?;
+        manifest.push_str(&format!("{}  {}\n", digest, manifest_path(&relative)));
+    }
+    std::fs::write(dir.join(MANIFEST_FILE), &manifest)?;
+    info!("Checksum manifest written to {}", dir.join(MANIFEST_FILE).display());
+
+    let signature_path = dir.join(SIGNATURE_FILE);
+    match signing_key_from_env()? {
+        Some(key) => {
+            let signature = key.sign(manifest.as_bytes());
+            std::fs::write(&signature_path, format!("{}\n", hex::encode(signature.to_bytes())))?;
+            info!(
+                "Manifest signed; verify with public key {}",
+                hex::encode(key.verifying_key().to_bytes())
+            );
+        }
+        // Don't leave a previous run's signature next to a manifest it doesn't match
+        None if signature_path.exists() => std::fs::remove_file(&signature_path)?,
+        None => {}
+    }
+    Ok(())
+}
+
+/// Check a run directory against its manifest (and signature, when a public key is given)
+pub fn run(args: &VerifyArgs) -> Result<()> {
+    let dir = &args.output_dir;
+    let manifest = std::fs::read_to_string(dir.join(MANIFEST_FILE))
+        .with_context(|| format!("No {} in {}", MANIFEST_FILE, dir.display()))?;
+
+    if let Some(public_key) = &args.public_key {
+        let key = VerifyingKey::from_bytes(&decode_key(public_key, "public key")?)?;
+        let signature = std::fs::read_to_string(dir.join(SIGNATURE_FILE))
+            .with_context(|| format!("No {} in {}", SIGNATURE_FILE, dir.display()))?;
+        let signature = Signature::from_bytes(&decode_key(&signature, "signature")?);
+        key.verify(manifest.as_bytes(), &signature)
+            .map_err(|_| anyhow!("Manifest signature does not match the public key"))?;
+        info!("Manifest signature is valid");
+    }
+
+    let mut listed = Vec::new();
+    let mut mismatches = 0;
+    for line in manifest.lines() {
+        let (expected, name) = line
+            .split_once("  ")
+            .ok_or_else(|| anyhow!("Malformed manifest line: {}", line))?;
+        listed.push(name.to_string());
+        match sha256_file(&dir.join(name)) {
+            Ok(actual) if actual == expected => {}
+            Ok(_) => {
+                warn!("{}: checksum mismatch", name);
+                mismatches += 1;
+            }
+            Err(e) => {
+                warn!("{}: {}", name, e);
+                mismatches += 1;
+            }
+        }
+    }
+
+    // Files added after the manifest was written aren't covered by it
+    for relative in dataset_files(dir)? {
+        let name = manifest_path(&relative);
+        if !listed.contains(&name) {
+            warn!("{}: not listed in the manifest", name);
+        }
+    }
+
+    if mismatches > 0 {
+        return Err(anyhow!("{} of {} files failed verification", mismatches, listed.len()));
+    }
+    info!("All {} files match the manifest", listed.len());
+    Ok(())
+}
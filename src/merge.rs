@@ -0,0 +1,100 @@
+use anyhow::{anyhow, Result};
+use clap::Args;
+use log::info;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::dataset::DataFormat;
+use crate::monitor::BlockObservation;
+use crate::observations::{self, ObservationLog};
+use crate::replay;
+use crate::store::SqliteStore;
+
+/// Arguments for the `merge` subcommand
+#[derive(Debug, Args)]
+pub struct MergeArgs {
+    /// Datasets to combine: run directories, NDJSON files, SQLite databases or DuckDB databases
+    #[arg(required = true, num_args = 2..)]
+    pub inputs: Vec<PathBuf>,
+
+    /// Where the combined observations go: a directory of daily observation logs, an
+    /// NDJSON file or a SQLite database, by extension (no extension means a directory).
+    /// It must not exist yet.
+    #[arg(long)]
+    pub out: PathBuf,
+
+    /// Format of the output; detected from the extension when omitted
+    #[arg(long, value_enum)]
+    pub to: Option<DataFormat>,
+}
+
+fn output_format(path: &Path) -> DataFormat {
+    if path.extension().is_none() {
+        DataFormat::Csv
+    } else {
+        DataFormat::detect(path).unwrap_or(DataFormat::Csv)
+    }
+}
+
+/// Combine the observations of several runs into one dataset, ordered by receipt time.
+///
+/// A block recorded by more than one run from the same vantage point (overlapping runs
+/// against the same chain) is kept once, as first received, so the merged deltas aren't
+/// weighted towards the overlap. Runs from different vantage points keep their own copies.
+pub fn run(args: &MergeArgs) -> Result<()> {
+    if args.out.exists() {
+        return Err(anyhow!("{} already exists; merge into a new path", args.out.display()));
+    }
+
+    let mut earliest: HashMap<(Option<String>, String, u64), BlockObservation> = HashMap::new();
+    let mut loaded = 0;
+    for input in &args.inputs {
+        let observations = replay::load_observations(input, None)?;
+        info!("Loaded {} observations from {}", observations.len(), input.display());
+        loaded += observations.len();
+        for observation in observations {
+            let key = (observation.vantage.clone(), observation.chain.clone(), observation.block_number);
+            match earliest.get(&key) {
+                Some(kept) if kept.received_at <= observation.received_at => {}
+                _ => {
+                    earliest.insert(key, observation);
+                }
+            }
+        }
+    }
+    let mut merged: Vec<BlockObservation> = earliest.into_values().collect();
+    merged.sort_by(|a, b| {
+        (a.received_at, &a.chain, a.block_number).cmp(&(b.received_at, &b.chain, b.block_number))
+    });
+
+    match args.to.unwrap_or_else(|| output_format(&args.out)) {
+        DataFormat::Csv => {
+            std::fs::create_dir_all(&args.out)?;
+            let mut log = ObservationLog::new(args.out.clone());
+            for observation in &merged {
+                log.append(observation)?;
+            }
+        }
+        DataFormat::Ndjson => {
+            let mut wtr = BufWriter::new(File::create(&args.out)?);
+            for observation in &merged {
+                writeln!(wtr, "{}", observations::record(observation))?;
+            }
+            wtr.flush()?;
+        }
+        DataFormat::Sqlite => SqliteStore::open(&args.out)?.insert_observations(&merged)?,
+        format @ (DataFormat::Parquet | DataFormat::Duckdb) => {
+            return Err(anyhow!("Merging into {:?} isn't supported; merge into csv, ndjson or sqlite", format));
+        }
+    }
+
+    info!(
+        "Merged {} observations ({} duplicates dropped) into {}",
+        merged.len(),
+        loaded - merged.len(),
+        args.out.display()
+    );
+    Ok(())
+}
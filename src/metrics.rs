@@ -0,0 +1,226 @@
+use anyhow::Result;
+use futures::future::{self, BoxFuture};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+
+use crate::delta::DeltaDirection;
+use crate::monitor::BlockObservation;
+use crate::server::{self, Request, Response};
+use crate::sink::ObservationSink;
+use crate::stats::ChainStats;
+
+/// Upper bounds of the delta histogram buckets in milliseconds; negative deltas are
+/// future timestamps
+pub(crate) const DELTA_BUCKETS_MS: &[i64] = &[-2000, -1000, -500, -250, 0, 250, 500, 1000, 2000, 4000, 8000];
+
+/// Name, type, help text and value of each single-valued metric
+type ScalarMetric = (&'static str, &'static str, &'static str, fn(&ChainMetrics) -> i64);
+
+const SCALAR_METRICS: &[ScalarMetric] = &[
+    ("block_timestamp_current_delta_ms", "gauge", "Delta of the most recently observed block", |c| c.current_delta_ms),
+    ("block_timestamp_blocks_observed_total", "counter", "Blocks observed", |c| c.blocks as i64),
+    ("block_timestamp_max_past_delta_ms", "gauge", "Largest delta of a block with a past timestamp", |c| c.max_past_delta_ms),
+    ("block_timestamp_max_future_delta_ms", "gauge", "Largest delta of a block with a future timestamp", |c| c.max_future_delta_ms),
+    ("block_timestamp_rpc_errors_total", "counter", "Failed RPC calls", |c| c.rpc_errors as i64),
+    ("block_timestamp_non_monotonic_blocks_total", "counter", "Blocks stamped at or before their parent's timestamp", |c| c.non_monotonic_blocks as i64),
+];
+
+#[derive(Debug, Default)]
+struct ChainMetrics {
+    blocks: usize,
+    current_delta_ms: i64,
+    max_past_delta_ms: i64,
+    max_future_delta_ms: i64,
+    rpc_errors: usize,
+    non_monotonic_blocks: usize,
+    // Cumulative counts for each bucket bound
+    bucket_counts: Vec<u64>,
+    delta_sum_ms: i64,
+    // Blocks observed since the exporter started, by delta direction
+    past: u64,
+    exact: u64,
+    future: u64,
+}
+
+/// Per-chain metrics in the Prometheus text format, updated from stats snapshots and, as an
+/// observation sink, from every block as it is recorded
+#[derive(Debug, Default)]
+pub struct Metrics {
+    chains: Mutex<BTreeMap<String, ChainMetrics>>,
+    // Chains no longer monitored, whose observations still in flight are ignored
+    removed: Mutex<BTreeSet<String>>,
+}
+
+impl Metrics {
+    /// Take over a chain's latest stats; the histogram buckets are read from its delta histogram
+    pub fn update(&self, stats: &ChainStats) {
+        let mut chains = self.chains.lock().unwrap();
+        let chain = chains.entry(stats.chain_name.clone()).or_default();
+
+        let histogram = &stats.delta_histogram;
+        chain.bucket_counts = DELTA_BUCKETS_MS.iter().map(|bound| histogram.count_at_most(*bound)).collect();
+        chain.delta_sum_ms = histogram.sum_ms();
+        chain.blocks = stats.total_blocks;
+        chain.current_delta_ms = stats.last_delta_ms;
+        chain.max_past_delta_ms = stats.max_past_delta_ms;
+        chain.max_future_delta_ms = stats.max_future_delta_ms;
+        chain.rpc_errors = stats.rpc_errors;
+        chain.non_monotonic_blocks = stats.non_monotonic_blocks;
+    }
+
+    /// Stop exporting a chain that is no longer monitored
+    pub fn remove(&self, chain: &str) {
+        self.chains.lock().unwrap().remove(chain);
+        self.removed.lock().unwrap().insert(chain.to_string());
+    }
+
+    /// Export a chain again once it is monitored again after being removed
+    pub fn restore(&self, chain: &str) {
+        self.removed.lock().unwrap().remove(chain);
+    }
+
+    fn render(&self) -> String {
+        let chains = self.chains.lock().unwrap();
+        let chains: Vec<(String, &ChainMetrics)> = chains.iter().map(|(chain, metrics)| (label_value(chain), metrics)).collect();
+        let mut out = String::new();
+
+        for (name, kind, help, value) in SCALAR_METRICS {
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} {}", name, help, name, kind);
+            for (chain, metrics) in &chains {
+                let _ = writeln!(out, "{}{{chain=\"{}\"}} {}", name, chain, value(metrics));
+            }
+        }
+
+        let name = "block_timestamp_blocks_by_direction_total";
+        let _ = writeln!(out, "# HELP {} Blocks observed, by delta direction\n# TYPE {} counter", name, name);
+        for (chain, metrics) in &chains {
+            for (direction, count) in [("past", metrics.past), ("exact", metrics.exact), ("future", metrics.future)] {
+                let _ = writeln!(out, "{}{{chain=\"{}\",direction=\"{}\"}} {}", name, chain, direction, count);
+            }
+        }
+
+        let name = "block_timestamp_delta_ms";
+        let _ = writeln!(out, "# HELP {} Receipt time minus block timestamp\n# TYPE {} histogram", name, name);
+        for (chain, metrics) in &chains {
+            for (bound, count) in DELTA_BUCKETS_MS.iter().zip(&metrics.bucket_counts) {
+                let _ = writeln!(out, "{}_bucket{{chain=\"{}\",le=\"{}\"}} {}", name, chain, bound, count);
+            }
+            let _ = writeln!(out, "{}_bucket{{chain=\"{}\",le=\"+Inf\"}} {}", name, chain, metrics.blocks);
+            let _ = writeln!(out, "{}_sum{{chain=\"{}\"}} {}", name, chain, metrics.delta_sum_ms);
+            let _ = writeln!(out, "{}_count{{chain=\"{}\"}} {}", name, chain, metrics.blocks);
+        }
+
+        out
+    }
+
+    /// Answer scrapes of `/metrics` on `listener` until the process exits
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) {
+        server::log_listening(&listener, "Prometheus metrics", "/metrics");
+        server::serve(listener, "Metrics", move |request: Request| {
+            let metrics = self.clone();
+            async move {
+                let (status, body) = if request.path == "/metrics" {
+                    ("200 OK", metrics.render())
+                } else {
+                    ("404 Not Found", "Not found\n".to_string())
+                };
+                Response {
+                    status,
+                    content_type: "text/plain; version=0.0.4",
+                    body,
+                }
+            }
+        })
+        .await
+    }
+}
+
+// A chain name as a label value, escaped as the text format requires
+fn label_value(chain: &str) -> String {
+    chain.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+impl ObservationSink for Arc<Metrics> {
+    fn name(&self) -> &str {
+        "Prometheus metrics"
+    }
+
+    fn write<'a>(&'a mut self, observation: &'a BlockObservation) -> BoxFuture<'a, Result<()>> {
+        // Observations still in flight for a chain that was removed don't bring it back, but a
+        // chain's first blocks count even before its first stats update
+        if !self.removed.lock().unwrap().contains(&observation.chain) {
+            let mut chains = self.chains.lock().unwrap();
+            let chain = chains.entry(observation.chain.clone()).or_default();
+            match observation.timestamp_delta().direction {
+                DeltaDirection::Past => chain.past += 1,
+                DeltaDirection::Exact => chain.exact += 1,
+                DeltaDirection::Future => chain.future += 1,
+            }
+        }
+        Box::pin(future::ready(Ok(())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+
+    use crate::clock::Stamp;
+
+    fn observation(chain: &str, block_number: u64, block_timestamp_ms: u64, delta_ms: i64) -> BlockObservation {
+        BlockObservation {
+            chain: chain.to_string(),
+            block_number,
+            block_timestamp_ms,
+            block_hash: None,
+            parent_hash: None,
+            gas_used: None,
+            tx_count: None,
+            received_at: DateTime::from_timestamp_millis(block_timestamp_ms as i64 + delta_ms).unwrap(),
+            responded_at: None,
+            delta_ms,
+            leap_affected: false,
+            l1_origin: None,
+            node_syncing: None,
+            anomaly_z: None,
+            vantage: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn direction_counts_add_up_to_the_blocks_and_histogram() {
+        let mut metrics = Arc::new(Metrics::default());
+        let mut stats = ChainStats::new("Base");
+        for (number, delta_ms) in [(1, 250), (2, 0), (3, -400), (4, 1200), (5, 0)] {
+            let observation = observation("Base", number, 1_700_000_000_000 + number * 2000, delta_ms);
+            stats.update(number, &observation.header(), Stamp::wall(observation.received_at), None, None, false);
+            // Blocks arriving before the chain's first stats update are counted too
+            metrics.write(&observation).await.unwrap();
+        }
+        metrics.update(&stats);
+
+        let chains = metrics.chains.lock().unwrap();
+        let chain = &chains["Base"];
+        assert_eq!((chain.past, chain.exact, chain.future), (2, 2, 1));
+        assert_eq!((chain.past + chain.exact + chain.future) as usize, stats.total_blocks);
+        assert_eq!(chain.blocks, stats.total_blocks);
+        let at_most_zero = DELTA_BUCKETS_MS.iter().position(|bound| *bound == 0).unwrap();
+        assert_eq!(chain.bucket_counts[at_most_zero], chain.exact + chain.future);
+    }
+
+    #[tokio::test]
+    async fn removed_chains_stay_removed_until_restored() {
+        let mut metrics = Arc::new(Metrics::default());
+        metrics.write(&observation("Base", 1, 1_700_000_000_000, 300)).await.unwrap();
+        metrics.remove("Base");
+        metrics.write(&observation("Base", 2, 1_700_000_002_000, 300)).await.unwrap();
+        assert!(metrics.chains.lock().unwrap().is_empty());
+
+        metrics.restore("Base");
+        metrics.write(&observation("Base", 3, 1_700_000_004_000, 300)).await.unwrap();
+        assert_eq!(metrics.chains.lock().unwrap()["Base"].past, 1);
+    }
+}
@@ -0,0 +1,1871 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use futures::{FutureExt, Stream};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
+use std::time::Instant;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+use tokio::time;
+use tracing::{info_span, Instrument};
+
+use crate::adapter::{BlockBatch, BlockHeader, ChainAdapter, L1Origin, StaleResponse};
+use crate::anomaly::{AnomalySettings, DeltaAnomalies};
+use crate::arbitrum::ArbitrumAdapter;
+use crate::breaker::{BreakerConfig, CircuitBreaker};
+use crate::cadence::{AdaptivePolling, CadencePoller};
+use crate::clock::{ClockSync, Stamp};
+use crate::compare::CompareThresholds;
+use crate::config::Config;
+use crate::consistency::HeadConsistency;
+use crate::delta::TimestampDelta;
+use crate::evm::{http_client, EvmAdapter};
+use crate::flashblocks::{FlashblockFeed, PreconfTracker};
+use crate::ipc;
+use crate::histogram::HistogramBuckets;
+use crate::http::{HttpSettings, RpcAuth};
+use crate::leap::LeapSeconds;
+use crate::notify::Notifiers;
+use crate::outage::Outages;
+use crate::reorg::HashHistory;
+use crate::retry::{is_transient, RetryPolicy};
+use crate::schedule::ScheduleAdherence;
+use crate::simulate::{self, SimulatedChain};
+use crate::stats::{ChainStats, GapEvent, GapReason, HeadLagSample};
+use crate::subscription::{HeadSubscription, SubscriptionHub};
+use crate::supervisor::{panic_message, Supervisor, SupervisorConfig};
+use crate::telemetry;
+use crate::timestamp::TimestampDecoding;
+use crate::timezone::ReportTimezone;
+use crate::vantage::Vantage;
+
+/// Most blocks fetched in one batch request while catching up
+const MAX_BATCH_BLOCKS: u64 = 100;
+
+/// Poll interval of a monitor observed on its own, unless its chain sets one
+const STANDALONE_POLL_INTERVAL_MS: u64 = 500;
+
+/// Responses whose `Date` header is averaged before the provider's clock is judged, and how
+/// far off ours that average must be to be reported
+const PROVIDER_CLOCK_MIN_RESPONSES: u64 = 20;
+const PROVIDER_CLOCK_WARN_MS: i64 = 1000;
+
+/// Chain information
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainInfo {
+    pub name: String,
+    pub rpc_url: String,
+    pub ws_url: Option<String>,
+    /// Second provider polled alongside `rpc_url`, to catch either one lagging or misbehaving
+    pub compare_rpc_url: Option<String>,
+    /// WebSocket feed of the chain's flashblocks, compared with the canonical blocks
+    pub flashblocks_url: Option<String>,
+    pub expected_block_time_ms: Option<u64>,
+    /// Overrides the global schedule tolerance for this chain
+    pub schedule_tolerance_ms: Option<u64>,
+    /// Overrides the global poll interval for this chain
+    pub poll_interval_ms: Option<u64>,
+    /// Chain ID the RPC endpoint should report, checked on the first poll
+    pub chain_id: Option<u64>,
+    pub timestamp_decoding: TimestampDecoding,
+    /// Timeouts and connection pooling of the chain's HTTP clients
+    pub http: HttpSettings,
+    pub kind: ChainKind,
+    /// Block tag whose blocks are measured
+    pub block_tag: BlockTag,
+    /// L1 endpoint the timestamps of Arbitrum blocks' L1 origins are read from
+    pub l1_rpc_url: Option<String>,
+}
+
+/// Which rules a chain's blocks follow, where they differ from plain Ethereum JSON-RPC
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChainKind {
+    /// Ethereum and chains answering like it, OP-stack chains included
+    #[default]
+    Standard,
+    /// Arbitrum Nitro: sequencer timestamps in whole seconds that repeat across blocks, and
+    /// L1 origins named by each header's `l1BlockNumber`
+    Arbitrum,
+}
+
+/// Which of a chain's heads its blocks are measured at: a block counts as received when it
+/// first shows up under the tag
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockTag {
+    /// The newest block the node has, found with `eth_blockNumber`
+    #[default]
+    Latest,
+    /// The block the node is building, whose timestamp the sequencer has already chosen
+    Pending,
+    /// The newest block derived from data posted to L1
+    Safe,
+    /// The newest block that can no longer be reorged
+    Finalized,
+}
+
+impl BlockTag {
+    /// The tag as JSON-RPC names it
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BlockTag::Latest => "latest",
+            BlockTag::Pending => "pending",
+            BlockTag::Safe => "safe",
+            BlockTag::Finalized => "finalized",
+        }
+    }
+}
+
+/// Hands out one HTTP client per provider origin, so chains served by the same
+/// gateway share its connection pool (and HTTP/2 session, when negotiated), and one
+/// WebSocket per subscription URL, which chains subscribing there share. Chains with
+/// different HTTP settings never share a client.
+pub(crate) struct ClientPool {
+    clients: HashMap<(String, HttpSettings), reqwest::Client>,
+    subscriptions: SubscriptionHub,
+    shared: bool,
+}
+
+impl ClientPool {
+    fn new(shared: bool) -> Self {
+        Self {
+            clients: HashMap::new(),
+            subscriptions: SubscriptionHub::default(),
+            shared,
+        }
+    }
+
+    fn client_for(&mut self, rpc_url: &str, settings: &HttpSettings) -> reqwest::Client {
+        let origin = match reqwest::Url::parse(rpc_url) {
+            Ok(url) if self.shared && ipc::socket_path(rpc_url).is_none() && !simulate::is_simulated(rpc_url) => {
+                url.origin().ascii_serialization()
+            }
+            _ => return http_client(rpc_url, settings),
+        };
+        if let Some(client) = self.clients.get(&(origin.clone(), settings.clone())) {
+            info!("Sharing connections to {}", origin);
+            return client.clone();
+        }
+        let client = http_client(rpc_url, settings);
+        self.clients.insert((origin, settings.clone()), client.clone());
+        client
+    }
+
+    // The chain's `newHeads` subscription, if it has a `ws_url`
+    fn subscription_for(&mut self, chain: &ChainInfo) -> Option<HeadSubscription> {
+        if !self.shared {
+            return own_subscription(chain);
+        }
+        let ws_url = chain.ws_url.clone()?;
+        Some(self.subscriptions.subscribe(
+            chain.name.clone(),
+            ws_url,
+            chain.timestamp_decoding.clone(),
+            chain.http.auth.clone(),
+        ))
+    }
+}
+
+// A `newHeads` subscription over a connection of the chain's own, if it has a `ws_url`
+fn own_subscription(chain: &ChainInfo) -> Option<HeadSubscription> {
+    chain.ws_url.clone().map(|ws_url| {
+        HeadSubscription::spawn(
+            chain.name.clone(),
+            ws_url,
+            chain.timestamp_decoding.clone(),
+            chain.http.auth.clone(),
+        )
+    })
+}
+
+/// Settings shared by every chain monitor
+#[derive(Debug, Clone)]
+pub struct MonitorOptions {
+    pub leap_seconds: LeapSeconds,
+    pub timezone: ReportTimezone,
+    pub strict_schema: bool,
+    pub wedge_timeout: time::Duration,
+    pub retry: RetryPolicy,
+    /// Fetch missed blocks with one JSON-RPC batch instead of one request each
+    pub batch_requests: bool,
+    /// Poll the latest block's header in one call instead of its number first, then the block
+    pub single_call_polling: bool,
+    /// Record the blocks that replace reorged ones as new observations
+    pub remeasure_reorgs: bool,
+    /// Per-block records each chain keeps in memory for the detailed CSV
+    pub max_raw_samples: usize,
+    /// How often to sample the safe and finalized heads; `None` doesn't track them
+    pub safe_head_interval: Option<time::Duration>,
+    /// How often to ask each node whether it is syncing; `None` doesn't ask
+    pub node_health_interval: Option<time::Duration>,
+    /// Fetch the L1 origin of every block, on chains derived from L1 such as the OP stack
+    pub track_l1_origin: bool,
+    /// Local clock offset from NTP time, applied to receipt times
+    pub clock: ClockSync,
+    /// When a chain's two providers count as disagreeing
+    pub compare: CompareThresholds,
+    /// Buckets the final report's delta histogram counts deltas in
+    pub histogram_buckets: HistogramBuckets,
+    /// Most blocks fetched after the head jumps ahead; older ones are skipped as a gap. `0`
+    /// always catches up.
+    pub max_catch_up_blocks: u64,
+    /// How far a block's arrival may stray from the expected block time and still count as
+    /// on schedule, unless the chain sets its own
+    pub schedule_tolerance_ms: u64,
+    /// When a delta is anomalous for its chain; `None` doesn't look for anomalies
+    pub anomaly: Option<AnomalySettings>,
+    /// Expected block times a chain may go without a new block before it counts as an
+    /// outage; `0` doesn't look for outages
+    pub outage_block_times: u64,
+    /// Where the chains are observed from, recorded with every observation
+    pub vantage: Vantage,
+    /// How long a head announced by `newHeads` or returned by polling may go unseen by the
+    /// other before it counts as a mismatch, on chains with a subscription; `0` doesn't poll
+    /// alongside the subscription
+    pub head_consistency_window_ms: u64,
+}
+
+/// One block as observed by a monitor
+#[derive(Debug, Clone)]
+pub struct BlockObservation {
+    pub chain: String,
+    pub block_number: u64,
+    /// Block timestamp in milliseconds since the epoch; whole seconds unless the chain's
+    /// timestamp decoding provides more precision
+    pub block_timestamp_ms: u64,
+    pub block_hash: Option<String>,
+    pub parent_hash: Option<String>,
+    /// Gas the block's transactions used, when the chain meters gas
+    pub gas_used: Option<u64>,
+    /// Transactions in the block; `None` for pushed heads
+    pub tx_count: Option<u64>,
+    /// Receipt time: when the block was requested, or when its pushed head arrived
+    pub received_at: DateTime<Utc>,
+    /// When the response to the block's request arrived; `None` for pushed heads and
+    /// blocks remeasured after a reorg
+    pub responded_at: Option<DateTime<Utc>>,
+    /// Receipt time minus block timestamp; negative means the timestamp is in the future,
+    /// see [`timestamp_delta`](Self::timestamp_delta)
+    pub delta_ms: i64,
+    /// Whether the observation fell in a leap-second window
+    pub leap_affected: bool,
+    /// The L1 block this block was derived from, when L1 origins are tracked
+    pub l1_origin: Option<L1Origin>,
+    /// Whether the node reported it was still syncing when the block was recorded, so a
+    /// late block can be told apart from a late sequencer; `None` without health checks
+    pub node_syncing: Option<bool>,
+    /// How many standard deviations the delta lies from the chain's recent baseline, for
+    /// deltas anomalous enough to flag; `None` for the others and in stored datasets
+    pub anomaly_z: Option<f64>,
+    /// Label of the vantage point the block was observed from; `None` in datasets
+    /// recorded before vantage points were
+    pub vantage: Option<String>,
+}
+
+impl BlockObservation {
+    /// The delta as a direction and magnitude
+    pub fn timestamp_delta(&self) -> TimestampDelta {
+        TimestampDelta::from_ms(self.delta_ms)
+    }
+
+    /// The header the block was recorded from, as far as the observation keeps it
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            timestamp_ms: self.block_timestamp_ms,
+            hash: self.block_hash.clone(),
+            parent_hash: self.parent_hash.clone(),
+            gas_used: self.gas_used,
+            tx_count: self.tx_count,
+        }
+    }
+
+    /// Time from sending the block's request to its response arriving. The delta measured
+    /// at the response is this round trip plus `delta_ms`, the timestamp offset.
+    pub fn round_trip_ms(&self) -> Option<i64> {
+        self
+            .responded_at
+            .map(|responded| responded.timestamp_millis() - self.received_at.timestamp_millis())
+    }
+
+    /// Block timestamp minus the timestamp of its L1 origin: how far the sequencer has run
+    /// ahead of L1
+    pub fn l1_drift_ms(&self) -> Option<i64> {
+        self
+            .l1_origin
+            .map(|origin| self.block_timestamp_ms as i64 - origin.timestamp_ms as i64)
+    }
+}
+
+// Ethereum JSON-RPC for `chain_info.rpc_url`, or a simulated chain for a `sim://` URL
+fn adapter_for(chain_info: &ChainInfo, options: &MonitorOptions, client: reqwest::Client) -> Box<dyn ChainAdapter> {
+    if simulate::is_simulated(&chain_info.rpc_url) {
+        Box::new(SimulatedChain::new(chain_info, &chain_info.rpc_url))
+    } else if chain_info.kind == ChainKind::Arbitrum {
+        Box::new(ArbitrumAdapter::new(chain_info, options, client))
+    } else {
+        Box::new(EvmAdapter::new(chain_info, options, client))
+    }
+}
+
+/// Monitors a chain for block timestamps
+pub struct ChainMonitor {
+    chain_info: ChainInfo,
+    adapter: Box<dyn ChainAdapter>,
+    last_block_number: Option<u64>,
+    stats: ChainStats,
+    options: MonitorOptions,
+    last_progress: Instant,
+    heads: Option<HeadSubscription>,
+    observer: Option<UnboundedSender<BlockObservation>>,
+    // Cleared if the provider turns out not to support batch requests
+    batching: bool,
+    hashes: HashHistory,
+    // Cleared if the provider doesn't know the safe and finalized block tags
+    safe_heads: bool,
+    last_safe_check: Option<Instant>,
+    // Cleared if the node doesn't answer `eth_syncing` or the chain has no such query
+    node_health: bool,
+    last_health_check: Option<Instant>,
+    // Cleared if the chain's blocks turn out not to record an L1 origin
+    l1_origins: bool,
+    // Last block processed before a resumed run stopped, and why it stopped
+    resume_after: Option<(u64, GapReason)>,
+    // Second provider whose head is checked against the primary's on every poll
+    comparison: Option<Box<dyn ChainAdapter>>,
+    comparison_failing: bool,
+    // Flashblocks feed, and the flashblocks waiting for their canonical block
+    flashblocks: Option<FlashblockFeed>,
+    preconfs: PreconfTracker,
+    // Title and message of an outage that just started or ended, for the notifiers
+    outage_notice: Option<(&'static str, String)>,
+    // Responses with a `Date` header and the sum of their skews, since the stats' histogram
+    // only holds the skews not yet sent to the main loop
+    provider_clock_skew: (u64, i64),
+    // Whether the provider's clock was reported as off ours
+    provider_clock_warned: bool,
+}
+
+impl ChainMonitor {
+    pub fn new(chain_info: ChainInfo, options: MonitorOptions, client: reqwest::Client) -> Self {
+        let heads = own_subscription(&chain_info);
+        Self::with_connections(chain_info, options, client, heads)
+    }
+
+    // Monitor through `client` and the `newHeads` subscription `heads`, either of which
+    // may be shared with other chains
+    fn with_connections(
+        chain_info: ChainInfo,
+        options: MonitorOptions,
+        client: reqwest::Client,
+        heads: Option<HeadSubscription>,
+    ) -> Self {
+        let adapter = adapter_for(&chain_info, &options, client);
+        let comparison = chain_info.compare_rpc_url.clone().map(|rpc_url| {
+            // The chain's credentials are for its own provider
+            let info = ChainInfo {
+                rpc_url,
+                http: HttpSettings {
+                    auth: RpcAuth::default(),
+                    ..chain_info.http.clone()
+                },
+                ..chain_info.clone()
+            };
+            let client = http_client(&info.rpc_url, &info.http);
+            adapter_for(&info, &options, client)
+        });
+        let mut monitor = Self::from_parts(chain_info, options, adapter, heads);
+        if let Some(comparison) = comparison {
+            monitor.compare_with(comparison);
+        }
+        monitor
+    }
+
+    /// Monitor a chain through `adapter` instead of Ethereum JSON-RPC
+    pub fn with_adapter(chain_info: ChainInfo, options: MonitorOptions, adapter: Box<dyn ChainAdapter>) -> Self {
+        let heads = own_subscription(&chain_info);
+        Self::from_parts(chain_info, options, adapter, heads)
+    }
+
+    fn from_parts(
+        chain_info: ChainInfo,
+        options: MonitorOptions,
+        adapter: Box<dyn ChainAdapter>,
+        heads: Option<HeadSubscription>,
+    ) -> Self {
+        let name = chain_info.name.clone();
+        let stats = ChainStats {
+            expected_block_time_ms: chain_info.expected_block_time_ms,
+            schedule: ScheduleAdherence::new(chain_info.schedule_tolerance_ms.unwrap_or(options.schedule_tolerance_ms)),
+            anomalies: DeltaAnomalies::new(options.anomaly),
+            outages: Outages::new(
+                chain_info
+                    .expected_block_time_ms
+                    .filter(|ms| *ms > 0 && options.outage_block_times > 0)
+                    .map(|ms| ms * options.outage_block_times),
+            ),
+            max_raw_samples: options.max_raw_samples,
+            histogram_buckets: options.histogram_buckets.clone(),
+            head_consistency: HeadConsistency::new(
+                Some(options.head_consistency_window_ms).filter(|ms| *ms > 0 && chain_info.ws_url.is_some()),
+            ),
+            ..ChainStats::new(&name)
+        };
+        let flashblocks = chain_info
+            .flashblocks_url
+            .clone()
+            .map(|url| FlashblockFeed::spawn(name.clone(), url));
+        let batching = options.batch_requests;
+        let safe_heads = options.safe_head_interval.is_some();
+        let node_health = options.node_health_interval.is_some();
+        // An Arbitrum chain given an L1 endpoint has its L1 origins tracked without asking
+        let l1_origins = options.track_l1_origin || chain_info.l1_rpc_url.is_some();
+        Self {
+            chain_info,
+            adapter,
+            last_block_number: None,
+            stats,
+            options,
+            last_progress: Instant::now(),
+            heads,
+            observer: None,
+            batching,
+            hashes: HashHistory::default(),
+            safe_heads,
+            last_safe_check: None,
+            node_health,
+            last_health_check: None,
+            l1_origins,
+            resume_after: None,
+            comparison: None,
+            comparison_failing: false,
+            flashblocks,
+            preconfs: PreconfTracker::default(),
+            outage_notice: None,
+            provider_clock_skew: (0, 0),
+            provider_clock_warned: false,
+        }
+    }
+
+    /// Also poll `adapter`, a second provider for the same chain, and log whenever its head
+    /// disagrees with the primary's
+    pub fn compare_with(&mut self, adapter: Box<dyn ChainAdapter>) {
+        self.comparison = Some(adapter);
+    }
+
+    /// Continue from the stats of an earlier run of this chain. Blocks produced while the
+    /// run was stopped are skipped, since their receipt times would only measure the outage.
+    pub fn resume(&mut self, mut stats: ChainStats) {
+        let reason = if stats.paused { GapReason::Paused } else { GapReason::Restart };
+        self.resume_after = stats.last_block_number.map(|last| (last, reason));
+        // Checkpointed stats have no rolling windows; a restarted monitor's still do
+        if stats.windows.is_empty() {
+            stats.windows = std::mem::take(&mut self.stats.windows);
+        }
+        stats.schedule.tolerance_ms = self.stats.schedule.tolerance_ms;
+        stats.anomalies.settings = self.stats.anomalies.settings;
+        stats.outages.threshold_ms = self.stats.outages.threshold_ms;
+        stats.head_consistency.window_ms = self.stats.head_consistency.window_ms;
+        self.stats = ChainStats {
+            chain_name: self.stats.chain_name.clone(),
+            expected_block_time_ms: self.stats.expected_block_time_ms,
+            max_raw_samples: self.stats.max_raw_samples,
+            histogram_buckets: self.stats.histogram_buckets.clone(),
+            paused: false,
+            ..stats
+        };
+    }
+
+    /// Also send every recorded observation to `observer`
+    pub fn set_observer(&mut self, observer: UnboundedSender<BlockObservation>) {
+        self.observer = Some(observer);
+    }
+
+    /// Monitor the chain in a background Tokio task, yielding every block it records, to
+    /// aggregate observations some other way than `ChainStats`. Polls every
+    /// `ChainInfo::poll_interval_ms` (500ms unless set), under the same supervision and
+    /// circuit breaker as the logger's monitors (`SUPERVISOR_*` and `BREAKER_*`). Monitoring
+    /// stops when the stream is dropped. Must be called from within a Tokio runtime.
+    pub fn observe(mut self) -> impl Stream<Item = BlockObservation> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.set_observer(sender.clone());
+        let poll_interval =
+            time::Duration::from_millis(self.chain_info.poll_interval_ms.unwrap_or(STANDALONE_POLL_INTERVAL_MS));
+        let task = tokio::spawn(monitor_chain(
+            self,
+            Supervisor::new(SupervisorConfig::from_env()),
+            CircuitBreaker::new(BreakerConfig::from_env()),
+            Notifiers::default(),
+            poll_interval,
+            None,
+            None,
+        ));
+        tokio::spawn(async move {
+            sender.closed().await;
+            task.abort();
+        });
+        observation_stream(receiver)
+    }
+
+    /// Poll once, recording every block produced since the previous check
+    pub async fn check_new_blocks(&mut self) -> Result<()> {
+        self.check_node_health().await;
+        self.check_safe_heads().await;
+        self.compare_providers().await;
+        
+        // Pushed heads carry their exact arrival time; polling only covers a dropped subscription
+        if self.heads.is_some() {
+            self.process_pushed_heads().await;
+            if self.heads.as_ref().is_some_and(|heads| heads.is_connected()) {
+                self.check_outage(Stamp::now());
+                self.audit_pushed_heads().await;
+                return Ok(());
+            }
+            // Nothing is pushed while the subscription is down, so there is nothing to match
+            self.stats.head_consistency.reset();
+        }
+        
+        // Get the latest block number, or the block the chain's tag points at along with its
+        // header, received when it was requested
+        let (latest_block, tagged) = match self.chain_info.block_tag {
+            BlockTag::Latest if !self.options.single_call_polling => (self.latest_height().await?, None),
+            tag => {
+                let received = Stamp::now();
+                let (height, header) = self.tagged_block(tag.as_str()).await?;
+                (height, Some((header, received, Stamp::now())))
+            }
+        };
+        let now = Stamp::now();
+        self.note_head(latest_block, now);
+        self.check_outage(now);
+        
+        // If this is our first check, initialize with current block and return
+        if self.last_block_number.is_none() {
+            self.check_chain_id().await;
+            self.start_at(latest_block);
+            return Ok(());
+        }
+        
+        // A head older than one we've already processed comes from a stale or cached backend
+        let last_known = self.last_block_number.unwrap();
+        if latest_block < last_known {
+            self.stats.stale_responses += 1;
+            debug!(
+                "{}: Stale head {} (already processed up to {})",
+                self.chain_info.name, latest_block, last_known
+            );
+            return Ok(());
+        }
+        
+        // Process any new blocks
+        self.stats.record_backlog(latest_block - last_known);
+        let Some((header, received, responded)) = tagged else {
+            self.catch_up(last_known, latest_block).await;
+            return Ok(());
+        };
+        if latest_block == last_known {
+            return Ok(());
+        }
+        // The tagged block is recorded as it was returned, which a pending block has to be
+        // since it can't be fetched by number; the blocks before it are fetched as usual
+        self.catch_up(last_known, latest_block - 1).await;
+        if self.last_block_number == Some(latest_block - 1) {
+            let span = info_span!(
+                "block_observation",
+                chain = %self.chain_info.name,
+                block_number = latest_block,
+                delta_ms = tracing::field::Empty,
+            );
+            let result = self
+                .record_block(latest_block, header, received, Some(responded))
+                .instrument(span)
+                .await;
+            self.settle_block(latest_block, result);
+        }
+        
+        Ok(())
+    }
+
+    // Note that the endpoint reported `head` at `at`, ending any outage it was in
+    fn note_head(&mut self, head: u64, at: Stamp) {
+        let Some(outage) = self.stats.outages.record_head(head, at) else {
+            return;
+        };
+        let message = format!(
+            "{} produced block {} after {:.1}s without a new block (since block {})",
+            self.chain_info.name,
+            head,
+            outage.duration_ms as f64 / 1000.0,
+            outage.last_block
+        );
+        info!("{}: Outage over: {}", self.chain_info.name, message);
+        self.outage_notice = Some(("Chain outage over", message));
+    }
+
+    // Open an outage once the head, just confirmed by the endpoint, has been still too long
+    fn check_outage(&mut self, now: Stamp) {
+        let Some(outage) = self.stats.outages.check(now, self.stats.max_raw_samples) else {
+            return;
+        };
+        let message = format!(
+            "{} has produced no block for {:.1}s, more than {} block times (head stuck at block {})",
+            self.chain_info.name,
+            outage.duration_ms as f64 / 1000.0,
+            self.options.outage_block_times,
+            outage.last_block
+        );
+        warn!("{}: Outage: {}", self.chain_info.name, message);
+        self.outage_notice = Some(("Chain outage", message));
+    }
+
+    // Warn if the endpoint serves a different chain than the one configured, e.g. a
+    // testnet URL given for a mainnet preset
+    async fn check_chain_id(&mut self) {
+        let Some(expected) = self.chain_info.chain_id else {
+            return;
+        };
+        let result = self.adapter.chain_id().await;
+        match self.settle_call(result) {
+            Ok(chain_id) if chain_id == expected => debug!("{}: Endpoint serves chain ID {}", self.chain_info.name, chain_id),
+            Ok(chain_id) => error!(
+                "{}: Endpoint serves chain ID {}, not {}; its blocks are recorded as {} regardless",
+                self.chain_info.name, chain_id, expected, self.chain_info.name
+            ),
+            Err(e) => debug!("{}: Couldn't check the chain ID: {}", self.chain_info.name, e),
+        }
+    }
+
+    // Start recording after `head`, or after the last block of a resumed run if a stale
+    // backend reports an older head, so no block is counted twice
+    fn start_at(&mut self, head: u64) {
+        match self.resume_after.take() {
+            Some((last, reason)) if head > last => {
+                info!(
+                    "{}: Resuming at block {}, skipping {} block(s) produced while {}",
+                    self.chain_info.name,
+                    head,
+                    head - last,
+                    if reason == GapReason::Paused { "paused" } else { "stopped" }
+                );
+                self.record_gap(last + 1, head, reason);
+                self.last_block_number = Some(head);
+            }
+            Some((last, _)) => {
+                info!("{}: Resuming after block {}", self.chain_info.name, last);
+                self.last_block_number = Some(last);
+            }
+            None => {
+                info!("{}: Starting at block {}", self.chain_info.name, head);
+                self.last_block_number = Some(head);
+            }
+        }
+    }
+
+    /// Fetch and record every block after `last_known` up to and including `target`
+    async fn catch_up(&mut self, last_known: u64, target: u64) {
+        let mut next = last_known + 1;
+        // After an outage, all but the latest blocks would only have their receipt times
+        // measure the outage; skip them and say so
+        let max_blocks = self.options.max_catch_up_blocks;
+        if max_blocks > 0 && target - last_known > max_blocks {
+            warn!(
+                "{}: Head jumped {} blocks, skipping blocks {}-{}",
+                self.chain_info.name,
+                target - last_known,
+                next,
+                target - 1
+            );
+            self.record_gap(next, target - 1, GapReason::HeadJump);
+            self.last_block_number = Some(target - 1);
+            next = target;
+        }
+        while next <= target {
+            // Fetch several missing blocks in one request, so fetching them one after another
+            // doesn't skew the later blocks' arrival times
+            if self.batching && target > next {
+                let last = target.min(next + MAX_BATCH_BLOCKS - 1);
+                let batch = self.blocks_in(next, last).await;
+                let responded = Stamp::now();
+                match batch {
+                    Ok(batch) => {
+                        for (block_num, header) in (next..=last).zip(batch.headers) {
+                            let span = info_span!(
+                                "block_observation",
+                                chain = %self.chain_info.name,
+                                block_number = block_num,
+                                delta_ms = tracing::field::Empty,
+                            );
+                            let result = match header {
+                                Ok(header) => {
+                                    self.record_block(block_num, header, batch.received, Some(responded))
+                                        .instrument(span)
+                                        .await
+                                }
+                                Err(e) => Err(e),
+                            };
+                            if !self.settle_block(block_num, result) {
+                                return;
+                            }
+                        }
+                        next = last + 1;
+                    }
+                    Err(e) if is_transient(&e) => {
+                        self.stats.rpc_errors += 1;
+                        error!("{}: Error fetching blocks {}-{}: {}", self.chain_info.name, next, last, e);
+                        return;
+                    }
+                    Err(e) => {
+                        warn!("{}: Batch request failed ({}), fetching blocks one at a time", self.chain_info.name, e);
+                        self.batching = false;
+                    }
+                }
+                continue;
+            }
+            
+            let span = info_span!(
+                "block_observation",
+                chain = %self.chain_info.name,
+                block_number = next,
+                delta_ms = tracing::field::Empty,
+            );
+            let result = self.process_block(next).instrument(span).await;
+            if !self.settle_block(next, result) {
+                return;
+            }
+            next += 1;
+        }
+    }
+
+    // Account for one block's outcome and advance past it. Returns false when the catch-up
+    // should stop and resume from this block on the next tick.
+    fn settle_block(&mut self, block_num: u64, result: Result<()>) -> bool {
+        match result {
+            Ok(_) => {},
+            // The backend serving this request hasn't seen the block yet; retry next tick
+            Err(e) if e.is::<StaleResponse>() => {
+                self.stats.stale_responses += 1;
+                debug!("{}: {}", self.chain_info.name, e);
+                return false;
+            }
+            // Still failing after the retries; leave the block for the next tick rather than skip it
+            Err(e) if is_transient(&e) => {
+                self.stats.rpc_errors += 1;
+                error!("{}: Error processing block {}: {}", self.chain_info.name, block_num, e);
+                return false;
+            }
+            Err(e) => {
+                self.stats.rpc_errors += 1;
+                error!("{}: Error processing block {}: {}", self.chain_info.name, block_num, e);
+                self.record_gap(block_num, block_num, GapReason::FetchFailed);
+            }
+        }
+        // Advance per block so an interrupted catch-up never re-counts blocks
+        self.last_block_number = Some(block_num);
+        true
+    }
+
+    // Count blocks `first..=last` as missed
+    fn record_gap(&mut self, first: u64, last: u64, reason: GapReason) {
+        self.stats.record_gap(GapEvent {
+            detected_at_ms: Utc::now().timestamp_millis(),
+            first_block: first,
+            last_block: last,
+            reason,
+        });
+    }
+
+    // Sample how far the safe and finalized heads trail the latest recorded block, at most
+    // once per `safe_head_interval`. Failures here never fail the poll.
+    async fn check_safe_heads(&mut self) {
+        let Some(interval) = self.options.safe_head_interval else {
+            return;
+        };
+        if !self.safe_heads || self.last_safe_check.is_some_and(|checked| checked.elapsed() < interval) {
+            return;
+        }
+        let Some(head) = self.stats.previous.clone() else {
+            return;
+        };
+        self.last_safe_check = Some(Instant::now());
+        
+        let result = async {
+            let safe = self.tagged_block("safe").await?;
+            let finalized = self.tagged_block("finalized").await?;
+            Ok::<_, anyhow::Error>((safe, finalized))
+        }
+        .await;
+        match result {
+            Ok(((safe, safe_header), (finalized, finalized_header))) => {
+                self.stats.record_head_lag(HeadLagSample {
+                    sampled_at_ms: Utc::now().timestamp_millis(),
+                    head: head.block_number,
+                    safe,
+                    finalized,
+                    safe_lag_ms: head.block_timestamp_ms as i64 - safe_header.timestamp_ms as i64,
+                    finalized_lag_ms: head.block_timestamp_ms as i64 - finalized_header.timestamp_ms as i64,
+                });
+            }
+            Err(e) if is_transient(&e) => {
+                self.stats.rpc_errors += 1;
+                error!("{}: Error fetching safe/finalized heads: {}", self.chain_info.name, e);
+            }
+            Err(e) => {
+                warn!(
+                    "{}: Safe/finalized heads unavailable ({}); no longer tracking them",
+                    self.chain_info.name, e
+                );
+                self.safe_heads = false;
+            }
+        }
+    }
+
+    // Ask the node whether it is syncing, at most once per `node_health_interval`, so the
+    // blocks recorded until the next check are annotated with the answer. Failures here
+    // never fail the poll.
+    async fn check_node_health(&mut self) {
+        let Some(interval) = self.options.node_health_interval else {
+            return;
+        };
+        if !self.node_health || self.last_health_check.is_some_and(|checked| checked.elapsed() < interval) {
+            return;
+        }
+        self.last_health_check = Some(Instant::now());
+        
+        let result = self.adapter.node_health().await;
+        match self.settle_call(result) {
+            Ok(health) => {
+                let was_syncing = self.stats.node_syncing == Some(true);
+                if health.syncing && !was_syncing {
+                    let behind = health
+                        .sync_lag_blocks()
+                        .map(|lag| format!(" ({} blocks behind)", lag))
+                        .unwrap_or_default();
+                    warn!(
+                        "{}: Node reports it is syncing{}; its deltas reflect the node catching up",
+                        self.chain_info.name, behind
+                    );
+                } else if !health.syncing && was_syncing {
+                    info!("{}: Node finished syncing", self.chain_info.name);
+                }
+                self.stats.record_node_health(&health);
+            }
+            Err(e) if is_transient(&e) => {
+                self.stats.rpc_errors += 1;
+                error!("{}: Error checking node health: {}", self.chain_info.name, e);
+            }
+            Err(e) => {
+                warn!("{}: Node health unavailable ({}); no longer checking it", self.chain_info.name, e);
+                self.node_health = false;
+            }
+        }
+    }
+
+    // The L1 origin of `block_number`, when tracked. The block was already received, so
+    // this extra request doesn't touch its delta; failures here never fail the block.
+    async fn fetch_l1_origin(&mut self, block_number: u64) -> Option<L1Origin> {
+        if !self.l1_origins {
+            return None;
+        }
+        let result = self.adapter.l1_origin(block_number).await;
+        match self.settle_call(result) {
+            Ok(origin) => Some(origin),
+            // A lagging backend, or an empty block on a chain without deposits
+            Err(e) if e.is::<StaleResponse>() => {
+                debug!("{}: No L1 origin for block {}", self.chain_info.name, block_number);
+                None
+            }
+            Err(e) if is_transient(&e) => {
+                self.stats.rpc_errors += 1;
+                error!("{}: Error fetching the L1 origin of block {}: {}", self.chain_info.name, block_number, e);
+                None
+            }
+            Err(e) => {
+                warn!("{}: L1 origin unavailable ({}); no longer tracking it", self.chain_info.name, e);
+                self.l1_origins = false;
+                None
+            }
+        }
+    }
+
+    // Compare the recorded block with the flashblocks that preconfirmed it, if the chain has
+    // a feed
+    fn compare_preconf(&mut self, block_number: u64, header: &BlockHeader, received: Stamp) {
+        let Some(feed) = self.flashblocks.as_mut() else {
+            return;
+        };
+        // Flashblock arrivals are corrected for our clock's offset like receipt times
+        while let Some(mut flashblock) = feed.try_next() {
+            flashblock.received = Stamp {
+                wall: self.options.clock.correct(flashblock.received.wall),
+                ..flashblock.received
+            };
+            if !self.preconfs.add(flashblock) {
+                self.stats.preconf.late_flashblocks += 1;
+            }
+        }
+        let received = Stamp {
+            wall: self.options.clock.correct(received.wall),
+            ..received
+        };
+        match self.preconfs.settle(block_number, header.timestamp_ms, header.hash.as_deref(), received) {
+            Some(sample) => self.stats.record_preconf(sample),
+            None if feed.is_connected() => {
+                self.stats.preconf.missed_blocks += 1;
+                debug!("{}: No flashblocks for block {}", self.chain_info.name, block_number);
+            }
+            None => {}
+        }
+    }
+
+    // Record heads received over the subscription, fetching any it skipped over HTTP
+    async fn process_pushed_heads(&mut self) {
+        while let Some(head) = self.heads.as_mut().and_then(|heads| heads.try_next()) {
+            self.last_progress = Instant::now();
+            self.note_head(head.number, head.received);
+            self.stats.head_consistency.record_pushed(head.number, head.received);
+            let last_known = match self.last_block_number {
+                Some(last_known) => last_known,
+                None => {
+                    self.start_at(head.number);
+                    continue;
+                }
+            };
+            // Already recorded, e.g. by polling while the subscription was down
+            if head.number <= last_known {
+                continue;
+            }
+            
+            self.catch_up(last_known, head.number - 1).await;
+            let span = info_span!(
+                "block_observation",
+                chain = %self.chain_info.name,
+                block_number = head.number,
+                delta_ms = tracing::field::Empty,
+            );
+            let header = BlockHeader {
+                timestamp_ms: head.timestamp_ms,
+                hash: head.hash,
+                parent_hash: head.parent_hash,
+                gas_used: head.gas_used,
+                tx_count: None,
+            };
+            let result = self.record_block(head.number, header, head.received, None).instrument(span).await;
+            if !self.settle_block(head.number, result) {
+                return;
+            }
+        }
+    }
+
+    // Poll the head alongside the subscription and flag heads only one of them reported
+    // within the window
+    async fn audit_pushed_heads(&mut self) {
+        if !self.stats.head_consistency.is_audited() {
+            return;
+        }
+        let sent = Stamp::now();
+        match self.latest_height().await {
+            Ok(height) => self.stats.head_consistency.record_polled(height, sent),
+            Err(e) => debug!("{}: Skipping the head consistency poll: {}", self.chain_info.name, e),
+        }
+        for mismatch in self.stats.head_consistency.expire(Stamp::now()) {
+            warn!(
+                "{}: Head {} was reported by {} but not by {} within {}ms",
+                self.chain_info.name,
+                mismatch.block_number,
+                mismatch.seen_by.as_str(),
+                mismatch.seen_by.other().as_str(),
+                self.options.head_consistency_window_ms
+            );
+        }
+    }
+
+    async fn process_block(&mut self, block_number: u64) -> Result<()> {
+        let now = Stamp::now();
+        
+        // Get the block by number
+        let header = self.block_at(block_number).await?;
+        let responded = Stamp::now();
+        
+        self.record_block(block_number, header, now, Some(responded)).await
+    }
+
+    /// Record a block received at `received` (with its response at `responded`, when it was
+    /// fetched), first checking that it builds on the block recorded below it
+    async fn record_block(
+        &mut self,
+        block_number: u64,
+        header: BlockHeader,
+        received: Stamp,
+        responded: Option<Stamp>,
+    ) -> Result<()> {
+        let known_parent = block_number.checked_sub(1).and_then(|n| self.hashes.get(n));
+        if let (Some(parent_hash), Some(known)) = (&header.parent_hash, known_parent) {
+            if parent_hash != known {
+                self.handle_reorg(block_number).await?;
+            }
+        }
+        
+        let l1_origin = self.fetch_l1_origin(block_number).await;
+        self.record_observation(block_number, &header, received, responded, l1_origin);
+        self.compare_preconf(block_number, &header, received);
+        if let Some(hash) = header.hash {
+            self.hashes.record(block_number, hash);
+        }
+        Ok(())
+    }
+
+    // Walk back from `block_number` until the canonical chain links up with the blocks we
+    // recorded, counting the recorded blocks it replaced
+    async fn handle_reorg(&mut self, block_number: u64) -> Result<()> {
+        let received = Stamp::now();
+        let mut replacements = Vec::new();
+        let mut number = block_number - 1;
+        loop {
+            let header = self.block_at(number).await?;
+            // Stop once the parent matches, or where our history (or the parent hash) runs out
+            let known_parent = number.checked_sub(1).and_then(|n| self.hashes.get(n));
+            let linked = match (&header.parent_hash, known_parent) {
+                (Some(parent_hash), Some(known)) => parent_hash == known,
+                _ => true,
+            };
+            replacements.push((number, header));
+            if linked {
+                break;
+            }
+            number -= 1;
+        }
+        
+        let depth = replacements.len();
+        self.stats.reorgs += 1;
+        self.stats.reorged_blocks += depth;
+        self.stats.max_reorg_depth = self.stats.max_reorg_depth.max(depth);
+        warn!(
+            "{}: Reorg detected at block {}: {} recorded block(s) replaced",
+            self.chain_info.name, block_number, depth
+        );
+        
+        for (number, header) in replacements.into_iter().rev() {
+            // Replacements are only seen now, so their deltas include the reorg delay
+            if self.options.remeasure_reorgs {
+                let l1_origin = self.fetch_l1_origin(number).await;
+                self.record_observation(number, &header, received, None, l1_origin);
+            }
+            if let Some(hash) = header.hash {
+                self.hashes.record(number, hash);
+            }
+        }
+        Ok(())
+    }
+
+    /// Record that `block_number`, with `header` and derived from `l1_origin`, was received
+    /// at `received` and, if it was fetched, responded to at `responded`
+    fn record_observation(
+        &mut self,
+        block_number: u64,
+        header: &BlockHeader,
+        received: Stamp,
+        responded: Option<Stamp>,
+        l1_origin: Option<L1Origin>,
+    ) {
+        // Correct for our own clock's offset so it isn't blamed on the sequencer
+        let now = self.options.clock.correct(received.wall);
+        // Round trips are intervals, so they come from the monotonic clock, and the response
+        // time follows from them rather than from a wall clock that may have stepped since
+        let round_trip_ms = responded.map(|responded| responded.ms_since(&received));
+        let responded = round_trip_ms.map(|ms| now + chrono::Duration::milliseconds(ms));
+        if let Some(offset) = self.options.clock.offset_ms() {
+            self.stats.clock_offset_ms = Some(offset);
+            self.stats.max_clock_offset_ms = self.stats.max_clock_offset_ms.max(offset.abs());
+        }
+        
+        // Tag blocks stamped or received around a leap second
+        let timestamp_ms = header.timestamp_ms;
+        let block_time = DateTime::from_timestamp_millis(timestamp_ms as i64).unwrap_or(now);
+        let leap_seconds = &self.options.leap_seconds;
+        let leap_affected = leap_seconds.affects(block_time) || leap_seconds.affects(now);
+        
+        // Update stats with block number, timestamp, and receipt time
+        let delta_ms = now.timestamp_millis() - timestamp_ms as i64;
+        let anomaly_z = info_span!("record")
+            .in_scope(|| {
+                let received = Stamp { wall: now, ..received };
+                self.stats.update(block_number, header, received, round_trip_ms, l1_origin, leap_affected)
+            });
+        tracing::Span::current().record("delta_ms", delta_ms);
+        telemetry::record_delta(&self.chain_info.name, delta_ms);
+        
+        debug!(
+            "{}: Block {} | Timestamp: {} | Received: {} | Delta: {}ms",
+            self.chain_info.name,
+            block_number,
+            self.options.timezone.format(block_time, "%H:%M:%S%.3f"),
+            self.options.timezone.format(now, "%H:%M:%S"),
+            delta_ms
+        );
+        if let (Some(z_score), Some((mean_ms, _))) = (anomaly_z, self.stats.anomalies.baseline_ms()) {
+            warn!(
+                "{}: Block {} delta of {}ms is {:+.1} standard deviations from its recent baseline of {:.0}ms",
+                self.chain_info.name, block_number, delta_ms, z_score, mean_ms
+            );
+        }
+        
+        // An observer that has gone away just stops receiving
+        if let Some(observer) = &self.observer {
+            let _ = observer.send(BlockObservation {
+                chain: self.chain_info.name.clone(),
+                block_number,
+                block_timestamp_ms: timestamp_ms,
+                block_hash: header.hash.clone(),
+                parent_hash: header.parent_hash.clone(),
+                gas_used: header.gas_used,
+                tx_count: header.tx_count,
+                received_at: now,
+                responded_at: responded,
+                delta_ms,
+                leap_affected,
+                l1_origin,
+                node_syncing: self.stats.node_syncing,
+                anomaly_z,
+                vantage: Some(self.options.vantage.label.clone()),
+            });
+        }
+    }
+
+    async fn latest_height(&mut self) -> Result<u64> {
+        let result = self.adapter.latest_height().await;
+        self.settle_call(result)
+    }
+
+    async fn block_at(&mut self, height: u64) -> Result<BlockHeader> {
+        let result = self.adapter.block_at(height).await;
+        self.settle_call(result)
+    }
+
+    async fn blocks_in(&mut self, first: u64, last: u64) -> Result<BlockBatch> {
+        let result = self.adapter.blocks_in(first, last).await;
+        self.settle_call(result)
+    }
+
+    async fn tagged_block(&mut self, tag: &'static str) -> Result<(u64, BlockHeader)> {
+        let result = self.adapter.tagged_block(tag).await;
+        self.settle_call(result)
+    }
+
+    // Fold the adapter's counters into the stats, and count an answered call as progress
+    fn settle_call<T>(&mut self, result: Result<T>) -> Result<T> {
+        let counters = self.adapter.take_counters();
+        self.stats.rpc_retries += counters.retries;
+        self.stats.schema_deviations += counters.schema_deviations;
+        for ms in counters.head_latencies_ms {
+            self.stats.head_rpc_latency.record(ms as i64);
+        }
+        for ms in counters.block_latencies_ms {
+            self.stats.block_rpc_latency.record(ms as i64);
+        }
+        // Against our clock as corrected for receipt times, so the skew and the deltas agree
+        for (server_ms, local) in counters.server_dates {
+            let skew_ms = server_ms - self.options.clock.correct(local).timestamp_millis();
+            self.stats.provider_clock_skew.record(skew_ms);
+            self.provider_clock_skew.0 += 1;
+            self.provider_clock_skew.1 += skew_ms;
+        }
+        self.check_provider_clock();
+        if result.is_ok() {
+            self.last_progress = Instant::now();
+        }
+        result
+    }
+
+    // Warn once when enough responses put the provider's clock at least a second off ours,
+    // which a whole-second `Date` header can't explain
+    fn check_provider_clock(&mut self) {
+        let (responses, sum_ms) = self.provider_clock_skew;
+        if self.provider_clock_warned
+            || responses < PROVIDER_CLOCK_MIN_RESPONSES
+            || (sum_ms / responses as i64).abs() < PROVIDER_CLOCK_WARN_MS
+        {
+            return;
+        }
+        self.provider_clock_warned = true;
+        warn!(
+            "{}: Provider's clock is {:+}ms off ours (average over {} responses' Date headers)",
+            self.chain_info.name,
+            sum_ms / responses as i64,
+            responses
+        );
+    }
+
+    // Fetch the latest block from both providers at once and log when they start or stop
+    // disagreeing. Failures here never fail the poll.
+    async fn compare_providers(&mut self) {
+        let Some(comparison) = self.comparison.as_mut() else {
+            return;
+        };
+        let (primary, secondary) = futures::join!(self.adapter.tagged_block("latest"), comparison.tagged_block("latest"));
+        // The comparison provider's counters describe a different endpoint; drop them
+        comparison.take_counters();
+        let primary = self.settle_call(primary);
+
+        let name = &self.chain_info.name;
+        let secondary = match secondary {
+            Ok((number, header)) => {
+                if self.comparison_failing {
+                    info!("{}: Comparison provider is answering again", name);
+                    self.comparison_failing = false;
+                }
+                (number, header.timestamp_ms)
+            }
+            Err(e) => {
+                if !self.comparison_failing {
+                    warn!("{}: Comparison provider failed: {}", name, e);
+                    self.comparison_failing = true;
+                }
+                return;
+            }
+        };
+        let primary = match primary {
+            Ok((number, header)) => (number, header.timestamp_ms),
+            Err(e) => {
+                debug!("{}: Skipping provider comparison: {}", name, e);
+                return;
+            }
+        };
+
+        let was_disagreeing = self.stats.provider_divergence.disagreeing;
+        let disagreeing = self.stats.provider_divergence.record(primary, secondary, &self.options.compare);
+        if disagreeing && !was_disagreeing {
+            warn!(
+                "{}: Providers disagree: primary head #{} at {}ms, comparison head #{} at {}ms",
+                name, primary.0, primary.1, secondary.0, secondary.1
+            );
+        } else if was_disagreeing && !disagreeing {
+            info!("{}: Providers agree again at head #{}", name, primary.0);
+        }
+    }
+
+    /// Rebuild connection state after repeated failures or a panic, keeping the
+    /// collected stats and the last processed block
+    fn restart(&mut self) {
+        self.adapter.reset();
+        if let Some(comparison) = self.comparison.as_mut() {
+            comparison.reset();
+        }
+    }
+
+    pub fn get_stats(&self) -> ChainStats {
+        ChainStats {
+            last_block_number: self.last_block_number,
+            ..self.stats.clone()
+        }
+    }
+
+    // The stats for the main loop to absorb: the counters, but only the records, samples
+    // and histogram counts added since the previous call
+    fn take_stats_update(&mut self) -> ChainStats {
+        ChainStats {
+            last_block_number: self.last_block_number,
+            ..self.stats.take_update()
+        }
+    }
+}
+
+/// The observations sent to `receiver`, as a stream that ends once every sender is gone
+pub(crate) fn observation_stream(receiver: UnboundedReceiver<BlockObservation>) -> impl Stream<Item = BlockObservation> {
+    futures::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|observation| (observation, receiver))
+    })
+}
+
+/// Run one polling iteration under supervision: errors and panics are contained, and a
+/// monitor that keeps failing, wedges or stops recording blocks is restarted with
+/// exponential backoff
+async fn supervised_check(
+    monitor: &mut ChainMonitor,
+    supervisor: &mut Supervisor,
+    breaker: &mut CircuitBreaker,
+    notifiers: &Notifiers,
+) {
+    if !supervisor.ready(Instant::now()) || !breaker.allows(Instant::now()) {
+        return;
+    }
+    
+    let name = monitor.chain_info.name.clone();
+    let wedge_timeout = monitor.options.wedge_timeout;
+    let outcome = time::timeout(wedge_timeout, AssertUnwindSafe(monitor.check_new_blocks()).catch_unwind()).await;
+    
+    // A call that neither succeeds nor fails is a wedged connection; rebuild the client.
+    // A long catch-up that is still making progress simply resumes on the next tick.
+    let outcome = match outcome {
+        Ok(outcome) => outcome,
+        Err(_) if monitor.last_progress.elapsed() < wedge_timeout => return,
+        Err(_) => {
+            monitor.stats.wedge_events += 1;
+            record_endpoint_failure(monitor, breaker, notifiers).await;
+            let reason = format!("wedged with no RPC progress for {}s", monitor.last_progress.elapsed().as_secs());
+            restart(monitor, supervisor, notifiers, &reason).await;
+            return;
+        }
+    };
+    
+    let reason = match outcome {
+        Ok(Ok(())) => {
+            if breaker.record_success() {
+                monitor.stats.degraded = false;
+                info!("{}: Circuit breaker closed; endpoint is responding again", name);
+                notifiers.notify("Endpoint circuit closed", &format!("{} endpoint is responding again", name)).await;
+            }
+            if supervisor.record_success(Instant::now()) {
+                info!("{}: Monitor recovered from crash loop", name);
+                notifiers.notify("Chain monitor recovered", &format!("{} is collecting blocks again", name)).await;
+            }
+            if let Some((title, message)) = monitor.outage_notice.take() {
+                notifiers.notify(title, &message).await;
+            }
+            // Polls that succeed without ever finding a block may be stuck on a stale connection
+            match supervisor.check_stall(monitor.stats.total_blocks, Instant::now()) {
+                Some(quiet) => format!("no blocks recorded for {}s", quiet.as_secs()),
+                None => return,
+            }
+        }
+        Ok(Err(e)) => {
+            monitor.stats.rpc_errors += 1;
+            error!("{}: Error checking blocks: {}", name, e);
+            record_endpoint_failure(monitor, breaker, notifiers).await;
+            if breaker.is_open() || !supervisor.record_failure() {
+                return;
+            }
+            format!("{} consecutive failures, last: {}", supervisor.consecutive_failures(), e)
+        }
+        Err(panic) => format!("panic: {}", panic_message(panic.as_ref())),
+    };
+    restart(monitor, supervisor, notifiers, &reason).await;
+}
+
+// Rebuild the monitor's connections, continuing from its stats, and back off before its
+// next poll, alerting when the chain restarts so often it is in a crash loop
+async fn restart(monitor: &mut ChainMonitor, supervisor: &mut Supervisor, notifiers: &Notifiers, reason: &str) {
+    let name = monitor.chain_info.name.clone();
+    monitor.restart();
+    monitor.stats.monitor_restarts += 1;
+    let event = supervisor.record_restart(Instant::now());
+    warn!(
+        "{}: Restarted monitor ({}); backing off for {}ms",
+        name,
+        reason,
+        event.backoff.as_millis()
+    );
+    
+    if event.entered_crash_loop {
+        let message = format!(
+            "{} restarted {} times in the last 10 minutes; last cause: {}",
+            name, event.recent_restarts, reason
+        );
+        error!("{}: Crash loop detected", name);
+        notifiers.notify("Chain monitor crash loop", &message).await;
+    }
+}
+
+// Count a failure against the chain's endpoint, opening its circuit breaker once it keeps failing
+async fn record_endpoint_failure(monitor: &mut ChainMonitor, breaker: &mut CircuitBreaker, notifiers: &Notifiers) {
+    if !breaker.record_failure(Instant::now()) {
+        return;
+    }
+    
+    monitor.stats.breaker_trips += 1;
+    monitor.stats.degraded = true;
+    let message = format!(
+        "{} endpoint failed {} times in a row; pausing it for {}s",
+        monitor.chain_info.name,
+        breaker.consecutive_failures(),
+        breaker.cool_down().as_secs()
+    );
+    warn!("{}: Circuit breaker opened, chain degraded", monitor.chain_info.name);
+    notifiers.notify("Endpoint circuit open", &message).await;
+}
+
+/// Spawn one task per chain, each polling on its own interval under its own supervisor
+/// and circuit breaker, so a slow endpoint never delays the others. A snapshot of the
+/// chain's stats is sent to `stats` after every poll. Chains with stats in `resumed`
+/// continue from them. Dropping the returned set stops the monitors.
+pub(crate) fn spawn_monitors(
+    config: Config,
+    notifiers: Notifiers,
+    observer: Option<UnboundedSender<BlockObservation>>,
+    stats: Option<UnboundedSender<ChainStats>>,
+    mut resumed: HashMap<String, ChainStats>,
+) -> MonitorSet {
+    let mut monitors = MonitorSet {
+        running: HashMap::new(),
+        paused: HashMap::new(),
+        // Chains on the same provider origin share one HTTP client unless disabled
+        clients: ClientPool::new(config.share_connections),
+        options: config.options,
+        poll_interval: config.poll_interval,
+        adaptive_polling: config.adaptive_polling,
+        supervisor: config.supervisor,
+        breaker: config.breaker,
+        notifiers,
+        observer,
+        stats,
+    };
+    for chain in config.chains {
+        let resumed = resumed.remove(&chain.name);
+        monitors.start(chain, resumed);
+    }
+    monitors
+}
+
+/// The running chain monitors, one task per chain. Dropping the set stops them.
+pub(crate) struct MonitorSet {
+    running: HashMap<String, RunningMonitor>,
+    // Chains whose polling is paused, with the settings to resume them with
+    paused: HashMap<String, ChainInfo>,
+    clients: ClientPool,
+    options: MonitorOptions,
+    poll_interval: time::Duration,
+    adaptive_polling: Option<AdaptivePolling>,
+    supervisor: SupervisorConfig,
+    breaker: BreakerConfig,
+    notifiers: Notifiers,
+    observer: Option<UnboundedSender<BlockObservation>>,
+    stats: Option<UnboundedSender<ChainStats>>,
+}
+
+struct RunningMonitor {
+    chain: ChainInfo,
+    poll_interval: time::Duration,
+    task: JoinHandle<()>,
+}
+
+impl MonitorSet {
+    /// Start monitoring `chain`, continuing from `resumed` when given
+    pub(crate) fn start(&mut self, chain: ChainInfo, resumed: Option<ChainStats>) {
+        let client = self.clients.client_for(&chain.rpc_url, &chain.http);
+        let heads = self.clients.subscription_for(&chain);
+        self.spawn(chain, resumed, client, heads);
+    }
+
+    fn spawn(
+        &mut self,
+        chain: ChainInfo,
+        resumed: Option<ChainStats>,
+        client: reqwest::Client,
+        heads: Option<HeadSubscription>,
+    ) {
+        let poll_interval = self.poll_interval_for(&chain);
+        let mut monitor = ChainMonitor::with_connections(chain.clone(), self.options.clone(), client, heads);
+        if let Some(observer) = &self.observer {
+            monitor.set_observer(observer.clone());
+        }
+        if let Some(stats) = resumed {
+            monitor.resume(stats);
+            // Whoever receives the stats already has the records so far; only new ones are sent
+            monitor.stats.forget_records();
+        }
+        let supervisor = Supervisor::new(self.supervisor);
+        let breaker = CircuitBreaker::new(self.breaker);
+        let task = tokio::spawn(monitor_chain(
+            monitor,
+            supervisor,
+            breaker,
+            self.notifiers.clone(),
+            poll_interval,
+            self.adaptive_polling,
+            self.stats.clone(),
+        ));
+        self.running.insert(
+            chain.name.clone(),
+            RunningMonitor {
+                chain,
+                poll_interval,
+                task,
+            },
+        );
+    }
+
+    /// Stop monitoring the chain called `name`, returning once its task has ended so no
+    /// further stats arrive from it
+    pub(crate) async fn stop(&mut self, name: &str) {
+        self.paused.remove(name);
+        if let Some(running) = self.running.remove(name) {
+            running.task.abort();
+            let _ = running.task.await;
+        }
+    }
+
+    /// Stop polling the chain called `name` until it is resumed, keeping its settings.
+    /// Returns false if it isn't running.
+    pub(crate) async fn pause(&mut self, name: &str) -> bool {
+        let Some(running) = self.running.remove(name) else {
+            return false;
+        };
+        running.task.abort();
+        let _ = running.task.await;
+        self.paused.insert(name.to_string(), running.chain);
+        true
+    }
+
+    /// Poll the paused chain called `name` again, continuing from `stats`. Returns false if
+    /// it isn't paused.
+    pub(crate) fn resume(&mut self, name: &str, stats: Option<ChainStats>) -> bool {
+        let Some(chain) = self.paused.remove(name) else {
+            return false;
+        };
+        self.start(chain, stats);
+        true
+    }
+
+    /// The name of the monitored chain, running or paused, that `name` refers to; chain
+    /// names are matched case-insensitively
+    pub(crate) fn find(&self, name: &str) -> Option<(String, bool)> {
+        let running = self.running.keys().map(|chain| (chain, false));
+        let paused = self.paused.keys().map(|chain| (chain, true));
+        running
+            .chain(paused)
+            .find(|(chain, _)| chain.eq_ignore_ascii_case(name))
+            .map(|(chain, paused)| (chain.clone(), paused))
+    }
+
+    /// The chains being polled, leaving out paused ones
+    pub(crate) fn polling(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.running.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Adopt `config`'s shared settings for the monitors started from now on, and list how
+    /// its chains differ from the running ones: new chains, chains no longer listed, and
+    /// chains whose settings or poll interval changed. Paused chains stay paused, taking
+    /// their new settings when resumed.
+    pub(crate) fn reconfigure(&mut self, config: &Config) -> MonitorChanges {
+        self.options = config.options.clone();
+        self.poll_interval = config.poll_interval;
+        self.adaptive_polling = config.adaptive_polling;
+        self.supervisor = config.supervisor;
+        self.breaker = config.breaker;
+
+        let mut changes = MonitorChanges::default();
+        for chain in &config.chains {
+            if let Some(paused) = self.paused.get_mut(&chain.name) {
+                *paused = chain.clone();
+                continue;
+            }
+            match self.running.get(&chain.name) {
+                None => changes.added.push(chain.clone()),
+                Some(running) if running.chain != *chain || running.poll_interval != self.poll_interval_for(chain) => {
+                    changes.changed.push(chain.clone())
+                }
+                Some(_) => {}
+            }
+        }
+        changes.removed = self
+            .running
+            .keys()
+            .chain(self.paused.keys())
+            .filter(|name| !config.chains.iter().any(|chain| &chain.name == *name))
+            .cloned()
+            .collect();
+        changes
+    }
+
+    fn poll_interval_for(&self, chain: &ChainInfo) -> time::Duration {
+        chain.poll_interval_ms.map_or(self.poll_interval, time::Duration::from_millis)
+    }
+}
+
+impl Drop for MonitorSet {
+    fn drop(&mut self) {
+        for running in self.running.values() {
+            running.task.abort();
+        }
+    }
+}
+
+/// How a reloaded configuration differs from the running monitors
+#[derive(Debug, Default)]
+pub(crate) struct MonitorChanges {
+    pub added: Vec<ChainInfo>,
+    pub removed: Vec<String>,
+    pub changed: Vec<ChainInfo>,
+}
+
+impl MonitorChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+// Poll one chain until stopped, reporting its stats after every iteration
+async fn monitor_chain(
+    mut monitor: ChainMonitor,
+    mut supervisor: Supervisor,
+    mut breaker: CircuitBreaker,
+    notifiers: Notifiers,
+    poll_interval: time::Duration,
+    adaptive_polling: Option<AdaptivePolling>,
+    stats: Option<UnboundedSender<ChainStats>>,
+) {
+    let mut poll_ticker = time::interval(poll_interval);
+    // A slow poll shouldn't be followed by a burst of catch-up ticks
+    poll_ticker.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+    let chain = &monitor.chain_info;
+    let mut poller = adaptive_polling
+        .map(|settings| CadencePoller::new(settings, &chain.name, poll_interval, chain.expected_block_time_ms));
+    let mut sent_progress = None;
+    loop {
+        match &poller {
+            Some(poller) => time::sleep(poller.next_poll_in(Instant::now())).await,
+            None => {
+                poll_ticker.tick().await;
+            }
+        }
+        let polled_at = Instant::now();
+        supervised_check(&mut monitor, &mut supervisor, &mut breaker, &notifiers).await;
+        if let Some(poller) = &mut poller {
+            poller.record(monitor.last_block_number, polled_at);
+        }
+        // Polls that recorded nothing new leave the main loop's copy as it is
+        let progress = (monitor.last_block_number, monitor.stats.progress());
+        if let Some(stats) = stats.as_ref().filter(|_| sent_progress != Some(progress)) {
+            if stats.send(monitor.take_stats_update()).is_err() {
+                return;
+            }
+            // Taken as of after the update, which empties the histograms progress counts in
+            sent_progress = Some((monitor.last_block_number, monitor.stats.progress()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::BoxFuture;
+    use std::sync::{Arc, Mutex};
+
+    // What the scripted chain serves; tests move its head and fork it by hand
+    #[derive(Default)]
+    struct Script {
+        head: u64,
+        // Blocks from this height on are replaced by a competing fork
+        fork_from: Option<u64>,
+        panic_next: bool,
+        resets: usize,
+        // Calls made, to tell single-call polling from the usual height-then-blocks polling
+        height_calls: usize,
+        fetched: Vec<u64>,
+    }
+
+    impl Script {
+        fn hash(&self, height: u64) -> String {
+            let fork = if self.fork_from.is_some_and(|from| height >= from) { "b" } else { "a" };
+            format!("0x{}{:x}", fork, height)
+        }
+
+        fn header(&self, height: u64) -> BlockHeader {
+            BlockHeader {
+                timestamp_ms: Utc::now().timestamp_millis() as u64 - 100,
+                hash: Some(self.hash(height)),
+                parent_hash: Some(self.hash(height - 1)),
+                gas_used: None,
+                tx_count: None,
+            }
+        }
+    }
+
+    struct ScriptedChain(Arc<Mutex<Script>>);
+
+    impl ChainAdapter for ScriptedChain {
+        fn latest_height(&mut self) -> BoxFuture<'_, Result<u64>> {
+            let (head, panic_next) = {
+                let mut script = self.0.lock().unwrap();
+                script.height_calls += 1;
+                (script.head, std::mem::take(&mut script.panic_next))
+            };
+            if panic_next {
+                panic!("scripted panic");
+            }
+            Box::pin(async move { Ok(head) })
+        }
+
+        fn block_at(&mut self, height: u64) -> BoxFuture<'_, Result<BlockHeader>> {
+            let mut script = self.0.lock().unwrap();
+            script.fetched.push(height);
+            let header = script.header(height);
+            Box::pin(async move { Ok(header) })
+        }
+
+        // The head comes back after a while, to tell the request's stamp from the response's
+        fn tagged_block(&mut self, _tag: &'static str) -> BoxFuture<'_, Result<(u64, BlockHeader)>> {
+            let script = self.0.lock().unwrap();
+            let (head, header) = (script.head, script.header(script.head));
+            Box::pin(async move {
+                time::sleep(time::Duration::from_millis(50)).await;
+                Ok((head, header))
+            })
+        }
+
+        fn reset(&mut self) {
+            self.0.lock().unwrap().resets += 1;
+        }
+    }
+
+    fn scripted_monitor(head: u64) -> (ChainMonitor, Arc<Mutex<Script>>) {
+        let config = Config::simulated(None).unwrap();
+        let script = Arc::new(Mutex::new(Script { head, ..Script::default() }));
+        let adapter = Box::new(ScriptedChain(script.clone()));
+        let monitor = ChainMonitor::with_adapter(config.chains[0].clone(), config.options, adapter);
+        (monitor, script)
+    }
+
+    #[tokio::test]
+    async fn reorg_is_walked_back_to_the_common_ancestor() {
+        let (mut monitor, script) = scripted_monitor(10);
+        monitor.check_new_blocks().await.unwrap();
+        script.lock().unwrap().head = 13;
+        monitor.check_new_blocks().await.unwrap();
+        assert_eq!(monitor.stats.reorgs, 0);
+
+        // Blocks 12 and 13 are replaced; 14 builds on the new 13
+        {
+            let mut script = script.lock().unwrap();
+            script.fork_from = Some(12);
+            script.head = 14;
+        }
+        monitor.check_new_blocks().await.unwrap();
+        assert_eq!(monitor.stats.reorgs, 1);
+        assert_eq!(monitor.stats.reorged_blocks, 2);
+        assert_eq!(monitor.stats.max_reorg_depth, 2);
+        assert_eq!(monitor.hashes.get(12), Some("0xbc"));
+        assert_eq!(monitor.hashes.get(11), Some("0xab"));
+        // Replacements aren't recorded again unless REORG_REMEASURE is set
+        assert_eq!(monitor.stats.total_blocks, 4);
+
+        script.lock().unwrap().head = 15;
+        monitor.check_new_blocks().await.unwrap();
+        assert_eq!(monitor.stats.reorgs, 1);
+    }
+
+    #[tokio::test]
+    async fn remeasured_reorgs_record_the_replacements() {
+        let (mut monitor, script) = scripted_monitor(10);
+        monitor.options.remeasure_reorgs = true;
+        monitor.check_new_blocks().await.unwrap();
+        script.lock().unwrap().head = 12;
+        monitor.check_new_blocks().await.unwrap();
+        {
+            let mut script = script.lock().unwrap();
+            script.fork_from = Some(12);
+            script.head = 13;
+        }
+        monitor.check_new_blocks().await.unwrap();
+        assert_eq!(monitor.stats.reorged_blocks, 1);
+        assert_eq!(monitor.stats.total_blocks, 4);
+    }
+
+    #[tokio::test]
+    async fn single_call_polling_records_the_tagged_head_and_catches_up_below_it() {
+        let (mut monitor, script) = scripted_monitor(10);
+        monitor.options.single_call_polling = true;
+        monitor.check_new_blocks().await.unwrap();
+        script.lock().unwrap().head = 13;
+        monitor.check_new_blocks().await.unwrap();
+
+        let script = script.lock().unwrap();
+        assert_eq!(script.height_calls, 0);
+        // Only the blocks below the head are fetched; the head is the one the tag returned
+        assert_eq!(script.fetched, vec![11, 12]);
+        assert_eq!(monitor.last_block_number, Some(13));
+        assert_eq!(monitor.stats.total_blocks, 3);
+
+        // The head is stamped when it was requested, before the catch-up that follows the
+        // response, and its round trip covers the wait for it
+        let head = monitor.stats.raw_samples.iter().find(|sample| sample.block_number == 13).unwrap();
+        let caught_up = monitor.stats.raw_samples.iter().find(|sample| sample.block_number == 11).unwrap();
+        assert!(head.receipt_time_ms + 50 <= caught_up.receipt_time_ms);
+        assert!(head.round_trip_ms.unwrap() >= 50);
+        assert_eq!(head.hash.as_deref(), Some("0xad"));
+    }
+
+    #[tokio::test]
+    async fn polling_asks_for_the_height_without_single_call_polling() {
+        let (mut monitor, script) = scripted_monitor(10);
+        monitor.check_new_blocks().await.unwrap();
+        script.lock().unwrap().head = 12;
+        monitor.check_new_blocks().await.unwrap();
+        let script = script.lock().unwrap();
+        assert_eq!(script.height_calls, 2);
+        assert_eq!(script.fetched, vec![11, 12]);
+    }
+
+    #[tokio::test]
+    async fn only_polls_that_record_something_send_stats() {
+        let (monitor, script) = scripted_monitor(10);
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let task = tokio::spawn(monitor_chain(
+            monitor,
+            Supervisor::new(SupervisorConfig::from_env()),
+            CircuitBreaker::new(BreakerConfig::from_env()),
+            Notifiers::default(),
+            time::Duration::from_millis(5),
+            None,
+            Some(sender),
+        ));
+        time::sleep(time::Duration::from_millis(100)).await;
+        script.lock().unwrap().head = 12;
+        time::sleep(time::Duration::from_millis(100)).await;
+        task.abort();
+
+        let updates: Vec<ChainStats> = std::iter::from_fn(|| receiver.try_recv().ok()).collect();
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[1].raw_samples.len(), 2);
+        assert_eq!(updates[1].last_block_number, Some(12));
+    }
+
+    #[tokio::test]
+    async fn panicking_monitor_restarts_and_keeps_its_stats() {
+        let (mut monitor, script) = scripted_monitor(10);
+        let mut supervisor = Supervisor::new(SupervisorConfig::from_env());
+        let mut breaker = CircuitBreaker::new(BreakerConfig::from_env());
+        let notifiers = Notifiers::default();
+        supervised_check(&mut monitor, &mut supervisor, &mut breaker, &notifiers).await;
+        script.lock().unwrap().head = 12;
+        supervised_check(&mut monitor, &mut supervisor, &mut breaker, &notifiers).await;
+        assert_eq!(monitor.stats.total_blocks, 2);
+
+        script.lock().unwrap().panic_next = true;
+        supervised_check(&mut monitor, &mut supervisor, &mut breaker, &notifiers).await;
+        assert_eq!(monitor.stats.monitor_restarts, 1);
+        assert_eq!(script.lock().unwrap().resets, 1);
+        assert!(!supervisor.ready(Instant::now()));
+
+        // Once the backoff is over the monitor carries on where it stopped, with no gap
+        script.lock().unwrap().head = 13;
+        monitor.check_new_blocks().await.unwrap();
+        assert_eq!(monitor.stats.total_blocks, 3);
+        assert_eq!(monitor.last_block_number, Some(13));
+        assert_eq!(monitor.stats.gaps, 0);
+    }
+
+    #[test]
+    fn chains_on_one_origin_share_a_client() {
+        let settings = HttpSettings::from_env();
+        let mut pool = ClientPool::new(true);
+        pool.client_for("https://base-mainnet.example.com/v2/key-a", &settings);
+        pool.client_for("https://base-mainnet.example.com/v2/key-b", &settings);
+        assert_eq!(pool.clients.len(), 1);
+
+        pool.client_for("https://opt-mainnet.example.com/v2/key-a", &settings);
+        let slower = HttpSettings {
+            request_timeout: settings.request_timeout * 2,
+            ..settings.clone()
+        };
+        pool.client_for("https://base-mainnet.example.com/v2/key-a", &slower);
+        assert_eq!(pool.clients.len(), 3);
+
+        let mut unshared = ClientPool::new(false);
+        unshared.client_for("https://base-mainnet.example.com/v2/key-a", &settings);
+        assert!(unshared.clients.is_empty());
+    }
+}
@@ -0,0 +1,151 @@
+use anyhow::{anyhow, Result};
+use log::{error, info};
+use serde_json::json;
+use std::env;
+
+use crate::redact;
+
+/// A destination for human-readable notifications
+#[derive(Debug, Clone)]
+pub enum Notifier {
+    /// Generic webhook receiving `{"text": "..."}` (Slack/Mattermost compatible)
+    Webhook { url: String },
+    /// Discord channel webhook
+    #[cfg(feature = "discord")]
+    Discord { url: String },
+    /// Slack incoming webhook
+    #[cfg(feature = "slack")]
+    Slack { url: String },
+    /// Telegram chat, through a bot
+    #[cfg(feature = "telegram")]
+    Telegram { token: String, chat_id: String },
+}
+
+impl Notifier {
+    async fn send(&self, client: &reqwest::Client, title: &str, body: &str) -> Result<()> {
+        let (url, payload) = match self {
+            Notifier::Webhook { url } => (url.clone(), json!({ "text": format!("*{}*\n{}", title, body) })),
+            #[cfg(feature = "discord")]
+            Notifier::Discord { url } => (
+                url.clone(),
+                json!({ "content": truncate(&format!("**{}**\n{}", title, body), 2000) }),
+            ),
+            #[cfg(feature = "slack")]
+            Notifier::Slack { url } => (
+                url.clone(),
+                json!({ "text": truncate(&format!("*{}*\n{}", title, body), 40_000) }),
+            ),
+            #[cfg(feature = "telegram")]
+            Notifier::Telegram { token, chat_id } => (
+                format!("https://api.telegram.org/bot{}/sendMessage", token),
+                json!({ "chat_id": chat_id, "text": truncate(&format!("{}\n{}", title, body), 4096) }),
+            ),
+        };
+        let response = client.post(&url).json(&payload).send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("{} returned {}", self.name(), response.status()));
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Notifier::Webhook { .. } => "Webhook",
+            #[cfg(feature = "discord")]
+            Notifier::Discord { .. } => "Discord",
+            #[cfg(feature = "slack")]
+            Notifier::Slack { .. } => "Slack",
+            #[cfg(feature = "telegram")]
+            Notifier::Telegram { .. } => "Telegram",
+        }
+    }
+}
+
+/// The set of configured notifiers; messages fan out to all of them
+#[derive(Debug, Clone, Default)]
+pub struct Notifiers {
+    client: reqwest::Client,
+    notifiers: Vec<Notifier>,
+}
+
+impl Notifiers {
+    /// Build notifiers from environment variables
+    pub fn from_env() -> Self {
+        let mut notifiers = Vec::new();
+
+        if let Ok(url) = env::var("NOTIFY_WEBHOOK_URL") {
+            notifiers.push(Notifier::Webhook { url });
+            info!("Webhook notifications enabled");
+        }
+
+        if let Ok(url) = env::var("DISCORD_WEBHOOK_URL") {
+            #[cfg(feature = "discord")]
+            {
+                notifiers.push(Notifier::Discord { url });
+                info!("Discord notifications enabled");
+            }
+            #[cfg(not(feature = "discord"))]
+            {
+                let _ = url;
+                log::warn!("DISCORD_WEBHOOK_URL is set but this build lacks the `discord` feature");
+            }
+        }
+
+        if let Ok(url) = env::var("SLACK_WEBHOOK_URL") {
+            #[cfg(feature = "slack")]
+            {
+                notifiers.push(Notifier::Slack { url });
+                info!("Slack notifications enabled");
+            }
+            #[cfg(not(feature = "slack"))]
+            {
+                let _ = url;
+                log::warn!("SLACK_WEBHOOK_URL is set but this build lacks the `slack` feature");
+            }
+        }
+
+        if let (Ok(token), Ok(chat_id)) = (env::var("TELEGRAM_BOT_TOKEN"), env::var("TELEGRAM_CHAT_ID")) {
+            #[cfg(feature = "telegram")]
+            {
+                notifiers.push(Notifier::Telegram { token, chat_id });
+                info!("Telegram notifications enabled");
+            }
+            #[cfg(not(feature = "telegram"))]
+            {
+                let _ = (token, chat_id);
+                log::warn!("TELEGRAM_BOT_TOKEN is set but this build lacks the `telegram` feature");
+            }
+        }
+
+        Self {
+            client: reqwest::Client::new(),
+            notifiers,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notifiers.is_empty()
+    }
+
+    /// Send a message to every notifier, with any URLs or credentials in it masked; failures
+    /// are logged, never propagated
+    pub async fn notify(&self, title: &str, body: &str) {
+        let body = redact::text(body);
+        for notifier in &self.notifiers {
+            if let Err(e) = notifier.send(&self.client, title, &body).await {
+                error!("Failed to send notification: {}", e);
+            }
+        }
+    }
+}
+
+// Cut a message down to a chat service's length limit (in characters)
+#[cfg(any(feature = "discord", feature = "slack", feature = "telegram"))]
+fn truncate(message: &str, max_chars: usize) -> String {
+    if message.chars().count() <= max_chars {
+        return message.to_string();
+    }
+    let mut truncated: String = message.chars().take(max_chars - 1).collect();
+    truncated.push('…');
+    truncated
+}
@@ -0,0 +1,154 @@
+use anyhow::Result;
+use chrono::NaiveDate;
+use futures::future::{self, BoxFuture};
+use log::info;
+use serde_json::json;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::monitor::BlockObservation;
+use crate::sink::ObservationSink;
+
+/// Columns of the CSV observation log, named so they load as the NDJSON fields of the same
+/// name: the fields of `record` but for its `table`
+const CSV_COLUMNS: &[(&str, &str)] = &[
+    ("chain", "Chain"),
+    ("block_number", "Block Number"),
+    ("block_timestamp_s", "Block Timestamp (s)"),
+    ("block_timestamp_ms", "Block Timestamp (ms)"),
+    ("block_hash", "Block Hash"),
+    ("parent_hash", "Parent Hash"),
+    ("gas_used", "Gas Used"),
+    ("tx_count", "Tx Count"),
+    ("receipt_time_ms", "Receipt Time (ms)"),
+    ("received_at", "Received At"),
+    ("delta_ms", "Delta (ms)"),
+    ("delta_direction", "Delta Direction"),
+    ("delta_magnitude_ms", "Delta Magnitude (ms)"),
+    ("leap_second_window", "Leap Second Window"),
+    ("response_time_ms", "Response Time (ms)"),
+    ("rpc_round_trip_ms", "RPC Round Trip (ms)"),
+    ("l1_origin_block", "L1 Origin Block"),
+    ("l1_origin_drift_ms", "L1 Origin Drift (ms)"),
+    ("node_syncing", "Node Syncing"),
+    ("vantage", "Vantage"),
+];
+
+/// Appends every block observation to a daily file as it arrives, as NDJSON or CSV.
+///
+/// Each NDJSON line carries `"table": "observations"`, so the files can be loaded with
+/// `query` or `convert` like any other NDJSON dataset. The CSV files hold the same fields,
+/// one row per block, and load into the same `observations` table with a run directory.
+pub struct ObservationLog {
+    dir: PathBuf,
+    csv: bool,
+    day: Option<NaiveDate>,
+    file: Option<File>,
+}
+
+impl ObservationLog {
+    /// Log to `observations_{date}.ndjson` in `dir`
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            csv: false,
+            day: None,
+            file: None,
+        }
+    }
+
+    /// Log to `observations_{date}.csv` in `dir`
+    pub fn csv(dir: PathBuf) -> Self {
+        Self {
+            csv: true,
+            ..Self::new(dir)
+        }
+    }
+
+    // File for the UTC day the observation was received on, rolling over at midnight
+    fn file_for(&mut self, day: NaiveDate) -> Result<&mut File> {
+        if self.day != Some(day) || self.file.is_none() {
+            let extension = if self.csv { "csv" } else { "ndjson" };
+            let path = self.dir.join(format!("observations_{}.{}", day.format("%Y-%m-%d"), extension));
+            let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+            // A file carried over from an earlier run already has its header
+            if self.csv && file.metadata()?.len() == 0 {
+                let headers: Vec<&str> = CSV_COLUMNS.iter().map(|(_, header)| *header).collect();
+                file.write_all(&csv_line(&headers)?)?;
+            }
+            info!("Writing block observations to {}", path.display());
+            self.day = Some(day);
+            self.file = Some(file);
+        }
+        Ok(self.file.as_mut().unwrap())
+    }
+
+    pub fn append(&mut self, observation: &BlockObservation) -> Result<()> {
+        // One write per line keeps the file current for anyone tailing it
+        let line = if self.csv {
+            let record = record(observation);
+            let values: Vec<String> = CSV_COLUMNS
+                .iter()
+                .map(|(column, _)| match &record[*column] {
+                    serde_json::Value::Null => String::new(),
+                    serde_json::Value::String(value) => value.clone(),
+                    value => value.to_string(),
+                })
+                .collect();
+            csv_line(&values)?
+        } else {
+            format!("{}\n", record(observation)).into_bytes()
+        };
+        self.file_for(observation.received_at.date_naive())?.write_all(&line)?;
+        Ok(())
+    }
+}
+
+impl ObservationSink for ObservationLog {
+    fn name(&self) -> &str {
+        if self.csv {
+            "the CSV observation log"
+        } else {
+            "the observation log"
+        }
+    }
+
+    fn write<'a>(&'a mut self, observation: &'a BlockObservation) -> BoxFuture<'a, Result<()>> {
+        Box::pin(future::ready(self.append(observation)))
+    }
+}
+
+// One CSV row, quoted where needed, with its line ending
+fn csv_line<T: AsRef<[u8]>>(fields: &[T]) -> Result<Vec<u8>> {
+    let mut wtr = csv::Writer::from_writer(Vec::new());
+    wtr.write_record(fields)?;
+    Ok(wtr.into_inner().map_err(|e| e.into_error())?)
+}
+
+/// The NDJSON line an observation is logged as
+pub(crate) fn record(observation: &BlockObservation) -> serde_json::Value {
+    json!({
+        "table": "observations",
+        "chain": observation.chain,
+        "block_number": observation.block_number,
+        "block_timestamp_s": observation.block_timestamp_ms / 1000,
+        "block_timestamp_ms": observation.block_timestamp_ms,
+        "block_hash": observation.block_hash,
+        "parent_hash": observation.parent_hash,
+        "gas_used": observation.gas_used,
+        "tx_count": observation.tx_count,
+        "receipt_time_ms": observation.received_at.timestamp_millis(),
+        "received_at": observation.received_at.to_rfc3339(),
+        "delta_ms": observation.delta_ms,
+        "delta_direction": observation.timestamp_delta().direction,
+        "delta_magnitude_ms": observation.timestamp_delta().magnitude_ms,
+        "leap_second_window": observation.leap_affected,
+        "response_time_ms": observation.responded_at.map(|responded| responded.timestamp_millis()),
+        "rpc_round_trip_ms": observation.round_trip_ms(),
+        "l1_origin_block": observation.l1_origin.map(|origin| origin.number),
+        "l1_origin_drift_ms": observation.l1_drift_ms(),
+        "node_syncing": observation.node_syncing,
+        "vantage": observation.vantage,
+    })
+}
@@ -0,0 +1,114 @@
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+use crate::clock::Stamp;
+
+/// A stretch in which the chain's head didn't move
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Outage {
+    pub started_at_ms: i64,       // When the head it stalled at was first seen
+    pub ended_at_ms: Option<i64>, // When a newer head was first seen; `None` while it lasts
+    pub duration_ms: i64,         // So far, while it lasts
+    pub last_block: u64,          // Head throughout the outage
+    pub next_block: Option<u64>,  // Head that ended it
+}
+
+impl Outage {
+    pub fn is_ongoing(&self) -> bool {
+        self.ended_at_ms.is_none()
+    }
+}
+
+/// Sequencer downtime: stretches in which a chain produced no new blocks for longer than
+/// its threshold, a number of expected block times (`OUTAGE_BLOCK_TIMES`).
+///
+/// Only a head the endpoint actually returned counts, so polls that fail during a provider
+/// outage neither open an outage nor prolong one; the chain may well have kept producing
+/// blocks the logger couldn't see.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Outages {
+    #[serde(skip)]
+    pub threshold_ms: Option<u64>, // `None` for chains without an expected block time; not checkpointed
+    pub count: usize,              // Outages opened, the ongoing one included
+    pub closed_ms: i64,            // Total duration of the outages that have ended
+    pub longest_ms: i64,           // Longest outage, the ongoing one included
+    pub recent: VecDeque<Outage>,  // Most recent outages, for the outages CSV; the ongoing one is last
+    #[serde(skip)]
+    head: Option<(u64, Stamp)>, // Latest head and when it was first seen
+}
+
+impl Outages {
+    pub fn new(threshold_ms: Option<u64>) -> Self {
+        Self {
+            threshold_ms,
+            ..Self::default()
+        }
+    }
+
+    /// The outage in progress, if any
+    pub fn ongoing(&self) -> Option<&Outage> {
+        self.recent.back().filter(|outage| outage.is_ongoing())
+    }
+
+    /// Time spent in outages, the ongoing one so far included
+    pub fn total_ms(&self) -> i64 {
+        self.closed_ms + self.ongoing().map_or(0, |outage| outage.duration_ms)
+    }
+
+    /// Note that the endpoint reported `head` at `at`, returning the outage it ends, if any
+    pub(crate) fn record_head(&mut self, head: u64, at: Stamp) -> Option<Outage> {
+        if self.head.is_some_and(|(known, _)| head <= known) {
+            return None;
+        }
+        let since = self.head.map(|(_, seen)| seen);
+        self.head = Some((head, at));
+        let outage = self.recent.back_mut().filter(|outage| outage.is_ongoing() && head > outage.last_block)?;
+        // An outage carried over from a checkpoint started before this run's clock readings
+        let since = since.unwrap_or_else(|| Stamp::wall(DateTime::from_timestamp_millis(outage.started_at_ms).unwrap_or_default()));
+        outage.ended_at_ms = Some(at.wall.timestamp_millis());
+        outage.duration_ms = at.ms_since(&since);
+        outage.next_block = Some(head);
+        self.closed_ms += outage.duration_ms;
+        self.longest_ms = self.longest_ms.max(outage.duration_ms);
+        Some(outage.clone())
+    }
+
+    /// Check at `now`, when the endpoint has just confirmed the head hasn't moved, whether the
+    /// chain has gone without a new block for longer than the threshold. Returns the outage
+    /// if this opens one; the ongoing one just grows. At most `max_kept` outages are kept.
+    pub(crate) fn check(&mut self, now: Stamp, max_kept: usize) -> Option<&Outage> {
+        let threshold_ms = self.threshold_ms?;
+        let (head, seen) = self.head?;
+        let stalled_ms = now.ms_since(&seen);
+        if let Some(outage) = self.recent.back_mut().filter(|outage| outage.is_ongoing()) {
+            outage.duration_ms = outage.duration_ms.max(stalled_ms);
+            self.longest_ms = self.longest_ms.max(outage.duration_ms);
+            return None;
+        }
+        if stalled_ms <= threshold_ms as i64 {
+            return None;
+        }
+        if self.recent.len() >= max_kept.max(1) {
+            self.recent.pop_front();
+        }
+        self.count += 1;
+        self.longest_ms = self.longest_ms.max(stalled_ms);
+        self.recent.push_back(Outage {
+            started_at_ms: seen.wall.timestamp_millis(),
+            ended_at_ms: None,
+            duration_ms: stalled_ms,
+            last_block: head,
+            next_block: None,
+        });
+        self.recent.back()
+    }
+
+    /// The outages' columns in `SUMMARY_HEADERS` order, empty when they aren't detected
+    pub(crate) fn record_columns(&self) -> Vec<String> {
+        if self.threshold_ms.is_none() {
+            return vec![String::new(); 3];
+        }
+        vec![self.count.to_string(), self.total_ms().to_string(), self.longest_ms.to_string()]
+    }
+}
@@ -0,0 +1,359 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use native_tls::TlsConnector;
+use postgres_native_tls::MakeTlsConnector;
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::time;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::Client;
+
+use crate::config::Config;
+use crate::monitor::BlockObservation;
+use crate::redact::origin;
+use crate::report::StatsSnapshot;
+use crate::store::{ColumnValue, OBSERVATION_COLUMNS};
+
+/// Observations buffered before they are inserted without waiting for the flush interval
+const MAX_BATCH_ROWS: usize = 500;
+
+/// Longest an observation waits in the buffer
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Advisory lock held while migrating, so instances starting together take turns
+const MIGRATION_LOCK_ID: i64 = 0x6274_6c5f_6d69_6772;
+
+/// The schema, one migration per entry. Applied migrations are recorded by their position
+/// in `schema_migrations`, so entries are only ever appended, never edited.
+const MIGRATIONS: &[&str] = &[
+    // 1: runs, the chains each one monitored, their observations and stats snapshots
+    "CREATE TABLE runs (
+        id BIGSERIAL PRIMARY KEY,
+        started_at TIMESTAMPTZ NOT NULL,
+        ended_at TIMESTAMPTZ,
+        version TEXT NOT NULL,
+        poll_interval_ms BIGINT NOT NULL
+    );
+    CREATE TABLE chains (
+        run_id BIGINT NOT NULL REFERENCES runs (id) ON DELETE CASCADE,
+        name TEXT NOT NULL,
+        rpc_origin TEXT,
+        expected_block_time_ms BIGINT,
+        PRIMARY KEY (run_id, name)
+    );
+    CREATE TABLE observations (
+        run_id BIGINT NOT NULL,
+        chain TEXT NOT NULL,
+        block_number BIGINT NOT NULL,
+        block_timestamp_ms BIGINT NOT NULL,
+        receipt_time_ms BIGINT NOT NULL,
+        received_at TIMESTAMPTZ NOT NULL,
+        delta_ms BIGINT NOT NULL,
+        leap_second_window BOOLEAN NOT NULL,
+        response_time_ms BIGINT,
+        rpc_round_trip_ms BIGINT,
+        l1_origin_block BIGINT,
+        l1_origin_drift_ms BIGINT,
+        FOREIGN KEY (run_id, chain) REFERENCES chains (run_id, name) ON DELETE CASCADE
+    );
+    CREATE INDEX observations_chain_received_at ON observations (chain, received_at);
+    CREATE TABLE stats_snapshots (
+        run_id BIGINT NOT NULL,
+        chain TEXT NOT NULL,
+        recorded_at TIMESTAMPTZ NOT NULL,
+        blocks BIGINT NOT NULL,
+        avg_delta_ms BIGINT,
+        p50_delta_ms BIGINT,
+        p90_delta_ms BIGINT,
+        p99_delta_ms BIGINT,
+        summary JSONB NOT NULL,
+        FOREIGN KEY (run_id, chain) REFERENCES chains (run_id, name) ON DELETE CASCADE
+    );
+    CREATE INDEX stats_snapshots_chain_recorded_at ON stats_snapshots (chain, recorded_at);",
+    // 2: where each run observed its chains from
+    "ALTER TABLE runs ADD COLUMN vantage TEXT, ADD COLUMN hostname TEXT, ADD COLUMN region TEXT;
+    CREATE INDEX runs_vantage ON runs (vantage);
+    ALTER TABLE observations ADD COLUMN vantage TEXT;",
+    // 3: what each observed block held
+    "ALTER TABLE observations ADD COLUMN block_hash TEXT, ADD COLUMN parent_hash TEXT,
+        ADD COLUMN gas_used BIGINT, ADD COLUMN tx_count BIGINT;",
+    // 4: whether the node was syncing when each block was recorded
+    "ALTER TABLE observations ADD COLUMN node_syncing BOOLEAN;",
+    // 5: the block timestamp in whole seconds, as the other databases store it
+    "ALTER TABLE observations ADD COLUMN block_timestamp_s BIGINT;",
+];
+
+fn postgres_type(value: ColumnValue) -> &'static str {
+    match value {
+        ColumnValue::Text(_) => "TEXT",
+        ColumnValue::Integer(_) => "BIGINT",
+        ColumnValue::Boolean(_) => "BOOLEAN",
+        ColumnValue::Timestamp(_) => "TIMESTAMPTZ",
+    }
+}
+
+// One column's values across the observations, as the array `UNNEST` takes
+fn postgres_array(value: ColumnValue, observations: &[BlockObservation]) -> Box<dyn ToSql + Send + Sync + '_> {
+    match value {
+        ColumnValue::Text(text) => Box::new(observations.iter().map(text).collect::<Vec<_>>()),
+        ColumnValue::Integer(integer) => Box::new(observations.iter().map(integer).collect::<Vec<_>>()),
+        ColumnValue::Boolean(boolean) => Box::new(observations.iter().map(boolean).collect::<Vec<_>>()),
+        ColumnValue::Timestamp(time) => Box::new(observations.iter().map(time).collect::<Vec<_>>()),
+    }
+}
+
+// The server a connection string points at, without its credentials
+fn server(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(url) => match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => "(local socket)".to_string(),
+        },
+        Err(_) => "(set)".to_string(),
+    }
+}
+
+async fn connect(url: &str) -> Result<Client> {
+    // `sslmode` in the URL decides whether TLS is used; the default prefers it when offered
+    let tls = MakeTlsConnector::new(TlsConnector::new()?);
+    let (client, connection) = tokio_postgres::connect(url, tls)
+        .await
+        .map_err(|e| anyhow!("Failed to connect to PostgreSQL at {}: {}", server(url), e))?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            error!("PostgreSQL connection closed: {}", e);
+        }
+    });
+    Ok(client)
+}
+
+// Bring the schema up to date, failing if it is newer than this build
+async fn migrate(client: &mut Client) -> Result<()> {
+    let tx = client.transaction().await?;
+    tx.execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATION_LOCK_ID]).await?;
+    // Later instances find the table there already; don't log a notice about it
+    tx.batch_execute("SET LOCAL client_min_messages = warning").await?;
+    tx.batch_execute(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY,
+            applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+        )",
+    )
+    .await?;
+    let applied: i32 = tx
+        .query_one("SELECT COALESCE(MAX(version), 0) FROM schema_migrations", &[])
+        .await?
+        .get(0);
+    if applied as usize > MIGRATIONS.len() {
+        return Err(anyhow!(
+            "The PostgreSQL schema is at version {}, newer than the {} this build knows; upgrade the logger",
+            applied,
+            MIGRATIONS.len()
+        ));
+    }
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(applied as usize) {
+        let version = version as i32 + 1;
+        tx.batch_execute(migration).await?;
+        tx.execute("INSERT INTO schema_migrations (version) VALUES ($1)", &[&version]).await?;
+        info!("Applied PostgreSQL schema migration {}", version);
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Stores every observation in a PostgreSQL database shared by any number of logger
+/// instances, along with a snapshot of each chain's stats with every report.
+///
+/// Each instance adds a row to `runs` when it starts, and its chains, observations and
+/// snapshots are keyed by that run's `id`. The schema is created and migrated on connect.
+pub struct PostgresSink {
+    url: String,
+    client: Client,
+    run_id: i64,
+    chains: HashSet<String>,
+    buffer: Vec<BlockObservation>,
+}
+
+impl PostgresSink {
+    /// Connect to `url`, migrate the schema and register a run of `config`'s chains that
+    /// started at `started_at`
+    pub async fn connect(url: &str, config: &Config, started_at: DateTime<Utc>) -> Result<Self> {
+        let mut client = connect(url).await?;
+        migrate(&mut client).await?;
+        let vantage = &config.options.vantage;
+        let run_id: i64 = client
+            .query_one(
+                "INSERT INTO runs (started_at, version, poll_interval_ms, vantage, hostname, region)
+                VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
+                &[
+                    &started_at,
+                    &env!("CARGO_PKG_VERSION"),
+                    &(config.poll_interval.as_millis() as i64),
+                    &vantage.label,
+                    &vantage.hostname,
+                    &vantage.region,
+                ],
+            )
+            .await?
+            .get(0);
+        let mut chains = HashSet::new();
+        for chain in &config.chains {
+            client
+                .execute(
+                    "INSERT INTO chains (run_id, name, rpc_origin, expected_block_time_ms) VALUES ($1, $2, $3, $4)",
+                    &[
+                        &run_id,
+                        &chain.name,
+                        &origin(&chain.rpc_url),
+                        &chain.expected_block_time_ms.map(|ms| ms as i64),
+                    ],
+                )
+                .await?;
+            chains.insert(chain.name.clone());
+        }
+        info!("Storing observations in PostgreSQL at {} as run {}", server(url), run_id);
+        Ok(Self {
+            url: url.to_string(),
+            client,
+            run_id,
+            chains,
+            buffer: Vec::new(),
+        })
+    }
+
+    // Reconnect if the connection was lost, and list chains a daemon added since
+    async fn prepare(&mut self, chains: impl Iterator<Item = &str>) -> Result<()> {
+        if self.client.is_closed() {
+            self.client = connect(&self.url).await?;
+            info!("Reconnected to PostgreSQL at {}", server(&self.url));
+        }
+        for chain in chains {
+            if !self.chains.contains(chain) {
+                self.client
+                    .execute(
+                        "INSERT INTO chains (run_id, name) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+                        &[&self.run_id, &chain],
+                    )
+                    .await?;
+                self.chains.insert(chain.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    // Insert the buffered observations in one statement; on failure they are dropped
+    // rather than held indefinitely
+    async fn flush(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let observations = std::mem::take(&mut self.buffer);
+        if let Err(e) = self.insert_observations(&observations).await {
+            error!("Failed to store {} observations in PostgreSQL: {}", observations.len(), e);
+        }
+    }
+
+    async fn insert_observations(&mut self, observations: &[BlockObservation]) -> Result<()> {
+        let names: HashSet<&str> = observations.iter().map(|observation| observation.chain.as_str()).collect();
+        self.prepare(names.into_iter()).await?;
+        let columns: Vec<&str> = OBSERVATION_COLUMNS.iter().map(|(column, _)| *column).collect();
+        let arrays: Vec<String> = OBSERVATION_COLUMNS
+            .iter()
+            .enumerate()
+            .map(|(i, (_, value))| format!("${}::{}[]", i + 2, postgres_type(*value)))
+            .collect();
+        let values: Vec<Box<dyn ToSql + Send + Sync + '_>> = OBSERVATION_COLUMNS
+            .iter()
+            .map(|(_, value)| postgres_array(*value, observations))
+            .collect();
+        let params: Vec<&(dyn ToSql + Sync)> = std::iter::once(&self.run_id as &(dyn ToSql + Sync))
+            .chain(values.iter().map(|value| value.as_ref() as &(dyn ToSql + Sync)))
+            .collect();
+        // One array parameter per column, unnested into a row per observation
+        let sql = format!(
+            "INSERT INTO observations (run_id, {}) SELECT $1, * FROM UNNEST({})",
+            columns.join(", "),
+            arrays.join(", ")
+        );
+        self.client.execute(&sql, &params).await?;
+        Ok(())
+    }
+
+    async fn insert_snapshot(&mut self, (recorded_at, summary): &StatsSnapshot) -> Result<()> {
+        let chain = summary["chain"].as_str().unwrap_or_default();
+        self.prepare(std::iter::once(chain)).await?;
+        let delta = |key: &str| summary["delta_ms"][key].as_i64();
+        self.client
+            .execute(
+                "INSERT INTO stats_snapshots (run_id, chain, recorded_at, blocks, avg_delta_ms, p50_delta_ms,
+                    p90_delta_ms, p99_delta_ms, summary)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+                &[
+                    &self.run_id,
+                    &chain,
+                    recorded_at,
+                    &summary["totals"]["blocks"].as_i64().unwrap_or_default(),
+                    &delta("avg"),
+                    &delta("p50"),
+                    &delta("p90"),
+                    &delta("p99"),
+                    summary,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Store every observation on its way to `next`, in batches of up to a second, and
+    /// every snapshot sent on `snapshots`. `next` is released once the observations end, and
+    /// the run is marked as ended once the snapshots do too.
+    pub async fn run(
+        mut self,
+        mut receiver: UnboundedReceiver<BlockObservation>,
+        mut next: Option<UnboundedSender<BlockObservation>>,
+        mut snapshots: UnboundedReceiver<StatsSnapshot>,
+    ) {
+        let mut flush_ticker = time::interval(FLUSH_INTERVAL);
+        let (mut observing, mut snapshotting) = (true, true);
+        while observing || snapshotting {
+            tokio::select! {
+                observation = receiver.recv(), if observing => match observation {
+                    Some(observation) => {
+                        if let Some(next) = &next {
+                            let _ = next.send(observation.clone());
+                        }
+                        self.buffer.push(observation);
+                        if self.buffer.len() >= MAX_BATCH_ROWS {
+                            self.flush().await;
+                        }
+                    }
+                    None => {
+                        observing = false;
+                        next = None;
+                        self.flush().await;
+                    }
+                },
+                snapshot = snapshots.recv(), if snapshotting => match snapshot {
+                    Some(snapshot) => {
+                        if let Err(e) = self.insert_snapshot(&snapshot).await {
+                            error!("Failed to store a stats snapshot in PostgreSQL: {}", e);
+                        }
+                    }
+                    None => snapshotting = false,
+                },
+                _ = flush_ticker.tick() => self.flush().await,
+            }
+        }
+        self.flush().await;
+        let ended = self
+            .client
+            .execute("UPDATE runs SET ended_at = now() WHERE id = $1", &[&self.run_id])
+            .await;
+        if let Err(e) = ended {
+            error!("Failed to mark PostgreSQL run {} as ended: {}", self.run_id, e);
+        }
+    }
+}
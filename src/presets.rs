@@ -0,0 +1,79 @@
+use anyhow::{anyhow, Result};
+
+use crate::http::HttpSettings;
+use crate::monitor::{BlockTag, ChainInfo, ChainKind};
+use crate::timestamp::TimestampDecoding;
+
+/// A well-known chain that can be monitored by name, without a chains file
+#[derive(Debug, Clone, Copy)]
+pub struct ChainPreset {
+    /// What the preset is called on the command line, e.g. `base-sepolia`
+    pub id: &'static str,
+    /// Name of the chain in reports and output files
+    pub name: &'static str,
+    pub chain_id: u64,
+    pub block_time_ms: u64,
+    /// Public, rate-limited RPC endpoint used unless another is given
+    pub rpc_url: &'static str,
+    pub kind: ChainKind,
+}
+
+/// Every built-in preset
+pub const PRESETS: &[ChainPreset] = &[
+    ChainPreset { id: "ethereum", name: "Ethereum", chain_id: 1, block_time_ms: 12_000, rpc_url: "https://ethereum-rpc.publicnode.com", kind: ChainKind::Standard },
+    ChainPreset { id: "sepolia", name: "Sepolia", chain_id: 11_155_111, block_time_ms: 12_000, rpc_url: "https://ethereum-sepolia-rpc.publicnode.com", kind: ChainKind::Standard },
+    ChainPreset { id: "optimism", name: "Optimism", chain_id: 10, block_time_ms: 2000, rpc_url: "https://mainnet.optimism.io", kind: ChainKind::Standard },
+    ChainPreset { id: "optimism-sepolia", name: "Optimism-Sepolia", chain_id: 11_155_420, block_time_ms: 2000, rpc_url: "https://sepolia.optimism.io", kind: ChainKind::Standard },
+    ChainPreset { id: "base", name: "Base", chain_id: 8453, block_time_ms: 2000, rpc_url: "https://mainnet.base.org", kind: ChainKind::Standard },
+    ChainPreset { id: "base-sepolia", name: "Base-Sepolia", chain_id: 84_532, block_time_ms: 2000, rpc_url: "https://sepolia.base.org", kind: ChainKind::Standard },
+    ChainPreset { id: "unichain", name: "Unichain", chain_id: 130, block_time_ms: 1000, rpc_url: "https://mainnet.unichain.org", kind: ChainKind::Standard },
+    ChainPreset { id: "unichain-sepolia", name: "Unichain-Sepolia", chain_id: 1301, block_time_ms: 1000, rpc_url: "https://sepolia.unichain.org", kind: ChainKind::Standard },
+    ChainPreset { id: "arbitrum-one", name: "Arbitrum-One", chain_id: 42_161, block_time_ms: 250, rpc_url: "https://arb1.arbitrum.io/rpc", kind: ChainKind::Arbitrum },
+    ChainPreset { id: "arbitrum-nova", name: "Arbitrum-Nova", chain_id: 42_170, block_time_ms: 250, rpc_url: "https://nova.arbitrum.io/rpc", kind: ChainKind::Arbitrum },
+    ChainPreset { id: "arbitrum-sepolia", name: "Arbitrum-Sepolia", chain_id: 421_614, block_time_ms: 250, rpc_url: "https://sepolia-rollup.arbitrum.io/rpc", kind: ChainKind::Arbitrum },
+    ChainPreset { id: "polygon", name: "Polygon", chain_id: 137, block_time_ms: 2000, rpc_url: "https://polygon-rpc.com", kind: ChainKind::Standard },
+    ChainPreset { id: "linea", name: "Linea", chain_id: 59_144, block_time_ms: 2000, rpc_url: "https://rpc.linea.build", kind: ChainKind::Standard },
+    ChainPreset { id: "scroll", name: "Scroll", chain_id: 534_352, block_time_ms: 3000, rpc_url: "https://rpc.scroll.io", kind: ChainKind::Standard },
+    ChainPreset { id: "zksync", name: "zkSync", chain_id: 324, block_time_ms: 1000, rpc_url: "https://mainnet.era.zksync.io", kind: ChainKind::Standard },
+];
+
+impl ChainPreset {
+    /// The preset called `id`, ignoring case
+    pub fn find(id: &str) -> Option<&'static ChainPreset> {
+        PRESETS.iter().find(|preset| preset.id.eq_ignore_ascii_case(id))
+    }
+
+    /// The chain to monitor for `spec`: a preset's id, optionally followed by `=URL` to use
+    /// that RPC endpoint instead of the public one
+    pub fn parse(spec: &str) -> Result<ChainInfo> {
+        let (id, rpc_url) = match spec.split_once('=') {
+            Some((id, rpc_url)) => (id.trim(), Some(rpc_url.trim())),
+            None => (spec.trim(), None),
+        };
+        let preset = Self::find(id).ok_or_else(|| {
+            let known: Vec<&str> = PRESETS.iter().map(|preset| preset.id).collect();
+            anyhow!("Unknown chain '{}'; the presets are {}", id, known.join(", "))
+        })?;
+        Ok(preset.chain_info(rpc_url.unwrap_or(preset.rpc_url)))
+    }
+
+    /// The preset as a chain reached at `rpc_url`
+    pub fn chain_info(&self, rpc_url: &str) -> ChainInfo {
+        ChainInfo {
+            name: self.name.to_string(),
+            rpc_url: rpc_url.to_string(),
+            ws_url: None,
+            compare_rpc_url: None,
+            flashblocks_url: None,
+            expected_block_time_ms: Some(self.block_time_ms),
+            schedule_tolerance_ms: None,
+            poll_interval_ms: None,
+            chain_id: Some(self.chain_id),
+            timestamp_decoding: TimestampDecoding::Seconds,
+            http: HttpSettings::from_env(),
+            kind: self.kind,
+            block_tag: BlockTag::Latest,
+            l1_rpc_url: None,
+        }
+    }
+}
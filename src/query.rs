@@ -0,0 +1,158 @@
+use anyhow::{anyhow, Result};
+use clap::{Args, ValueEnum};
+use log::info;
+use chrono::DateTime;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::ValueRef;
+use rusqlite::Connection;
+use std::io;
+use std::path::PathBuf;
+
+use crate::dataset::{self, DataFormat};
+use crate::timezone::ReportTimezone;
+
+/// How query results are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Table,
+    Csv,
+}
+
+/// Arguments for the `query` subcommand
+#[derive(Debug, Args)]
+pub struct QueryArgs {
+    /// SQL statement to execute
+    pub sql: String,
+
+    /// Run directory (or NDJSON, SQLite, Parquet or DuckDB file) containing the data to query
+    #[arg(long, env = "OUTPUT_DIR", default_value = "./logs")]
+    pub output_dir: PathBuf,
+
+    /// Format of the data; detected from the path when omitted
+    #[arg(long, value_enum)]
+    pub input_format: Option<DataFormat>,
+
+    /// Output format for the result rows
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    pub format: OutputFormat,
+
+    /// Timezone for the `local_time` and `local_hour` SQL functions (IANA name)
+    #[arg(long, env = "REPORT_TIMEZONE", value_parser = ReportTimezone::parse)]
+    pub timezone: Option<ReportTimezone>,
+}
+
+/// Load a run's outputs into SQLite and execute `args.sql`.
+///
+/// Every `{Chain}_{kind}.csv` file becomes rows in a table named `kind` (e.g. `stats`,
+/// `detailed`, `deltas`) with a `chain` column taken from the file name. Column names are
+/// the CSV headers lowercased with punctuation replaced by underscores, so `Delta (ms)`
+/// becomes `delta_ms`.
+///
+/// `local_time(ms)` and `local_hour(ms)` render epoch milliseconds (such as
+/// `receipt_time_ms`) in the report timezone, the latter truncated to the hour for
+/// per-hour aggregation.
+pub fn run(args: &QueryArgs) -> Result<()> {
+    let format = match args.input_format {
+        Some(format) => format,
+        None => DataFormat::detect(&args.output_dir)?,
+    };
+    let conn = dataset::open(&args.output_dir, format)?;
+    register_time_functions(&conn, args.timezone.unwrap_or_default())?;
+    let tables = dataset::table_names(&conn)?;
+    if tables.is_empty() {
+        return Err(anyhow!("No data found in {}", args.output_dir.display()));
+    }
+    info!("Loaded tables: {}", tables.join(", "));
+
+    let mut stmt = conn.prepare(&args.sql)?;
+    let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+
+    let mut rows = Vec::new();
+    let mut result = stmt.query([])?;
+    while let Some(row) = result.next()? {
+        let mut values = Vec::with_capacity(columns.len());
+        for i in 0..columns.len() {
+            values.push(format_value(row.get_ref(i)?));
+        }
+        rows.push(values);
+    }
+
+    match args.format {
+        OutputFormat::Table => print_table(&columns, &rows),
+        OutputFormat::Csv => {
+            let mut wtr = csv::Writer::from_writer(io::stdout());
+            wtr.write_record(&columns)?;
+            for row in &rows {
+                wtr.write_record(row)?;
+            }
+            wtr.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+// Renders epoch milliseconds as text in the report timezone
+type TimeRenderer = fn(&ReportTimezone, i64) -> Option<String>;
+
+fn register_time_functions(conn: &Connection, timezone: ReportTimezone) -> Result<()> {
+    let functions: [(&str, TimeRenderer); 2] = [
+        ("local_time", |tz, ms| {
+            DateTime::from_timestamp_millis(ms).map(|t| tz.format(t, "%Y-%m-%d %H:%M:%S%.3f %Z"))
+        }),
+        ("local_hour", |tz, ms| {
+            DateTime::from_timestamp_millis(ms).map(|t| tz.hour_bucket(t))
+        }),
+    ];
+
+    for (name, render) in functions {
+        conn.create_scalar_function(
+            name,
+            1,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            move |ctx| {
+                let ms: Option<i64> = ctx.get(0)?;
+                Ok(ms.and_then(|ms| render(&timezone, ms)))
+            },
+        )?;
+    }
+    Ok(())
+}
+
+fn format_value(value: ValueRef<'_>) -> String {
+    match value {
+        ValueRef::Null => "NULL".to_string(),
+        ValueRef::Integer(i) => i.to_string(),
+        ValueRef::Real(f) => f.to_string(),
+        ValueRef::Text(t) => String::from_utf8_lossy(t).into_owned(),
+        ValueRef::Blob(b) => format!("<{} bytes>", b.len()),
+    }
+}
+
+fn print_table(columns: &[String], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = columns.iter().map(|c| c.len()).collect();
+    for row in rows {
+        for (i, value) in row.iter().enumerate() {
+            widths[i] = widths[i].max(value.len());
+        }
+    }
+
+    let format_row = |values: &[String]| {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| format!("{:<width$}", v, width = widths[i]))
+            .collect::<Vec<_>>()
+            .join(" | ")
+    };
+
+    println!("{}", format_row(columns));
+    println!(
+        "{}",
+        widths.iter().map(|w| "-".repeat(*w)).collect::<Vec<_>>().join("-+-")
+    );
+    for row in rows {
+        println!("{}", format_row(row));
+    }
+    println!("({} rows)", rows.len());
+}
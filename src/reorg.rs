@@ -0,0 +1,62 @@
+use std::collections::VecDeque;
+
+/// Blocks whose hashes are kept per chain, which bounds the reorg depth that can be measured
+const HASH_HISTORY: usize = 128;
+
+/// Hashes of the most recently recorded blocks, for spotting parent-hash mismatches
+#[derive(Debug, Default)]
+pub struct HashHistory {
+    hashes: VecDeque<(u64, String)>,
+}
+
+impl HashHistory {
+    /// Hash recorded for `number`, if it is still in the history
+    pub fn get(&self, number: u64) -> Option<&str> {
+        self.hashes
+            .iter()
+            .rev()
+            .find(|(n, _)| *n == number)
+            .map(|(_, hash)| hash.as_str())
+    }
+
+    /// Remember the hash of `number`, forgetting anything recorded at or above that height
+    pub fn record(&mut self, number: u64, hash: String) {
+        while self.hashes.back().is_some_and(|(n, _)| *n >= number) {
+            self.hashes.pop_back();
+        }
+        self.hashes.push_back((number, hash));
+        if self.hashes.len() > HASH_HISTORY {
+            self.hashes.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recording_below_the_top_forgets_the_replaced_blocks() {
+        let mut history = HashHistory::default();
+        for number in 1..=5 {
+            history.record(number, format!("a{}", number));
+        }
+        history.record(4, "b4".to_string());
+        assert_eq!(history.get(3), Some("a3"));
+        assert_eq!(history.get(4), Some("b4"));
+        assert_eq!(history.get(5), None);
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let mut history = HashHistory::default();
+        let last = HASH_HISTORY as u64 + 10;
+        for number in 1..=last {
+            history.record(number, number.to_string());
+        }
+        assert_eq!(history.hashes.len(), HASH_HISTORY);
+        assert_eq!(history.get(10), None);
+        assert_eq!(history.get(11), Some("11"));
+        assert_eq!(history.get(last), Some(last.to_string().as_str()));
+    }
+}
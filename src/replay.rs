@@ -0,0 +1,338 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, TimeDelta, Utc};
+use clap::Args;
+use log::{info, warn};
+use rusqlite::types::Value;
+use rusqlite::Connection;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::adapter::L1Origin;
+use crate::anomaly::{AnomalySettings, DeltaAnomalies};
+use crate::archive::CONFIG_SNAPSHOT_FILE;
+#[cfg(feature = "charts")]
+use crate::charts;
+use crate::clock::Stamp;
+use crate::columnar::OutputFormat;
+use crate::combined;
+use crate::dataset::{self, DataFormat, TableSet};
+use crate::histogram::HistogramBuckets;
+use crate::html;
+use crate::monitor::BlockObservation;
+use crate::report;
+use crate::schedule::ScheduleAdherence;
+use crate::skew::{CrossChainSkew, SKEW_FILE};
+use crate::stats::ChainStats;
+use crate::timezone::ReportTimezone;
+
+/// Arguments for the `report` subcommand
+#[derive(Debug, Args)]
+pub struct ReportArgs {
+    /// Stored observations: a run directory, NDJSON file, SQLite database or DuckDB database
+    #[arg(env = "OUTPUT_DIR", default_value = "./logs")]
+    pub input: PathBuf,
+
+    /// Format of the input; detected from the path when omitted
+    #[arg(long, value_enum)]
+    pub from: Option<DataFormat>,
+
+    /// Directory the regenerated stats files, skew and summary are written to
+    #[arg(long, default_value = "./report")]
+    pub out: PathBuf,
+
+    /// File format of the regenerated per-block and delta datasets
+    #[arg(long, value_enum, default_value_t = OutputFormat::Csv)]
+    pub format: OutputFormat,
+
+    /// Also render each chain's delta over time and delta histogram as PNGs; needs a build
+    /// with the `charts` feature
+    #[arg(long)]
+    pub charts: bool,
+
+    /// Timezone the charts of `report.html` and the PNGs label receipt times in (IANA name)
+    /// [default: UTC]
+    #[arg(long, env = "REPORT_TIMEZONE", value_parser = ReportTimezone::parse)]
+    pub timezone: Option<ReportTimezone>,
+}
+
+/// Settings from a run's config snapshot that shape the stats replayed from it
+#[derive(Debug, Default)]
+pub(crate) struct ReplaySettings {
+    expected_block_time_ms: HashMap<String, u64>,
+    schedule_tolerance_ms: HashMap<String, u64>,
+    default_tolerance_ms: Option<u64>,
+}
+
+impl ReplaySettings {
+    /// Read the config snapshot kept with `input` (in the run directory, or next to a
+    /// file), so chains are scored against the block times they were run with
+    pub(crate) fn for_input(input: &Path) -> Self {
+        let dir = if input.is_dir() { Some(input) } else { input.parent() };
+        let Some(path) = dir.map(|dir| dir.join(CONFIG_SNAPSHOT_FILE)).filter(|path| path.exists()) else {
+            return Self::default();
+        };
+        let snapshot: serde_json::Value = match std::fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|text| Ok(serde_json::from_str(&text)?))
+        {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                warn!("Ignoring unreadable {}: {}", path.display(), e);
+                return Self::default();
+            }
+        };
+
+        let mut settings = Self {
+            default_tolerance_ms: snapshot["settings"]["SCHEDULE_TOLERANCE_MS"]
+                .as_str()
+                .and_then(|ms| ms.parse().ok()),
+            ..Self::default()
+        };
+        for chain in snapshot["chains"].as_array().into_iter().flatten() {
+            let Some(name) = chain["name"].as_str() else {
+                continue;
+            };
+            if let Some(ms) = chain["expected_block_time_ms"].as_u64() {
+                settings.expected_block_time_ms.insert(name.to_string(), ms);
+            }
+            if let Some(ms) = chain["schedule_tolerance_ms"].as_u64() {
+                settings.schedule_tolerance_ms.insert(name.to_string(), ms);
+            }
+        }
+        settings
+    }
+
+    fn new_stats(&self, chain: &str, buckets: &HistogramBuckets) -> ChainStats {
+        let mut stats = ChainStats::new(chain);
+        stats.histogram_buckets = buckets.clone();
+        stats.expected_block_time_ms = self.expected_block_time_ms.get(chain).copied();
+        let tolerance_ms = self
+            .schedule_tolerance_ms
+            .get(chain)
+            .copied()
+            .or(self.default_tolerance_ms)
+            .unwrap_or_else(|| crate::get_env_as_u64("SCHEDULE_TOLERANCE_MS", 250));
+        stats.schedule = ScheduleAdherence::new(tolerance_ms);
+        stats.anomalies = DeltaAnomalies::new(AnomalySettings::from_env());
+        stats
+    }
+}
+
+// A run directory's observation logs, or its CSV outputs when it has none
+fn open_run_dir(dir: &Path) -> Result<Connection> {
+    let mut logs: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.extension().is_some_and(|ext| ext == "ndjson")
+                && path.file_name().is_some_and(|name| name.to_string_lossy().starts_with("observations_"))
+        })
+        .collect();
+    if logs.is_empty() {
+        return dataset::open(dir, DataFormat::Csv);
+    }
+    logs.sort();
+
+    let conn = Connection::open_in_memory()?;
+    let mut tables = TableSet::default();
+    for path in logs {
+        dataset::load_ndjson(&conn, &path, &mut tables)?;
+    }
+    Ok(conn)
+}
+
+fn column_names(conn: &Connection, table: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!("SELECT name FROM pragma_table_info('{}')", table))?;
+    let names = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    Ok(names)
+}
+
+// The leap flag is an integer in NDJSON and SQLite but "true"/"false" in the detailed CSVs
+fn is_true(value: Value) -> bool {
+    match value {
+        Value::Integer(i) => i != 0,
+        Value::Text(text) => text.eq_ignore_ascii_case("true"),
+        _ => false,
+    }
+}
+
+/// Every block observation stored in `input`, in the order they were received.
+///
+/// A run directory is read from its daily `observations_*.ndjson` logs, falling back to
+/// the `{Chain}_detailed.csv` files (which only hold each chain's most recent blocks);
+/// NDJSON files and SQLite and DuckDB databases are read from their `observations` table.
+pub(crate) fn load_observations(input: &Path, format: Option<DataFormat>) -> Result<Vec<BlockObservation>> {
+    let format = match format {
+        Some(format) => format,
+        None => DataFormat::detect(input)?,
+    };
+    let conn = match format {
+        DataFormat::Csv => open_run_dir(input)?,
+        DataFormat::Ndjson | DataFormat::Sqlite | DataFormat::Parquet | DataFormat::Duckdb => dataset::open(input, format)?,
+    };
+
+    let tables = dataset::table_names(&conn)?;
+    let table = ["observations", "detailed"]
+        .into_iter()
+        .find(|table| tables.iter().any(|name| name == table))
+        .ok_or_else(|| anyhow!("No block observations found in {}", input.display()))?;
+    if table == "detailed" {
+        warn!(
+            "{} has no observation log; using the detailed CSVs, which only hold each chain's most recent blocks unless they were rotated",
+            input.display()
+        );
+    }
+
+    let columns = column_names(&conn, table)?;
+    let has = |column: &str| columns.iter().any(|name| name == column);
+    // Older datasets only have whole-second timestamps
+    let timestamp = if has("block_timestamp_ms") {
+        "COALESCE(block_timestamp_ms, block_timestamp_s * 1000)"
+    } else {
+        "block_timestamp_s * 1000"
+    };
+    let leap = if has("leap_second_window") { "leap_second_window" } else { "0" };
+    // Response times were added later, and pushed heads have none (an empty CSV cell)
+    let response = if has("response_time_ms") { "NULLIF(response_time_ms, '')" } else { "NULL" };
+    // So were L1 origins, which only OP-stack chains tracking them have
+    let l1_origin = if has("l1_origin_block") {
+        "NULLIF(l1_origin_block, ''), NULLIF(l1_origin_drift_ms, '')"
+    } else {
+        "NULL, NULL"
+    };
+    // And vantage points, which runs from before then are named after their dataset by
+    // whatever compares them
+    let vantage = if has("vantage") { "NULLIF(vantage, '')" } else { "NULL" };
+    // As were block hashes, gas and transaction counts
+    let contents = if has("block_hash") {
+        "NULLIF(block_hash, ''), NULLIF(parent_hash, ''), NULLIF(gas_used, ''), NULLIF(tx_count, '')"
+    } else {
+        "NULL, NULL, NULL, NULL"
+    };
+    // And node health, only recorded where the node was asked
+    let syncing = if has("node_syncing") { "NULLIF(node_syncing, '')" } else { "NULL" };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT chain, block_number, {}, receipt_time_ms, {}, {}, {}, {}, {}, {} FROM \"{}\" ORDER BY receipt_time_ms, chain, block_number",
+        timestamp, leap, response, l1_origin, vantage, contents, syncing, table
+    ))?;
+
+    let mut observations = Vec::new();
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let block_timestamp_ms: i64 = row.get(2)?;
+        let receipt_time_ms: i64 = row.get(3)?;
+        let received_at = DateTime::from_timestamp_millis(receipt_time_ms)
+            .ok_or_else(|| anyhow!("Invalid receipt time {} in {}", receipt_time_ms, input.display()))?;
+        let responded_at = row.get::<_, Option<i64>>(5)?.and_then(DateTime::from_timestamp_millis);
+        let l1_origin = match (row.get::<_, Option<i64>>(6)?, row.get::<_, Option<i64>>(7)?) {
+            (Some(number), Some(drift_ms)) => Some(L1Origin {
+                number: number as u64,
+                timestamp_ms: (block_timestamp_ms - drift_ms) as u64,
+            }),
+            _ => None,
+        };
+        observations.push(BlockObservation {
+            chain: row.get(0)?,
+            block_number: row.get::<_, i64>(1)? as u64,
+            block_timestamp_ms: block_timestamp_ms as u64,
+            block_hash: row.get(9)?,
+            parent_hash: row.get(10)?,
+            gas_used: row.get::<_, Option<i64>>(11)?.map(|gas| gas as u64),
+            tx_count: row.get::<_, Option<i64>>(12)?.map(|count| count as u64),
+            received_at,
+            responded_at,
+            delta_ms: receipt_time_ms - block_timestamp_ms,
+            leap_affected: is_true(row.get(4)?),
+            l1_origin,
+            node_syncing: match row.get::<_, Value>(13)? {
+                Value::Null => None,
+                value => Some(is_true(value)),
+            },
+            anomaly_z: None,
+            vantage: row.get(8)?,
+        });
+    }
+    Ok(observations)
+}
+
+/// Rebuild each chain's stats by recording `observations` in order.
+///
+/// Only what the observations themselves carry is recovered: RPC errors, reorgs, gaps,
+/// backlog and head lag were never stored per block and stay at zero. With `skew`, the
+/// cross-chain skew is sampled as each wall-clock minute boundary is passed.
+pub(crate) fn replay(
+    observations: &[BlockObservation],
+    settings: &ReplaySettings,
+    mut skew: Option<&mut CrossChainSkew>,
+) -> Result<HashMap<String, ChainStats>> {
+    let buckets = HistogramBuckets::from_env()?;
+    let mut chain_stats: HashMap<String, ChainStats> = HashMap::new();
+    let mut next_sample: Option<DateTime<Utc>> = None;
+    for observation in observations {
+        if let Some(skew) = skew.as_deref_mut() {
+            let due = *next_sample.get_or_insert_with(|| CrossChainSkew::first_sample_at(observation.received_at));
+            if observation.received_at >= due {
+                skew.sample(&chain_stats, due)?;
+                // Idle stretches (such as between merged runs) get a single sample
+                next_sample = Some(CrossChainSkew::first_sample_at(observation.received_at).max(due + TimeDelta::minutes(1)));
+            }
+        }
+        let stats = chain_stats
+            .entry(observation.chain.clone())
+            .or_insert_with(|| settings.new_stats(&observation.chain, &buckets));
+        stats.node_syncing = observation.node_syncing;
+        stats.update(
+            observation.block_number,
+            &observation.header(),
+            Stamp::wall(observation.received_at),
+            observation.round_trip_ms(),
+            observation.l1_origin,
+            observation.leap_affected,
+        );
+    }
+    Ok(chain_stats)
+}
+
+/// Regenerate a run's stats files, skew and JSON summary from its stored observations,
+/// writing them to `args.out` and printing the final report
+pub fn run(args: &ReportArgs) -> Result<()> {
+    #[cfg(not(feature = "charts"))]
+    if args.charts {
+        return Err(anyhow!("Rendering charts needs a build with the `charts` feature"));
+    }
+    let observations = load_observations(&args.input, args.from)?;
+    let (Some(first), Some(last)) = (observations.first(), observations.last()) else {
+        return Err(anyhow!("No block observations found in {}", args.input.display()));
+    };
+    info!("Replaying {} observations from {}", observations.len(), args.input.display());
+
+    std::fs::create_dir_all(&args.out)?;
+    // Skew rows are appended, so start from an empty file
+    let skew_path = args.out.join(SKEW_FILE);
+    if skew_path.exists() {
+        std::fs::remove_file(&skew_path)?;
+    }
+    let mut skew = CrossChainSkew::new(&args.out);
+    let chain_stats = replay(&observations, &ReplaySettings::for_input(&args.input), Some(&mut skew))?;
+
+    for stats in chain_stats.values() {
+        stats.write_to_csv(&args.out, args.format)?;
+        #[cfg(feature = "charts")]
+        if args.charts {
+            charts::write_charts(&args.out, stats, args.timezone.unwrap_or_default())?;
+        }
+    }
+    let summary = report::replayed_summary(&chain_stats, &skew, &args.input, first.received_at, last.received_at);
+    report::write_summary(&args.out, &summary)?;
+    combined::write(&args.out, &summary, &chain_stats)?;
+
+    let mut lines = report::final_report(&chain_stats);
+    lines.extend(skew.final_report());
+    html::write_html_report(&args.out, &summary, &chain_stats, &lines, args.timezone.unwrap_or_default())?;
+    for line in lines {
+        println!("{}", line);
+    }
+    info!("Wrote the regenerated report to {}", args.out.display());
+    Ok(())
+}
@@ -0,0 +1,680 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::histogram::BucketCount;
+use crate::redact::origin;
+use crate::skew::CrossChainSkew;
+use crate::stats::{ChainStats, GapSummary, HeadLag};
+
+/// Machine-readable summary of the run, rewritten with every report and at shutdown
+pub const SUMMARY_REPORT_FILE: &str = "summary.json";
+
+/// When a chain's section of the summary was taken, and the section
+pub(crate) type StatsSnapshot = (DateTime<Utc>, serde_json::Value);
+
+/// Characters in the longest bar of the final report's delta histogram
+const CHART_WIDTH: usize = 40;
+
+/// What a run was started with, kept for the run metadata in the JSON summary
+#[derive(Debug, Clone)]
+pub struct RunInfo {
+    started_at: DateTime<Utc>,
+    poll_interval: Duration,
+    adaptive_polling: bool,
+    duration_limit: Option<Duration>,
+    vantage: serde_json::Value,
+    chains: Vec<serde_json::Value>,
+}
+
+impl RunInfo {
+    pub fn new(config: &Config, started_at: DateTime<Utc>) -> Self {
+        let mut run_info = Self {
+            started_at,
+            poll_interval: config.poll_interval,
+            adaptive_polling: config.adaptive_polling.is_some(),
+            duration_limit: config.duration,
+            vantage: config.options.vantage.summary(),
+            chains: Vec::new(),
+        };
+        run_info.set_chains(config);
+        run_info
+    }
+
+    /// Describe the chains and poll interval of `config`, after a daemon reloaded it
+    pub fn set_chains(&mut self, config: &Config) {
+        self.poll_interval = config.poll_interval;
+        self.adaptive_polling = config.adaptive_polling.is_some();
+        self.chains = config
+            .chains
+            .iter()
+            .map(|chain| {
+                serde_json::json!({
+                    "name": chain.name,
+                    "rpc_origin": origin(&chain.rpc_url),
+                    "ws_origin": chain.ws_url.as_deref().map(origin),
+                    "poll_interval_ms": chain.poll_interval_ms.unwrap_or(config.poll_interval.as_millis() as u64),
+                    "chain_id": chain.chain_id,
+                    "expected_block_time_ms": chain.expected_block_time_ms,
+                })
+            })
+            .collect();
+    }
+
+    /// The summary of every chain's stats, and the skew between them, as of `now`;
+    /// `complete` marks the final one
+    pub fn summary(
+        &self,
+        chain_stats: &HashMap<String, ChainStats>,
+        skew: &CrossChainSkew,
+        now: DateTime<Utc>,
+        complete: bool,
+    ) -> serde_json::Value {
+        serde_json::json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "status": if complete { "complete" } else { "running" },
+            "started_at": self.started_at.to_rfc3339(),
+            "updated_at": now.to_rfc3339(),
+            "elapsed_secs": (now - self.started_at).num_seconds(),
+            "duration_limit_secs": self.duration_limit.map(|limit| limit.as_secs()),
+            "poll_interval_ms": self.poll_interval.as_millis() as u64,
+            "adaptive_polling": self.adaptive_polling,
+            "vantage": self.vantage,
+            "rpc_endpoints": self.chains,
+            "chains": sorted(chain_stats).into_iter().map(chain_summary).collect::<Vec<_>>(),
+            "cross_chain_skew": skew.summary(),
+        })
+    }
+}
+
+/// The summary of stats regenerated from the observations stored in `source`, which were
+/// received between `first` and `last`
+pub(crate) fn replayed_summary(
+    chain_stats: &HashMap<String, ChainStats>,
+    skew: &CrossChainSkew,
+    source: &Path,
+    first: DateTime<Utc>,
+    last: DateTime<Utc>,
+) -> serde_json::Value {
+    serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "status": "replayed",
+        "source": source.display().to_string(),
+        "started_at": first.to_rfc3339(),
+        "updated_at": last.to_rfc3339(),
+        "elapsed_secs": (last - first).num_seconds(),
+        "chains": sorted(chain_stats).into_iter().map(chain_summary).collect::<Vec<_>>(),
+        "cross_chain_skew": skew.summary(),
+    })
+}
+
+/// Write `summary` to `summary.json` in `dir`
+pub fn write_summary(dir: &Path, summary: &serde_json::Value) -> Result<()> {
+    std::fs::write(dir.join(SUMMARY_REPORT_FILE), serde_json::to_string_pretty(summary)? + "\n")?;
+    Ok(())
+}
+
+// Chains in name order, so reports list them the same way every time
+pub(crate) fn sorted(chain_stats: &HashMap<String, ChainStats>) -> Vec<&ChainStats> {
+    let mut stats: Vec<&ChainStats> = chain_stats.values().collect();
+    stats.sort_by(|a, b| a.chain_name.cmp(&b.chain_name));
+    stats
+}
+
+/// One chain's section of the summary
+pub(crate) fn chain_summary(stats: &ChainStats) -> serde_json::Value {
+    let [p50, p90, p99, p999] = stats.delta_percentiles();
+    serde_json::json!({
+        "chain": stats.chain_name,
+        "degraded": stats.degraded,
+        "paused": stats.paused,
+        "totals": {
+            "blocks": stats.total_blocks,
+            "past_timestamp_blocks": stats.timestamp_past_blocks,
+            "future_timestamp_blocks": stats.timestamp_future_blocks,
+            "exact_timestamp_blocks": stats.timestamp_exact_blocks,
+            "leap_tagged_blocks": stats.leap_tagged_blocks,
+            "same_timestamp_blocks": stats.same_timestamp_blocks,
+            "non_monotonic_blocks": stats.non_monotonic_blocks,
+            "rpc_errors": stats.rpc_errors,
+            "rpc_retries": stats.rpc_retries,
+            "stale_responses": stats.stale_responses,
+            "reorgs": stats.reorgs,
+            "reorged_blocks": stats.reorged_blocks,
+            "gaps": stats.gaps,
+            "missed_blocks": stats.missed_blocks,
+            "breaker_trips": stats.breaker_trips,
+            "monitor_restarts": stats.monitor_restarts,
+        },
+        "delta_ms": {
+            "avg": stats.avg_time_delta_ms,
+            "stddev": stats.delta_stddev_ms(),
+            "p50": p50,
+            "p90": p90,
+            "p99": p99,
+            "p99_9": p999,
+        },
+        "maxima": {
+            "past_delta_ms": stats.max_past_delta_ms,
+            "future_delta_ms": stats.max_future_delta_ms,
+            "backlog_depth": stats.max_backlog_depth,
+            "reorg_depth": stats.max_reorg_depth,
+            "timestamp_regression_ms": stats.max_timestamp_regression_ms,
+        },
+        "block_gap_ms": stats.block_gap_summary().map(gap_summary),
+        "arrival_gap_ms": stats.arrival_gap_summary().map(gap_summary),
+        "rpc_latency_ms": {
+            "head": stats.head_rpc_latency_summary().map(gap_summary),
+            "block": stats.block_rpc_latency_summary().map(gap_summary),
+        },
+        "delta_breakdown_ms": stats.delta_breakdown().map(|breakdown| serde_json::json!({
+            "timestamp_offset": breakdown.timestamp_offset_ms,
+            "fetch_round_trip": breakdown.round_trip_ms,
+            "response_delta": breakdown.response_delta_ms,
+        })),
+        "safe_lag": head_lag(&stats.safe_lag),
+        "finalized_lag": head_lag(&stats.finalized_lag),
+        "l1_origin_drift_ms": stats.l1_origin_drift_summary().map(gap_summary),
+        "timestamp_granularity_ms": stats.timestamp_granularity_ms(),
+        "avg_block_interval_ms": stats.avg_block_interval_ms(),
+        "expected_block_time_ms": stats.expected_block_time_ms,
+        "clock_offset_ms": stats.clock_offset_ms,
+        "max_clock_offset_ms": stats.clock_offset_ms.map(|_| stats.max_clock_offset_ms),
+        "provider_clock_skew_ms": stats.provider_clock_skew_summary().map(gap_summary),
+        "node_health": stats.node_syncing.map(|syncing| serde_json::json!({
+            "syncing": syncing,
+            "syncing_blocks": stats.syncing_blocks,
+            "max_sync_lag_blocks": stats.max_sync_lag_blocks,
+            "peer_count": stats.peer_count,
+            "min_peer_count": stats.min_peer_count,
+        })),
+        "schedule": stats.schedule.on_schedule_pct().map(|pct| serde_json::json!({
+            "tolerance_ms": stats.schedule.tolerance_ms,
+            "on_schedule": stats.schedule.on_schedule,
+            "early": stats.schedule.early,
+            "late": stats.schedule.late,
+            "on_schedule_pct": pct,
+            "avg_deviation_ms": stats.schedule.avg_deviation_ms(),
+            "longest_stall_ms": stats.schedule.longest_stall_ms,
+            "longest_stall_block": stats.schedule.longest_stall_block,
+            "fastest_burst_blocks": stats.schedule.fastest_burst,
+        })),
+        "anomalies": stats.anomalies.settings.map(|settings| serde_json::json!({
+            "z_threshold": settings.z_threshold,
+            "count": stats.anomalies.anomalies,
+            "above_baseline": stats.anomalies.above_baseline,
+            "below_baseline": stats.anomalies.below_baseline,
+            "max_z_score": stats.anomalies.max_z_score,
+            "last_block": stats.anomalies.last_anomaly_block,
+            "baseline_mean_ms": stats.anomalies.baseline_ms().map(|(mean_ms, _)| mean_ms),
+            "baseline_stddev_ms": stats.anomalies.baseline_ms().map(|(_, stddev_ms)| stddev_ms),
+        })),
+        "outages": stats.outages.threshold_ms.map(|threshold_ms| serde_json::json!({
+            "threshold_ms": threshold_ms,
+            "count": stats.outages.count,
+            "total_ms": stats.outages.total_ms(),
+            "longest_ms": stats.outages.longest_ms,
+            "ongoing": stats.outages.ongoing(),
+            "recent": stats.outages.recent,
+        })),
+        "provider_comparison": (stats.provider_divergence.checks > 0).then(|| serde_json::json!({
+            "checks": stats.provider_divergence.checks,
+            "disagreements": stats.provider_divergence.disagreements,
+            "max_head_diff": stats.provider_divergence.max_head_diff,
+            "max_timestamp_diff_ms": stats.provider_divergence.max_timestamp_diff_ms,
+        })),
+        "preconf": stats.preconf.is_tracked().then(|| serde_json::json!({
+            "blocks": stats.preconf.blocks,
+            "missed_blocks": stats.preconf.missed_blocks,
+            "flashblocks": stats.preconf.flashblocks,
+            "late_flashblocks": stats.preconf.late_flashblocks,
+            "timestamp_mismatches": stats.preconf.timestamp_mismatches,
+            "hash_mismatches": stats.preconf.hash_mismatches,
+            "delta_ms": stats.preconf.delta_summary().map(gap_summary),
+            "lead_ms": stats.preconf.lead_summary().map(gap_summary),
+        })),
+        "head_consistency": stats.head_consistency.window_ms.map(|window_ms| serde_json::json!({
+            "window_ms": window_ms,
+            "pushed_heads": stats.head_consistency.pushed_heads,
+            "polled_heads": stats.head_consistency.polled_heads,
+            "pushed_only": stats.head_consistency.pushed_only,
+            "polled_only": stats.head_consistency.polled_only,
+            "poll_lag_ms": stats.head_consistency.poll_lag_summary().map(gap_summary),
+            "recent_mismatches": stats.head_consistency.recent,
+        })),
+    })
+}
+
+fn gap_summary(gaps: GapSummary) -> serde_json::Value {
+    serde_json::json!({
+        "min": gaps.min_ms,
+        "max": gaps.max_ms,
+        "avg": gaps.avg_ms,
+        "p50": gaps.p50_ms,
+        "p90": gaps.p90_ms,
+        "p99": gaps.p99_ms,
+    })
+}
+
+// Null until the head has been sampled
+fn head_lag(lag: &HeadLag) -> serde_json::Value {
+    if lag.history_ms.is_empty() {
+        return serde_json::Value::Null;
+    }
+    serde_json::json!({
+        "blocks": lag.blocks,
+        "ms": lag.ms,
+        "max_blocks": lag.max_blocks,
+        "max_ms": lag.max_ms,
+        "p99_ms": lag.history_ms.percentile(99.0),
+    })
+}
+
+/// The periodic report: one line per chain, followed by its rolling windows
+pub(crate) fn current_report(chain_stats: &HashMap<String, ChainStats>, now: DateTime<Utc>) -> Vec<String> {
+    let mut lines = Vec::new();
+    for stats in sorted(chain_stats) {
+        lines.push(format!(
+            "{}{}{}: {} blocks | Avg delta: {}ms | p99: {}ms | Past: {} | Exact: {} | Future: {} | Backlog: {} ({:+.2})",
+            stats.chain_name,
+            if stats.degraded { " [degraded]" } else { "" },
+            if stats.paused { " [paused]" } else { "" },
+            stats.total_blocks,
+            stats.avg_time_delta_ms,
+            stats.delta_percentiles()[2],
+            stats.timestamp_past_blocks,
+            stats.timestamp_exact_blocks,
+            stats.timestamp_future_blocks,
+            stats.backlog_depth,
+            stats.backlog_trend()
+        ));
+        let windows: Vec<String> = stats
+            .window_summaries(now)
+            .iter()
+            .map(|(label, summary)| format!(
+                "last {}: {} blocks, avg {}ms, p99 {}ms, {} future",
+                label, summary.blocks, summary.avg_delta_ms, summary.p99_delta_ms, summary.future_blocks
+            ))
+            .collect();
+        lines.push(format!("  {}", windows.join(" | ")));
+    }
+    lines
+}
+
+/// One bar per bucket, scaled so the fullest bucket spans `CHART_WIDTH` characters
+pub(crate) fn histogram_chart(buckets: &[BucketCount]) -> Vec<String> {
+    let total: u64 = buckets.iter().map(|bucket| bucket.count).sum();
+    let fullest = buckets.iter().map(|bucket| bucket.count).max().unwrap_or(0).max(1);
+    let labels: Vec<String> = buckets.iter().map(BucketCount::label).collect();
+    let label_width = labels.iter().map(String::len).max().unwrap_or(0);
+    buckets
+        .iter()
+        .zip(&labels)
+        .map(|(bucket, label)| {
+            // Round up so a non-empty bucket always shows at least one mark
+            let width = (bucket.count * CHART_WIDTH as u64).div_ceil(fullest) as usize;
+            format!(
+                "      {:>label_width$} | {:<chart_width$} | {} ({:.1}%)",
+                label,
+                "#".repeat(width),
+                bucket.count,
+                bucket.count as f64 * 100.0 / total.max(1) as f64,
+                label_width = label_width,
+                chart_width = CHART_WIDTH
+            )
+        })
+        .collect()
+}
+
+// The deltas of each hour of the day that had blocks, and the hours they were highest and
+// lowest in
+fn hourly_table(stats: &ChainStats) -> Vec<String> {
+    let mut lines = vec!["  - Deltas by hour of day (UTC):".to_string()];
+    for (hour, deltas) in stats.hourly.hours().filter(|(_, deltas)| !deltas.is_empty()) {
+        lines.push(format!(
+            "      {:02}:00 | {:>7} blocks | avg {:>6}ms | p50 {:>6}ms | p99 {:>6}ms | {:>5.1}% future",
+            hour,
+            deltas.len(),
+            deltas.mean_ms(),
+            deltas.percentile(50.0),
+            deltas.percentile(99.0),
+            deltas.future_beyond(stats.timestamp_granularity_ms()) as f64 * 100.0 / deltas.len() as f64
+        ));
+    }
+    if let Some(((highest, highest_ms), (lowest, lowest_ms))) = stats.hourly.extremes() {
+        lines.push(format!(
+            "  - Hourly average delta: highest at {:02}:00 UTC ({}ms), lowest at {:02}:00 UTC ({}ms), {}ms apart",
+            highest,
+            highest_ms,
+            lowest,
+            lowest_ms,
+            highest_ms - lowest_ms
+        ));
+    }
+    lines
+}
+
+/// The end-of-run report, with a section per chain
+pub(crate) fn final_report(chain_stats: &HashMap<String, ChainStats>) -> Vec<String> {
+    let mut lines = Vec::new();
+    for stats in sorted(chain_stats) {
+        lines.push(format!("{}: {} blocks analyzed", stats.chain_name, stats.total_blocks));
+        lines.push(format!(
+            "  - Past timestamps: {} blocks (max delta: {}ms)",
+            stats.timestamp_past_blocks,
+            stats.max_past_delta_ms
+        ));
+        lines.push(format!(
+            "  - Future timestamps: {} blocks (max delta: {}ms)",
+            stats.timestamp_future_blocks,
+            stats.max_future_delta_ms
+        ));
+        lines.push(format!("  - Exact timestamps: {} blocks (delta of 0ms)", stats.timestamp_exact_blocks));
+        lines.push(format!("  - Average time delta: {}ms", stats.avg_time_delta_ms));
+        let [p50, p90, p99, p999] = stats.delta_percentiles();
+        lines.push(format!(
+            "  - Delta percentiles: p50 {}ms | p90 {}ms | p99 {}ms | p99.9 {}ms (stddev {:.1}ms)",
+            p50,
+            p90,
+            p99,
+            p999,
+            stats.delta_stddev_ms()
+        ));
+        if !stats.delta_histogram.is_empty() {
+            lines.push("  - Delta histogram (ms):".to_string());
+            lines.extend(histogram_chart(&stats.delta_buckets()));
+            lines.extend(hourly_table(stats));
+        }
+        lines.push(format!(
+            "  - Timestamp granularity: {}ms ({} future timestamps within one step)",
+            stats.timestamp_granularity_ms(),
+            stats.future_within_granularity()
+        ));
+        lines.push(format!("  - Backlog depth: max {} blocks behind head", stats.max_backlog_depth));
+        if let Some(offset) = stats.clock_offset_ms {
+            lines.push(format!(
+                "  - Local clock offset: {:+}ms (largest {}ms)",
+                offset,
+                stats.max_clock_offset_ms
+            ));
+        }
+        if let Some(skew) = stats.provider_clock_skew_summary() {
+            lines.push(format!(
+                "  - Provider clock: {:+}ms from ours on average over {} responses (p50 {:+}ms, each good to ±500ms)",
+                skew.avg_ms,
+                stats.provider_clock_skew.len(),
+                skew.p50_ms
+            ));
+        }
+        if let Some(peers) = stats.peer_count {
+            lines.push(format!(
+                "  - Node peers: {} (fewest {})",
+                peers,
+                stats.min_peer_count.unwrap_or(peers)
+            ));
+        }
+        let divergence = &stats.provider_divergence;
+        if divergence.checks > 0 {
+            lines.push(format!(
+                "  - Provider comparison: {} disagreements in {} checks (max head diff {} blocks, max timestamp diff {}ms)",
+                divergence.disagreements,
+                divergence.checks,
+                divergence.max_head_diff,
+                divergence.max_timestamp_diff_ms
+            ));
+        }
+        let preconf = &stats.preconf;
+        if preconf.is_tracked() {
+            lines.push(format!(
+                "  - Preconfirmations: {} of {} blocks ({} flashblocks, {} late) | {} timestamp mismatches | {} hash mismatches",
+                preconf.blocks,
+                preconf.blocks + preconf.missed_blocks,
+                preconf.flashblocks,
+                preconf.late_flashblocks,
+                preconf.timestamp_mismatches,
+                preconf.hash_mismatches
+            ));
+            for (label, summary) in [("delta", preconf.delta_summary()), ("lead", preconf.lead_summary())] {
+                if let Some(summary) = summary {
+                    lines.push(format!(
+                        "  - Preconf {}: min {}ms | avg {}ms | p50 {}ms | p90 {}ms | p99 {}ms | max {}ms",
+                        label,
+                        summary.min_ms,
+                        summary.avg_ms,
+                        summary.p50_ms,
+                        summary.p90_ms,
+                        summary.p99_ms,
+                        summary.max_ms
+                    ));
+                }
+            }
+        }
+        let (jitter_stddev, jitter_mean_abs) = stats.observation_jitter_ms();
+        lines.push(format!(
+            "  - Observation jitter: {:.1}ms stddev ({:.1}ms mean absolute)",
+            jitter_stddev,
+            jitter_mean_abs
+        ));
+        if stats.stale_responses > 0 {
+            lines.push(format!(
+                "  - Stale provider responses: {} (excluded from stats)",
+                stats.stale_responses
+            ));
+        }
+        if let Some(interval) = stats.avg_block_interval_ms() {
+            lines.push(match stats.expected_block_time_ms {
+                Some(expected) => format!("  - Avg block interval: {:.1}ms (expected {}ms)", interval, expected),
+                None => format!("  - Avg block interval: {:.1}ms", interval),
+            });
+        }
+        if let (Some(pct), Some(expected)) = (stats.schedule.on_schedule_pct(), stats.expected_block_time_ms) {
+            let schedule = &stats.schedule;
+            lines.push(format!(
+                "  - On schedule: {:.1}% of blocks arrived within ±{}ms of {}ms ({} early, {} late, avg deviation {}ms)",
+                pct,
+                schedule.tolerance_ms,
+                expected,
+                schedule.early,
+                schedule.late,
+                schedule.avg_deviation_ms().unwrap_or_default()
+            ));
+            lines.push(format!(
+                "  - Longest stall: {}ms before block {} | fastest burst: {} blocks within {}ms",
+                schedule.longest_stall_ms,
+                schedule.longest_stall_block.map_or("-".to_string(), |block| block.to_string()),
+                schedule.fastest_burst,
+                expected.saturating_sub(schedule.tolerance_ms).max(1)
+            ));
+        }
+        if let Some(settings) = stats.anomalies.settings {
+            let anomalies = &stats.anomalies;
+            lines.push(match anomalies.baseline_ms() {
+                Some((mean_ms, stddev_ms)) if anomalies.anomalies > 0 => format!(
+                    "  - Anomalies: {} beyond {}σ of the recent baseline ({} above, {} below, up to {:.1}σ, last at block {}) | baseline now {:.0}ms ± {:.0}ms",
+                    anomalies.anomalies,
+                    settings.z_threshold,
+                    anomalies.above_baseline,
+                    anomalies.below_baseline,
+                    anomalies.max_z_score,
+                    anomalies.last_anomaly_block.map_or("-".to_string(), |block| block.to_string()),
+                    mean_ms,
+                    stddev_ms
+                ),
+                Some((mean_ms, stddev_ms)) => format!(
+                    "  - Anomalies: none beyond {}σ of the recent baseline | baseline now {:.0}ms ± {:.0}ms",
+                    settings.z_threshold, mean_ms, stddev_ms
+                ),
+                None => "  - Anomalies: too few blocks for a baseline yet".to_string(),
+            });
+        }
+        if let Some(threshold_ms) = stats.outages.threshold_ms {
+            let outages = &stats.outages;
+            lines.push(if outages.count == 0 {
+                format!("  - Outages: none longer than {:.1}s without a new block", threshold_ms as f64 / 1000.0)
+            } else {
+                format!(
+                    "  - Outages: {} longer than {:.1}s without a new block (longest {:.1}s, {:.1}s in total){}",
+                    outages.count,
+                    threshold_ms as f64 / 1000.0,
+                    outages.longest_ms as f64 / 1000.0,
+                    outages.total_ms() as f64 / 1000.0,
+                    outages.ongoing().map_or(String::new(), |outage| format!(
+                        " | ongoing for {:.1}s since block {}",
+                        outage.duration_ms as f64 / 1000.0,
+                        outage.last_block
+                    ))
+                )
+            });
+        }
+        if let Some(gaps) = stats.block_gap_summary() {
+            lines.push(format!(
+                "  - Block gaps: min {}ms | avg {}ms | p50 {}ms | p90 {}ms | p99 {}ms | max {}ms ({} blocks share their parent's timestamp)",
+                gaps.min_ms,
+                gaps.avg_ms,
+                gaps.p50_ms,
+                gaps.p90_ms,
+                gaps.p99_ms,
+                gaps.max_ms,
+                stats.same_timestamp_blocks
+            ));
+            lines.push(format!(
+                "  - Non-monotonic timestamps: {} blocks stamped at or before their parent | worst regression {}ms",
+                stats.non_monotonic_blocks, stats.max_timestamp_regression_ms
+            ));
+        }
+        if let Some(gaps) = stats.arrival_gap_summary() {
+            lines.push(format!(
+                "  - Arrival gaps: min {}ms | avg {}ms | p50 {}ms | p90 {}ms | p99 {}ms | max {}ms",
+                gaps.min_ms,
+                gaps.avg_ms,
+                gaps.p50_ms,
+                gaps.p90_ms,
+                gaps.p99_ms,
+                gaps.max_ms
+            ));
+        }
+        for (label, latency) in [
+            ("Head", stats.head_rpc_latency_summary()),
+            ("Block", stats.block_rpc_latency_summary()),
+        ] {
+            if let Some(latency) = latency {
+                lines.push(format!(
+                    "  - {} RPC latency: min {}ms | avg {}ms | p50 {}ms | p90 {}ms | p99 {}ms | max {}ms",
+                    label,
+                    latency.min_ms,
+                    latency.avg_ms,
+                    latency.p50_ms,
+                    latency.p90_ms,
+                    latency.p99_ms,
+                    latency.max_ms
+                ));
+            }
+        }
+        if let Some(breakdown) = stats.delta_breakdown() {
+            lines.push(format!(
+                "  - Delta breakdown (avg): {}ms timestamp offset + {}ms RPC round trip = {}ms at response",
+                breakdown.timestamp_offset_ms, breakdown.round_trip_ms, breakdown.response_delta_ms
+            ));
+        }
+        for (label, lag) in [("Safe", &stats.safe_lag), ("Finalized", &stats.finalized_lag)] {
+            if !lag.history_ms.is_empty() {
+                lines.push(format!(
+                    "  - {} head lag: {} blocks / {}ms (max {} blocks / {}ms, p99 {}ms)",
+                    label,
+                    lag.blocks,
+                    lag.ms,
+                    lag.max_blocks,
+                    lag.max_ms,
+                    lag.history_ms.percentile(99.0)
+                ));
+            }
+        }
+        if let Some(drift) = stats.l1_origin_drift_summary() {
+            lines.push(format!(
+                "  - L1 origin drift: min {}ms | avg {}ms | p50 {}ms | p90 {}ms | p99 {}ms | max {}ms",
+                drift.min_ms,
+                drift.avg_ms,
+                drift.p50_ms,
+                drift.p90_ms,
+                drift.p99_ms,
+                drift.max_ms
+            ));
+        }
+        if stats.rpc_retries > 0 {
+            lines.push(format!("  - RPC retries: {}", stats.rpc_retries));
+        }
+        if stats.reorgs > 0 {
+            lines.push(format!(
+                "  - Reorgs: {} ({} recorded blocks replaced, deepest {})",
+                stats.reorgs,
+                stats.reorged_blocks,
+                stats.max_reorg_depth
+            ));
+        }
+        if stats.gaps > 0 {
+            lines.push(format!("  - Missed blocks: {} in {} gap(s)", stats.missed_blocks, stats.gaps));
+        }
+        if stats.breaker_trips > 0 {
+            lines.push(format!("  - Circuit breaker trips: {}", stats.breaker_trips));
+        }
+        if stats.wedge_events > 0 {
+            lines.push(format!("  - Wedge events: {}", stats.wedge_events));
+        }
+        if stats.monitor_restarts > 0 {
+            lines.push(format!("  - Monitor restarts: {}", stats.monitor_restarts));
+        }
+        if stats.schema_deviations > 0 {
+            lines.push(format!("  - Schema deviations: {}", stats.schema_deviations));
+        }
+        if stats.syncing_blocks > 0 {
+            // Replayed runs only know which blocks were marked, not how far behind the node was
+            let behind = Some(stats.max_sync_lag_blocks)
+                .filter(|lag| *lag > 0)
+                .map(|lag| format!(" (up to {} blocks behind)", lag))
+                .unwrap_or_default();
+            lines.push(format!(
+                "  - Node syncing: {} blocks recorded while the node was catching up{}; their deltas reflect the node, not the sequencer",
+                stats.syncing_blocks,
+                behind
+            ));
+        }
+        if stats.leap_tagged_blocks > 0 {
+            lines.push(format!(
+                "  - Leap-second window: {} blocks tagged and excluded from past/exact/future counts",
+                stats.leap_tagged_blocks
+            ));
+        }
+        lines.extend(head_consistency_section(stats));
+    }
+    lines
+}
+
+// How the chain's `newHeads` subscription and polling agreed, with the latest heads only
+// one of them reported
+fn head_consistency_section(stats: &ChainStats) -> Vec<String> {
+    let audit = &stats.head_consistency;
+    let Some(window_ms) = audit.window_ms else {
+        return Vec::new();
+    };
+    let mut lines = vec![format!(
+        "  - Head consistency (newHeads vs eth_blockNumber): {} pushed and {} polled heads | {} only pushed | {} only polled (within {}ms)",
+        audit.pushed_heads, audit.polled_heads, audit.pushed_only, audit.polled_only, window_ms
+    )];
+    if let Some(lag) = audit.poll_lag_summary() {
+        lines.push(format!(
+            "      Poll lag (eth_blockNumber minus newHeads): min {}ms | avg {}ms | p50 {}ms | p90 {}ms | p99 {}ms | max {}ms",
+            lag.min_ms, lag.avg_ms, lag.p50_ms, lag.p90_ms, lag.p99_ms, lag.max_ms
+        ));
+    }
+    for mismatch in &audit.recent {
+        lines.push(format!(
+            "      Block {}: only {} reported it",
+            mismatch.block_number,
+            mismatch.seen_by.as_str()
+        ));
+    }
+    lines
+}
@@ -0,0 +1,81 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::time::Duration;
+
+use crate::get_env_as_u64;
+
+/// How often, and how patiently, a failed RPC request is retried before it counts as an error
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_env() -> Self {
+        Self {
+            max_attempts: get_env_as_u64("RPC_RETRY_ATTEMPTS", 3).max(1) as u32,
+            base_delay: Duration::from_millis(get_env_as_u64("RPC_RETRY_BASE_MS", 100)),
+            max_delay: Duration::from_millis(get_env_as_u64("RPC_RETRY_MAX_MS", 2000)),
+            jitter: Duration::from_millis(get_env_as_u64("RPC_RETRY_JITTER_MS", 100)),
+        }
+    }
+
+    /// Total attempts per request, including the first
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Delay before retry number `retry` (starting at 1): exponential in the retry count,
+    /// capped, plus a random jitter so chains sharing a provider don't retry in lockstep
+    pub fn delay(&self, retry: u32) -> Duration {
+        let exponent = retry.saturating_sub(1).min(16);
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.pow(exponent))
+            .min(self.max_delay);
+        let jitter_ms = self.jitter.as_millis() as u64;
+        if jitter_ms == 0 {
+            return backoff;
+        }
+        backoff + Duration::from_millis(random_u64() % (jitter_ms + 1))
+    }
+}
+
+/// The provider answered with an HTTP status that is worth retrying (429 or 5xx)
+#[derive(Debug)]
+pub struct TransientStatus(pub reqwest::StatusCode);
+
+impl std::fmt::Display for TransientStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HTTP {}", self.0)
+    }
+}
+
+impl std::error::Error for TransientStatus {}
+
+impl TransientStatus {
+    pub fn check(status: reqwest::StatusCode) -> Option<Self> {
+        (status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error()).then_some(Self(status))
+    }
+}
+
+/// Whether a failed request may succeed if simply sent again: rate limiting, server errors,
+/// timeouts and dropped connections (HTTP or IPC). JSON-RPC errors and bad payloads are
+/// not retried.
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    if error.is::<TransientStatus>() || error.is::<io::Error>() {
+        return true;
+    }
+    error
+        .downcast_ref::<reqwest::Error>()
+        .is_some_and(|e| e.is_timeout() || e.is_connect() || e.is_request())
+}
+
+// Randomness for jitter without pulling in a dependency: each RandomState is freshly keyed
+fn random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
@@ -0,0 +1,346 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::{info, warn};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::stats::{ChainStats, RawSample, DELTAS_HEADERS, DETAILED_HEADERS};
+
+/// When the per-block and delta CSVs move on to a new file
+#[derive(Debug, Clone, Copy)]
+pub struct RotationPolicy {
+    /// Start a new file at every multiple of this interval of receipt time (e.g. on the hour)
+    pub interval: Option<Duration>,
+    /// Start a new file once the per-block file reaches this many bytes
+    pub max_bytes: Option<u64>,
+    /// Gzip each file once it is complete
+    pub gzip: bool,
+}
+
+impl RotationPolicy {
+    /// Rotate every `interval_minutes` and/or once a file reaches `size_mb` megabytes; `None`
+    /// when neither is set, in which case the CSVs are rewritten on every report as before
+    pub fn new(interval_minutes: Option<u64>, size_mb: Option<u64>, gzip: bool) -> Option<Self> {
+        let interval = interval_minutes.filter(|minutes| *minutes > 0).map(|minutes| Duration::from_secs(minutes * 60));
+        let max_bytes = size_mb.filter(|mb| *mb > 0).map(|mb| mb * 1024 * 1024);
+        (interval.is_some() || max_bytes.is_some()).then_some(Self {
+            interval,
+            max_bytes,
+            gzip,
+        })
+    }
+
+    // Start of the interval `receipt_time_ms` falls in, if rotating on time
+    fn period_start_ms(&self, receipt_time_ms: i64) -> Option<i64> {
+        let interval_ms = self.interval?.as_millis() as i64;
+        Some(receipt_time_ms.div_euclid(interval_ms) * interval_ms)
+    }
+}
+
+/// The pair of files a chain's blocks are currently appended to
+struct Segment {
+    period_start_ms: Option<i64>,
+    detailed: PathBuf,
+    deltas: PathBuf,
+    bytes: u64,
+}
+
+#[derive(Default)]
+struct ChainFiles {
+    written_blocks: usize,
+    segment: Option<Segment>,
+}
+
+/// Appends each chain's per-block records to `{Chain}_detailed.{start}.csv` and its deltas
+/// to `{Chain}_deltas.{start}.csv`, instead of rewriting `{Chain}_detailed.csv` and
+/// `{Chain}_deltas.csv` from scratch on every report.
+///
+/// `{start}` is when the file's period began (or its first block arrived, when only
+/// rotating by size), as `20250101T130000Z`. A file is complete once a block arrives after
+/// its period or it reaches the size limit, and is then gzipped to `.csv.gz` if asked.
+pub(crate) struct CsvRotation {
+    dir: PathBuf,
+    policy: RotationPolicy,
+    chains: HashMap<String, ChainFiles>,
+    // Files completed since `take_completed` was last called
+    completed: Vec<PathBuf>,
+}
+
+impl CsvRotation {
+    pub(crate) fn new(dir: PathBuf, policy: RotationPolicy) -> Self {
+        Self {
+            dir,
+            policy,
+            chains: HashMap::new(),
+            completed: Vec::new(),
+        }
+    }
+
+    /// Count the blocks in resumed stats as written: their checkpoint was saved right after
+    /// the files that hold them were
+    pub(crate) fn resume(&mut self, resumed: &HashMap<String, ChainStats>) {
+        for (name, stats) in resumed {
+            self.chains.entry(name.clone()).or_default().written_blocks = stats.total_blocks;
+        }
+    }
+
+    /// Append the blocks `stats` recorded since the last call to the chain's current files,
+    /// moving on to new ones as the policy says
+    pub(crate) fn append(&mut self, stats: &ChainStats, now: DateTime<Utc>) -> Result<()> {
+        let files = self.chains.entry(stats.chain_name.clone()).or_default();
+        let new_blocks = stats.total_blocks.saturating_sub(files.written_blocks);
+        files.written_blocks = stats.total_blocks;
+        if new_blocks > stats.raw_samples.len() && stats.max_raw_samples > 0 {
+            warn!(
+                "{}: {} blocks arrived since the last write but only the latest {} are kept; raise MAX_RAW_SAMPLES to rotate them all",
+                stats.chain_name,
+                new_blocks,
+                stats.raw_samples.len()
+            );
+        }
+
+        let skip = stats.raw_samples.len().saturating_sub(new_blocks);
+        let mut open: Option<(BufWriter<File>, BufWriter<File>)> = None;
+        for sample in stats.raw_samples.iter().skip(skip) {
+            let period_start_ms = self.policy.period_start_ms(sample.receipt_time_ms);
+            let full = files.segment.as_ref().is_some_and(|segment| {
+                segment.period_start_ms != period_start_ms
+                    || self.policy.max_bytes.is_some_and(|max_bytes| segment.bytes >= max_bytes)
+            });
+            if full {
+                open = None;
+                if let Some(segment) = files.segment.take() {
+                    self.completed.extend(close(&segment, self.policy.gzip)?);
+                }
+            }
+            let segment = match &mut files.segment {
+                Some(segment) => segment,
+                None => files.segment.insert(start_segment(&self.dir, &stats.chain_name, &self.policy, sample)?),
+            };
+            let (detailed, deltas) = match &mut open {
+                Some(writers) => writers,
+                None => open.insert(open_segment(segment)?),
+            };
+            let line = csv_line(&sample.detailed_record())?;
+            detailed.write_all(&line)?;
+            deltas.write_all(&csv_line(&[sample.delta_ms.to_string()])?)?;
+            segment.bytes += line.len() as u64;
+        }
+        if let Some((mut detailed, mut deltas)) = open {
+            detailed.flush()?;
+            deltas.flush()?;
+        }
+
+        // Don't hold a finished period open until the chain's next block
+        let now_period = self.policy.period_start_ms(now.timestamp_millis());
+        if files.segment.as_ref().is_some_and(|segment| segment.period_start_ms < now_period) {
+            if let Some(segment) = files.segment.take() {
+                self.completed.extend(close(&segment, self.policy.gzip)?);
+            }
+        }
+        if let Some(segment) = &files.segment {
+            info!("Detailed block data for {} appended to {}", stats.chain_name, segment.detailed.display());
+        }
+        Ok(())
+    }
+
+    /// Close every chain's current files, compressing them if the policy asks
+    pub(crate) fn finish(&mut self) -> Result<()> {
+        for files in self.chains.values_mut() {
+            if let Some(segment) = files.segment.take() {
+                self.completed.extend(close(&segment, self.policy.gzip)?);
+            }
+        }
+        Ok(())
+    }
+
+    /// The files completed since the last call, e.g. to upload them
+    pub(crate) fn take_completed(&mut self) -> Vec<PathBuf> {
+        std::mem::take(&mut self.completed)
+    }
+}
+
+// One CSV record as the bytes it is written as
+fn csv_line<T: AsRef<[u8]>>(record: &[T]) -> Result<Vec<u8>> {
+    let mut wtr = csv::Writer::from_writer(Vec::new());
+    wtr.write_record(record)?;
+    Ok(wtr.into_inner().map_err(|e| e.into_error())?)
+}
+
+fn gz_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".gz");
+    PathBuf::from(name)
+}
+
+// Pick the files for the segment `sample` starts: named after its period, or its receipt
+// time. A file left by an earlier process is continued unless it is already complete.
+fn start_segment(dir: &Path, chain: &str, policy: &RotationPolicy, sample: &RawSample) -> Result<Segment> {
+    let period_start_ms = policy.period_start_ms(sample.receipt_time_ms);
+    let start = DateTime::from_timestamp_millis(period_start_ms.unwrap_or(sample.receipt_time_ms)).unwrap_or_else(Utc::now);
+    let stamp = start.format("%Y%m%dT%H%M%SZ").to_string();
+    for sequence in 0.. {
+        let suffix = if sequence == 0 { stamp.clone() } else { format!("{}-{}", stamp, sequence) };
+        let detailed = dir.join(format!("{}_detailed.{}.csv", chain, suffix));
+        let bytes = std::fs::metadata(&detailed).map_or(0, |metadata| metadata.len());
+        let complete = gz_path(&detailed).exists() || policy.max_bytes.is_some_and(|max_bytes| bytes >= max_bytes);
+        if !complete {
+            return Ok(Segment {
+                period_start_ms,
+                deltas: dir.join(format!("{}_deltas.{}.csv", chain, suffix)),
+                detailed,
+                bytes,
+            });
+        }
+    }
+    unreachable!("a free segment name is always found")
+}
+
+// Open both files of `segment` for appending, writing their headers if they are new
+fn open_segment(segment: &Segment) -> Result<(BufWriter<File>, BufWriter<File>)> {
+    let open = |path: &Path, headers: &[&str]| -> Result<BufWriter<File>> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let is_new = file.metadata()?.len() == 0;
+        let mut wtr = BufWriter::new(file);
+        if is_new {
+            wtr.write_all(&csv_line(headers)?)?;
+        }
+        Ok(wtr)
+    };
+    Ok((open(&segment.detailed, DETAILED_HEADERS)?, open(&segment.deltas, DELTAS_HEADERS)?))
+}
+
+// Finish with a segment's files, replacing each with a gzipped copy if asked, returning the
+// complete files
+fn close(segment: &Segment, gzip: bool) -> Result<Vec<PathBuf>> {
+    let files: Vec<&PathBuf> = [&segment.detailed, &segment.deltas].into_iter().filter(|path| path.exists()).collect();
+    if !gzip {
+        info!("Rotated {}", segment.detailed.display());
+        return Ok(files.into_iter().cloned().collect());
+    }
+    let mut compressed_files = Vec::new();
+    for path in files {
+        let compressed = gz_path(path);
+        let mut encoder = GzEncoder::new(File::create(&compressed)?, Compression::default());
+        std::io::copy(&mut File::open(path)?, &mut encoder)?;
+        encoder.finish()?.sync_all()?;
+        std::fs::remove_file(path)?;
+        compressed_files.push(compressed);
+    }
+    info!("Rotated and compressed {}", gz_path(&segment.detailed).display());
+    Ok(compressed_files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2025-01-01T12:59:30Z
+    const BEFORE_THE_HOUR_MS: i64 = 1_735_736_370_000;
+
+    fn sample(block_number: u64, receipt_time_ms: i64) -> RawSample {
+        RawSample {
+            block_number,
+            block_timestamp_ms: receipt_time_ms as u64 - 200,
+            receipt_time_ms,
+            received_instant: None,
+            delta_ms: 200,
+            leap_affected: false,
+            round_trip_ms: None,
+            l1_origin_block: None,
+            l1_drift_ms: None,
+            hash: None,
+            parent_hash: None,
+            gas_used: None,
+            tx_count: None,
+            node_syncing: None,
+        }
+    }
+
+    // Append blocks received at each of `receipt_times_ms` to "Base"'s files, one call each
+    fn append_all(rotation: &mut CsvRotation, receipt_times_ms: &[i64]) {
+        let mut stats = ChainStats::new("Base");
+        for (i, receipt_time_ms) in receipt_times_ms.iter().enumerate() {
+            stats.raw_samples.push_back(sample(i as u64, *receipt_time_ms));
+            stats.total_blocks += 1;
+            let now = DateTime::from_timestamp_millis(*receipt_time_ms).unwrap();
+            rotation.append(&stats, now).unwrap();
+        }
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rotation-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn file_names(paths: &[PathBuf]) -> Vec<String> {
+        paths.iter().map(|path| path.file_name().unwrap().to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn rotates_when_a_block_arrives_after_the_period() {
+        let dir = test_dir("interval");
+        let policy = RotationPolicy::new(Some(60), None, false).unwrap();
+        let mut rotation = CsvRotation::new(dir.clone(), policy);
+        append_all(&mut rotation, &[BEFORE_THE_HOUR_MS, BEFORE_THE_HOUR_MS + 20_000, BEFORE_THE_HOUR_MS + 40_000]);
+
+        assert_eq!(
+            file_names(&rotation.take_completed()),
+            ["Base_detailed.20250101T120000Z.csv", "Base_deltas.20250101T120000Z.csv"]
+        );
+        // Header plus the two blocks before the hour, and the one after it in the next file
+        let lines = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap().lines().count();
+        assert_eq!(lines("Base_detailed.20250101T120000Z.csv"), 3);
+        assert_eq!(lines("Base_detailed.20250101T130000Z.csv"), 2);
+        assert_eq!(lines("Base_deltas.20250101T130000Z.csv"), 2);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rotates_once_the_size_limit_is_reached() {
+        let dir = test_dir("size");
+        let policy = RotationPolicy {
+            interval: None,
+            max_bytes: Some(1),
+            gzip: false,
+        };
+        let mut rotation = CsvRotation::new(dir.clone(), policy);
+        append_all(&mut rotation, &[BEFORE_THE_HOUR_MS; 3]);
+
+        // Every block fills a file, so files started in the same second get a sequence number
+        assert_eq!(
+            file_names(&rotation.take_completed()),
+            [
+                "Base_detailed.20250101T125930Z.csv",
+                "Base_deltas.20250101T125930Z.csv",
+                "Base_detailed.20250101T125930Z-1.csv",
+                "Base_deltas.20250101T125930Z-1.csv",
+            ]
+        );
+        assert!(dir.join("Base_detailed.20250101T125930Z-2.csv").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn gzips_completed_files() {
+        let dir = test_dir("gzip");
+        let policy = RotationPolicy::new(Some(60), None, true).unwrap();
+        let mut rotation = CsvRotation::new(dir.clone(), policy);
+        append_all(&mut rotation, &[BEFORE_THE_HOUR_MS]);
+        rotation.finish().unwrap();
+
+        assert_eq!(
+            file_names(&rotation.take_completed()),
+            ["Base_detailed.20250101T120000Z.csv.gz", "Base_deltas.20250101T120000Z.csv.gz"]
+        );
+        assert!(!dir.join("Base_detailed.20250101T120000Z.csv").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
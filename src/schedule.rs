@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// How closely a chain's blocks arrive on its expected block time: the gap in receipt
+/// times between consecutive blocks is on schedule within `tolerance_ms` of the block time
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ScheduleAdherence {
+    pub tolerance_ms: u64,
+    pub on_schedule: usize,           // Blocks that arrived within the tolerance
+    pub early: usize,                 // Blocks that arrived sooner than that
+    pub late: usize,                  // Blocks that arrived later than that
+    pub deviation_sum_ms: u64,        // Sum of each arrival gap's distance from the block time
+    pub longest_stall_ms: i64,        // Longest wait between consecutive blocks
+    pub longest_stall_block: Option<u64>, // Block that ended the longest stall
+    pub fastest_burst: usize,         // Most blocks received closer together than the schedule allows
+    #[serde(skip)]
+    recent_receipts: VecDeque<i64>,   // Receipt times within the burst window, for bursts
+}
+
+impl ScheduleAdherence {
+    pub fn new(tolerance_ms: u64) -> Self {
+        Self {
+            tolerance_ms,
+            ..Self::default()
+        }
+    }
+
+    /// Blocks scored against the schedule
+    pub fn scored(&self) -> usize {
+        self.on_schedule + self.early + self.late
+    }
+
+    /// Share of the scored blocks that arrived on schedule, in percent
+    pub fn on_schedule_pct(&self) -> Option<f64> {
+        let scored = self.scored();
+        (scored > 0).then(|| self.on_schedule as f64 * 100.0 / scored as f64)
+    }
+
+    /// Average distance of the arrival gaps from the block time
+    pub fn avg_deviation_ms(&self) -> Option<u64> {
+        let scored = self.scored() as u64;
+        (scored > 0).then(|| self.deviation_sum_ms / scored)
+    }
+
+    /// Record a block received at `receipt_ms`. `arrival_gap_ms` is the time since its parent
+    /// arrived, when the parent was recorded too.
+    pub(crate) fn record(&mut self, block_number: u64, receipt_ms: i64, arrival_gap_ms: Option<i64>, block_time_ms: u64) {
+        // A burst is blocks arriving within one block time less the tolerance, so on-schedule
+        // blocks never form one. Blocks fetched together during a catch-up share a receipt time.
+        let burst_window_ms = block_time_ms.saturating_sub(self.tolerance_ms).max(1) as i64;
+        self.recent_receipts.push_back(receipt_ms);
+        while self
+            .recent_receipts
+            .front()
+            .is_some_and(|received| receipt_ms - received >= burst_window_ms)
+        {
+            self.recent_receipts.pop_front();
+        }
+        self.fastest_burst = self.fastest_burst.max(self.recent_receipts.len());
+
+        let Some(gap) = arrival_gap_ms else {
+            return;
+        };
+        let deviation = gap - block_time_ms as i64;
+        if deviation.unsigned_abs() <= self.tolerance_ms {
+            self.on_schedule += 1;
+        } else if deviation < 0 {
+            self.early += 1;
+        } else {
+            self.late += 1;
+        }
+        self.deviation_sum_ms += deviation.unsigned_abs();
+        if gap > self.longest_stall_ms {
+            self.longest_stall_ms = gap;
+            self.longest_stall_block = Some(block_number);
+        }
+    }
+
+    /// The adherence's columns in `SUMMARY_HEADERS` order, empty for chains without an
+    /// expected block time
+    pub(crate) fn record_columns(&self) -> Vec<String> {
+        let (Some(pct), Some(avg_deviation)) = (self.on_schedule_pct(), self.avg_deviation_ms()) else {
+            return vec![String::new(); 9];
+        };
+        vec![
+            self.tolerance_ms.to_string(),
+            self.on_schedule.to_string(),
+            self.early.to_string(),
+            self.late.to_string(),
+            format!("{:.2}", pct),
+            avg_deviation.to_string(),
+            self.longest_stall_ms.to_string(),
+            self.longest_stall_block.map(|block| block.to_string()).unwrap_or_default(),
+            self.fastest_burst.to_string(),
+        ]
+    }
+}
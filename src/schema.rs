@@ -0,0 +1,138 @@
+use log::warn;
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// Fields an execution-layer block object may carry (through Prague)
+const KNOWN_BLOCK_FIELDS: &[&str] = &[
+    "baseFeePerGas",
+    "blobGasUsed",
+    "difficulty",
+    "excessBlobGas",
+    "extraData",
+    "gasLimit",
+    "gasUsed",
+    "hash",
+    "logsBloom",
+    "miner",
+    "mixHash",
+    "nonce",
+    "number",
+    "parentBeaconBlockRoot",
+    "parentHash",
+    "receiptsRoot",
+    "requestsHash",
+    "sha3Uncles",
+    "size",
+    "stateRoot",
+    "timestamp",
+    "totalDifficulty",
+    "transactions",
+    "transactionsRoot",
+    "uncles",
+    "withdrawals",
+    "withdrawalsRoot",
+];
+
+/// Block fields that must be present
+const REQUIRED_BLOCK_FIELDS: &[&str] = &["number", "hash", "parentHash", "timestamp", "transactions"];
+
+/// Block fields that must be hex-encoded quantities
+const QUANTITY_BLOCK_FIELDS: &[&str] = &["number", "timestamp", "gasLimit", "gasUsed"];
+
+const KNOWN_ENVELOPE_FIELDS: &[&str] = &["jsonrpc", "id", "result", "error"];
+
+// A hex quantity: "0x" followed by at least one hex digit
+fn is_quantity(value: &Value) -> bool {
+    value
+        .as_str()
+        .and_then(|s| s.strip_prefix("0x"))
+        .is_some_and(|digits| !digits.is_empty() && digits.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Strict-mode checks of JSON-RPC responses against the expected schema.
+///
+/// Each kind of deviation is logged once per provider; every occurrence is counted.
+#[derive(Debug, Default)]
+pub struct SchemaValidator {
+    provider: String,
+    reported: HashSet<String>,
+}
+
+impl SchemaValidator {
+    pub fn new(provider: &str) -> Self {
+        Self {
+            provider: provider.to_string(),
+            reported: HashSet::new(),
+        }
+    }
+
+    fn deviation(&mut self, description: String) -> usize {
+        if self.reported.insert(description.clone()) {
+            warn!(
+                "{}: schema deviation: {} (further occurrences are only counted)",
+                self.provider, description
+            );
+        }
+        1
+    }
+
+    /// Check the response envelope: version, matching id, and no unexpected members.
+    /// Returns the number of deviations found.
+    pub fn check_envelope(&mut self, raw: &Value, jsonrpc: &str, id: u64, expected_id: u64) -> usize {
+        let mut deviations = 0;
+
+        if jsonrpc != "2.0" {
+            deviations += self.deviation(format!("jsonrpc version '{}'", jsonrpc));
+        }
+        if id != expected_id {
+            deviations += self.deviation("response id does not match request id".to_string());
+        }
+        if let Some(object) = raw.as_object() {
+            for key in object.keys() {
+                if !KNOWN_ENVELOPE_FIELDS.contains(&key.as_str()) {
+                    deviations += self.deviation(format!("unexpected envelope field '{}'", key));
+                }
+            }
+        }
+
+        deviations
+    }
+
+    /// Check an `eth_blockNumber` result
+    pub fn check_block_number(&mut self, result: &Value) -> usize {
+        if is_quantity(result) {
+            0
+        } else {
+            self.deviation(format!("eth_blockNumber result is not a hex quantity: {}", result))
+        }
+    }
+
+    /// Check an `eth_getBlockByNumber` block object
+    pub fn check_block(&mut self, block: &Value) -> usize {
+        let object = match block.as_object() {
+            Some(object) => object,
+            None => return self.deviation("block is not a JSON object".to_string()),
+        };
+        let mut deviations = 0;
+
+        for field in REQUIRED_BLOCK_FIELDS {
+            if !object.contains_key(*field) {
+                deviations += self.deviation(format!("missing block field '{}'", field));
+            }
+        }
+        for field in QUANTITY_BLOCK_FIELDS {
+            if let Some(value) = object.get(*field) {
+                if !is_quantity(value) {
+                    deviations += self.deviation(format!("block field '{}' is not a hex quantity", field));
+                }
+            }
+        }
+        for key in object.keys() {
+            if !KNOWN_BLOCK_FIELDS.contains(&key.as_str()) {
+                deviations += self.deviation(format!("unexpected block field '{}'", key));
+            }
+        }
+
+        deviations
+    }
+}
@@ -0,0 +1,134 @@
+use anyhow::{bail, Result};
+use log::{info, warn};
+use std::future::Future;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Longest request line and headers accepted; anything longer is dropped unanswered
+const MAX_REQUEST_HEAD: u64 = 8192;
+
+/// The method, path and headers of a request
+#[derive(Debug)]
+pub(crate) struct Request {
+    pub method: String,
+    pub path: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    /// A request for `target` as `read_request` would parse it
+    #[cfg(test)]
+    pub fn new(method: &str, target: &str, headers: &[(&str, &str)]) -> Self {
+        Self {
+            method: method.to_string(),
+            path: path_of(target).to_string(),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    /// The value of the header `name`, matched case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Status line, content type and body of a reply
+#[derive(Debug)]
+pub(crate) struct Response {
+    pub status: &'static str,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+/// Answer each connection on `listener` with `handler` until the process exits; `name` says
+/// which server a failed request belongs to in the log
+pub(crate) async fn serve<H, F>(listener: TcpListener, name: &'static str, handler: H)
+where
+    H: Fn(Request) -> F + Clone + Send + 'static,
+    F: Future<Output = Response> + Send,
+{
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = respond(stream, handler).await {
+                        warn!("{} request failed: {}", name, e);
+                    }
+                });
+            }
+            Err(e) => warn!("Failed to accept {} connection: {}", name, e),
+        }
+    }
+}
+
+/// Log where a server is listening, as `http://{addr}{path}`
+pub(crate) fn log_listening(listener: &TcpListener, what: &str, path: &str) {
+    info!("Serving {} on http://{}{}", what, listener.local_addr().map_or("?".to_string(), |a| a.to_string()), path);
+}
+
+// Minimal HTTP/1.1: read the request line and headers, reply and close
+async fn respond<H, F>(mut stream: TcpStream, handler: H) -> Result<()>
+where
+    H: Fn(Request) -> F,
+    F: Future<Output = Response>,
+{
+    let request = read_request(&mut stream).await?;
+    let response = handler(request).await;
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.content_type,
+        response.body.len(),
+        response.body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+// The request line and headers, each read up to its `\r\n` however many packets they arrive in;
+// a body is never read
+async fn read_request(stream: &mut TcpStream) -> Result<Request> {
+    let mut reader = BufReader::new(stream.take(MAX_REQUEST_HEAD));
+    let request_line = read_line(&mut reader).await?;
+    let mut words = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (words.next(), words.next()) else {
+        bail!("malformed request line {:?}", request_line);
+    };
+    let (method, path) = (method.to_string(), path_of(target).to_string());
+
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(&mut reader).await?;
+        if line.is_empty() {
+            break;
+        }
+        // Malformed header lines are skipped rather than failing the request
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    Ok(Request { method, path, headers })
+}
+
+// The path of a request target; its query string is dropped since no endpoint takes one
+fn path_of(target: &str) -> &str {
+    target.split_once('?').map_or(target, |(path, _)| path)
+}
+
+// One line without its `\r\n`
+async fn read_line(reader: &mut (impl AsyncBufReadExt + Unpin)) -> Result<String> {
+    let mut line = String::new();
+    reader.read_line(&mut line).await?;
+    match line.strip_suffix("\r\n") {
+        Some(line) => Ok(line.to_string()),
+        None => bail!("request incomplete or longer than {} bytes", MAX_REQUEST_HEAD),
+    }
+}
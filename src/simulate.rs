@@ -0,0 +1,310 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use log::error;
+use std::time::Duration;
+
+use crate::adapter::{AdapterCounters, BlockBatch, BlockHeader, ChainAdapter, L1Origin, NodeHealth, StaleResponse};
+use crate::clock::Stamp;
+use crate::monitor::ChainInfo;
+
+/// Height the first simulated block gets
+const FIRST_BLOCK: u64 = 1_000_000;
+
+/// Blocks the simulated safe and finalized heads trail the latest block by
+const SAFE_DEPTH: u64 = 10;
+const FINALIZED_DEPTH: u64 = 64;
+
+/// Peers the simulated node reports
+const SIMULATED_PEERS: u64 = 25;
+
+/// L1 block time, and how many L1 blocks the origin of each simulated block trails its slot
+/// by (the OP-stack sequencer's default confirmation depth)
+const L1_BLOCK_TIME_MS: i64 = 12_000;
+const L1_ORIGIN_DEPTH: i64 = 4;
+
+/// Height of the L1 block produced at the first simulated block's slot
+const FIRST_L1_BLOCK: i64 = 20_000_000;
+
+/// Whether `rpc_url` names a simulated chain rather than a real endpoint
+pub(crate) fn is_simulated(rpc_url: &str) -> bool {
+    rpc_url.starts_with("sim://")
+}
+
+// Read `key` from the URL's query, falling back to `SIMULATE_{KEY}` and then `default`
+fn setting(url: &reqwest::Url, key: &str, default: i64) -> Result<i64> {
+    if let Some((_, value)) = url.query_pairs().find(|(name, _)| name == key) {
+        return value
+            .parse()
+            .map_err(|_| anyhow!("Invalid {} '{}' in simulated chain URL {}", key, value, url));
+    }
+    let var = format!("SIMULATE_{}", key.to_uppercase());
+    match std::env::var(&var) {
+        Ok(value) => value.parse().map_err(|_| anyhow!("Invalid {} '{}'", var, value)),
+        Err(_) => Ok(default),
+    }
+}
+
+/// How a simulated chain produces blocks, from the query of its `sim://` URL (e.g.
+/// `sim://?skew_ms=-300&reorg_every=50`) or the matching `SIMULATE_*` variables
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SimulationSettings {
+    /// Time between blocks; defaults to the chain's expected block time
+    pub block_time_ms: u64,
+    /// Offset of the sequencer's clock from real time; positive stamps blocks in the future
+    pub skew_ms: i64,
+    /// Time from a block's slot until the node serves it
+    pub delay_ms: u64,
+    /// Most random extra delay added to each block's `delay_ms`
+    pub jitter_ms: u64,
+    /// Round-trip time of every simulated RPC call
+    pub latency_ms: u64,
+    /// Replace the latest blocks every this many blocks; `0` never reorgs
+    pub reorg_every: u64,
+    /// Blocks replaced by each reorg
+    pub reorg_depth: u64,
+    /// Seeds the jitter and block hashes, so a run can be repeated exactly
+    pub seed: u64,
+}
+
+impl SimulationSettings {
+    /// Settings for `chain_info` from its `rpc_url`, failing on values that aren't numbers
+    /// or can't be simulated
+    pub(crate) fn parse(rpc_url: &str, chain_info: &ChainInfo) -> Result<Self> {
+        let url = reqwest::Url::parse(rpc_url)?;
+        let block_time_ms = setting(&url, "block_time_ms", chain_info.expected_block_time_ms.unwrap_or(2000) as i64)?;
+        if block_time_ms <= 0 {
+            return Err(anyhow!("Simulated chain {} needs a positive block_time_ms", chain_info.name));
+        }
+        let seed = chain_info
+            .name
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3));
+        let settings = Self {
+            block_time_ms: block_time_ms as u64,
+            skew_ms: setting(&url, "skew_ms", 0)?,
+            delay_ms: setting(&url, "delay_ms", 100)?.max(0) as u64,
+            jitter_ms: setting(&url, "jitter_ms", 100)?.max(0) as u64,
+            latency_ms: setting(&url, "latency_ms", 20)?.max(0) as u64,
+            reorg_every: setting(&url, "reorg_every", 0)?.max(0) as u64,
+            reorg_depth: setting(&url, "reorg_depth", 2)?.max(1) as u64,
+            seed: setting(&url, "seed", seed as i64)? as u64,
+        };
+        if settings.reorg_every > 0 && settings.reorg_depth >= settings.reorg_every {
+            return Err(anyhow!(
+                "Simulated chain {}: reorg_depth must be below reorg_every",
+                chain_info.name
+            ));
+        }
+        Ok(settings)
+    }
+}
+
+// SplitMix64 over the seed and the inputs: the same block always gets the same jitter and hash
+fn mix(seed: u64, a: u64, b: u64) -> u64 {
+    let mut z = seed ^ a.wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ b.wrapping_mul(0xc2b2_ae3d_27d4_eb4f);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// A chain that exists only in memory, producing a block every block time from when it
+/// was created, so the stats, reports and alerts can be exercised without an RPC endpoint.
+///
+/// Slots start on a whole second, so with whole-second block times and skew the
+/// timestamps look like a real chain's. Each block is stamped with its slot plus the skew,
+/// and served from its slot plus the delay and a random jitter.
+/// Every `reorg_every` blocks, the last `reorg_depth` blocks are replaced by new ones once
+/// the next block is served, so their successor no longer builds on what was recorded.
+/// Like an OP-stack chain, each block is derived from an L1 block a few 12s slots earlier.
+pub(crate) struct SimulatedChain {
+    settings: SimulationSettings,
+    // Slot of the first block, in ms since the epoch
+    origin_ms: i64,
+    counters: AdapterCounters,
+}
+
+impl SimulatedChain {
+    /// A chain simulated as `rpc_url` asks. The URL was checked when the configuration was
+    /// loaded, so the plain defaults are only a fallback.
+    pub(crate) fn new(chain_info: &ChainInfo, rpc_url: &str) -> Self {
+        let settings = SimulationSettings::parse(rpc_url, chain_info).unwrap_or_else(|e| {
+            error!("{}: {:#}; simulating with the default settings", chain_info.name, e);
+            SimulationSettings {
+                block_time_ms: chain_info.expected_block_time_ms.unwrap_or(2000).max(1),
+                skew_ms: 0,
+                delay_ms: 100,
+                jitter_ms: 100,
+                latency_ms: 20,
+                reorg_every: 0,
+                reorg_depth: 2,
+                seed: 0,
+            }
+        });
+        Self {
+            settings,
+            origin_ms: Utc::now().timestamp_millis().div_euclid(1000) * 1000,
+            counters: AdapterCounters::default(),
+        }
+    }
+
+    fn slot_ms(&self, height: u64) -> i64 {
+        self.origin_ms + (height.saturating_sub(FIRST_BLOCK) * self.settings.block_time_ms) as i64
+    }
+
+    // When the node starts serving `height`
+    fn served_at_ms(&self, height: u64) -> i64 {
+        let jitter = match self.settings.jitter_ms {
+            0 => 0,
+            jitter_ms => mix(self.settings.seed, height, 1) % (jitter_ms + 1),
+        };
+        self.slot_ms(height) + (self.settings.delay_ms + jitter) as i64
+    }
+
+    fn head_at(&self, now_ms: i64) -> u64 {
+        let elapsed = (now_ms - self.origin_ms).max(0) as u64;
+        let mut head = FIRST_BLOCK + elapsed / self.settings.block_time_ms;
+        while head > FIRST_BLOCK && self.served_at_ms(head) > now_ms {
+            head -= 1;
+        }
+        head
+    }
+
+    // Whether the block at `height` has been replaced by a reorg as of `head`
+    fn replaced(&self, height: u64, head: u64) -> bool {
+        let every = self.settings.reorg_every;
+        if every == 0 {
+            return false;
+        }
+        // The reorg covering `height`, if any, happens at the next multiple of `every`
+        let reorg_at = height.div_ceil(every) * every;
+        reorg_at - height < self.settings.reorg_depth && head > reorg_at
+    }
+
+    fn hash(&self, height: u64, head: u64) -> String {
+        format!("0x{:016x}{:048x}", mix(self.settings.seed, height, 2 + self.replaced(height, head) as u64), height)
+    }
+
+    fn header(&self, height: u64, now_ms: i64) -> Result<BlockHeader> {
+        let head = self.head_at(now_ms);
+        if height > head || height < FIRST_BLOCK {
+            return Err(StaleResponse(height).into());
+        }
+        // Up to 99 transactions of 50k gas each, so roughly one block in a hundred is empty
+        let tx_count = mix(self.settings.seed, height, u64::MAX) % 100;
+        Ok(BlockHeader {
+            timestamp_ms: (self.slot_ms(height) + self.settings.skew_ms) as u64,
+            hash: Some(self.hash(height, head)),
+            parent_hash: Some(self.hash(height - 1, head)),
+            gas_used: Some(tx_count * 50_000),
+            tx_count: Some(tx_count),
+        })
+    }
+
+    // The block being built on top of `head`: stamped with its slot, and without a hash or
+    // transactions yet
+    fn pending_header(&self, head: u64) -> BlockHeader {
+        BlockHeader {
+            timestamp_ms: (self.slot_ms(head + 1) + self.settings.skew_ms) as u64,
+            hash: None,
+            parent_hash: Some(self.hash(head, head)),
+            gas_used: Some(0),
+            tx_count: Some(0),
+        }
+    }
+
+    // The L1 block `L1_ORIGIN_DEPTH` blocks before the last one produced by the block's
+    // slot, with L1 slots starting alongside the first block's
+    fn l1_origin_of(&self, height: u64) -> L1Origin {
+        let l1_slot = (self.slot_ms(height) - self.origin_ms).div_euclid(L1_BLOCK_TIME_MS) - L1_ORIGIN_DEPTH;
+        L1Origin {
+            number: (FIRST_L1_BLOCK + l1_slot) as u64,
+            timestamp_ms: (self.origin_ms + l1_slot * L1_BLOCK_TIME_MS) as u64,
+        }
+    }
+
+    // Wait out the simulated round trip, counting it like a real call. The node's clock is
+    // accurate, whatever the sequencer's skew, and its `Date` header names whole seconds.
+    async fn round_trip(&mut self, head: bool) {
+        let midway_ms = Utc::now().timestamp_millis() + self.settings.latency_ms as i64 / 2;
+        tokio::time::sleep(Duration::from_millis(self.settings.latency_ms)).await;
+        let midway = DateTime::from_timestamp_millis(midway_ms).unwrap_or_default();
+        self.counters.server_dates.push((midway_ms.div_euclid(1000) * 1000 + 500, midway));
+        let latencies = if head {
+            &mut self.counters.head_latencies_ms
+        } else {
+            &mut self.counters.block_latencies_ms
+        };
+        latencies.push(self.settings.latency_ms);
+    }
+}
+
+impl ChainAdapter for SimulatedChain {
+    fn latest_height(&mut self) -> BoxFuture<'_, Result<u64>> {
+        Box::pin(async move {
+            let now_ms = Utc::now().timestamp_millis();
+            self.round_trip(true).await;
+            Ok(self.head_at(now_ms))
+        })
+    }
+
+    fn block_at(&mut self, height: u64) -> BoxFuture<'_, Result<BlockHeader>> {
+        Box::pin(async move {
+            let now_ms = Utc::now().timestamp_millis();
+            self.round_trip(false).await;
+            self.header(height, now_ms)
+        })
+    }
+
+    fn blocks_in(&mut self, first: u64, last: u64) -> BoxFuture<'_, Result<BlockBatch>> {
+        Box::pin(async move {
+            let received = Stamp::now();
+            self.round_trip(false).await;
+            let headers = (first..=last).map(|height| self.header(height, received.wall.timestamp_millis())).collect();
+            Ok(BlockBatch { received, headers })
+        })
+    }
+
+    fn tagged_block(&mut self, tag: &'static str) -> BoxFuture<'_, Result<(u64, BlockHeader)>> {
+        Box::pin(async move {
+            let now_ms = Utc::now().timestamp_millis();
+            self.round_trip(false).await;
+            let head = self.head_at(now_ms);
+            let height = match tag {
+                "latest" => head,
+                "pending" => return Ok((head + 1, self.pending_header(head))),
+                "safe" => head.saturating_sub(SAFE_DEPTH).max(FIRST_BLOCK),
+                "finalized" => head.saturating_sub(FINALIZED_DEPTH).max(FIRST_BLOCK),
+                _ => return Err(anyhow!("No {} head on this chain", tag)),
+            };
+            Ok((height, self.header(height, now_ms)?))
+        })
+    }
+
+    fn l1_origin(&mut self, height: u64) -> BoxFuture<'_, Result<L1Origin>> {
+        Box::pin(async move {
+            let now_ms = Utc::now().timestamp_millis();
+            self.round_trip(false).await;
+            if height > self.head_at(now_ms) {
+                return Err(StaleResponse(height).into());
+            }
+            Ok(self.l1_origin_of(height))
+        })
+    }
+
+    // The simulated node is always synced
+    fn node_health(&mut self) -> BoxFuture<'_, Result<NodeHealth>> {
+        Box::pin(async move {
+            self.round_trip(false).await;
+            Ok(NodeHealth {
+                syncing: false,
+                peer_count: Some(SIMULATED_PEERS),
+                ..NodeHealth::default()
+            })
+        })
+    }
+
+    fn take_counters(&mut self) -> AdapterCounters {
+        std::mem::take(&mut self.counters)
+    }
+}
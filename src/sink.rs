@@ -0,0 +1,292 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use futures::future::{self, BoxFuture};
+use log::{debug, error, info};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+use tokio::time::{self, Interval};
+
+use crate::monitor::BlockObservation;
+use crate::observations;
+use crate::timezone::ReportTimezone;
+
+/// Text reports appended by the file sink
+pub const REPORT_LOG_FILE: &str = "report.log";
+
+/// Where the periodic and final reports go
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportSink {
+    /// Log lines, alongside the rest of the program's logging
+    Log,
+    /// Appended to `report.log` in the output directory
+    File,
+    /// The JSON summary, POSTed to the report webhook
+    Webhook,
+}
+
+/// The configured report sinks; every report goes to all of them
+#[derive(Debug, Clone)]
+pub struct ReportSinks {
+    log: bool,
+    file: Option<PathBuf>,
+    webhook: Option<(reqwest::Client, String)>,
+    timezone: ReportTimezone,
+}
+
+impl ReportSinks {
+    pub fn new(sinks: &[ReportSink], dir: &Path, webhook_url: Option<String>, timezone: ReportTimezone) -> Result<Self> {
+        let webhook = if sinks.contains(&ReportSink::Webhook) {
+            let url = webhook_url.ok_or_else(|| anyhow!("The webhook report sink needs --report-webhook-url"))?;
+            let client = reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default();
+            Some((client, url))
+        } else {
+            None
+        };
+        Ok(Self {
+            log: sinks.contains(&ReportSink::Log),
+            file: sinks.contains(&ReportSink::File).then(|| dir.join(REPORT_LOG_FILE)),
+            webhook,
+            timezone,
+        })
+    }
+
+    /// The same sinks minus the log, for while the dashboard shows the stats instead
+    pub fn without_log(&self) -> Self {
+        Self { log: false, ..self.clone() }
+    }
+
+    /// Send one report: `lines` to the log and file sinks, `summary` to the webhook.
+    /// Failures are logged, never propagated.
+    pub async fn publish(&self, title: &str, lines: &[String], summary: &serde_json::Value, now: DateTime<Utc>) {
+        if self.log {
+            info!("{}:", title);
+            for line in lines {
+                info!("{}", line);
+            }
+        }
+        if let Some(path) = &self.file {
+            if let Err(e) = self.append(path, title, lines, now) {
+                error!("Failed to write report to {}: {}", path.display(), e);
+            }
+        }
+        if let Some((client, url)) = &self.webhook {
+            if let Err(e) = post(client, url, summary).await {
+                error!("Failed to send report to webhook: {}", e);
+            }
+        }
+    }
+
+    fn append(&self, path: &Path, title: &str, lines: &[String], now: DateTime<Utc>) -> Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "[{}] {}:", self.timezone.format(now, "%Y-%m-%d %H:%M:%S %Z"), title)?;
+        for line in lines {
+            writeln!(file, "{}", line)?;
+        }
+        writeln!(file)?;
+        Ok(())
+    }
+}
+
+async fn post(client: &reqwest::Client, url: &str, summary: &serde_json::Value) -> Result<()> {
+    let response = client.post(url).json(summary).send().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!("Report webhook returned {}", response.status()));
+    }
+    Ok(())
+}
+
+/// Observations buffered by the webhook sink before a POST is sent without waiting for the
+/// flush interval
+const MAX_WEBHOOK_BATCH: usize = 500;
+
+/// Somewhere every block observation is written as it is recorded. Implementing this is all
+/// it takes to send observations somewhere new: `ObservationSinks` gives each sink its own
+/// task, so a sink that is slow, failing or panicking holds up none of the others.
+pub trait ObservationSink: Send {
+    /// What the sink writes to, for the log, e.g. `InfluxDB`
+    fn name(&self) -> &str;
+
+    /// Write one observation, or buffer it until the next flush
+    fn write<'a>(&'a mut self, observation: &'a BlockObservation) -> BoxFuture<'a, Result<()>>;
+
+    /// Write out whatever is buffered. Called every `flush_interval` and once more when the
+    /// monitors have stopped; sinks that write each observation as it comes keep the default.
+    fn flush(&mut self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(future::ready(Ok(())))
+    }
+
+    /// Longest an observation may wait in the sink's buffer; `None` for sinks without one
+    fn flush_interval(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Optional observation sinks selected with `--observation-sinks`; the others are enabled
+/// by their own settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ObservationSinkKind {
+    /// One JSON object per line in `observations_{date}.ndjson`
+    Ndjson,
+    /// One row per block in `observations_{date}.csv`
+    Csv,
+    /// Batches of JSON objects, POSTed to the observation webhook
+    Webhook,
+}
+
+/// Every observation sink of the run. Each observation goes to all of them, and a sink that
+/// fails is logged once until it works again, while the others carry on.
+#[derive(Default)]
+pub struct ObservationSinks {
+    sinks: Vec<Box<dyn ObservationSink>>,
+}
+
+impl ObservationSinks {
+    pub fn push(&mut self, sink: Box<dyn ObservationSink>) {
+        self.sinks.push(sink);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    /// Write every observation to every sink until the monitors have stopped, then flush
+    /// them all. A sink whose task ends early (i.e. panicked) is dropped and the rest keep
+    /// receiving observations.
+    pub async fn run(self, mut receiver: UnboundedReceiver<BlockObservation>) {
+        let mut feeds: Vec<(String, UnboundedSender<BlockObservation>, JoinHandle<()>)> = self
+            .sinks
+            .into_iter()
+            .map(|sink| {
+                let name = sink.name().to_string();
+                let (sender, receiver) = mpsc::unbounded_channel();
+                (name, sender, tokio::spawn(drive(sink, receiver)))
+            })
+            .collect();
+        while let Some(observation) = receiver.recv().await {
+            feeds.retain(|(name, sender, _)| {
+                let sent = sender.send(observation.clone()).is_ok();
+                if !sent {
+                    error!("Observation sink {} stopped; continuing without it", name);
+                }
+                sent
+            });
+        }
+        for (name, sender, task) in feeds {
+            drop(sender);
+            if let Err(e) = task.await {
+                error!("Observation sink {} stopped unexpectedly: {}", name, e);
+            }
+        }
+    }
+}
+
+// Feed one sink until its observations run out, flushing it on its interval and at the end
+async fn drive(mut sink: Box<dyn ObservationSink>, mut receiver: UnboundedReceiver<BlockObservation>) {
+    let mut flush_ticker = sink.flush_interval().map(time::interval);
+    let mut failures = 0;
+    loop {
+        let result = tokio::select! {
+            observation = receiver.recv() => match observation {
+                Some(observation) => sink.write(&observation).await,
+                None => break,
+            },
+            _ = tick(&mut flush_ticker) => sink.flush().await,
+        };
+        settle(sink.as_ref(), result, &mut failures);
+    }
+    let result = sink.flush().await;
+    settle(sink.as_ref(), result, &mut failures);
+}
+
+async fn tick(ticker: &mut Option<Interval>) {
+    match ticker {
+        Some(ticker) => {
+            ticker.tick().await;
+        }
+        None => future::pending().await,
+    }
+}
+
+// Log the first failure in a row and the recovery after it, rather than every failure
+fn settle(sink: &dyn ObservationSink, result: Result<()>, failures: &mut usize) {
+    match result {
+        Ok(()) if *failures > 0 => {
+            info!("Writing observations to {} again after {} failed attempts", sink.name(), failures);
+            *failures = 0;
+        }
+        Ok(()) => {}
+        Err(e) if *failures == 0 => {
+            *failures = 1;
+            error!("Failed to write observations to {}: {:#}", sink.name(), e);
+        }
+        Err(e) => {
+            *failures += 1;
+            debug!("Failed to write observations to {} again: {:#}", sink.name(), e);
+        }
+    }
+}
+
+/// POSTs every observation, as the same JSON object as its NDJSON line, to a webhook in
+/// batches: a JSON array of up to a second's observations
+pub struct ObservationWebhook {
+    client: reqwest::Client,
+    url: String,
+    buffer: Vec<serde_json::Value>,
+}
+
+impl ObservationWebhook {
+    pub fn new(url: String) -> Self {
+        info!("Sending observations to the observation webhook");
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+            url,
+            buffer: Vec::new(),
+        }
+    }
+
+    // Send the buffered observations; on failure they are dropped rather than held indefinitely
+    async fn send(&mut self) -> Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let batch = serde_json::Value::Array(std::mem::take(&mut self.buffer));
+        let response = self.client.post(&self.url).json(&batch).send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("Observation webhook returned {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+impl ObservationSink for ObservationWebhook {
+    fn name(&self) -> &str {
+        "the observation webhook"
+    }
+
+    fn write<'a>(&'a mut self, observation: &'a BlockObservation) -> BoxFuture<'a, Result<()>> {
+        self.buffer.push(observations::record(observation));
+        if self.buffer.len() < MAX_WEBHOOK_BATCH {
+            return Box::pin(future::ready(Ok(())));
+        }
+        Box::pin(self.send())
+    }
+
+    fn flush(&mut self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(self.send())
+    }
+
+    fn flush_interval(&self) -> Option<Duration> {
+        Some(Duration::from_secs(1))
+    }
+}
@@ -0,0 +1,166 @@
+use anyhow::Result;
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+
+use crate::stats::ChainStats;
+
+/// One row per pair of chains per minute
+pub const SKEW_FILE: &str = "skew.csv";
+
+/// Running skew between two chains' heads
+#[derive(Debug, Clone, Copy)]
+struct PairSkew {
+    last_ms: i64,
+    min_ms: i64,
+    max_ms: i64,
+    sum_ms: i64,
+    samples: u64,
+}
+
+impl PairSkew {
+    fn new(skew_ms: i64) -> Self {
+        Self {
+            last_ms: skew_ms,
+            min_ms: skew_ms,
+            max_ms: skew_ms,
+            sum_ms: skew_ms,
+            samples: 1,
+        }
+    }
+
+    fn record(&mut self, skew_ms: i64) {
+        self.last_ms = skew_ms;
+        self.min_ms = self.min_ms.min(skew_ms);
+        self.max_ms = self.max_ms.max(skew_ms);
+        self.sum_ms += skew_ms;
+        self.samples += 1;
+    }
+
+    fn avg_ms(&self) -> i64 {
+        self.sum_ms / self.samples as i64
+    }
+}
+
+/// Relative skew between the monitored chains' head timestamps, sampled once per wall-clock
+/// minute.
+///
+/// Each head's timestamp is carried forward to the sampling instant by the time since it
+/// was received, so the skew between two chains is the difference of their latest deltas
+/// and doesn't depend on when each head happened to arrive. A positive skew for a pair
+/// (a, b) means a's head timestamp is ahead of b's.
+#[derive(Debug)]
+pub struct CrossChainSkew {
+    path: PathBuf,
+    pairs: BTreeMap<(String, String), PairSkew>,
+}
+
+impl CrossChainSkew {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            path: dir.join(SKEW_FILE),
+            pairs: BTreeMap::new(),
+        }
+    }
+
+    /// When the first sample is due: the start of the next wall-clock minute
+    pub fn first_sample_at(now: DateTime<Utc>) -> DateTime<Utc> {
+        now.duration_trunc(TimeDelta::minutes(1)).unwrap_or(now) + TimeDelta::minutes(1)
+    }
+
+    /// Compare the latest heads of every pair of chains that has recorded a block, appending
+    /// the minute's rows to `skew.csv`
+    pub fn sample(&mut self, chain_stats: &HashMap<String, ChainStats>, now: DateTime<Utc>) -> Result<()> {
+        let mut heads: Vec<(&str, i64)> = chain_stats
+            .values()
+            .filter_map(|stats| Some((stats.chain_name.as_str(), stats.previous.as_ref()?.delta_ms)))
+            .collect();
+        if heads.len() < 2 {
+            return Ok(());
+        }
+        heads.sort();
+
+        let new_file = !self.path.exists();
+        let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        let mut wtr = csv::Writer::from_writer(file);
+        if new_file {
+            wtr.write_record(["Minute", "Chain", "Reference", "Skew (ms)"])?;
+        }
+        let minute = now.duration_trunc(TimeDelta::minutes(1)).unwrap_or(now);
+        for (i, (chain, delta_ms)) in heads.iter().enumerate() {
+            for (reference, reference_delta_ms) in &heads[i + 1..] {
+                // A smaller delta means the head is stamped later relative to when we saw it
+                let skew_ms = reference_delta_ms - delta_ms;
+                self.pairs
+                    .entry((chain.to_string(), reference.to_string()))
+                    .and_modify(|pair| pair.record(skew_ms))
+                    .or_insert_with(|| PairSkew::new(skew_ms));
+                wtr.write_record([
+                    minute.to_rfc3339(),
+                    chain.to_string(),
+                    reference.to_string(),
+                    skew_ms.to_string(),
+                ])?;
+            }
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+
+    /// The latest skew of each pair of chains in `chain_stats`, e.g. "Unichain head timestamp
+    /// is 1.4s ahead of Base"; pairs with a chain a daemon stopped monitoring are left out
+    pub fn current_report(&self, chain_stats: &HashMap<String, ChainStats>) -> Vec<String> {
+        self.pairs
+            .iter()
+            .filter(|((chain, reference), _)| chain_stats.contains_key(chain) && chain_stats.contains_key(reference))
+            .map(|((chain, reference), pair)| format!("Skew: {}", describe(chain, reference, pair.last_ms)))
+            .collect()
+    }
+
+    /// Each pair's latest, average and extreme skew over the run
+    pub fn final_report(&self) -> Vec<String> {
+        self.pairs
+            .iter()
+            .map(|((chain, reference), pair)| {
+                format!(
+                    "Skew {} vs {}: last {:+}ms | avg {:+}ms | min {:+}ms | max {:+}ms ({} minutes sampled)",
+                    chain,
+                    reference,
+                    pair.last_ms,
+                    pair.avg_ms(),
+                    pair.min_ms,
+                    pair.max_ms,
+                    pair.samples
+                )
+            })
+            .collect()
+    }
+
+    /// The pairs' skew for the JSON summary
+    pub fn summary(&self) -> serde_json::Value {
+        self.pairs
+            .iter()
+            .map(|((chain, reference), pair)| {
+                serde_json::json!({
+                    "chain": chain,
+                    "reference": reference,
+                    "last_ms": pair.last_ms,
+                    "avg_ms": pair.avg_ms(),
+                    "min_ms": pair.min_ms,
+                    "max_ms": pair.max_ms,
+                    "samples": pair.samples,
+                })
+            })
+            .collect()
+    }
+}
+
+fn describe(chain: &str, reference: &str, skew_ms: i64) -> String {
+    let secs = skew_ms.unsigned_abs() as f64 / 1000.0;
+    match skew_ms {
+        0 => format!("{} and {} head timestamps are level", chain, reference),
+        skew if skew > 0 => format!("{} head timestamp is {:.1}s ahead of {}", chain, secs, reference),
+        _ => format!("{} head timestamp is {:.1}s behind {}", chain, secs, reference),
+    }
+}
@@ -0,0 +1,110 @@
+use anyhow::{anyhow, Result};
+use clap::Args;
+use log::{error, info};
+use std::collections::HashMap;
+
+use crate::report;
+use crate::stats::ChainStats;
+
+/// Limits a run's timestamps must stay within; a run that breaks any of them exits with an
+/// error once its final report is written, so it can gate a CI job or an alert
+#[derive(Debug, Clone, Default, Args)]
+pub struct SloThresholds {
+    /// Fail the run if any block's timestamp was more than this many ms in the future
+    #[arg(long, env = "FAIL_IF_MAX_FUTURE_DELTA_MS")]
+    pub fail_if_max_future_delta_ms: Option<u64>,
+
+    /// Fail the run if any block arrived more than this many ms after its timestamp
+    #[arg(long, env = "FAIL_IF_MAX_PAST_DELTA_MS")]
+    pub fail_if_max_past_delta_ms: Option<u64>,
+
+    /// Fail the run if a chain's p99 delta exceeds this many ms
+    #[arg(long, env = "FAIL_IF_P99_DELTA_MS")]
+    pub fail_if_p99_delta_ms: Option<i64>,
+
+    /// Fail the run if more than this percentage of a chain's blocks had future timestamps
+    #[arg(long, env = "FAIL_IF_FUTURE_PCT")]
+    pub fail_if_future_pct: Option<f64>,
+
+    /// Fail the run if a chain went more than this many ms without a new block
+    #[arg(long, env = "FAIL_IF_STALL_MS")]
+    pub fail_if_stall_ms: Option<i64>,
+
+    /// Fail the run if a chain skipped more than this many blocks
+    #[arg(long, env = "FAIL_IF_MISSED_BLOCKS")]
+    pub fail_if_missed_blocks: Option<u64>,
+}
+
+impl SloThresholds {
+    fn is_empty(&self) -> bool {
+        self.fail_if_max_future_delta_ms.is_none()
+            && self.fail_if_max_past_delta_ms.is_none()
+            && self.fail_if_p99_delta_ms.is_none()
+            && self.fail_if_future_pct.is_none()
+            && self.fail_if_stall_ms.is_none()
+            && self.fail_if_missed_blocks.is_none()
+    }
+
+    /// Every threshold a chain broke, described for the log
+    fn violations(&self, stats: &ChainStats) -> Vec<String> {
+        // Nothing can be said about a chain without blocks, which is a failure in itself
+        if stats.total_blocks == 0 {
+            return vec!["no blocks were recorded".to_string()];
+        }
+        let mut violations = Vec::new();
+        if let Some(limit) = self.fail_if_max_future_delta_ms {
+            if stats.max_future_delta_ms as u64 > limit {
+                violations.push(format!("max future delta {}ms > {}ms", stats.max_future_delta_ms, limit));
+            }
+        }
+        if let Some(limit) = self.fail_if_max_past_delta_ms {
+            if stats.max_past_delta_ms as u64 > limit {
+                violations.push(format!("max past delta {}ms > {}ms", stats.max_past_delta_ms, limit));
+            }
+        }
+        if let Some(limit) = self.fail_if_p99_delta_ms {
+            let p99 = stats.delta_histogram.percentile(99.0);
+            if p99 > limit {
+                violations.push(format!("p99 delta {}ms > {}ms", p99, limit));
+            }
+        }
+        if let Some(limit) = self.fail_if_future_pct {
+            let future_pct = stats.timestamp_future_blocks as f64 * 100.0 / stats.total_blocks as f64;
+            if future_pct > limit {
+                violations.push(format!("{:.2}% future timestamps > {}%", future_pct, limit));
+            }
+        }
+        if let Some(limit) = self.fail_if_stall_ms {
+            if let Some(stall) = stats.arrival_gap_summary().map(|gaps| gaps.max_ms).filter(|stall| *stall > limit) {
+                violations.push(format!("{}ms without a block > {}ms", stall, limit));
+            }
+        }
+        if let Some(limit) = self.fail_if_missed_blocks {
+            if stats.missed_blocks > limit {
+                violations.push(format!("{} missed blocks > {}", stats.missed_blocks, limit));
+            }
+        }
+        violations
+    }
+
+    /// Check every chain's final stats, logging each broken threshold and failing if any was
+    pub fn check(&self, chain_stats: &HashMap<String, ChainStats>) -> Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        let mut failed = Vec::new();
+        for stats in report::sorted(chain_stats) {
+            let violations = self.violations(stats);
+            if violations.is_empty() {
+                continue;
+            }
+            error!("{} failed its SLO: {}", stats.chain_name, violations.join(", "));
+            failed.push(stats.chain_name.as_str());
+        }
+        if failed.is_empty() {
+            info!("Every chain met its SLO");
+            return Ok(());
+        }
+        Err(anyhow!("SLO violated by {}", failed.join(", ")))
+    }
+}
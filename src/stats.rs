@@ -0,0 +1,1548 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::mem::take;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::adapter::{BlockHeader, L1Origin, NodeHealth};
+use crate::anomaly::DeltaAnomalies;
+use crate::clock::Stamp;
+use crate::columnar::{self, OutputFormat};
+use crate::compare::ProviderDivergence;
+use crate::consistency::HeadConsistency;
+use crate::delta::{DeltaDirection, TimestampDelta};
+use crate::flashblocks::{PreconfSample, PreconfStats};
+use crate::histogram::{BucketCount, DeltaHistogram, HistogramBuckets};
+use crate::hourly::HourlyDeltas;
+use crate::outage::Outages;
+use crate::schedule::ScheduleAdherence;
+
+/// Number of polls averaged on each side of the backlog trend comparison
+const BACKLOG_TREND_WINDOW: usize = 20;
+
+/// Per-block records kept for the detailed CSV unless `--max-raw-samples` says otherwise
+pub const DEFAULT_MAX_RAW_SAMPLES: usize = 100_000;
+
+/// Delta percentiles included in reports
+pub const DELTA_PERCENTILES: [f64; 4] = [50.0, 90.0, 99.0, 99.9];
+
+/// Trailing windows reported alongside the run-lifetime stats: label and length in seconds
+pub const STATS_WINDOWS: [(&str, i64); 2] = [("5m", 5 * 60), ("1h", 60 * 60)];
+
+/// Column headers of the per-chain stats summary, matching `ChainStats::summary_record`
+pub const SUMMARY_HEADERS: &[&str] = &[
+    "Chain",
+    "Total Blocks",
+    "Past Timestamp Blocks",
+    "Future Timestamp Blocks",
+    "Exact Timestamp Blocks",
+    "Max Past Delta (ms)",
+    "Max Future Delta (ms)",
+    "Avg Delta (ms)",
+    "Timestamp Granularity (ms)",
+    "Future Within Granularity",
+    "Leap Tagged Blocks",
+    "Schema Deviations",
+    "Monitor Restarts",
+    "Wedge Events",
+    "Backlog Depth",
+    "Max Backlog Depth",
+    "Backlog Trend",
+    "Observation Jitter (ms)",
+    "Stale Responses",
+    "Breaker Trips",
+    "Expected Block Time (ms)",
+    "Avg Block Interval (ms)",
+    "P50 Delta (ms)",
+    "P90 Delta (ms)",
+    "P99 Delta (ms)",
+    "P99.9 Delta (ms)",
+    "Delta Stddev (ms)",
+    "RPC Retries",
+    "Reorgs",
+    "Reorged Blocks",
+    "Max Reorg Depth",
+    "Gaps",
+    "Missed Blocks",
+    "Min Block Gap (ms)",
+    "Max Block Gap (ms)",
+    "Avg Block Gap (ms)",
+    "P50 Block Gap (ms)",
+    "P90 Block Gap (ms)",
+    "P99 Block Gap (ms)",
+    "Same Timestamp Blocks",
+    "Non-Monotonic Blocks",
+    "Max Timestamp Regression (ms)",
+    "Min Arrival Gap (ms)",
+    "Max Arrival Gap (ms)",
+    "Avg Arrival Gap (ms)",
+    "P50 Arrival Gap (ms)",
+    "P90 Arrival Gap (ms)",
+    "P99 Arrival Gap (ms)",
+    "Min Head RPC Latency (ms)",
+    "Max Head RPC Latency (ms)",
+    "Avg Head RPC Latency (ms)",
+    "P50 Head RPC Latency (ms)",
+    "P90 Head RPC Latency (ms)",
+    "P99 Head RPC Latency (ms)",
+    "Min Block RPC Latency (ms)",
+    "Max Block RPC Latency (ms)",
+    "Avg Block RPC Latency (ms)",
+    "P50 Block RPC Latency (ms)",
+    "P90 Block RPC Latency (ms)",
+    "P99 Block RPC Latency (ms)",
+    "Avg Timestamp Offset (ms)",
+    "Avg Fetch Round Trip (ms)",
+    "Avg Response Delta (ms)",
+    "Safe Lag (blocks)",
+    "Max Safe Lag (blocks)",
+    "Safe Lag (ms)",
+    "Max Safe Lag (ms)",
+    "P99 Safe Lag (ms)",
+    "Finalized Lag (blocks)",
+    "Max Finalized Lag (blocks)",
+    "Finalized Lag (ms)",
+    "Max Finalized Lag (ms)",
+    "P99 Finalized Lag (ms)",
+    "Min L1 Origin Drift (ms)",
+    "Max L1 Origin Drift (ms)",
+    "Avg L1 Origin Drift (ms)",
+    "P50 L1 Origin Drift (ms)",
+    "P90 L1 Origin Drift (ms)",
+    "P99 L1 Origin Drift (ms)",
+    "Clock Offset (ms)",
+    "Max Clock Offset (ms)",
+    "Min Provider Clock Skew (ms)",
+    "Max Provider Clock Skew (ms)",
+    "Avg Provider Clock Skew (ms)",
+    "P50 Provider Clock Skew (ms)",
+    "P90 Provider Clock Skew (ms)",
+    "P99 Provider Clock Skew (ms)",
+    "Node Syncing",
+    "Syncing Blocks",
+    "Max Sync Lag (blocks)",
+    "Peer Count",
+    "Min Peer Count",
+    "Provider Checks",
+    "Provider Disagreements",
+    "Max Provider Head Diff (blocks)",
+    "Max Provider Timestamp Diff (ms)",
+    "Preconf Blocks",
+    "Preconf Missed Blocks",
+    "Flashblocks",
+    "Late Flashblocks",
+    "Preconf Timestamp Mismatches",
+    "Preconf Hash Mismatches",
+    "Min Preconf Delta (ms)",
+    "Max Preconf Delta (ms)",
+    "Avg Preconf Delta (ms)",
+    "P50 Preconf Delta (ms)",
+    "P90 Preconf Delta (ms)",
+    "P99 Preconf Delta (ms)",
+    "Min Preconf Lead (ms)",
+    "Max Preconf Lead (ms)",
+    "Avg Preconf Lead (ms)",
+    "P50 Preconf Lead (ms)",
+    "P90 Preconf Lead (ms)",
+    "P99 Preconf Lead (ms)",
+    "Schedule Tolerance (ms)",
+    "On Schedule Blocks",
+    "Early Blocks",
+    "Late Blocks",
+    "On Schedule (%)",
+    "Avg Schedule Deviation (ms)",
+    "Longest Stall (ms)",
+    "Longest Stall Block",
+    "Fastest Burst (blocks)",
+    "Anomalies",
+    "Anomalies Above Baseline",
+    "Anomalies Below Baseline",
+    "Max Anomaly Z-Score",
+    "Outages",
+    "Outage Time (ms)",
+    "Longest Outage (ms)",
+    "Blocks (5m)",
+    "Avg Delta (5m, ms)",
+    "P99 Delta (5m, ms)",
+    "Future Blocks (5m)",
+    "Blocks (1h)",
+    "Avg Delta (1h, ms)",
+    "P99 Delta (1h, ms)",
+    "Future Blocks (1h)",
+    "Pushed Heads",
+    "Polled Heads",
+    "Pushed Only Heads",
+    "Polled Only Heads",
+    "Min Head Poll Lag (ms)",
+    "Max Head Poll Lag (ms)",
+    "Avg Head Poll Lag (ms)",
+    "P50 Head Poll Lag (ms)",
+    "P90 Head Poll Lag (ms)",
+    "P99 Head Poll Lag (ms)",
+];
+
+/// Columns of the per-block `{Chain}_detailed.csv`, matching `RawSample::detailed_record`
+pub const DETAILED_HEADERS: &[&str] = &[
+    "Block Number",
+    "Block Timestamp (s)",
+    "Receipt Time (ms)",
+    "Delta (ms)",
+    "Leap Second Window",
+    "Block Timestamp (ms)",
+    "Response Time (ms)",
+    "RPC Round Trip (ms)",
+    "L1 Origin Block",
+    "L1 Origin Drift (ms)",
+    "Delta Direction",
+    "Delta Magnitude (ms)",
+    "Block Hash",
+    "Parent Hash",
+    "Gas Used",
+    "Tx Count",
+    "Node Syncing",
+];
+
+/// Column of `{Chain}_deltas.csv`
+pub const DELTAS_HEADERS: &[&str] = &["Delta (ms)"];
+
+/// Columns of `{Chain}_head_lag.csv`
+pub const HEAD_LAG_HEADERS: &[&str] = &[
+    "Sampled At (ms)",
+    "Head Block",
+    "Safe Block",
+    "Finalized Block",
+    "Safe Lag (ms)",
+    "Finalized Lag (ms)",
+];
+
+/// Columns of `{Chain}_preconf.csv`
+pub const PRECONF_HEADERS: &[&str] = &[
+    "Block Number",
+    "Flashblocks",
+    "Block Timestamp (ms)",
+    "First Flashblock (ms)",
+    "Receipt Time (ms)",
+    "Preconf Delta (ms)",
+    "Preconf Lead (ms)",
+    "Timestamp Match",
+    "Hash Match",
+];
+
+/// Columns of `{Chain}_gaps.csv`
+pub const GAPS_HEADERS: &[&str] = &["Detected At (ms)", "First Block", "Last Block", "Blocks", "Reason"];
+
+/// Columns of `{Chain}_outages.csv`
+pub const OUTAGES_HEADERS: &[&str] = &["Started At (ms)", "Ended At (ms)", "Duration (ms)", "Last Block", "Next Block"];
+
+/// Columns of `{Chain}_histogram.csv`
+pub const HISTOGRAM_HEADERS: &[&str] = &["Lower Bound (ms)", "Upper Bound (ms)", "Blocks", "Share (%)"];
+
+/// Columns of `{Chain}_hourly.csv`
+pub const HOURLY_HEADERS: &[&str] = &[
+    "Hour (UTC)",
+    "Blocks",
+    "Future Blocks",
+    "Avg Delta (ms)",
+    "StdDev Delta (ms)",
+    "Min Delta (ms)",
+    "P50 Delta (ms)",
+    "P90 Delta (ms)",
+    "P99 Delta (ms)",
+    "Max Delta (ms)",
+];
+
+/// Statistical data collected for each chain
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ChainStats {
+    pub chain_name: String,
+    pub total_blocks: usize,
+    pub timestamp_future_blocks: usize,
+    pub timestamp_past_blocks: usize,
+    pub timestamp_exact_blocks: usize,   // Blocks received at exactly their timestamp
+    pub max_future_delta_ms: i64,
+    pub max_past_delta_ms: i64,
+    pub avg_time_delta_ms: i64,
+    pub last_delta_ms: i64,               // Delta of the most recently recorded block
+    pub delta_histogram: DeltaHistogram,  // Distribution of every delta, in bounded memory
+    #[serde(skip)]
+    pub histogram_buckets: HistogramBuckets, // Buckets for the delta chart and histogram CSV; not checkpointed
+    pub raw_samples: VecDeque<RawSample>, // Most recent per-block records, for the detailed CSV
+    pub max_raw_samples: usize,           // Cap on `raw_samples`; older records are dropped
+    pub rpc_errors: usize,                // Failed RPC calls (polling or block fetches)
+    pub rpc_retries: usize,               // Transient RPC failures that were retried
+    pub leap_tagged_blocks: usize,        // Records excluded from the delta stats as leap-affected
+    pub schema_deviations: usize,         // Strict mode: responses deviating from the expected schema
+    pub monitor_restarts: usize,          // Times the supervisor restarted this chain's monitor
+    pub wedge_events: usize,              // Times the monitor hung and its client was rebuilt
+    pub backlog_depth: u64,               // Blocks behind the observed head at the latest poll
+    pub max_backlog_depth: u64,           // Largest backlog seen during the run
+    pub backlog_history: VecDeque<u64>,   // Recent backlog samples, for the trend
+    pub stale_responses: usize,           // Heads that went backwards or blocks a lagging backend lacked
+    pub breaker_trips: usize,             // Times the endpoint's circuit breaker opened
+    pub reorgs: usize,                    // Reorgs detected through parent-hash mismatches
+    pub reorged_blocks: usize,            // Recorded blocks that a reorg later replaced
+    pub max_reorg_depth: usize,           // Most recorded blocks replaced by a single reorg
+    pub gaps: usize,                      // Ranges of blocks that were skipped rather than recorded
+    pub missed_blocks: u64,               // Blocks in those ranges
+    pub gap_events: VecDeque<GapEvent>,   // Most recent gaps, for the gaps CSV
+    pub degraded: bool,                   // Whether the breaker is currently open
+    pub paused: bool,                     // Whether polling is paused through the stats API
+    pub expected_block_time_ms: Option<u64>, // Configured block time, for comparison with the observed one
+    #[serde(skip)]
+    pub windows: Vec<RollingWindow>,      // Recent deltas for each of `STATS_WINDOWS`; not checkpointed
+    pub block_gaps: DeltaHistogram,       // Timestamp gaps between consecutive blocks
+    pub arrival_gaps: DeltaHistogram,     // Receipt-time gaps between consecutive blocks
+    pub same_timestamp_blocks: usize,     // Blocks stamped with the same timestamp as their parent
+    pub non_monotonic_blocks: usize,      // Blocks stamped at or before their parent's timestamp
+    pub max_timestamp_regression_ms: i64, // Furthest a block's timestamp went back from its parent's
+    pub head_rpc_latency: DeltaHistogram, // Round-trip times of head height requests
+    pub block_rpc_latency: DeltaHistogram, // Round-trip times of block requests
+    pub fetch_round_trips: DeltaHistogram, // Request-to-response time of each fetched block
+    pub response_deltas: DeltaHistogram,  // Deltas of fetched blocks measured at the response
+    pub safe_lag: HeadLag,                // How far the safe head trails the latest block
+    pub finalized_lag: HeadLag,           // How far the finalized head trails the latest block
+    pub l1_origin_drift: DeltaHistogram,  // Block timestamp minus its L1 origin's, when tracked
+    pub head_lag_history: VecDeque<HeadLagSample>, // Most recent head lag samples, for the head lag CSV
+    pub clock_offset_ms: Option<i64>,     // Local clock offset from NTP time at the latest block
+    pub max_clock_offset_ms: i64,         // Largest offset magnitude seen while recording blocks
+    pub provider_clock_skew: DeltaHistogram, // Provider's clock (HTTP `Date` header) minus ours, per response
+    pub node_syncing: Option<bool>,       // Whether the node reported syncing at its latest health check
+    pub syncing_blocks: usize,            // Blocks recorded while the node reported syncing
+    pub max_sync_lag_blocks: u64,         // Furthest a syncing node was behind the highest block it knew of
+    pub peer_count: Option<u64>,          // Node's peer count at its latest health check, when it says
+    pub min_peer_count: Option<u64>,      // Fewest peers the node reported
+    pub provider_divergence: ProviderDivergence, // Agreement with the comparison provider, if any
+    pub preconf: PreconfStats,            // Flashblocks compared with the canonical blocks, if there is a feed
+    pub preconf_history: VecDeque<PreconfSample>, // Most recent comparisons, for the preconf CSV
+    pub schedule: ScheduleAdherence,      // Arrivals scored against the expected block time, if any
+    pub anomalies: DeltaAnomalies,        // Deltas far from the chain's recent baseline
+    pub outages: Outages,                 // Stretches without new blocks, if the chain has an expected block time
+    pub hourly: HourlyDeltas,             // Deltas by the UTC hour of day their blocks were received in
+    pub head_consistency: HeadConsistency, // `newHeads` checked against polling, if audited
+    pub(crate) first_block: Option<(u64, u64)>, // Number and timestamp (ms) of the first block
+    pub(crate) previous: Option<RawSample>,     // Latest block, for gaps to the next one
+    pub(crate) granularity_ms: u64,             // Running gcd of the gaps between timestamps
+    pub(crate) jitter: JitterSums,
+    pub last_block_number: Option<u64>,   // Last block the monitor processed, where a resumed run continues
+}
+
+// `kept` followed by `new`, without its oldest entries beyond `limit`
+fn appended<T>(mut kept: VecDeque<T>, new: VecDeque<T>, limit: usize) -> VecDeque<T> {
+    kept.extend(new);
+    let excess = kept.len().saturating_sub(limit);
+    kept.drain(..excess);
+    kept
+}
+
+// `kept` with the deltas of `new` added
+fn added(mut kept: DeltaHistogram, new: &DeltaHistogram) -> DeltaHistogram {
+    kept.add(new);
+    kept
+}
+
+/// One block as recorded, kept in the detailed CSV
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawSample {
+    pub block_number: u64,
+    pub block_timestamp_ms: u64,
+    pub receipt_time_ms: i64,
+    /// Monotonic clock reading at receipt, which arrival gaps are measured with; not kept
+    /// across restarts, since readings from another process can't be compared
+    #[serde(skip)]
+    pub received_instant: Option<Instant>,
+    pub delta_ms: i64,              // Receipt time minus block timestamp; see `timestamp_delta`
+    pub leap_affected: bool,
+    pub round_trip_ms: Option<i64>, // Request-to-response time; `None` for pushed heads
+    pub l1_origin_block: Option<u64>, // L1 block this block was derived from, when tracked
+    pub l1_drift_ms: Option<i64>,   // Block timestamp minus the L1 origin's timestamp
+    pub hash: Option<String>,
+    pub parent_hash: Option<String>,
+    pub gas_used: Option<u64>,
+    pub tx_count: Option<u64>,      // `None` for pushed heads, which don't list transactions
+    pub node_syncing: Option<bool>, // Whether the node reported syncing; `None` without health checks
+}
+
+impl RawSample {
+    /// Milliseconds between `previous`'s receipt and this one's, by the monotonic clock when
+    /// both readings are from this run, so a clock step between them doesn't bend the gap
+    pub fn arrival_gap_ms(&self, previous: &RawSample) -> i64 {
+        let stamp = |sample: &RawSample| Stamp {
+            wall: DateTime::from_timestamp_millis(sample.receipt_time_ms).unwrap_or_default(),
+            monotonic: sample.received_instant,
+        };
+        stamp(self).ms_since(&stamp(previous))
+    }
+
+    /// When the block's response arrived, if it was fetched
+    pub fn response_time_ms(&self) -> Option<i64> {
+        self.round_trip_ms.map(|ms| self.receipt_time_ms + ms)
+    }
+
+    /// The sample's delta as a direction and magnitude
+    pub fn timestamp_delta(&self) -> TimestampDelta {
+        TimestampDelta::from_ms(self.delta_ms)
+    }
+
+    /// The sample's row in the detailed CSV, in `DETAILED_HEADERS` order
+    pub fn detailed_record(&self) -> [String; 17] {
+        let delta = self.timestamp_delta();
+        [
+            self.block_number.to_string(),
+            (self.block_timestamp_ms / 1000).to_string(),
+            self.receipt_time_ms.to_string(),
+            self.delta_ms.to_string(),
+            self.leap_affected.to_string(),
+            self.block_timestamp_ms.to_string(),
+            self.response_time_ms().map(|ms| ms.to_string()).unwrap_or_default(),
+            self.round_trip_ms.map(|ms| ms.to_string()).unwrap_or_default(),
+            self.l1_origin_block.map(|block| block.to_string()).unwrap_or_default(),
+            self.l1_drift_ms.map(|ms| ms.to_string()).unwrap_or_default(),
+            delta.direction.to_string(),
+            delta.magnitude_ms.to_string(),
+            self.hash.clone().unwrap_or_default(),
+            self.parent_hash.clone().unwrap_or_default(),
+            self.gas_used.map(|gas| gas.to_string()).unwrap_or_default(),
+            self.tx_count.map(|count| count.to_string()).unwrap_or_default(),
+            self.node_syncing.map(|syncing| syncing.to_string()).unwrap_or_default(),
+        ]
+    }
+}
+
+/// Running sums of the observation jitter samples, see `ChainStats::observation_jitter_ms`
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub(crate) struct JitterSums {
+    count: u64,
+    sum: f64,
+    sum_of_squares: f64,
+    sum_abs: f64,
+}
+
+/// Lag of the safe or finalized head behind the latest recorded block, in blocks and in
+/// block-timestamp time
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct HeadLag {
+    pub blocks: u64,
+    pub ms: i64,
+    pub max_blocks: u64,
+    pub max_ms: i64,
+    pub history_ms: DeltaHistogram, // Every sampled lag, for percentiles
+}
+
+impl HeadLag {
+    fn record(&mut self, blocks: u64, ms: i64) {
+        self.blocks = blocks;
+        self.ms = ms;
+        self.max_blocks = self.max_blocks.max(blocks);
+        self.max_ms = self.max_ms.max(ms);
+        self.history_ms.record(ms);
+    }
+
+    // This lag with only the samples recorded since the previous call, which are moved out
+    fn take_update(&mut self) -> Self {
+        Self {
+            blocks: self.blocks,
+            ms: self.ms,
+            max_blocks: self.max_blocks,
+            max_ms: self.max_ms,
+            history_ms: take(&mut self.history_ms),
+        }
+    }
+
+    /// The lag's columns in `SUMMARY_HEADERS` order, empty until the head was sampled
+    fn record_columns(&self) -> Vec<String> {
+        if self.history_ms.is_empty() {
+            return vec![String::new(); 5];
+        }
+        vec![
+            self.blocks.to_string(),
+            self.max_blocks.to_string(),
+            self.ms.to_string(),
+            self.max_ms.to_string(),
+            self.history_ms.percentile(99.0).to_string(),
+        ]
+    }
+}
+
+/// One sample of the latest, safe and finalized heads
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct HeadLagSample {
+    pub sampled_at_ms: i64,
+    pub head: u64,
+    pub safe: u64,
+    pub finalized: u64,
+    pub safe_lag_ms: i64,
+    pub finalized_lag_ms: i64,
+}
+
+/// Why a range of blocks was skipped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GapReason {
+    /// The head advanced further than `MAX_CATCH_UP_BLOCKS` between two checks
+    HeadJump,
+    /// The block couldn't be fetched or parsed, even after retries
+    FetchFailed,
+    /// Produced while the monitor was stopped or restarting
+    Restart,
+    /// Produced while polling was paused through the stats API
+    Paused,
+}
+
+impl GapReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GapReason::HeadJump => "head_jump",
+            GapReason::FetchFailed => "fetch_failed",
+            GapReason::Restart => "restart",
+            GapReason::Paused => "paused",
+        }
+    }
+}
+
+/// A range of blocks that was skipped rather than recorded
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct GapEvent {
+    pub detected_at_ms: i64,
+    pub first_block: u64,
+    pub last_block: u64,
+    pub reason: GapReason,
+}
+
+impl GapEvent {
+    pub fn blocks(&self) -> u64 {
+        self.last_block - self.first_block + 1
+    }
+}
+
+/// Distribution of the gaps between consecutive blocks, of RPC round-trip times, of L1
+/// origin drift, or of preconfirmation timings, in milliseconds
+#[derive(Debug, Clone, Copy)]
+pub struct GapSummary {
+    pub min_ms: i64,
+    pub max_ms: i64,
+    pub avg_ms: i64,
+    pub p50_ms: i64,
+    pub p90_ms: i64,
+    pub p99_ms: i64,
+}
+
+impl GapSummary {
+    /// Summary of the values in `gaps`, or `None` while it is empty
+    pub(crate) fn of(gaps: &DeltaHistogram) -> Option<Self> {
+        if gaps.is_empty() {
+            return None;
+        }
+        Some(Self {
+            min_ms: gaps.min_ms(),
+            max_ms: gaps.max_ms(),
+            avg_ms: gaps.mean_ms(),
+            p50_ms: gaps.percentile(50.0),
+            p90_ms: gaps.percentile(90.0),
+            p99_ms: gaps.percentile(99.0),
+        })
+    }
+
+    /// The summary's columns in `SUMMARY_HEADERS` order, empty when there are no gaps
+    pub(crate) fn record(summary: Option<Self>) -> Vec<String> {
+        match summary {
+            Some(s) => [s.min_ms, s.max_ms, s.avg_ms, s.p50_ms, s.p90_ms, s.p99_ms]
+                .iter()
+                .map(|ms| ms.to_string())
+                .collect(),
+            None => vec![String::new(); 6],
+        }
+    }
+}
+
+/// Where the average delta of fetched blocks comes from: the block's timestamp offset from
+/// when it was requested, plus the RPC round trip until its response arrived
+#[derive(Debug, Clone, Copy)]
+pub struct DeltaBreakdown {
+    pub timestamp_offset_ms: i64,
+    pub round_trip_ms: i64,
+    pub response_delta_ms: i64,
+}
+
+/// Deltas received within a trailing time window, so a long run shows how a chain is
+/// behaving now rather than averaged over its whole lifetime
+#[derive(Debug, Clone)]
+pub struct RollingWindow {
+    pub label: &'static str,
+    span_ms: i64,
+    samples: VecDeque<WindowSample>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct WindowSample {
+    received_ms: i64,
+    delta_ms: i64,
+}
+
+/// Stats over the blocks received within a rolling window
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WindowSummary {
+    pub blocks: usize,
+    pub avg_delta_ms: i64,
+    pub p99_delta_ms: i64,
+    pub future_blocks: usize,
+}
+
+impl RollingWindow {
+    pub fn new(label: &'static str, span_secs: i64) -> Self {
+        Self {
+            label,
+            span_ms: span_secs * 1000,
+            samples: VecDeque::new(),
+        }
+    }
+
+    // This window with only the samples pushed since the previous call, which are moved out
+    fn take_update(&mut self) -> Self {
+        Self {
+            label: self.label,
+            span_ms: self.span_ms,
+            samples: take(&mut self.samples),
+        }
+    }
+
+    // Push the samples of `update`, this window's from `take_update`
+    fn absorb(&mut self, update: Self) {
+        for sample in update.samples {
+            self.push(sample);
+        }
+    }
+
+    fn push(&mut self, sample: WindowSample) {
+        self.samples.push_back(sample);
+        // Receipt times only move forward, so expired samples are all at the front
+        let cutoff = sample.received_ms - self.span_ms;
+        while self.samples.front().is_some_and(|s| s.received_ms < cutoff) {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Summary of the blocks received in the window ending at `now`
+    pub fn summary(&self, now: DateTime<Utc>) -> WindowSummary {
+        let cutoff = now.timestamp_millis() - self.span_ms;
+        let recent: Vec<&WindowSample> = self.samples.iter().filter(|s| s.received_ms >= cutoff).collect();
+        if recent.is_empty() {
+            return WindowSummary::default();
+        }
+        
+        let mut sorted: Vec<i64> = recent.iter().map(|s| s.delta_ms).collect();
+        sorted.sort_unstable();
+        WindowSummary {
+            blocks: recent.len(),
+            avg_delta_ms: sorted.iter().sum::<i64>() / recent.len() as i64,
+            p99_delta_ms: percentile(&sorted, 99.0),
+            future_blocks: recent.iter().filter(|s| s.delta_ms < 0).count(),
+        }
+    }
+}
+
+impl ChainStats {
+    pub fn new(chain_name: &str) -> Self {
+        Self {
+            chain_name: chain_name.to_string(),
+            windows: STATS_WINDOWS
+                .iter()
+                .map(|(label, span_secs)| RollingWindow::new(label, *span_secs))
+                .collect(),
+            max_raw_samples: DEFAULT_MAX_RAW_SAMPLES,
+            ..Default::default()
+        }
+    }
+
+    /// Record a block with `header` (stamped in whole seconds on most chains) received at
+    /// `received`, whose response took `round_trip_ms` when it was fetched, and derived
+    /// from `l1_origin` when L1 origins are tracked. Returns the delta's z-score against the
+    /// chain's recent baseline when that makes it anomalous.
+    pub(crate) fn update(
+        &mut self,
+        block_number: u64,
+        header: &BlockHeader,
+        received: Stamp,
+        round_trip_ms: Option<i64>,
+        l1_origin: Option<L1Origin>,
+        leap_affected: bool,
+    ) -> Option<f64> {
+        self.total_blocks += 1;
+        let received_time = received.wall;
+        let block_timestamp_ms = header.timestamp_ms;
+        
+        // Convert block timestamp to DateTime
+        let block_time = DateTime::from_timestamp_millis(block_timestamp_ms as i64)
+            .unwrap_or_else(Utc::now);
+        
+        // Calculate time difference
+        let delta = received_time.signed_duration_since(block_time);
+        let delta_ms = delta.num_milliseconds();
+        self.last_delta_ms = delta_ms;
+        // Clocks disagree around leap seconds; tag these deltas instead of letting them skew
+        // the distribution, the hourly and rolling averages or the anomaly baseline
+        if !leap_affected {
+            self.delta_histogram.record(delta_ms);
+            self.hourly.record(received_time, delta_ms);
+            for window in &mut self.windows {
+                window.push(WindowSample {
+                    received_ms: received_time.timestamp_millis(),
+                    delta_ms,
+                });
+            }
+        }
+        
+        let timestamp_delta = TimestampDelta::from_ms(delta_ms);
+        let magnitude_ms = timestamp_delta.magnitude_ms as i64;
+        match timestamp_delta.direction {
+            _ if leap_affected => self.leap_tagged_blocks += 1,
+            DeltaDirection::Past => {
+                self.timestamp_past_blocks += 1;
+                self.max_past_delta_ms = self.max_past_delta_ms.max(magnitude_ms);
+            }
+            DeltaDirection::Exact => self.timestamp_exact_blocks += 1,
+            DeltaDirection::Future => {
+                self.timestamp_future_blocks += 1;
+                self.max_future_delta_ms = self.max_future_delta_ms.max(magnitude_ms);
+            }
+        }
+        
+        self.avg_time_delta_ms = self.delta_histogram.mean_ms();
+        let anomaly_z = if leap_affected { None } else { self.anomalies.record(block_number, delta_ms) };
+        // A catching-up node serves old blocks late; count them so the deltas aren't blamed
+        // on the sequencer
+        if self.node_syncing == Some(true) {
+            self.syncing_blocks += 1;
+        }
+        if let Some(round_trip_ms) = round_trip_ms {
+            self.fetch_round_trips.record(round_trip_ms);
+            if !leap_affected {
+                self.response_deltas.record(delta_ms + round_trip_ms);
+            }
+        }
+        let l1_drift_ms = l1_origin.map(|origin| block_timestamp_ms as i64 - origin.timestamp_ms as i64);
+        if let Some(drift_ms) = l1_drift_ms {
+            self.l1_origin_drift.record(drift_ms);
+        }
+        
+        let sample = RawSample {
+            block_number,
+            block_timestamp_ms,
+            receipt_time_ms: received_time.timestamp_millis(),
+            received_instant: received.monotonic,
+            delta_ms,
+            leap_affected,
+            round_trip_ms,
+            l1_origin_block: l1_origin.map(|origin| origin.number),
+            l1_drift_ms,
+            hash: header.hash.clone(),
+            parent_hash: header.parent_hash.clone(),
+            gas_used: header.gas_used,
+            tx_count: header.tx_count,
+            node_syncing: self.node_syncing,
+        };
+        self.record_gaps(&sample);
+        
+        // Keep only the most recent raw records; the aggregates above cover the whole run
+        if self.max_raw_samples > 0 {
+            if self.raw_samples.len() >= self.max_raw_samples {
+                self.raw_samples.pop_front();
+            }
+            self.raw_samples.push_back(sample);
+        }
+        anomaly_z
+    }
+    
+    // Fold the gaps between this block and the previous one into the running aggregates
+    fn record_gaps(&mut self, sample: &RawSample) {
+        self.first_block.get_or_insert((sample.block_number, sample.block_timestamp_ms));
+        let arrival_gap = self
+            .previous
+            .as_ref()
+            .filter(|previous| sample.block_number == previous.block_number + 1)
+            .map(|previous| sample.arrival_gap_ms(previous));
+        if let Some(block_time_ms) = self.expected_block_time_ms.filter(|ms| *ms > 0) {
+            self.schedule.record(sample.block_number, sample.receipt_time_ms, arrival_gap, block_time_ms);
+        }
+        if let Some(previous) = &self.previous {
+            let gap = sample.block_timestamp_ms.saturating_sub(previous.block_timestamp_ms);
+            if gap > 0 {
+                self.granularity_ms = gcd(self.granularity_ms, gap);
+            }
+            if sample.block_number == previous.block_number + 1 {
+                let arrival_gap = sample.arrival_gap_ms(previous);
+                let timestamp_gap = sample.block_timestamp_ms as i64 - previous.block_timestamp_ms as i64;
+                self.block_gaps.record(timestamp_gap);
+                self.arrival_gaps.record(arrival_gap);
+                if timestamp_gap == 0 {
+                    self.same_timestamp_blocks += 1;
+                }
+                if timestamp_gap <= 0 {
+                    self.non_monotonic_blocks += 1;
+                    self.max_timestamp_regression_ms = self.max_timestamp_regression_ms.max(-timestamp_gap);
+                }
+                let jitter = (arrival_gap - timestamp_gap) as f64;
+                self.jitter.count += 1;
+                self.jitter.sum += jitter;
+                self.jitter.sum_of_squares += jitter * jitter;
+                self.jitter.sum_abs += jitter.abs();
+            }
+        }
+        self.previous = Some(sample.clone());
+    }
+
+    /// Record where the safe and finalized heads were relative to the latest block
+    pub(crate) fn record_head_lag(&mut self, sample: HeadLagSample) {
+        self.safe_lag.record(sample.head.saturating_sub(sample.safe), sample.safe_lag_ms);
+        self.finalized_lag.record(sample.head.saturating_sub(sample.finalized), sample.finalized_lag_ms);
+        if self.max_raw_samples > 0 {
+            if self.head_lag_history.len() >= self.max_raw_samples {
+                self.head_lag_history.pop_front();
+            }
+            self.head_lag_history.push_back(sample);
+        }
+    }
+
+    /// Record what the node reported about itself; later blocks are annotated with it
+    pub(crate) fn record_node_health(&mut self, health: &NodeHealth) {
+        self.node_syncing = Some(health.syncing);
+        if let Some(lag) = health.sync_lag_blocks() {
+            self.max_sync_lag_blocks = self.max_sync_lag_blocks.max(lag);
+        }
+        if let Some(peers) = health.peer_count {
+            self.peer_count = Some(peers);
+            self.min_peer_count = Some(self.min_peer_count.map_or(peers, |min| min.min(peers)));
+        }
+    }
+
+    /// Record how a canonical block compared with its flashblocks
+    pub(crate) fn record_preconf(&mut self, sample: PreconfSample) {
+        self.preconf.record(&sample);
+        if self.max_raw_samples > 0 {
+            if self.preconf_history.len() >= self.max_raw_samples {
+                self.preconf_history.pop_front();
+            }
+            self.preconf_history.push_back(sample);
+        }
+    }
+
+    /// Record that blocks `first_block..=last_block` were skipped. A range that continues the
+    /// previous gap for the same reason, such as consecutive blocks failing to fetch, extends it.
+    pub(crate) fn record_gap(&mut self, event: GapEvent) {
+        self.missed_blocks += event.blocks();
+        if let Some(previous) = self.gap_events.back_mut() {
+            if previous.reason == event.reason && previous.last_block + 1 == event.first_block {
+                previous.last_block = event.last_block;
+                return;
+            }
+        }
+        self.gaps += 1;
+        if self.max_raw_samples > 0 {
+            if self.gap_events.len() >= self.max_raw_samples {
+                self.gap_events.pop_front();
+            }
+            self.gap_events.push_back(event);
+        }
+    }
+
+    /// Counters that move whenever something is recorded, to tell whether a poll did
+    pub(crate) fn progress(&self) -> [u64; 11] {
+        [
+            self.total_blocks as u64,
+            self.rpc_errors as u64,
+            self.stale_responses as u64,
+            self.missed_blocks,
+            self.reorgs as u64,
+            self.monitor_restarts as u64,
+            self.wedge_events as u64,
+            self.breaker_trips as u64,
+            self.degraded as u64,
+            self.safe_lag.history_ms.len(),
+            self.peer_count.unwrap_or(0) << 1 | self.node_syncing.unwrap_or(false) as u64,
+        ]
+    }
+
+    /// A copy of these stats for the main loop, carrying only what was recorded since the
+    /// previous one: the per-block records, the rolling windows' samples and the histograms
+    /// are moved out rather than cloned, so only the counters and the bounded state left
+    /// are copied. The latest gap stays, since the next may extend it.
+    pub(crate) fn take_update(&mut self) -> ChainStats {
+        let raw_samples = take(&mut self.raw_samples);
+        let head_lag_history = take(&mut self.head_lag_history);
+        let preconf_history = take(&mut self.preconf_history);
+        let gap_events = take(&mut self.gap_events);
+        self.gap_events.extend(gap_events.back().copied());
+        let windows = self.windows.iter_mut().map(RollingWindow::take_update).collect();
+        let delta_histogram = take(&mut self.delta_histogram);
+        let hourly = take(&mut self.hourly);
+        let block_gaps = take(&mut self.block_gaps);
+        let arrival_gaps = take(&mut self.arrival_gaps);
+        let head_rpc_latency = take(&mut self.head_rpc_latency);
+        let block_rpc_latency = take(&mut self.block_rpc_latency);
+        let fetch_round_trips = take(&mut self.fetch_round_trips);
+        let response_deltas = take(&mut self.response_deltas);
+        let l1_origin_drift = take(&mut self.l1_origin_drift);
+        let provider_clock_skew = take(&mut self.provider_clock_skew);
+        let safe_lag = self.safe_lag.take_update();
+        let finalized_lag = self.finalized_lag.take_update();
+        let preconf = self.preconf.take_update();
+        let head_consistency = self.head_consistency.take_update();
+        ChainStats {
+            raw_samples,
+            head_lag_history,
+            preconf_history,
+            gap_events,
+            windows,
+            delta_histogram,
+            hourly,
+            block_gaps,
+            arrival_gaps,
+            head_rpc_latency,
+            block_rpc_latency,
+            fetch_round_trips,
+            response_deltas,
+            l1_origin_drift,
+            provider_clock_skew,
+            safe_lag,
+            finalized_lag,
+            preconf,
+            head_consistency,
+            ..self.clone()
+        }
+    }
+
+    /// Treat everything recorded so far as already taken, for stats resumed from a copy
+    /// that keeps it itself
+    pub(crate) fn forget_records(&mut self) {
+        self.take_update();
+    }
+
+    /// Take over `update`, newer stats from `take_update`, appending its records to the
+    /// ones kept here up to `max_raw_samples` and adding its samples and histograms to the
+    /// ones kept here
+    pub(crate) fn absorb(&mut self, mut update: ChainStats) {
+        // A gap sent again may have been extended since
+        if let (Some(kept), Some(first)) = (self.gap_events.back(), update.gap_events.front()) {
+            if kept.first_block == first.first_block && kept.reason == first.reason {
+                self.gap_events.pop_back();
+            }
+        }
+        let limit = update.max_raw_samples;
+        update.raw_samples = appended(take(&mut self.raw_samples), update.raw_samples, limit);
+        update.head_lag_history = appended(take(&mut self.head_lag_history), update.head_lag_history, limit);
+        update.preconf_history = appended(take(&mut self.preconf_history), update.preconf_history, limit);
+        update.gap_events = appended(take(&mut self.gap_events), update.gap_events, limit);
+
+        // Stats resumed from a checkpoint have no rolling windows; the update's start them
+        if self.windows.len() == update.windows.len() {
+            for (window, new) in self.windows.iter_mut().zip(take(&mut update.windows)) {
+                window.absorb(new);
+            }
+            update.windows = take(&mut self.windows);
+        }
+        update.delta_histogram = added(take(&mut self.delta_histogram), &update.delta_histogram);
+        self.hourly.add(&update.hourly);
+        update.hourly = take(&mut self.hourly);
+        update.block_gaps = added(take(&mut self.block_gaps), &update.block_gaps);
+        update.arrival_gaps = added(take(&mut self.arrival_gaps), &update.arrival_gaps);
+        update.head_rpc_latency = added(take(&mut self.head_rpc_latency), &update.head_rpc_latency);
+        update.block_rpc_latency = added(take(&mut self.block_rpc_latency), &update.block_rpc_latency);
+        update.fetch_round_trips = added(take(&mut self.fetch_round_trips), &update.fetch_round_trips);
+        update.response_deltas = added(take(&mut self.response_deltas), &update.response_deltas);
+        update.l1_origin_drift = added(take(&mut self.l1_origin_drift), &update.l1_origin_drift);
+        update.provider_clock_skew = added(take(&mut self.provider_clock_skew), &update.provider_clock_skew);
+        update.safe_lag.history_ms = added(take(&mut self.safe_lag.history_ms), &update.safe_lag.history_ms);
+        update.finalized_lag.history_ms = added(take(&mut self.finalized_lag.history_ms), &update.finalized_lag.history_ms);
+        update.preconf.deltas = added(take(&mut self.preconf.deltas), &update.preconf.deltas);
+        update.preconf.leads = added(take(&mut self.preconf.leads), &update.preconf.leads);
+        update.head_consistency.poll_lags =
+            added(take(&mut self.head_consistency.poll_lags), &update.head_consistency.poll_lags);
+        // The sender's average only covers its own histogram, which starts over with each update
+        update.avg_time_delta_ms = update.delta_histogram.mean_ms();
+        *self = update;
+    }
+
+    /// Deltas at each of `DELTA_PERCENTILES`, in milliseconds
+    pub fn delta_percentiles(&self) -> [i64; 4] {
+        DELTA_PERCENTILES.map(|p| self.delta_histogram.percentile(p))
+    }
+
+    /// Population standard deviation of the deltas in milliseconds
+    pub fn delta_stddev_ms(&self) -> f64 {
+        self.delta_histogram.stddev_ms()
+    }
+
+    /// Every delta counted in `histogram_buckets`, lowest bucket first, with future deltas
+    /// within one timestamp step counted as zero
+    pub fn delta_buckets(&self) -> Vec<BucketCount> {
+        self.histogram_buckets.counts(&self.delta_histogram, self.timestamp_granularity_ms())
+    }
+
+    /// Record how many blocks behind the head the monitor was when it polled
+    pub(crate) fn record_backlog(&mut self, depth: u64) {
+        self.backlog_depth = depth;
+        self.max_backlog_depth = self.max_backlog_depth.max(depth);
+        self.backlog_history.push_back(depth);
+        if self.backlog_history.len() > 2 * BACKLOG_TREND_WINDOW {
+            self.backlog_history.pop_front();
+        }
+    }
+
+    /// Change in average backlog between the previous and the latest window of polls;
+    /// positive means the monitor is falling behind
+    pub fn backlog_trend(&self) -> f64 {
+        if self.backlog_history.len() < 2 * BACKLOG_TREND_WINDOW {
+            return 0.0;
+        }
+        let samples: Vec<u64> = self.backlog_history.iter().copied().collect();
+        let (older, recent) = samples.split_at(BACKLOG_TREND_WINDOW);
+        let mean = |samples: &[u64]| samples.iter().sum::<u64>() as f64 / samples.len() as f64;
+        mean(recent) - mean(older)
+    }
+
+    /// Jitter of our observation cadence: for consecutive blocks, how far the gap between
+    /// receipt times strays from the gap between block timestamps. Returns the standard
+    /// deviation and mean absolute value of that difference in milliseconds; large values
+    /// mean the sampling itself is irregular and deltas carry that much uncertainty.
+    pub fn observation_jitter_ms(&self) -> (f64, f64) {
+        let sums = self.jitter;
+        if sums.count == 0 {
+            return (0.0, 0.0);
+        }
+        
+        let n = sums.count as f64;
+        let mean = sums.sum / n;
+        let variance = (sums.sum_of_squares / n - mean * mean).max(0.0);
+        (variance.sqrt(), sums.sum_abs / n)
+    }
+
+    /// Average block interval from the timestamps of the first and last observed blocks
+    pub fn avg_block_interval_ms(&self) -> Option<f64> {
+        let ((first, first_timestamp_ms), last) = (self.first_block?, self.previous.as_ref()?);
+        if last.block_number <= first {
+            return None;
+        }
+        let span_ms = last.block_timestamp_ms as f64 - first_timestamp_ms as f64;
+        Some(span_ms / (last.block_number - first) as f64)
+    }
+
+    /// Gaps between the timestamps of consecutive blocks; a regular sequencer keeps these
+    /// pinned to the block time
+    pub fn block_gap_summary(&self) -> Option<GapSummary> {
+        GapSummary::of(&self.block_gaps)
+    }
+
+    /// Gaps between the receipt times of consecutive blocks, i.e. how regularly they arrive
+    pub fn arrival_gap_summary(&self) -> Option<GapSummary> {
+        GapSummary::of(&self.arrival_gaps)
+    }
+
+    /// Round-trip times of the requests for the chain's head height
+    pub fn head_rpc_latency_summary(&self) -> Option<GapSummary> {
+        GapSummary::of(&self.head_rpc_latency)
+    }
+
+    /// Round-trip times of the requests for block headers, so provider latency can be told
+    /// apart from timestamp drift
+    pub fn block_rpc_latency_summary(&self) -> Option<GapSummary> {
+        GapSummary::of(&self.block_rpc_latency)
+    }
+
+    /// How far block timestamps ran ahead of their L1 origins': the sequencer drift that
+    /// OP-stack chains cap. `None` unless L1 origins are tracked.
+    pub fn l1_origin_drift_summary(&self) -> Option<GapSummary> {
+        GapSummary::of(&self.l1_origin_drift)
+    }
+
+    /// How far the provider's clock, read from the `Date` header of its HTTP responses, is
+    /// ahead of ours, to tell a skewed provider from a skewed sequencer. Each reading is only
+    /// good to half a second either way, so the average is what counts. `None` over IPC and
+    /// for providers that send no `Date` header.
+    pub fn provider_clock_skew_summary(&self) -> Option<GapSummary> {
+        GapSummary::of(&self.provider_clock_skew)
+    }
+
+    /// Average delta of fetched blocks split into timestamp offset and RPC round trip, or
+    /// `None` when no block was fetched (e.g. every block came from a head subscription)
+    pub fn delta_breakdown(&self) -> Option<DeltaBreakdown> {
+        if self.fetch_round_trips.is_empty() {
+            return None;
+        }
+        let round_trip_ms = self.fetch_round_trips.mean_ms();
+        let response_delta_ms = self.response_deltas.mean_ms();
+        Some(DeltaBreakdown {
+            timestamp_offset_ms: response_delta_ms - round_trip_ms,
+            round_trip_ms,
+            response_delta_ms,
+        })
+    }
+
+    /// Effective timestamp granularity: the largest step every observed gap between
+    /// block timestamps is a multiple of (e.g. 2000ms on chains with 2s blocks).
+    /// Returns 0 until two distinct timestamps have been seen.
+    pub fn timestamp_granularity_ms(&self) -> u64 {
+        self.granularity_ms
+    }
+
+    /// Future-timestamp blocks whose delta is smaller than one timestamp step, i.e.
+    /// indistinguishable from the chain quantizing timestamps to its block slots
+    pub fn future_within_granularity(&self) -> usize {
+        self.delta_histogram.future_within(self.timestamp_granularity_ms()) as usize
+    }
+
+    /// Summaries of each rolling window as of `now`, labelled as in `STATS_WINDOWS`
+    pub fn window_summaries(&self, now: DateTime<Utc>) -> Vec<(&'static str, WindowSummary)> {
+        self.windows
+            .iter()
+            .map(|window| (window.label, window.summary(now)))
+            .collect()
+    }
+
+    /// One summary row, in `SUMMARY_HEADERS` order
+    pub fn summary_record(&self) -> Vec<String> {
+        let [p50, p90, p99, p999] = self.delta_percentiles();
+        let mut record = vec![
+            self.chain_name.clone(),
+            self.total_blocks.to_string(),
+            self.timestamp_past_blocks.to_string(),
+            self.timestamp_future_blocks.to_string(),
+            self.timestamp_exact_blocks.to_string(),
+            self.max_past_delta_ms.to_string(),
+            self.max_future_delta_ms.to_string(),
+            self.avg_time_delta_ms.to_string(),
+            self.timestamp_granularity_ms().to_string(),
+            self.future_within_granularity().to_string(),
+            self.leap_tagged_blocks.to_string(),
+            self.schema_deviations.to_string(),
+            self.monitor_restarts.to_string(),
+            self.wedge_events.to_string(),
+            self.backlog_depth.to_string(),
+            self.max_backlog_depth.to_string(),
+            format!("{:.2}", self.backlog_trend()),
+            format!("{:.1}", self.observation_jitter_ms().0),
+            self.stale_responses.to_string(),
+            self.breaker_trips.to_string(),
+            self.expected_block_time_ms.map(|ms| ms.to_string()).unwrap_or_default(),
+            self.avg_block_interval_ms().map(|ms| format!("{:.1}", ms)).unwrap_or_default(),
+            p50.to_string(),
+            p90.to_string(),
+            p99.to_string(),
+            p999.to_string(),
+            format!("{:.1}", self.delta_stddev_ms()),
+            self.rpc_retries.to_string(),
+            self.reorgs.to_string(),
+            self.reorged_blocks.to_string(),
+            self.max_reorg_depth.to_string(),
+            self.gaps.to_string(),
+            self.missed_blocks.to_string(),
+        ];
+        record.extend(GapSummary::record(self.block_gap_summary()));
+        record.push(self.same_timestamp_blocks.to_string());
+        record.push(self.non_monotonic_blocks.to_string());
+        record.push(self.max_timestamp_regression_ms.to_string());
+        record.extend(GapSummary::record(self.arrival_gap_summary()));
+        record.extend(GapSummary::record(self.head_rpc_latency_summary()));
+        record.extend(GapSummary::record(self.block_rpc_latency_summary()));
+        match self.delta_breakdown() {
+            Some(breakdown) => record.extend(
+                [breakdown.timestamp_offset_ms, breakdown.round_trip_ms, breakdown.response_delta_ms]
+                    .map(|ms| ms.to_string()),
+            ),
+            None => record.extend(std::iter::repeat_n(String::new(), 3)),
+        }
+        record.extend(self.safe_lag.record_columns());
+        record.extend(self.finalized_lag.record_columns());
+        record.extend(GapSummary::record(self.l1_origin_drift_summary()));
+        record.push(self.clock_offset_ms.map(|ms| ms.to_string()).unwrap_or_default());
+        record.push(self.clock_offset_ms.map(|_| self.max_clock_offset_ms.to_string()).unwrap_or_default());
+        record.extend(GapSummary::record(self.provider_clock_skew_summary()));
+        // Empty for nodes that were never asked
+        match self.node_syncing {
+            Some(syncing) => record.extend([
+                syncing.to_string(),
+                self.syncing_blocks.to_string(),
+                self.max_sync_lag_blocks.to_string(),
+            ]),
+            None => record.extend(std::iter::repeat_n(String::new(), 3)),
+        }
+        record.push(self.peer_count.map(|peers| peers.to_string()).unwrap_or_default());
+        record.push(self.min_peer_count.map(|peers| peers.to_string()).unwrap_or_default());
+        record.extend(self.provider_divergence.record_columns());
+        record.extend(self.preconf.record_columns());
+        record.extend(self.schedule.record_columns());
+        record.extend(self.anomalies.record_columns());
+        record.extend(self.outages.record_columns());
+        // Chains built without `ChainStats::new` have no windows; leave those columns empty
+        for (i, _) in STATS_WINDOWS.iter().enumerate() {
+            match self.windows.get(i) {
+                Some(window) => {
+                    let summary = window.summary(Utc::now());
+                    record.extend([
+                        summary.blocks.to_string(),
+                        summary.avg_delta_ms.to_string(),
+                        summary.p99_delta_ms.to_string(),
+                        summary.future_blocks.to_string(),
+                    ]);
+                }
+                None => record.extend(std::iter::repeat_n(String::new(), 4)),
+            }
+        }
+        record.extend(self.head_consistency.record_columns());
+        record
+    }
+
+    /// Write the stats summary and the head lag history as CSV, and the per-block and delta
+    /// datasets in `format`
+    pub fn write_to_csv(&self, output_dir: &Path, format: OutputFormat) -> Result<()> {
+        self.write_summaries(output_dir)?;
+        
+        let detailed_path = match format {
+            OutputFormat::Csv => self.write_samples_csv(output_dir)?,
+            OutputFormat::Parquet => columnar::write_samples(output_dir, &self.chain_name, &self.raw_samples)?,
+        };
+        if self.raw_samples.len() < self.total_blocks {
+            info!(
+                "Detailed block data (latest {} of {} blocks) written to {}",
+                self.raw_samples.len(),
+                self.total_blocks,
+                detailed_path.display()
+            );
+        } else {
+            info!("Detailed block data written to {}", detailed_path.display());
+        }
+        
+        Ok(())
+    }
+
+    /// Write the stats summary, head lag history, preconfirmations, gaps and delta histogram
+    /// CSVs, leaving the per-block and delta datasets to the caller
+    pub(crate) fn write_summaries(&self, output_dir: &Path) -> Result<()> {
+        // Ensure directory exists
+        std::fs::create_dir_all(output_dir)?;
+        
+        // Write summary stats
+        let file_path = output_dir.join(format!("{}_stats.csv", self.chain_name));
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&file_path)?;
+        
+        let mut wtr = csv::Writer::from_writer(file);
+        
+        // Write header and data
+        wtr.write_record(SUMMARY_HEADERS)?;
+        wtr.write_record(self.summary_record())?;
+        
+        wtr.flush()?;
+        
+        // Safe and finalized head lag over time, when it is tracked
+        if !self.head_lag_history.is_empty() {
+            let head_lag_path = output_dir.join(format!("{}_head_lag.csv", self.chain_name));
+            let mut head_lag_wtr = csv::Writer::from_path(&head_lag_path)?;
+            head_lag_wtr.write_record(HEAD_LAG_HEADERS)?;
+            for sample in &self.head_lag_history {
+                head_lag_wtr.write_record([
+                    &sample.sampled_at_ms.to_string(),
+                    &sample.head.to_string(),
+                    &sample.safe.to_string(),
+                    &sample.finalized.to_string(),
+                    &sample.safe_lag_ms.to_string(),
+                    &sample.finalized_lag_ms.to_string(),
+                ])?;
+            }
+            head_lag_wtr.flush()?;
+        }
+        
+        // Canonical blocks compared with their flashblocks, when there is a feed
+        if !self.preconf_history.is_empty() {
+            let preconf_path = output_dir.join(format!("{}_preconf.csv", self.chain_name));
+            let mut preconf_wtr = csv::Writer::from_path(&preconf_path)?;
+            preconf_wtr.write_record(PRECONF_HEADERS)?;
+            for sample in &self.preconf_history {
+                preconf_wtr.write_record([
+                    sample.block_number.to_string(),
+                    sample.flashblocks.to_string(),
+                    sample.block_timestamp_ms.to_string(),
+                    sample.first_received_ms.to_string(),
+                    sample.receipt_time_ms.to_string(),
+                    sample.delta_ms().to_string(),
+                    sample.lead_ms().to_string(),
+                    sample.timestamp_matches.map(|matches| matches.to_string()).unwrap_or_default(),
+                    sample.hash_matches.map(|matches| matches.to_string()).unwrap_or_default(),
+                ])?;
+            }
+            preconf_wtr.flush()?;
+        }
+        
+        // Skipped block ranges, when there were any
+        if !self.gap_events.is_empty() {
+            let gaps_path = output_dir.join(format!("{}_gaps.csv", self.chain_name));
+            let mut gaps_wtr = csv::Writer::from_path(&gaps_path)?;
+            gaps_wtr.write_record(GAPS_HEADERS)?;
+            for gap in &self.gap_events {
+                gaps_wtr.write_record([
+                    &gap.detected_at_ms.to_string(),
+                    &gap.first_block.to_string(),
+                    &gap.last_block.to_string(),
+                    &gap.blocks().to_string(),
+                    gap.reason.as_str(),
+                ])?;
+            }
+            gaps_wtr.flush()?;
+        }
+        
+        // Stretches without new blocks, when there were any
+        if !self.outages.recent.is_empty() {
+            let outages_path = output_dir.join(format!("{}_outages.csv", self.chain_name));
+            let mut outages_wtr = csv::Writer::from_path(&outages_path)?;
+            outages_wtr.write_record(OUTAGES_HEADERS)?;
+            for outage in &self.outages.recent {
+                outages_wtr.write_record([
+                    outage.started_at_ms.to_string(),
+                    outage.ended_at_ms.map(|ms| ms.to_string()).unwrap_or_default(),
+                    outage.duration_ms.to_string(),
+                    outage.last_block.to_string(),
+                    outage.next_block.map(|block| block.to_string()).unwrap_or_default(),
+                ])?;
+            }
+            outages_wtr.flush()?;
+        }
+        
+        // Deltas counted in the configured buckets
+        if !self.delta_histogram.is_empty() {
+            let histogram_path = output_dir.join(format!("{}_histogram.csv", self.chain_name));
+            let mut histogram_wtr = csv::Writer::from_path(&histogram_path)?;
+            histogram_wtr.write_record(HISTOGRAM_HEADERS)?;
+            let total = self.delta_histogram.len();
+            for bucket in self.delta_buckets() {
+                histogram_wtr.write_record([
+                    bucket.lower_ms.map(|ms| ms.to_string()).unwrap_or_default(),
+                    bucket.upper_ms.map(|ms| ms.to_string()).unwrap_or_default(),
+                    bucket.count.to_string(),
+                    format!("{:.2}", bucket.count as f64 * 100.0 / total as f64),
+                ])?;
+            }
+            histogram_wtr.flush()?;
+        }
+        
+        // Deltas by hour of day, every hour listed so the day's shape shows
+        if !self.delta_histogram.is_empty() {
+            let hourly_path = output_dir.join(format!("{}_hourly.csv", self.chain_name));
+            let mut hourly_wtr = csv::Writer::from_path(&hourly_path)?;
+            hourly_wtr.write_record(HOURLY_HEADERS)?;
+            for (hour, deltas) in self.hourly.hours() {
+                let future = deltas.future_beyond(self.timestamp_granularity_ms());
+                let mut record = vec![hour.to_string(), deltas.len().to_string(), future.to_string()];
+                if deltas.is_empty() {
+                    record.extend(vec![String::new(); 7]);
+                } else {
+                    record.extend([
+                        deltas.mean_ms().to_string(),
+                        format!("{:.1}", deltas.stddev_ms()),
+                        deltas.min_ms().to_string(),
+                        deltas.percentile(50.0).to_string(),
+                        deltas.percentile(90.0).to_string(),
+                        deltas.percentile(99.0).to_string(),
+                        deltas.max_ms().to_string(),
+                    ]);
+                }
+                hourly_wtr.write_record(&record)?;
+            }
+            hourly_wtr.flush()?;
+        }
+        
+        info!("Stats for {} written to {}", self.chain_name, file_path.display());
+        Ok(())
+    }
+
+    // Write the per-block records to `{Chain}_detailed.csv` and their deltas to
+    // `{Chain}_deltas.csv`, returning the path of the detailed file
+    fn write_samples_csv(&self, output_dir: &Path) -> Result<PathBuf> {
+        // Write detailed block data with raw timestamps
+        let detailed_path = output_dir.join(format!("{}_detailed.csv", self.chain_name));
+        let detailed_file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&detailed_path)?;
+        
+        let mut detailed_wtr = csv::Writer::from_writer(detailed_file);
+        
+        // Write header for detailed data
+        detailed_wtr.write_record(DETAILED_HEADERS)?;
+        
+        // Write each retained block record
+        for sample in &self.raw_samples {
+            detailed_wtr.write_record(sample.detailed_record())?;
+        }
+        
+        detailed_wtr.flush()?;
+        
+        // Also keep the delta-only file for backward compatibility
+        let deltas_path = output_dir.join(format!("{}_deltas.csv", self.chain_name));
+        let deltas_file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&deltas_path)?;
+        
+        let mut deltas_wtr = csv::Writer::from_writer(deltas_file);
+        deltas_wtr.write_record(DELTAS_HEADERS)?;
+        
+        for sample in &self.raw_samples {
+            deltas_wtr.write_record([&sample.delta_ms.to_string()])?;
+        }
+        
+        deltas_wtr.flush()?;
+        
+        Ok(detailed_path)
+    }
+}
+
+/// Greatest common divisor, treating 0 as the identity
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Nearest-rank percentile of an already sorted slice
+pub fn percentile(sorted: &[i64], p: f64) -> i64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(timestamp_ms: u64) -> BlockHeader {
+        BlockHeader {
+            timestamp_ms,
+            hash: None,
+            parent_hash: None,
+            gas_used: None,
+            tx_count: None,
+        }
+    }
+
+    #[test]
+    fn leap_affected_deltas_stay_out_of_the_aggregates() {
+        // 2016-12-31T23:59:59Z, a second before the leap
+        let block_ms = 1_483_228_799_000;
+        let received = |delta_ms: i64| Stamp::wall(DateTime::from_timestamp_millis(block_ms as i64 + delta_ms).unwrap());
+        let mut stats = ChainStats::new("Base");
+        stats.update(1, &header(block_ms), received(200), None, None, false);
+        stats.update(2, &header(block_ms), received(-5_000), None, None, true);
+
+        assert_eq!(stats.total_blocks, 2);
+        assert_eq!(stats.leap_tagged_blocks, 1);
+        assert_eq!(stats.timestamp_past_blocks, 1);
+        assert_eq!(stats.timestamp_future_blocks, 0);
+        assert_eq!(stats.max_future_delta_ms, 0);
+        assert_eq!(stats.delta_histogram.len(), 1);
+        assert_eq!(stats.avg_time_delta_ms, 200);
+        let window = stats.windows[0].summary(DateTime::from_timestamp_millis(block_ms as i64).unwrap());
+        assert_eq!((window.blocks, window.future_blocks), (1, 0));
+        // The raw record keeps the tagged block
+        assert!(stats.raw_samples.back().unwrap().leap_affected);
+    }
+
+    #[test]
+    fn timestamp_regressions_only_count_between_consecutive_blocks() {
+        let block_ms: i64 = 1_700_000_000_000;
+        let received = |number: u64| Stamp::wall(DateTime::from_timestamp_millis(block_ms + number as i64 * 2000 + 300).unwrap());
+        let mut stats = ChainStats::new("Base");
+        // Block offsets from `block_ms`: equal, then decreasing, for consecutive blocks; across
+        // the skipped blocks 4 and 7, and back to an already seen number, nothing is counted
+        for (number, offset_ms) in [(1, 2000), (2, 2000), (3, 1500), (5, -4000), (6, -4000), (8, 6000), (6, 0)] {
+            stats.update(number, &header((block_ms + offset_ms) as u64), received(number), None, None, false);
+        }
+
+        assert_eq!(stats.same_timestamp_blocks, 2);
+        assert_eq!(stats.non_monotonic_blocks, 3);
+        assert_eq!(stats.max_timestamp_regression_ms, 500);
+    }
+
+    #[test]
+    fn absorbed_updates_rebuild_the_records_without_resending_them() {
+        let block_ms = 1_700_000_000_000;
+        let received = |number: u64| Stamp::wall(DateTime::from_timestamp_millis(block_ms as i64 + number as i64 * 2000 + 300).unwrap());
+        let gap = |first_block, last_block| GapEvent {
+            detected_at_ms: 0,
+            first_block,
+            last_block,
+            reason: GapReason::FetchFailed,
+        };
+        let mut monitor = ChainStats {
+            max_raw_samples: 3,
+            ..ChainStats::new("Base")
+        };
+        let mut kept = ChainStats::default();
+        for number in 1..=2 {
+            monitor.update(number, &header(block_ms + number * 2000), received(number), None, None, false);
+        }
+        monitor.record_gap(gap(3, 3));
+        kept.absorb(monitor.take_update());
+        assert!(monitor.raw_samples.is_empty());
+
+        // The gap is extended after it was sent, and more blocks push out the oldest
+        monitor.record_gap(gap(4, 4));
+        for number in 5..=6 {
+            monitor.update(number, &header(block_ms + number * 2000), received(number), None, None, false);
+        }
+        let update = monitor.take_update();
+        assert_eq!(update.raw_samples.len(), 2);
+        kept.absorb(update);
+
+        assert_eq!(kept.total_blocks, 4);
+        let numbers: Vec<u64> = kept.raw_samples.iter().map(|sample| sample.block_number).collect();
+        assert_eq!(numbers, [2, 5, 6]);
+        assert_eq!(kept.gap_events.len(), 1);
+        assert_eq!((kept.gap_events[0].first_block, kept.gap_events[0].last_block), (3, 4));
+    }
+
+    #[test]
+    fn absorbed_updates_add_up_to_the_stats_of_the_whole_run() {
+        let block_ms: i64 = 1_700_000_000_000;
+        let received = |number: u64| Stamp::wall(DateTime::from_timestamp_millis(block_ms + number as i64 * 2000 + 150 * (number as i64 % 4)).unwrap());
+        let mut whole = ChainStats::new("Base");
+        let mut monitor = ChainStats::new("Base");
+        // Resumed stats have no rolling windows until the first update
+        let mut kept = ChainStats::default();
+        for number in 1..=12 {
+            let header = header((block_ms + number as i64 * 2000) as u64);
+            whole.update(number, &header, received(number), Some(40), None, false);
+            monitor.update(number, &header, received(number), Some(40), None, false);
+            if number % 5 == 0 {
+                kept.absorb(monitor.take_update());
+                // Nothing is sent twice
+                assert!(monitor.delta_histogram.is_empty() && monitor.arrival_gaps.is_empty());
+            }
+        }
+        kept.absorb(monitor.take_update());
+
+        assert_eq!(kept.total_blocks, 12);
+        assert_eq!(kept.delta_histogram.len(), 12);
+        assert_eq!(kept.delta_percentiles(), whole.delta_percentiles());
+        assert_eq!(kept.avg_time_delta_ms, whole.avg_time_delta_ms);
+        assert_eq!(kept.arrival_gaps.len(), 11);
+        assert_eq!(kept.arrival_gaps.sum_ms(), whole.arrival_gaps.sum_ms());
+        assert_eq!(kept.fetch_round_trips.len(), 12);
+        let hourly = |stats: &ChainStats| stats.hourly.hours().map(|(_, deltas)| deltas.len()).collect::<Vec<_>>();
+        assert_eq!(hourly(&kept), hourly(&whole));
+        let now = DateTime::from_timestamp_millis(block_ms + 30_000).unwrap();
+        let windows = |stats: &ChainStats| stats.windows.iter().map(|window| window.summary(now).blocks).collect::<Vec<_>>();
+        assert_eq!(windows(&kept), windows(&whole));
+        assert!(windows(&kept).iter().all(|blocks| *blocks == 12));
+    }
+}
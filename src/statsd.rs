@@ -0,0 +1,118 @@
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use futures::future::BoxFuture;
+use log::info;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::net::UdpSocket;
+
+use crate::monitor::BlockObservation;
+use crate::sink::ObservationSink;
+
+/// How chains and directions are attached to StatsD metrics
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum StatsdFormat {
+    /// DogStatsD tags, e.g. `block_timestamp.delta_ms:412|h|#chain:Base,direction:past`
+    #[default]
+    Dogstatsd,
+    /// Plain StatsD, with the chain and direction in the metric name, e.g.
+    /// `block_timestamp.Base.past.delta_ms:412|h`
+    Plain,
+}
+
+/// Sends every observation to a StatsD or DogStatsD agent over UDP, as one datagram per
+/// block holding:
+///
+/// - `{prefix}.delta_ms`: the signed delta, as a histogram
+/// - `{prefix}.delta_magnitude_ms`: the delta's magnitude, as a timing
+/// - `{prefix}.blocks`: a count of 1
+/// - `{prefix}.rpc_round_trip_ms`: the block request's round trip, as a timing, for fetched
+///   blocks
+///
+/// each tagged (or named) with the chain and delta direction, and the vantage point when
+/// tagged.
+pub struct StatsdSink {
+    socket: UdpSocket,
+    target: SocketAddr,
+    name: String,
+    prefix: String,
+    format: StatsdFormat,
+}
+
+impl StatsdSink {
+    /// Send to the agent at `addr` (`host:port`), naming metrics `{prefix}.*`
+    pub async fn connect(addr: &str, prefix: &str, format: StatsdFormat) -> Result<Self> {
+        let target = tokio::net::lookup_host(addr)
+            .await?
+            .next()
+            .ok_or_else(|| anyhow!("StatsD address {} did not resolve", addr))?;
+        let local: SocketAddr = if target.is_ipv4() {
+            (Ipv4Addr::UNSPECIFIED, 0).into()
+        } else {
+            (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        // Left unconnected, so an agent that isn't listening doesn't surface as errors on
+        // later sends: StatsD is fire-and-forget
+        let socket = UdpSocket::bind(local).await?;
+        info!("Sending observations to StatsD at {}", addr);
+        Ok(Self {
+            socket,
+            target,
+            name: format!("StatsD at {}", addr),
+            prefix: prefix.trim_end_matches('.').to_string(),
+            format,
+        })
+    }
+
+    fn datagram(&self, observation: &BlockObservation) -> String {
+        let delta = observation.timestamp_delta();
+        let direction = delta.direction.as_str();
+        let (name, tags) = match self.format {
+            StatsdFormat::Dogstatsd => {
+                let mut tags = format!("|#chain:{},direction:{}", tag(&observation.chain), direction);
+                if let Some(vantage) = &observation.vantage {
+                    tags.push_str(&format!(",vantage:{}", tag(vantage)));
+                }
+                (self.prefix.clone(), tags)
+            }
+            StatsdFormat::Plain => (
+                format!("{}.{}.{}", self.prefix, metric_segment(&observation.chain), direction),
+                String::new(),
+            ),
+        };
+        let mut lines = vec![
+            format!("{}.delta_ms:{}|h{}", name, observation.delta_ms, tags),
+            format!("{}.delta_magnitude_ms:{}|ms{}", name, delta.magnitude_ms, tags),
+            format!("{}.blocks:1|c{}", name, tags),
+        ];
+        if let Some(round_trip_ms) = observation.round_trip_ms() {
+            lines.push(format!("{}.rpc_round_trip_ms:{}|ms{}", name, round_trip_ms, tags));
+        }
+        lines.join("\n")
+    }
+}
+
+impl ObservationSink for StatsdSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn write<'a>(&'a mut self, observation: &'a BlockObservation) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.socket.send_to(self.datagram(observation).as_bytes(), self.target).await?;
+            Ok(())
+        })
+    }
+}
+
+// DogStatsD tag values can't hold the separators of the datagram
+fn tag(value: &str) -> String {
+    value.replace([',', '|', '#', '\n'], "_")
+}
+
+// Plain StatsD metric name segments keep to letters, digits, `-` and `_`
+fn metric_segment(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect()
+}
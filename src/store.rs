@@ -0,0 +1,172 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use futures::future::{self, BoxFuture};
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::dataset::column_name;
+use crate::monitor::BlockObservation;
+use crate::sink::ObservationSink;
+use crate::stats::{ChainStats, SUMMARY_HEADERS};
+
+/// How an observation column is read from an observation, by the column's type
+#[derive(Clone, Copy)]
+pub(crate) enum ColumnValue {
+    Text(fn(&BlockObservation) -> Option<&str>),
+    Integer(fn(&BlockObservation) -> Option<i64>),
+    Boolean(fn(&BlockObservation) -> Option<bool>),
+    Timestamp(fn(&BlockObservation) -> DateTime<Utc>),
+}
+
+/// Columns of the `observations` table and their values. Every database backend stores
+/// these, under these names, so the same queries work on all of them
+pub(crate) const OBSERVATION_COLUMNS: &[(&str, ColumnValue)] = &[
+    ("chain", ColumnValue::Text(|observation| Some(&observation.chain))),
+    ("block_number", ColumnValue::Integer(|observation| Some(observation.block_number as i64))),
+    ("block_timestamp_s", ColumnValue::Integer(|observation| Some((observation.block_timestamp_ms / 1000) as i64))),
+    ("receipt_time_ms", ColumnValue::Integer(|observation| Some(observation.received_at.timestamp_millis()))),
+    ("received_at", ColumnValue::Timestamp(|observation| observation.received_at)),
+    ("delta_ms", ColumnValue::Integer(|observation| Some(observation.delta_ms))),
+    ("leap_second_window", ColumnValue::Boolean(|observation| Some(observation.leap_affected))),
+    ("block_timestamp_ms", ColumnValue::Integer(|observation| Some(observation.block_timestamp_ms as i64))),
+    ("response_time_ms", ColumnValue::Integer(|observation| observation.responded_at.map(|responded| responded.timestamp_millis()))),
+    ("rpc_round_trip_ms", ColumnValue::Integer(BlockObservation::round_trip_ms)),
+    ("l1_origin_block", ColumnValue::Integer(|observation| observation.l1_origin.map(|origin| origin.number as i64))),
+    ("l1_origin_drift_ms", ColumnValue::Integer(BlockObservation::l1_drift_ms)),
+    ("vantage", ColumnValue::Text(|observation| observation.vantage.as_deref())),
+    ("block_hash", ColumnValue::Text(|observation| observation.block_hash.as_deref())),
+    ("parent_hash", ColumnValue::Text(|observation| observation.parent_hash.as_deref())),
+    ("gas_used", ColumnValue::Integer(|observation| observation.gas_used.map(|gas| gas as i64))),
+    ("tx_count", ColumnValue::Integer(|observation| observation.tx_count.map(|count| count as i64))),
+    ("node_syncing", ColumnValue::Boolean(|observation| observation.node_syncing)),
+];
+
+/// Where a run's data is kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StorageBackend {
+    /// Per-chain CSV files in the output directory, rewritten on every report
+    Csv,
+    /// A SQLite database that every observation and stats snapshot is appended to
+    Sqlite,
+}
+
+/// Appends a run's observations and stats snapshots to a SQLite database as they happen,
+/// so everything collected survives a crash.
+///
+/// Tables and columns are named the way `query` names the CSV outputs: `observations` holds
+/// one row per block and `stats` one row per chain per report, stamped with `recorded_at`.
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+impl SqliteStore {
+    /// Open (or create) the database, adding any tables and columns it lacks
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        // WAL lets the observation writer and the stats snapshots share the file
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.busy_timeout(Duration::from_secs(5))?;
+
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS observations (chain TEXT);
+            CREATE TABLE IF NOT EXISTS stats (recorded_at TEXT, chain TEXT);",
+        )?;
+
+        // Databases from older versions may predate some of the columns
+        let stats_columns = stats_columns();
+        add_missing_columns(&conn, "observations", OBSERVATION_COLUMNS.iter().map(|(column, value)| (*column, sqlite_type(*value))))?;
+        add_missing_columns(&conn, "stats", stats_columns.iter().map(|column| (column.as_str(), "NUMERIC")))?;
+
+        Ok(Self { conn })
+    }
+
+    pub fn insert_observation(&self, observation: &BlockObservation) -> Result<()> {
+        let columns: Vec<&str> = OBSERVATION_COLUMNS.iter().map(|(column, _)| *column).collect();
+        let sql = format!(
+            "INSERT INTO observations ({}) VALUES ({})",
+            columns.join(", "),
+            vec!["?"; columns.len()].join(", ")
+        );
+        let values = OBSERVATION_COLUMNS.iter().map(|(_, value)| sqlite_value(*value, observation));
+        self.conn.execute(&sql, params_from_iter(values))?;
+        Ok(())
+    }
+
+    /// Add many observations in a single transaction
+    pub fn insert_observations(&self, observations: &[BlockObservation]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        for observation in observations {
+            self.insert_observation(observation)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Add a snapshot of one chain's summary stats
+    pub fn insert_stats(&self, stats: &ChainStats, recorded_at: DateTime<Utc>) -> Result<()> {
+        let columns = stats_columns();
+        let sql = format!(
+            "INSERT INTO stats (recorded_at, {}) VALUES (?{})",
+            columns.iter().map(|c| format!("\"{}\"", c)).collect::<Vec<_>>().join(", "),
+            ", ?".repeat(columns.len())
+        );
+        let values = std::iter::once(recorded_at.to_rfc3339()).chain(stats.summary_record());
+        self.conn.execute(&sql, params_from_iter(values))?;
+        Ok(())
+    }
+}
+
+impl ObservationSink for SqliteStore {
+    fn name(&self) -> &str {
+        "the SQLite database"
+    }
+
+    fn write<'a>(&'a mut self, observation: &'a BlockObservation) -> BoxFuture<'a, Result<()>> {
+        Box::pin(future::ready(self.insert_observation(observation)))
+    }
+}
+
+fn add_missing_columns<'a>(
+    conn: &Connection,
+    table: &str,
+    columns: impl Iterator<Item = (&'a str, &'a str)>,
+) -> Result<()> {
+    let existing: Vec<String> = {
+        let mut stmt = conn.prepare(&format!("SELECT name FROM pragma_table_info('{}')", table))?;
+        let names = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        names
+    };
+    for (column, kind) in columns {
+        if !existing.iter().any(|name| name == column) {
+            conn.execute(&format!("ALTER TABLE \"{}\" ADD COLUMN \"{}\" {}", table, column, kind), [])?;
+        }
+    }
+    Ok(())
+}
+
+// SQLite keeps booleans as integers and times as RFC 3339 text
+fn sqlite_type(value: ColumnValue) -> &'static str {
+    match value {
+        ColumnValue::Text(_) | ColumnValue::Timestamp(_) => "TEXT",
+        ColumnValue::Integer(_) | ColumnValue::Boolean(_) => "INTEGER",
+    }
+}
+
+fn sqlite_value(value: ColumnValue, observation: &BlockObservation) -> Value {
+    let value = match value {
+        ColumnValue::Text(text) => text(observation).map(|text| Value::Text(text.to_string())),
+        ColumnValue::Integer(integer) => integer(observation).map(Value::Integer),
+        ColumnValue::Boolean(boolean) => boolean(observation).map(|flag| Value::Integer(flag.into())),
+        ColumnValue::Timestamp(time) => Some(Value::Text(time(observation).to_rfc3339())),
+    };
+    value.unwrap_or(Value::Null)
+}
+
+fn stats_columns() -> Vec<String> {
+    SUMMARY_HEADERS.iter().map(|header| column_name(header)).collect()
+}
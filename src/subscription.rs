@@ -0,0 +1,380 @@
+use anyhow::{anyhow, Result};
+use futures::{SinkExt, StreamExt};
+use log::{info, warn};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::{HeaderName, HeaderValue};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::clock::Stamp;
+use crate::http::RpcAuth;
+use crate::timestamp::TimestampDecoding;
+
+/// Longest wait between reconnection attempts
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// A head announced over `newHeads`, stamped the moment its message arrived
+#[derive(Debug, Clone)]
+pub struct PushedHead {
+    pub number: u64,
+    pub timestamp_ms: u64,
+    pub hash: Option<String>,
+    pub parent_hash: Option<String>,
+    pub gas_used: Option<u64>,
+    pub received: Stamp,
+}
+
+/// A background `eth_subscribe("newHeads")` subscription that resubscribes whenever its
+/// connection is reestablished
+pub struct HeadSubscription {
+    receiver: mpsc::UnboundedReceiver<PushedHead>,
+    connected: Arc<AtomicBool>,
+    // The connection's task, when the subscription has the connection to itself
+    task: Option<JoinHandle<()>>,
+}
+
+impl HeadSubscription {
+    /// Subscribe at `ws_url` over a connection of its own, sending `auth`'s headers with the
+    /// handshake
+    pub fn spawn(chain: String, ws_url: String, decoding: TimestampDecoding, auth: RpcAuth) -> Self {
+        let (socket, task) = SharedSocket::spawn(ws_url, auth);
+        let mut subscription = socket.subscribe(chain, decoding);
+        subscription.task = Some(task);
+        subscription
+    }
+
+    /// Whether the subscription is currently established
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    /// Next head received since the last call, if any
+    pub fn try_next(&mut self) -> Option<PushedHead> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+impl Drop for HeadSubscription {
+    fn drop(&mut self) {
+        if let Some(task) = &self.task {
+            task.abort();
+        }
+    }
+}
+
+/// Hands out `newHeads` subscriptions, sharing one WebSocket between the chains that
+/// subscribe at the same URL with the same credentials. Each chain keeps its own
+/// `eth_subscribe` id on the shared connection.
+#[derive(Default)]
+pub(crate) struct SubscriptionHub {
+    sockets: HashMap<(String, RpcAuth), SharedSocket>,
+}
+
+impl SubscriptionHub {
+    pub(crate) fn subscribe(
+        &mut self,
+        chain: String,
+        ws_url: String,
+        decoding: TimestampDecoding,
+        auth: RpcAuth,
+    ) -> HeadSubscription {
+        let key = (ws_url, auth);
+        match self.sockets.get(&key) {
+            Some(socket) if socket.is_open() => {
+                info!("{}: Sharing a newHeads connection with other chains", chain);
+                socket.subscribe(chain, decoding)
+            }
+            _ => {
+                // The connection's task ends with the hub, once its subscriptions are dropped
+                let (socket, _) = SharedSocket::spawn(key.0.clone(), key.1.clone());
+                let subscription = socket.subscribe(chain, decoding);
+                self.sockets.insert(key, socket);
+                subscription
+            }
+        }
+    }
+
+    /// WebSocket connections handed out so far
+    #[cfg(test)]
+    fn connections(&self) -> usize {
+        self.sockets.len()
+    }
+}
+
+// One chain's subscription on a connection
+struct Subscriber {
+    chain: String,
+    decoding: TimestampDecoding,
+    sender: mpsc::UnboundedSender<PushedHead>,
+    connected: Arc<AtomicBool>,
+}
+
+// A WebSocket connection carrying the `newHeads` subscriptions of one or more chains
+struct SharedSocket {
+    subscribers: mpsc::UnboundedSender<Subscriber>,
+}
+
+impl SharedSocket {
+    fn spawn(ws_url: String, auth: RpcAuth) -> (Self, JoinHandle<()>) {
+        let (subscribers, receiver) = mpsc::unbounded_channel();
+        let task = tokio::spawn(socket_loop(ws_url, auth, receiver));
+        (Self { subscribers }, task)
+    }
+
+    fn is_open(&self) -> bool {
+        !self.subscribers.is_closed()
+    }
+
+    fn subscribe(&self, chain: String, decoding: TimestampDecoding) -> HeadSubscription {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let connected = Arc::new(AtomicBool::new(false));
+        let _ = self.subscribers.send(Subscriber {
+            chain,
+            decoding,
+            sender,
+            connected: connected.clone(),
+        });
+        HeadSubscription {
+            receiver,
+            connected,
+            task: None,
+        }
+    }
+}
+
+fn parse_quantity(value: &Value) -> Result<u64> {
+    let hex = value
+        .as_str()
+        .ok_or_else(|| anyhow!("Expected a hex quantity, got {}", value))?;
+    Ok(u64::from_str_radix(hex.trim_start_matches("0x"), 16)?)
+}
+
+// Connect, (re)subscribe every chain and stream their heads until the last subscription is
+// dropped. Without subscriptions the connection is closed until a chain subscribes again.
+async fn socket_loop(ws_url: String, auth: RpcAuth, mut new_subscribers: mpsc::UnboundedReceiver<Subscriber>) {
+    let mut subscribers: Vec<Subscriber> = Vec::new();
+    let mut delay = Duration::from_secs(1);
+    loop {
+        subscribers.retain(|subscriber| !subscriber.sender.is_closed());
+        if subscribers.is_empty() {
+            match new_subscribers.recv().await {
+                Some(subscriber) => subscribers.push(subscriber),
+                None => return,
+            }
+        }
+
+        let mut established = false;
+        let result = stream_heads(&ws_url, &auth, &mut subscribers, &mut new_subscribers, &mut established).await;
+        for subscriber in &subscribers {
+            subscriber.connected.store(false, Ordering::Relaxed);
+        }
+        subscribers.retain(|subscriber| !subscriber.sender.is_closed());
+        if subscribers.is_empty() {
+            continue;
+        }
+
+        // Back off only while connections keep failing outright
+        if established {
+            delay = Duration::from_secs(1);
+        }
+        let chains = subscribers.iter().map(|subscriber| subscriber.chain.as_str()).collect::<Vec<_>>().join(", ");
+        match result {
+            Ok(()) => warn!("{}: newHeads subscription closed; polling until it reconnects", chains),
+            Err(e) => warn!("{}: newHeads subscription failed: {}; polling until it reconnects", chains, e),
+        }
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+// `eth_subscribe` for the subscriber at `index`, which its reply's id refers back to
+fn subscribe_request(index: usize) -> Message {
+    let request = json!({"jsonrpc": "2.0", "id": index, "method": "eth_subscribe", "params": ["newHeads"]});
+    Message::Text(request.to_string().into())
+}
+
+async fn stream_heads(
+    ws_url: &str,
+    auth: &RpcAuth,
+    subscribers: &mut Vec<Subscriber>,
+    new_subscribers: &mut mpsc::UnboundedReceiver<Subscriber>,
+    established: &mut bool,
+) -> Result<()> {
+    let mut request = ws_url.into_client_request()?;
+    for (name, value) in auth.headers() {
+        let mut value = HeaderValue::from_str(value)?;
+        value.set_sensitive(true);
+        request.headers_mut().insert(HeaderName::from_bytes(name.as_bytes())?, value);
+    }
+    let (mut ws, _) = tokio_tungstenite::connect_async(request).await?;
+    for index in 0..subscribers.len() {
+        ws.send(subscribe_request(index)).await?;
+    }
+
+    // Subscription ids the node assigned, and the subscriber each belongs to
+    let mut subscription_ids: HashMap<String, usize> = HashMap::new();
+    let mut accepting = true;
+    loop {
+        let message = tokio::select! {
+            subscriber = new_subscribers.recv(), if accepting => {
+                match subscriber {
+                    Some(subscriber) => {
+                        subscribers.push(subscriber);
+                        ws.send(subscribe_request(subscribers.len() - 1)).await?;
+                    }
+                    None => accepting = false,
+                }
+                continue;
+            }
+            message = ws.next() => message,
+        };
+        let Some(message) = message else {
+            return Ok(());
+        };
+        // Stamp arrival before any parsing so it reflects when the head reached us
+        let received = Stamp::now();
+        let text = match message? {
+            Message::Text(text) => text,
+            Message::Close(_) => return Ok(()),
+            _ => continue,
+        };
+        let value: Value = serde_json::from_str(text.as_str())?;
+
+        // Replies to eth_subscribe; everything else is a notification
+        if let Some(id) = value.get("id") {
+            let Some(subscriber) = id.as_u64().and_then(|index| subscribers.get(index as usize)) else {
+                continue;
+            };
+            if let Some(error) = value.get("error") {
+                return Err(anyhow!("eth_subscribe for {} rejected: {}", subscriber.chain, error));
+            }
+            let subscription = value["result"]
+                .as_str()
+                .ok_or_else(|| anyhow!("Expected a subscription id, got {}", value["result"]))?;
+            subscription_ids.insert(subscription.to_string(), id.as_u64().unwrap_or_default() as usize);
+            subscriber.connected.store(true, Ordering::Relaxed);
+            *established = true;
+            info!("{}: Subscribed to newHeads", subscriber.chain);
+            continue;
+        }
+
+        let subscription = value["params"]["subscription"].as_str().unwrap_or_default();
+        let Some(&index) = subscription_ids.get(subscription) else {
+            continue;
+        };
+        let subscriber = &subscribers[index];
+        let head = match parse_head(&value["params"]["result"], &subscriber.decoding, received) {
+            Ok(head) => head,
+            Err(e) => {
+                warn!("{}: Skipping unreadable newHeads notification: {}", subscriber.chain, e);
+                continue;
+            }
+        };
+        if subscriber.sender.send(head).is_err() {
+            // The chain stopped listening; leave the others subscribed
+            subscription_ids.remove(subscription);
+            let request = json!({"jsonrpc": "2.0", "id": "unsubscribe", "method": "eth_unsubscribe", "params": [subscription]});
+            ws.send(Message::Text(request.to_string().into())).await?;
+            if subscribers.iter().all(|subscriber| subscriber.sender.is_closed()) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn parse_head(header: &Value, decoding: &TimestampDecoding, received: Stamp) -> Result<PushedHead> {
+    let fields = header
+        .as_object()
+        .ok_or_else(|| anyhow!("Expected a header object, got {}", header))?;
+    Ok(PushedHead {
+        number: parse_quantity(&header["number"])?,
+        timestamp_ms: decoding.to_millis(parse_quantity(&header["timestamp"])?, fields)?,
+        hash: header["hash"].as_str().map(str::to_string),
+        parent_hash: header["parentHash"].as_str().map(str::to_string),
+        gas_used: header.get("gasUsed").map(parse_quantity).transpose()?,
+        received,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use tokio::net::TcpListener;
+
+    // A node counting its WebSocket connections, which answers every eth_subscribe with a
+    // subscription id and then one head on it, numbered 100 plus the request's id
+    async fn serve_heads(listener: TcpListener, connections: Arc<AtomicUsize>) {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            connections.fetch_add(1, Ordering::Relaxed);
+            tokio::spawn(async move {
+                let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+                while let Some(Ok(Message::Text(text))) = ws.next().await {
+                    let request: Value = serde_json::from_str(text.as_str()).unwrap();
+                    let Some(id) = request["id"].as_u64() else {
+                        continue;
+                    };
+                    let subscription = format!("0x{:x}", 0xa0 + id);
+                    let reply = json!({"jsonrpc": "2.0", "id": id, "result": subscription});
+                    let head = json!({
+                        "jsonrpc": "2.0",
+                        "method": "eth_subscription",
+                        "params": {
+                            "subscription": subscription,
+                            "result": {"number": format!("0x{:x}", 100 + id), "timestamp": "0x6553f100"},
+                        },
+                    });
+                    ws.send(Message::Text(reply.to_string().into())).await.unwrap();
+                    ws.send(Message::Text(head.to_string().into())).await.unwrap();
+                }
+            });
+        }
+    }
+
+    async fn next_head(subscription: &mut HeadSubscription) -> PushedHead {
+        let wait = async {
+            loop {
+                if let Some(head) = subscription.try_next() {
+                    return head;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), wait).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn chains_at_one_url_share_a_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let ws_url = format!("ws://{}", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        tokio::spawn(serve_heads(listener, connections.clone()));
+
+        let mut hub = SubscriptionHub::default();
+        let subscribe = |hub: &mut SubscriptionHub, chain: &str, auth: RpcAuth| {
+            hub.subscribe(chain.to_string(), ws_url.clone(), TimestampDecoding::Seconds, auth)
+        };
+        let mut base = subscribe(&mut hub, "Base", RpcAuth::default());
+        let mut base_pending = subscribe(&mut hub, "Base Pending", RpcAuth::default());
+
+        // Each chain only gets the heads of its own subscription
+        assert_eq!(next_head(&mut base).await.number, 100);
+        assert_eq!(next_head(&mut base_pending).await.number, 101);
+        assert!(base.is_connected() && base_pending.is_connected());
+        assert_eq!(connections.load(Ordering::Relaxed), 1);
+        assert_eq!(hub.connections(), 1);
+
+        // Other credentials need a connection of their own
+        let mut private = subscribe(&mut hub, "Private", RpcAuth::bearer("secret").unwrap());
+        assert_eq!(next_head(&mut private).await.number, 100);
+        assert_eq!(connections.load(Ordering::Relaxed), 2);
+        assert_eq!(hub.connections(), 2);
+    }
+}
@@ -0,0 +1,252 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::get_env_as_u64;
+
+/// How long restarts are remembered when looking for a crash loop
+const CRASH_LOOP_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// Restart and backoff limits shared by every chain's supervisor
+#[derive(Debug, Clone, Copy)]
+pub struct SupervisorConfig {
+    max_consecutive_failures: u32,
+    base_backoff: Duration,
+    max_backoff: Duration,
+    crash_loop_restarts: usize,
+    stall_timeout: Option<Duration>,
+}
+
+impl SupervisorConfig {
+    pub fn from_env() -> Self {
+        Self {
+            max_consecutive_failures: get_env_as_u64("SUPERVISOR_MAX_FAILURES", 5).max(1) as u32,
+            base_backoff: Duration::from_millis(get_env_as_u64("SUPERVISOR_BACKOFF_MS", 1000)),
+            max_backoff: Duration::from_millis(get_env_as_u64("SUPERVISOR_MAX_BACKOFF_MS", 60_000)),
+            crash_loop_restarts: get_env_as_u64("CRASH_LOOP_RESTARTS", 5).max(1) as usize,
+            // How long a monitor may go without recording a block before it is restarted
+            stall_timeout: Some(get_env_as_u64("STALL_RESTART_SECS", 300))
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+        }
+    }
+}
+
+/// What happened when a monitor was restarted
+pub struct RestartEvent {
+    pub backoff: Duration,
+    pub recent_restarts: usize,
+    pub entered_crash_loop: bool,
+}
+
+/// Decides when a chain monitor must be restarted and how long to back off afterwards
+pub struct Supervisor {
+    config: SupervisorConfig,
+    consecutive_failures: u32,
+    recent_restarts: VecDeque<Instant>,
+    backoff_until: Option<Instant>,
+    in_crash_loop: bool,
+    // Blocks the monitor had recorded when last checked, and since when (or since it last
+    // restarted)
+    blocks_seen: usize,
+    quiet_since: Option<Instant>,
+}
+
+impl Supervisor {
+    pub fn new(config: SupervisorConfig) -> Self {
+        Self {
+            config,
+            consecutive_failures: 0,
+            recent_restarts: VecDeque::new(),
+            backoff_until: None,
+            in_crash_loop: false,
+            blocks_seen: 0,
+            quiet_since: None,
+        }
+    }
+
+    /// Whether the monitor may run now (i.e. isn't backing off after a restart)
+    pub fn ready(&self, now: Instant) -> bool {
+        self.backoff_until.is_none_or(|until| now >= until)
+    }
+
+    pub fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures
+    }
+
+    /// Record a successful iteration. Returns true if this ends a crash loop.
+    pub fn record_success(&mut self, now: Instant) -> bool {
+        self.consecutive_failures = 0;
+        self.prune(now);
+        if self.in_crash_loop && self.recent_restarts.len() < self.config.crash_loop_restarts {
+            self.in_crash_loop = false;
+            return true;
+        }
+        false
+    }
+
+    /// Record a failed iteration. Returns true once the failure streak calls for a restart.
+    pub fn record_failure(&mut self) -> bool {
+        self.consecutive_failures += 1;
+        self.consecutive_failures >= self.config.max_consecutive_failures
+    }
+
+    /// Note that the monitor has recorded `total_blocks` blocks so far. Returns how long it
+    /// has gone without a new one once that reaches the stall timeout.
+    pub fn check_stall(&mut self, total_blocks: usize, now: Instant) -> Option<Duration> {
+        if total_blocks != self.blocks_seen {
+            self.blocks_seen = total_blocks;
+            self.quiet_since = Some(now);
+            return None;
+        }
+        let quiet = now.duration_since(*self.quiet_since.get_or_insert(now));
+        self.config.stall_timeout.filter(|timeout| quiet >= *timeout).map(|_| quiet)
+    }
+
+    /// Record a restart and schedule the exponential backoff that follows it
+    pub fn record_restart(&mut self, now: Instant) -> RestartEvent {
+        self.consecutive_failures = 0;
+        self.quiet_since = Some(now);
+        self.prune(now);
+        self.recent_restarts.push_back(now);
+
+        let recent = self.recent_restarts.len();
+        let exponent = (recent - 1).min(16) as u32;
+        let backoff = self
+            .config
+            .base_backoff
+            .saturating_mul(2u32.pow(exponent))
+            .min(self.config.max_backoff);
+        self.backoff_until = Some(now + backoff);
+
+        let entered_crash_loop = !self.in_crash_loop && recent >= self.config.crash_loop_restarts;
+        if entered_crash_loop {
+            self.in_crash_loop = true;
+        }
+
+        RestartEvent {
+            backoff,
+            recent_restarts: recent,
+            entered_crash_loop,
+        }
+    }
+
+    // Forget restarts that fell out of the crash-loop window
+    fn prune(&mut self, now: Instant) {
+        while let Some(oldest) = self.recent_restarts.front() {
+            if now.duration_since(*oldest) > CRASH_LOOP_WINDOW {
+                self.recent_restarts.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+/// Best-effort text of a caught panic payload
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STALL_TIMEOUT: Duration = Duration::from_secs(300);
+
+    fn supervisor() -> Supervisor {
+        Supervisor::new(SupervisorConfig {
+            max_consecutive_failures: 3,
+            base_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(5),
+            crash_loop_restarts: 3,
+            stall_timeout: Some(STALL_TIMEOUT),
+        })
+    }
+
+    #[test]
+    fn failure_streak_calls_for_a_restart_and_success_resets_it() {
+        let mut supervisor = supervisor();
+        assert!(!supervisor.record_failure());
+        assert!(!supervisor.record_failure());
+        supervisor.record_success(Instant::now());
+        assert_eq!(supervisor.consecutive_failures(), 0);
+        assert!(!supervisor.record_failure());
+        assert!(!supervisor.record_failure());
+        assert!(supervisor.record_failure());
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let start = Instant::now();
+        let mut supervisor = supervisor();
+        let backoffs: Vec<u64> = (0..4)
+            .map(|i| supervisor.record_restart(start + Duration::from_secs(i)).backoff.as_secs())
+            .collect();
+        assert_eq!(backoffs, [1, 2, 4, 5]);
+        assert!(!supervisor.ready(start + Duration::from_secs(4)));
+        assert!(supervisor.ready(start + Duration::from_secs(8)));
+    }
+
+    #[test]
+    fn crash_loop_is_entered_once_and_left_when_restarts_age_out() {
+        let start = Instant::now();
+        let mut supervisor = supervisor();
+        assert!(!supervisor.record_restart(start).entered_crash_loop);
+        assert!(!supervisor.record_restart(start).entered_crash_loop);
+        let event = supervisor.record_restart(start);
+        assert!(event.entered_crash_loop);
+        assert_eq!(event.recent_restarts, 3);
+        assert!(!supervisor.record_restart(start).entered_crash_loop);
+
+        assert!(!supervisor.record_success(start + Duration::from_secs(60)));
+        assert!(supervisor.record_success(start + CRASH_LOOP_WINDOW + Duration::from_secs(1)));
+        assert_eq!(supervisor.record_restart(start + CRASH_LOOP_WINDOW * 2).backoff, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn stall_is_reported_only_after_the_timeout_without_new_blocks() {
+        let start = Instant::now();
+        let mut supervisor = supervisor();
+        assert_eq!(supervisor.check_stall(0, start), None);
+        assert_eq!(supervisor.check_stall(0, start + STALL_TIMEOUT / 2), None);
+        // A new block restarts the clock
+        assert_eq!(supervisor.check_stall(4, start + STALL_TIMEOUT / 2), None);
+        assert_eq!(supervisor.check_stall(4, start + STALL_TIMEOUT), None);
+        assert_eq!(
+            supervisor.check_stall(4, start + STALL_TIMEOUT / 2 + STALL_TIMEOUT),
+            Some(STALL_TIMEOUT)
+        );
+
+        // So does a restart, which is how a stall is acted on
+        let restarted = start + STALL_TIMEOUT * 2;
+        supervisor.record_restart(restarted);
+        assert_eq!(supervisor.check_stall(4, restarted + STALL_TIMEOUT / 2), None);
+        assert_eq!(supervisor.check_stall(4, restarted + STALL_TIMEOUT), Some(STALL_TIMEOUT));
+    }
+
+    #[test]
+    fn stall_timeout_of_zero_never_restarts() {
+        let start = Instant::now();
+        let mut supervisor = Supervisor::new(SupervisorConfig {
+            stall_timeout: None,
+            ..supervisor().config
+        });
+        supervisor.check_stall(0, start);
+        assert_eq!(supervisor.check_stall(0, start + STALL_TIMEOUT * 10), None);
+    }
+
+    #[test]
+    fn panic_message_reads_str_and_string_payloads() {
+        let payload = std::panic::catch_unwind(|| panic!("boom")).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "boom");
+        let payload = std::panic::catch_unwind(|| panic!("{} blocks", 3)).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "3 blocks");
+    }
+}
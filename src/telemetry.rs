@@ -0,0 +1,291 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, Write};
+
+use crate::redact;
+
+/// How log lines are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// One JSON object per line, with the fields of the enclosing span (chain, block number,
+    /// delta) attached, for Loki or Elasticsearch
+    Json,
+}
+
+// Text logger that masks URLs and credentials before env_logger formats each record
+struct RedactingLogger(env_logger::Logger);
+
+impl log::Log for RedactingLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.0.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.0.matches(record) {
+            return;
+        }
+        let message = record.args().to_string();
+        match redact::text(&message) {
+            Cow::Borrowed(_) => self.0.log(record),
+            Cow::Owned(redacted) => self.0.log(
+                &log::Record::builder()
+                    .args(format_args!("{}", redacted))
+                    .metadata(record.metadata().clone())
+                    .module_path(record.module_path())
+                    .file(record.file())
+                    .line(record.line())
+                    .build(),
+            ),
+        }
+    }
+
+    fn flush(&self) {
+        self.0.flush()
+    }
+}
+
+// JSON log writer that masks URLs and credentials; every event is written in one piece
+struct RedactingMakeWriter(tracing_subscriber::fmt::writer::BoxMakeWriter);
+
+impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for RedactingMakeWriter {
+    type Writer = RedactingWriter<Box<dyn Write + 'a>>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter(self.0.make_writer())
+    }
+}
+
+struct RedactingWriter<W>(W);
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match std::str::from_utf8(buf) {
+            Ok(text) => {
+                self.0.write_all(redact::text(text).as_bytes())?;
+                Ok(buf.len())
+            }
+            Err(_) => self.0.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Keeps the OpenTelemetry exporters alive; call `shutdown` to flush pending spans and metrics
+pub struct TelemetryGuard {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+    #[cfg(feature = "otel")]
+    meter_provider: Option<opentelemetry_sdk::metrics::SdkMeterProvider>,
+}
+
+/// Instruments recorded for every block observation, created once the meter provider is set
+#[cfg(feature = "otel")]
+struct BlockInstruments {
+    delta: opentelemetry::metrics::Histogram<f64>,
+}
+
+#[cfg(feature = "otel")]
+static BLOCK_INSTRUMENTS: std::sync::OnceLock<BlockInstruments> = std::sync::OnceLock::new();
+
+/// Set up logging in `log_format`, to `log_file` instead of stderr when given, filtered by
+/// `RUST_LOG` (default `info`).
+///
+/// URLs and credentials are masked in every log line, as [`redact::text`] does, unless
+/// `log_full_urls` is set.
+///
+/// Also exports the per-block tracing spans and delta metrics over OTLP when built with the
+/// `otel` feature and `OTEL_EXPORTER_OTLP_ENDPOINT` is set. Otherwise the spans are only
+/// seen by JSON logs.
+pub fn init(log_format: LogFormat, log_file: Option<File>, log_full_urls: bool) -> Result<TelemetryGuard> {
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+    use tracing_subscriber::Layer;
+
+    let json = match log_format {
+        LogFormat::Text => {
+            let mut logger = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"));
+            if let Some(file) = log_file {
+                logger.target(env_logger::Target::Pipe(Box::new(file)));
+            }
+            if log_full_urls {
+                logger.init();
+            } else {
+                let logger = RedactingLogger(logger.build());
+                log::set_max_level(logger.0.filter());
+                log::set_boxed_logger(Box::new(logger))?;
+            }
+            None
+        }
+        LogFormat::Json => {
+            let writer = match log_file {
+                Some(file) => tracing_subscriber::fmt::writer::BoxMakeWriter::new(std::sync::Mutex::new(file)),
+                None => tracing_subscriber::fmt::writer::BoxMakeWriter::new(std::io::stderr),
+            };
+            let writer = if log_full_urls {
+                writer
+            } else {
+                tracing_subscriber::fmt::writer::BoxMakeWriter::new(RedactingMakeWriter(writer))
+            };
+            let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"));
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .flatten_event(true)
+                    .with_current_span(true)
+                    .with_span_list(false)
+                    .with_writer(writer)
+                    .with_filter(filter),
+            )
+        }
+    };
+
+    #[cfg(feature = "otel")]
+    let (otel, provider, meter_provider) = match std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+        Ok(_) => {
+            use opentelemetry::trace::TracerProvider;
+
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_tonic()
+                .build()?;
+            let resource = opentelemetry_sdk::Resource::builder()
+                .with_service_name(env!("CARGO_PKG_NAME"))
+                .build();
+            let provider = opentelemetry_sdk::trace::SdkTracerProvider::builder()
+                .with_batch_exporter(exporter)
+                .with_resource(resource.clone())
+                .build();
+            let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+
+            let metric_exporter = opentelemetry_otlp::MetricExporter::builder()
+                .with_tonic()
+                .build()?;
+            let meter_provider = opentelemetry_sdk::metrics::SdkMeterProvider::builder()
+                .with_periodic_exporter(metric_exporter)
+                .with_resource(resource)
+                .build();
+            let meter = opentelemetry::metrics::MeterProvider::meter(&meter_provider, env!("CARGO_PKG_NAME"));
+            let delta = meter
+                .f64_histogram("block_timestamp.delta")
+                .with_unit("ms")
+                .with_description("Receipt time minus block timestamp")
+                .with_boundaries(crate::metrics::DELTA_BUCKETS_MS.iter().map(|bound| *bound as f64).collect())
+                .build();
+            let _ = BLOCK_INSTRUMENTS.set(BlockInstruments { delta });
+
+            // RPC requests carry a `traceparent` header, so an instrumented endpoint's spans
+            // join the block's trace
+            opentelemetry::global::set_text_map_propagator(
+                opentelemetry_sdk::propagation::TraceContextPropagator::new(),
+            );
+            (Some(tracing_opentelemetry::layer().with_tracer(tracer)), Some(provider), Some(meter_provider))
+        }
+        Err(_) => (None, None, None),
+    };
+    #[cfg(not(feature = "otel"))]
+    {
+        if std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").is_ok() {
+            log::warn!("OTEL_EXPORTER_OTLP_ENDPOINT is set but this build lacks the `otel` feature");
+        }
+    }
+
+    #[cfg(feature = "otel")]
+    let exporting = otel.is_some();
+    #[cfg(not(feature = "otel"))]
+    let exporting = false;
+
+    let registry = tracing_subscriber::registry().with(json);
+    #[cfg(feature = "otel")]
+    let registry = registry.with(otel);
+    if log_format == LogFormat::Json {
+        // Also routes the `log` records through the JSON layer
+        registry.try_init()?;
+    } else if exporting {
+        // env_logger already handles the `log` records
+        tracing::subscriber::set_global_default(registry)?;
+    }
+    if exporting {
+        log::info!("Exporting block observation traces and metrics over OTLP");
+    }
+
+    Ok(TelemetryGuard {
+        #[cfg(feature = "otel")]
+        provider,
+        #[cfg(feature = "otel")]
+        meter_provider,
+    })
+}
+
+/// Add a block's delta to the exported `block_timestamp.delta` histogram; a no-op unless
+/// metrics are being exported
+#[cfg_attr(not(feature = "otel"), allow(unused_variables))]
+pub(crate) fn record_delta(chain: &str, delta_ms: i64) {
+    #[cfg(feature = "otel")]
+    if let Some(instruments) = BLOCK_INSTRUMENTS.get() {
+        let direction = crate::delta::TimestampDelta::from_ms(delta_ms).direction.as_str();
+        instruments.delta.record(
+            delta_ms as f64,
+            &[
+                opentelemetry::KeyValue::new("chain", chain.to_string()),
+                opentelemetry::KeyValue::new("direction", direction),
+            ],
+        );
+    }
+}
+
+/// Trace context headers for an outgoing request made in the current span; empty unless
+/// traces are being exported
+pub(crate) fn trace_headers() -> reqwest::header::HeaderMap {
+    #[allow(unused_mut)]
+    let mut headers = reqwest::header::HeaderMap::new();
+    #[cfg(feature = "otel")]
+    {
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+        let context = tracing::Span::current().context();
+        opentelemetry::global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(&context, &mut HeaderInjector(&mut headers))
+        });
+    }
+    headers
+}
+
+#[cfg(feature = "otel")]
+struct HeaderInjector<'a>(&'a mut reqwest::header::HeaderMap);
+
+#[cfg(feature = "otel")]
+impl opentelemetry::propagation::Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            reqwest::header::HeaderName::from_bytes(key.as_bytes()),
+            reqwest::header::HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+impl TelemetryGuard {
+    pub fn shutdown(self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = self.provider {
+            if let Err(e) = provider.shutdown() {
+                log::error!("Failed to flush traces: {}", e);
+            }
+        }
+        #[cfg(feature = "otel")]
+        if let Some(meter_provider) = self.meter_provider {
+            if let Err(e) = meter_provider.shutdown() {
+                log::error!("Failed to flush metrics: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use anyhow::Result;
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+
+use crate::stats::ChainStats;
+
+/// Columns of `{Chain}_timeseries.csv`
+pub const TIMESERIES_HEADERS: &[&str] = &["Window End", "Blocks", "Avg Delta (ms)", "Min Delta (ms)", "Max Delta (ms)"];
+
+/// Each chain's blocks and deltas over every report interval, one row per report, so the
+/// delta can be plotted against wall-clock time; the stats CSVs only hold run totals.
+///
+/// A window runs from the previous report (or the start of the logger) to the current one
+/// and covers the blocks received in it. Rows are appended to `{Chain}_timeseries.csv`,
+/// so a resumed run continues the same files.
+#[derive(Debug)]
+pub struct DeltaTimeseries {
+    dir: PathBuf,
+    window_start: DateTime<Utc>,
+}
+
+impl DeltaTimeseries {
+    pub fn new(dir: &Path, started_at: DateTime<Utc>) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            window_start: started_at,
+        }
+    }
+
+    /// Append every chain's row for the window ending at `now`, and start the next window
+    pub fn append(&mut self, chain_stats: &HashMap<String, ChainStats>, now: DateTime<Utc>) -> Result<()> {
+        let (start_ms, end_ms) = (self.window_start.timestamp_millis(), now.timestamp_millis());
+        self.window_start = now;
+        for stats in chain_stats.values() {
+            let deltas: Vec<i64> = stats
+                .raw_samples
+                .iter()
+                .rev()
+                .take_while(|sample| sample.receipt_time_ms >= start_ms)
+                .filter(|sample| sample.receipt_time_ms < end_ms)
+                .map(|sample| sample.delta_ms)
+                .collect();
+
+            let path = self.dir.join(format!("{}_timeseries.csv", stats.chain_name));
+            let new_file = !path.exists();
+            let file = OpenOptions::new().create(true).append(true).open(&path)?;
+            let mut wtr = csv::Writer::from_writer(file);
+            if new_file {
+                wtr.write_record(TIMESERIES_HEADERS)?;
+            }
+            // A window without blocks is still written, so stalls show up as gaps in the plot
+            let (avg, min, max) = match (deltas.iter().min(), deltas.iter().max()) {
+                (Some(min), Some(max)) => (
+                    (deltas.iter().sum::<i64>() / deltas.len() as i64).to_string(),
+                    min.to_string(),
+                    max.to_string(),
+                ),
+                _ => Default::default(),
+            };
+            wtr.write_record([now.to_rfc3339_opts(SecondsFormat::Millis, true), deltas.len().to_string(), avg, min, max])?;
+            wtr.flush()?;
+        }
+        Ok(())
+    }
+}
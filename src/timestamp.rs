@@ -0,0 +1,92 @@
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value};
+
+/// How a chain's block headers carry their timestamp
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum TimestampDecoding {
+    /// The standard `timestamp` field, in whole seconds
+    #[default]
+    Seconds,
+    /// An extension field holding the full timestamp in milliseconds (e.g. `timestampMs`),
+    /// falling back to the `timestamp` field on headers without it
+    MillisecondsField(String),
+}
+
+impl TimestampDecoding {
+    /// Block timestamp in milliseconds, given the header's `timestamp` in seconds and its
+    /// other fields
+    pub fn to_millis(&self, seconds: u64, fields: &Map<String, Value>) -> Result<u64> {
+        let field = match self {
+            TimestampDecoding::Seconds => return Ok(seconds * 1000),
+            TimestampDecoding::MillisecondsField(field) => field,
+        };
+        let Some(value) = fields.get(field) else {
+            return Ok(seconds * 1000);
+        };
+        
+        let millis = parse_number(value).map_err(|e| anyhow!("Invalid {}: {}", field, e))?;
+        // Both fields describe the same instant, so they can't be a second or more apart
+        if millis.abs_diff(seconds * 1000) >= 1000 {
+            return Err(anyhow!("{} of {}ms disagrees with timestamp {}s", field, millis, seconds));
+        }
+        Ok(millis)
+    }
+}
+
+/// A header field holding a number: extension fields come as hex quantities, decimal
+/// strings or plain numbers
+pub(crate) fn parse_number(value: &Value) -> Result<u64> {
+    match value {
+        Value::Number(number) => number.as_u64().ok_or_else(|| anyhow!("{} is not a whole number", number)),
+        Value::String(text) => match text.strip_prefix("0x") {
+            Some(hex) => Ok(u64::from_str_radix(hex, 16)?),
+            None => Ok(text.parse()?),
+        },
+        other => Err(anyhow!("expected a number, got {}", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fields(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn millisecond_fields_refine_the_timestamp() {
+        let decoding = TimestampDecoding::MillisecondsField("timestampMs".to_string());
+        let seconds = 1_700_000_000;
+        for value in [json!("0x18bcfe56a8a"), json!("1700000000650"), json!(1_700_000_000_650u64)] {
+            let value = fields(json!({ "timestampMs": value }));
+            assert_eq!(decoding.to_millis(seconds, &value).unwrap(), 1_700_000_000_650);
+        }
+        // Chains decoded in seconds ignore the field
+        let value = fields(json!({"timestampMs": "1700000000650"}));
+        assert_eq!(TimestampDecoding::Seconds.to_millis(seconds, &value).unwrap(), 1_700_000_000_000);
+    }
+
+    #[test]
+    fn headers_without_the_field_fall_back_to_seconds() {
+        let decoding = TimestampDecoding::MillisecondsField("timestampMs".to_string());
+        let value = fields(json!({"timestamp_ms": "1700000000650"}));
+        assert_eq!(decoding.to_millis(1_700_000_000, &value).unwrap(), 1_700_000_000_000);
+    }
+
+    #[test]
+    fn malformed_fields_are_rejected() {
+        let decoding = TimestampDecoding::MillisecondsField("timestampMs".to_string());
+        for value in [json!("0xzz"), json!("soon"), json!(-1), json!(1.5), json!(null), json!([1])] {
+            let value = fields(json!({ "timestampMs": value }));
+            let error = decoding.to_millis(1_700_000_000, &value).unwrap_err();
+            assert!(error.to_string().starts_with("Invalid timestampMs"), "{}", error);
+        }
+        // A value in seconds, or from another block, disagrees with the timestamp
+        for value in [json!(1_700_000_000u64), json!(1_700_000_001_000u64)] {
+            let value = fields(json!({ "timestampMs": value }));
+            assert!(decoding.to_millis(1_700_000_000, &value).is_err());
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::env;
+
+/// Timezone used when rendering times in reports; all stored data stays in UTC
+#[derive(Debug, Clone, Copy)]
+pub struct ReportTimezone(Tz);
+
+impl Default for ReportTimezone {
+    fn default() -> Self {
+        ReportTimezone(Tz::UTC)
+    }
+}
+
+impl ReportTimezone {
+    /// Parse an IANA timezone name such as `Europe/Berlin`
+    pub fn parse(name: &str) -> Result<Self> {
+        name.parse::<Tz>()
+            .map(ReportTimezone)
+            .map_err(|e| anyhow!("Invalid timezone '{}': {}", name, e))
+    }
+
+    /// Read `REPORT_TIMEZONE`, defaulting to UTC
+    pub fn from_env() -> Result<Self> {
+        match env::var("REPORT_TIMEZONE") {
+            Ok(name) => Self::parse(&name),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.0.name()
+    }
+
+    /// Render a UTC instant in this timezone
+    pub fn format(&self, time: DateTime<Utc>, fmt: &str) -> String {
+        time.with_timezone(&self.0).format(fmt).to_string()
+    }
+
+    /// Start of the local hour containing `time`, e.g. "2025-04-29 13:00 CEST".
+    /// Uses the local offset in effect at that instant, so buckets stay correct across DST changes.
+    pub fn hour_bucket(&self, time: DateTime<Utc>) -> String {
+        self.format(time, "%Y-%m-%d %H:00 %Z")
+    }
+
+    /// Convert a local wall-clock time to UTC. Times skipped by a DST change resolve to
+    /// the instant an hour later; ambiguous times resolve to the earlier instant.
+    pub fn to_utc(self, local: NaiveDateTime) -> DateTime<Utc> {
+        match self.0.from_local_datetime(&local).earliest() {
+            Some(time) => time.with_timezone(&Utc),
+            None => self.to_utc(local + chrono::Duration::hours(1)),
+        }
+    }
+
+    /// Current local wall-clock time
+    pub fn now_local(&self, now: DateTime<Utc>) -> NaiveDateTime {
+        now.with_timezone(&self.0).naive_local()
+    }
+}
@@ -0,0 +1,328 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use clap::Args;
+use log::{info, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::path::{Path, PathBuf};
+
+use crate::analyze::AnalysisFormat;
+use crate::dataset::DataFormat;
+use crate::delta::{DeltaDirection, TimestampDelta};
+use crate::replay;
+
+/// Variables holding the region a machine runs in: `VANTAGE_REGION`, then those set by
+/// AWS, Fly.io, Railway and Vercel
+const REGION_VARS: &[&str] = &[
+    "VANTAGE_REGION",
+    "AWS_REGION",
+    "AWS_DEFAULT_REGION",
+    "FLY_REGION",
+    "RAILWAY_REPLICA_REGION",
+    "VERCEL_REGION",
+];
+
+/// Where a run observes its chains from. The label is recorded with every observation, so
+/// runs from several machines can be merged and compared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vantage {
+    /// Name the observations are recorded under: `--vantage-label`, or else the hostname
+    pub label: String,
+    pub hostname: Option<String>,
+    /// Cloud region, from `VANTAGE_REGION` or the platform's own variable
+    pub region: Option<String>,
+}
+
+impl Vantage {
+    /// The vantage point named `label`, or after this machine when there is none, with the
+    /// hostname and region detected from the environment
+    pub fn detect(label: Option<&str>) -> Self {
+        let hostname = hostname();
+        let region = REGION_VARS
+            .iter()
+            .find_map(|var| env::var(var).ok().filter(|region| !region.is_empty()));
+        let label = label
+            .filter(|label| !label.is_empty())
+            .map(str::to_string)
+            .or_else(|| hostname.clone())
+            .unwrap_or_else(|| "local".to_string());
+        Self { label, hostname, region }
+    }
+
+    /// The vantage point as recorded in `summary.json` and `run_config.json`
+    pub fn summary(&self) -> serde_json::Value {
+        serde_json::json!({
+            "label": self.label,
+            "hostname": self.hostname,
+            "region": self.region,
+        })
+    }
+}
+
+fn hostname() -> Option<String> {
+    ["HOSTNAME", "COMPUTERNAME"]
+        .iter()
+        .find_map(|var| env::var(var).ok())
+        .or_else(|| std::fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// Arguments for `analyze compare-vantage`
+#[derive(Debug, Args)]
+pub struct CompareVantageArgs {
+    /// Datasets to compare: run directories, NDJSON files, SQLite or DuckDB databases, each from
+    /// one vantage point or merged from several
+    #[arg(required = true)]
+    pub inputs: Vec<PathBuf>,
+
+    /// Format of the inputs; detected from each path when omitted
+    #[arg(long, value_enum)]
+    pub from: Option<DataFormat>,
+
+    /// Only compare these chains, comma-separated
+    #[arg(long, value_delimiter = ',')]
+    pub chain: Vec<String>,
+
+    /// Only blocks received at or after this time (RFC 3339)
+    #[arg(long)]
+    pub since: Option<DateTime<Utc>>,
+
+    /// Only blocks received before this time (RFC 3339)
+    #[arg(long)]
+    pub until: Option<DateTime<Utc>>,
+
+    /// Vantage point the others are compared with; the first in name order by default
+    #[arg(long)]
+    pub baseline: Option<String>,
+
+    /// Print the comparison as text or as JSON
+    #[arg(long, value_enum, default_value_t = AnalysisFormat::Text)]
+    pub format: AnalysisFormat,
+}
+
+/// One chain's deltas as seen from one vantage point
+#[derive(Default)]
+struct VantageDeltas {
+    sorted: Vec<i64>,
+    by_block: HashMap<u64, i64>,
+    future: usize,
+}
+
+impl VantageDeltas {
+    fn percentile(&self, p: f64) -> i64 {
+        let rank = ((p / 100.0) * self.sorted.len() as f64).ceil() as usize;
+        self.sorted[rank.clamp(1, self.sorted.len()) - 1]
+    }
+
+    fn mean(&self) -> i64 {
+        (self.sorted.iter().map(|delta| *delta as i128).sum::<i128>() / self.sorted.len() as i128) as i64
+    }
+
+    fn future_pct(&self) -> f64 {
+        self.future as f64 * 100.0 / self.sorted.len() as f64
+    }
+
+    fn summary(&self, vantage: &str) -> serde_json::Value {
+        serde_json::json!({
+            "vantage": vantage,
+            "blocks": self.sorted.len(),
+            "future_timestamp_blocks": self.future,
+            "future_pct": self.future_pct(),
+            "delta_ms": {
+                "min": self.sorted[0],
+                "avg": self.mean(),
+                "max": self.sorted[self.sorted.len() - 1],
+                "p50": self.percentile(50.0),
+                "p90": self.percentile(90.0),
+                "p99": self.percentile(99.0),
+            },
+        })
+    }
+}
+
+/// How one vantage point's view of a chain differs from the baseline's
+struct VantageComparison {
+    common_blocks: usize,
+    // Median of this vantage's delta minus the baseline's, over the blocks both recorded:
+    // how much later (or, negative, earlier) the same blocks arrive here
+    median_paired_diff_ms: Option<i64>,
+    future_pct_diff: f64,
+    // Largest gap between the two cumulative delta distributions, from 0 (identical) to 1
+    ks_distance: f64,
+}
+
+impl VantageComparison {
+    fn of(deltas: &VantageDeltas, baseline: &VantageDeltas) -> Self {
+        let mut diffs: Vec<i64> = deltas
+            .by_block
+            .iter()
+            .filter_map(|(block, delta)| baseline.by_block.get(block).map(|base| delta - base))
+            .collect();
+        diffs.sort_unstable();
+        Self {
+            common_blocks: diffs.len(),
+            median_paired_diff_ms: (!diffs.is_empty()).then(|| diffs[(diffs.len() - 1) / 2]),
+            future_pct_diff: deltas.future_pct() - baseline.future_pct(),
+            ks_distance: ks_distance(&deltas.sorted, &baseline.sorted),
+        }
+    }
+}
+
+// Two-sample Kolmogorov-Smirnov statistic of two sorted samples
+pub(crate) fn ks_distance(a: &[i64], b: &[i64]) -> f64 {
+    let (mut i, mut j, mut distance) = (0, 0, 0.0_f64);
+    while i < a.len() && j < b.len() {
+        let x = a[i].min(b[j]);
+        while i < a.len() && a[i] <= x {
+            i += 1;
+        }
+        while j < b.len() && b[j] <= x {
+            j += 1;
+        }
+        distance = distance.max((i as f64 / a.len() as f64 - j as f64 / b.len() as f64).abs());
+    }
+    distance
+}
+
+// Observations that predate vantage labels are named after the dataset they came from
+fn fallback_label(input: &Path) -> String {
+    input
+        .file_stem()
+        .or_else(|| input.file_name())
+        .map_or_else(|| input.display().to_string(), |name| name.to_string_lossy().into_owned())
+}
+
+/// Compare each chain's delta distribution between the vantage points its observations
+/// were recorded from, such as loggers in different regions watching the same chains
+pub fn compare(args: &CompareVantageArgs) -> Result<()> {
+    // Chain -> vantage -> deltas
+    let mut chains: BTreeMap<String, BTreeMap<String, VantageDeltas>> = BTreeMap::new();
+    for input in &args.inputs {
+        let observations = replay::load_observations(input, args.from)?;
+        info!("Loaded {} observations from {}", observations.len(), input.display());
+        let fallback = fallback_label(input);
+        for observation in observations {
+            if !(args.chain.is_empty() || args.chain.contains(&observation.chain))
+                || args.since.is_some_and(|since| observation.received_at < since)
+                || args.until.is_some_and(|until| observation.received_at >= until)
+            {
+                continue;
+            }
+            let vantage = observation.vantage.unwrap_or_else(|| fallback.clone());
+            let deltas = chains.entry(observation.chain).or_default().entry(vantage).or_default();
+            // A block recorded twice from the same vantage point counts as first received
+            if deltas.by_block.contains_key(&observation.block_number) {
+                continue;
+            }
+            deltas.by_block.insert(observation.block_number, observation.delta_ms);
+            deltas.sorted.push(observation.delta_ms);
+            deltas.future += (TimestampDelta::from_ms(observation.delta_ms).direction == DeltaDirection::Future) as usize;
+        }
+    }
+    chains.retain(|_, vantages| vantages.len() > 1);
+    if chains.is_empty() {
+        return Err(anyhow!("No chain in the inputs was observed from more than one vantage point"));
+    }
+
+    if let Some(baseline) = &args.baseline {
+        if !chains.values().any(|vantages| vantages.contains_key(baseline)) {
+            return Err(anyhow!("No chain was observed from vantage point '{}'", baseline));
+        }
+    }
+
+    let mut json_chains = Vec::new();
+    for (chain, mut vantages) in chains {
+        let baseline = match &args.baseline {
+            Some(baseline) if vantages.contains_key(baseline) => baseline.clone(),
+            Some(baseline) => {
+                warn!("{} has no observations from vantage point '{}'; skipping it", chain, baseline);
+                continue;
+            }
+            None => vantages.keys().next().cloned().unwrap_or_default(),
+        };
+        for deltas in vantages.values_mut() {
+            deltas.sorted.sort_unstable();
+        }
+        let comparisons: Vec<(&String, VantageComparison)> = vantages
+            .iter()
+            .filter(|(vantage, _)| **vantage != baseline)
+            .map(|(vantage, deltas)| (vantage, VantageComparison::of(deltas, &vantages[&baseline])))
+            .collect();
+        match args.format {
+            AnalysisFormat::Text => {
+                for line in text_report(&chain, &baseline, &vantages, &comparisons) {
+                    println!("{}", line);
+                }
+            }
+            AnalysisFormat::Json => json_chains.push(json_report(&chain, &baseline, &vantages, &comparisons)),
+        }
+    }
+    if args.format == AnalysisFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&serde_json::json!({ "chains": json_chains }))?);
+    }
+    Ok(())
+}
+
+fn text_report(
+    chain: &str,
+    baseline: &str,
+    vantages: &BTreeMap<String, VantageDeltas>,
+    comparisons: &[(&String, VantageComparison)],
+) -> Vec<String> {
+    let width = vantages.keys().map(String::len).max().unwrap_or(0).max("Vantage".len());
+    let mut lines = vec![
+        format!("{}: {} vantage points, compared with {}", chain, vantages.len(), baseline),
+        format!(
+            "  {:<width$} | {:>8} | {:>7} | {:>7} | {:>7} | {:>7} | {:>7}",
+            "Vantage", "Blocks", "Future", "Avg", "P50", "P90", "P99",
+            width = width
+        ),
+    ];
+    for (vantage, deltas) in vantages {
+        lines.push(format!(
+            "  {:<width$} | {:>8} | {:>6.1}% | {:>5}ms | {:>5}ms | {:>5}ms | {:>5}ms",
+            vantage,
+            deltas.sorted.len(),
+            deltas.future_pct(),
+            deltas.mean(),
+            deltas.percentile(50.0),
+            deltas.percentile(90.0),
+            deltas.percentile(99.0),
+            width = width
+        ));
+    }
+    for (vantage, comparison) in comparisons {
+        lines.push(format!(
+            "  - {} vs {}: {} common blocks, median paired difference {} | future share {:+.1} points | KS distance {:.3}",
+            vantage,
+            baseline,
+            comparison.common_blocks,
+            comparison.median_paired_diff_ms.map_or("-".to_string(), |ms| format!("{:+}ms", ms)),
+            comparison.future_pct_diff,
+            comparison.ks_distance
+        ));
+    }
+    lines
+}
+
+fn json_report(
+    chain: &str,
+    baseline: &str,
+    vantages: &BTreeMap<String, VantageDeltas>,
+    comparisons: &[(&String, VantageComparison)],
+) -> serde_json::Value {
+    serde_json::json!({
+        "chain": chain,
+        "baseline": baseline,
+        "vantages": vantages.iter().map(|(vantage, deltas)| deltas.summary(vantage)).collect::<Vec<_>>(),
+        "comparisons": comparisons.iter().map(|(vantage, comparison)| serde_json::json!({
+            "vantage": vantage,
+            "common_blocks": comparison.common_blocks,
+            "median_paired_diff_ms": comparison.median_paired_diff_ms,
+            "future_pct_diff": comparison.future_pct_diff,
+            "ks_distance": comparison.ks_distance,
+        })).collect::<Vec<_>>(),
+    })
+}